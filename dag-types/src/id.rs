@@ -143,7 +143,16 @@ impl Group {
     ///   will refer to a bounded subset in this group.
     pub const NON_MASTER: Self = Self(1);
 
-    pub const ALL: [Self; 2] = [Self::MASTER, Self::NON_MASTER];
+    /// The "virtual" group.
+    /// - In-memory only. Ids in this group are never written to disk.
+    ///   For example, working copy parents, or pending commits.
+    /// - Can depend on vertexes in other groups, but not the other way.
+    pub const VIRTUAL: Self = Self(2);
+
+    pub const ALL: [Self; 3] = [Self::MASTER, Self::NON_MASTER, Self::VIRTUAL];
+
+    /// Groups that are written to disk.
+    pub const PERSIST: [Self; 2] = [Self::MASTER, Self::NON_MASTER];
 
     pub const COUNT: usize = Self::ALL.len();
 
@@ -214,6 +223,8 @@ impl fmt::Display for Id {
         let group = self.group();
         if group == Group::NON_MASTER {
             write!(f, "N")?;
+        } else if group == Group::VIRTUAL {
+            write!(f, "V")?;
         }
         write!(f, "{}", self.0 - group.min_id().0)
     }
@@ -230,6 +241,7 @@ impl fmt::Display for Group {
         match *self {
             Group::MASTER => write!(f, "Group Master"),
             Group::NON_MASTER => write!(f, "Group Non-Master"),
+            Group::VIRTUAL => write!(f, "Group Virtual"),
             _ => write!(f, "Group {}", self.0),
         }
     }
@@ -0,0 +1,427 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! # bulk_import
+//!
+//! Import a large graph using a parents function that is slow to call,
+//! ex. reading commits from a store. See [`bulk_import`].
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+
+use futures::channel::mpsc::unbounded;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::StreamExt;
+
+use crate::errors::bug;
+use crate::errors::programming;
+use crate::ops::DagPersistent;
+use crate::ops::IdConvert;
+use crate::Group;
+use crate::Result;
+use crate::VertexListWithOptions;
+use crate::VertexName;
+use crate::VertexOptions;
+
+/// Report progress every this many resolved vertexes.
+const PROGRESS_INTERVAL: u64 = 1000;
+
+/// Options for [`bulk_import`].
+#[derive(Clone)]
+pub struct BulkImportOptions {
+    threads: usize,
+    queue_size: usize,
+    batch_size: usize,
+    progress: Option<Arc<ProgressFunc>>,
+}
+
+type ProgressFunc = dyn Fn(&BulkImportProgress) + Send + Sync;
+
+/// Progress of [`bulk_import`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BulkImportProgress {
+    /// Number of vertexes with resolved parents.
+    pub resolved: u64,
+
+    /// Number of vertexes found, but not yet resolved.
+    pub pending: u64,
+
+    /// Number of vertexes inserted into the graph.
+    pub inserted: u64,
+}
+
+impl BulkImportOptions {
+    /// Default options. Use one thread per CPU to resolve parents.
+    pub fn new() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            threads,
+            queue_size: 1024,
+            batch_size: 100_000,
+            progress: None,
+        }
+    }
+
+    /// Set the number of threads calling the parents function.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Set the maximum number of vertexes waiting for, or being processed
+    /// by the parents function.
+    pub fn queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size.max(1);
+        self
+    }
+
+    /// Set the number of vertexes to insert and write to disk together.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Set the function to call with progress.
+    pub fn progress(
+        mut self,
+        progress: impl Fn(&BulkImportProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    fn report(&self, progress: BulkImportProgress) {
+        if let Some(func) = &self.progress {
+            func(&progress);
+        }
+    }
+}
+
+impl Default for BulkImportOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for BulkImportOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BulkImportOptions")
+            .field("threads", &self.threads)
+            .field("queue_size", &self.queue_size)
+            .field("batch_size", &self.batch_size)
+            .finish()
+    }
+}
+
+/// Add `heads` and their ancestors to `dag`, and write to disk.
+///
+/// This is similar to [`DagPersistent::add_heads_and_flush`], but the
+/// `parents` function is called by multiple threads. Vertexes whose
+/// parents are resolved are queued to be resolved by the next available
+/// thread. Ancestors already in `dag` are not resolved.
+///
+/// Once all ancestors of a vertex are resolved, it is queued for insertion.
+/// Queued vertexes are inserted and written to disk every `batch_size`
+/// vertexes. Parents of other resolved vertexes are kept in memory. For
+/// example, in a linear graph, nothing can be inserted until the root is
+/// resolved.
+///
+/// Heads are imported group by group, starting from the `MASTER` group.
+/// Heads in the `VIRTUAL` group are rejected since they cannot be written
+/// to disk.
+pub async fn bulk_import<D, F>(
+    dag: &mut D,
+    parents: F,
+    heads: &VertexListWithOptions,
+    options: &BulkImportOptions,
+) -> Result<()>
+where
+    D: DagPersistent + IdConvert + ?Sized,
+    F: Fn(VertexName) -> Result<Vec<VertexName>> + Send + Sync + 'static,
+{
+    let virtual_heads = heads.vertexes_by_group(Group::VIRTUAL);
+    if !virtual_heads.is_empty() {
+        return programming(format!(
+            "bulk_import called with virtual heads ({:?})",
+            virtual_heads
+        ));
+    }
+    let mut workers = Workers::spawn(Arc::new(parents), options)?;
+    let mut progress = BulkImportProgress::default();
+    for group in Group::PERSIST {
+        let group_heads: Vec<(VertexName, VertexOptions)> = heads
+            .vertex_options()
+            .into_iter()
+            .filter(|(_, opts)| opts.highest_group == group)
+            .collect();
+        if group_heads.is_empty() {
+            continue;
+        }
+        let mut import = GroupImport {
+            dag: &mut *dag,
+            options,
+            progress: &mut progress,
+            group,
+            heads: group_heads,
+            seen: Default::default(),
+            waiting: Default::default(),
+            children: Default::default(),
+            batch: Default::default(),
+        };
+        import.run(&mut workers).await?;
+    }
+    Ok(())
+}
+
+type ParentsFunc = dyn Fn(VertexName) -> Result<Vec<VertexName>> + Send + Sync;
+
+/// Threads calling the parents function.
+struct Workers {
+    work_tx: mpsc::SyncSender<VertexName>,
+    result_rx: UnboundedReceiver<(VertexName, Result<Vec<VertexName>>)>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Workers {
+    fn spawn(parents: Arc<ParentsFunc>, options: &BulkImportOptions) -> Result<Self> {
+        let (work_tx, work_rx) = mpsc::sync_channel::<VertexName>(options.queue_size);
+        let work_rx = Arc::new(Mutex::new(work_rx));
+        let (result_tx, result_rx) = unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        for i in 0..options.threads {
+            let parents = parents.clone();
+            let work_rx = work_rx.clone();
+            let result_tx = result_tx.clone();
+            let stop = stop.clone();
+            // Workers spawned so far exit after `work_tx` is dropped on error.
+            thread::Builder::new()
+                .name(format!("dag-bulk-import-{}", i))
+                .spawn(move || loop {
+                    let vertex = match work_rx.lock().unwrap().recv() {
+                        Ok(vertex) => vertex,
+                        Err(_) => break,
+                    };
+                    if stop.load(Ordering::Acquire) {
+                        continue;
+                    }
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| parents(vertex.clone())))
+                        .unwrap_or_else(|_| {
+                            programming(format!("parents({:?}) panicked", &vertex))
+                        });
+                    if result_tx.unbounded_send((vertex, result)).is_err() {
+                        break;
+                    }
+                })?;
+        }
+
+        Ok(Self {
+            work_tx,
+            result_rx,
+            stop,
+        })
+    }
+
+    fn send(&self, vertex: VertexName) -> Result<()> {
+        match self.work_tx.send(vertex) {
+            Ok(()) => Ok(()),
+            Err(_) => bug("bulk import workers exited unexpectedly"),
+        }
+    }
+
+    async fn recv(&mut self) -> Result<(VertexName, Result<Vec<VertexName>>)> {
+        match self.result_rx.next().await {
+            Some(result) => Ok(result),
+            None => bug("bulk import workers exited unexpectedly"),
+        }
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        // Workers skip vertexes still in the queue, and exit after
+        // `work_tx` is dropped. They are not joined, since that might
+        // block the async executor.
+        self.stop.store(true, Ordering::Release);
+    }
+}
+
+/// State of importing heads in one group.
+struct GroupImport<'a, D: ?Sized> {
+    dag: &'a mut D,
+    options: &'a BulkImportOptions,
+    progress: &'a mut BulkImportProgress,
+    group: Group,
+    heads: Vec<(VertexName, VertexOptions)>,
+
+    /// Vertexes found, but not yet inserted.
+    seen: HashSet<VertexName>,
+
+    /// Resolved vertexes with ancestors not in `batch`, and their parents,
+    /// and the number of their parents not in `batch`.
+    waiting: HashMap<VertexName, (Vec<VertexName>, usize)>,
+
+    /// Vertexes in `waiting` that are children of a vertex not in `batch`.
+    children: HashMap<VertexName, Vec<VertexName>>,
+
+    /// Vertexes with all ancestors in `batch` or `dag`.
+    batch: Batch,
+}
+
+/// Vertexes to insert together.
+#[derive(Default)]
+struct Batch {
+    parents: HashMap<VertexName, Vec<VertexName>>,
+
+    /// Keys of `parents`, parents first.
+    vertexes: Vec<VertexName>,
+}
+
+impl<'a, D: DagPersistent + IdConvert + ?Sized> GroupImport<'a, D> {
+    // `is_multiple_of` requires Rust 1.87.
+    #[allow(clippy::manual_is_multiple_of)]
+    async fn run(&mut self, workers: &mut Workers) -> Result<()> {
+        let mut to_resolve: VecDeque<VertexName> = VecDeque::new();
+        for (head, _) in &self.heads {
+            if !self.seen.contains(head) && !self.dag.contains_vertex_name(head).await? {
+                self.seen.insert(head.clone());
+                to_resolve.push_back(head.clone());
+            }
+        }
+
+        // Number of vertexes sent to workers, but not received back.
+        // This is bounded by `queue_size` so sending to workers does not
+        // block.
+        let mut in_flight = 0;
+        loop {
+            while in_flight < self.options.queue_size {
+                match to_resolve.pop_front() {
+                    Some(vertex) => {
+                        workers.send(vertex)?;
+                        in_flight += 1;
+                    }
+                    None => break,
+                }
+            }
+            if in_flight == 0 {
+                break;
+            }
+
+            let (vertex, vertex_parents) = workers.recv().await?;
+            in_flight -= 1;
+            let vertex_parents = vertex_parents?;
+            let mut missing = 0;
+            for parent in &vertex_parents {
+                if self.seen.contains(parent) {
+                    if self.batch.parents.contains_key(parent) {
+                        continue;
+                    }
+                } else if self.dag.contains_vertex_name(parent).await? {
+                    continue;
+                } else {
+                    self.seen.insert(parent.clone());
+                    to_resolve.push_back(parent.clone());
+                }
+                let children = self.children.entry(parent.clone()).or_default();
+                children.push(vertex.clone());
+                missing += 1;
+            }
+            if missing > 0 {
+                self.waiting.insert(vertex, (vertex_parents, missing));
+            } else {
+                self.add_to_batch(vertex, vertex_parents);
+            }
+
+            self.progress.resolved += 1;
+            self.progress.pending = (to_resolve.len() + in_flight) as u64;
+            if self.progress.resolved % PROGRESS_INTERVAL == 0 {
+                self.options.report(*self.progress);
+            }
+            if self.batch.vertexes.len() >= self.options.batch_size {
+                self.insert_batch().await?;
+            }
+        }
+
+        if let Some(vertex) = self.waiting.keys().next() {
+            return programming(format!("parents of {:?} form a cycle", vertex));
+        }
+        self.insert_batch().await
+    }
+
+    /// Add `vertex` to `batch`, and children waiting only for it.
+    fn add_to_batch(&mut self, vertex: VertexName, parents: Vec<VertexName>) {
+        let mut ready = vec![(vertex, parents)];
+        while let Some((vertex, parents)) = ready.pop() {
+            for child in self.children.remove(&vertex).unwrap_or_default() {
+                if let Entry::Occupied(mut entry) = self.waiting.entry(child) {
+                    entry.get_mut().1 -= 1;
+                    if entry.get().1 == 0 {
+                        let (child, (child_parents, _)) = entry.remove_entry();
+                        ready.push((child, child_parents));
+                    }
+                }
+            }
+            self.batch.parents.insert(vertex.clone(), parents);
+            self.batch.vertexes.push(vertex);
+        }
+    }
+
+    /// Insert and write vertexes in `batch`.
+    async fn insert_batch(&mut self) -> Result<()> {
+        if self.batch.vertexes.is_empty() {
+            return Ok(());
+        }
+
+        // Use heads passed to bulk_import first, then other vertexes that
+        // are not parents of vertexes in the batch.
+        let batch = std::mem::take(&mut self.batch);
+        let mut heads: Vec<(VertexName, VertexOptions)> = self
+            .heads
+            .iter()
+            .filter(|(v, _)| batch.parents.contains_key(v))
+            .cloned()
+            .collect();
+        let non_heads: HashSet<&VertexName> = batch
+            .parents
+            .values()
+            .flatten()
+            .chain(heads.iter().map(|(v, _)| v))
+            .collect();
+        let opts = VertexOptions {
+            highest_group: self.group,
+            ..Default::default()
+        };
+        let other_heads: Vec<(VertexName, VertexOptions)> = batch
+            .vertexes
+            .iter()
+            .rev()
+            .filter(|v| !non_heads.contains(v))
+            .map(|v| (v.clone(), opts.clone()))
+            .collect();
+        heads.extend(other_heads);
+        let heads = VertexListWithOptions::from(heads);
+        self.dag.add_heads_and_flush(&batch.parents, &heads).await?;
+
+        for vertex in &batch.vertexes {
+            self.seen.remove(vertex);
+        }
+        self.progress.inserted += batch.vertexes.len() as u64;
+        self.options.report(*self.progress);
+        Ok(())
+    }
+}
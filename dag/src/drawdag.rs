@@ -5,15 +5,47 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+//! # drawdag
+//!
+//! Declare graphs visually in tests and docs. See [`DrawDag`].
+//!
+//! Available with the `drawdag` feature.
+
 use std::collections::HashMap;
 use std::collections::HashSet;
 
+use nonblocking::non_blocking_result;
+
 use crate::namedag::MemNameDag;
+use crate::ops::DagAddHeads;
 use crate::ops::Parents;
 use crate::Result;
 use crate::Vertex;
 
 /// Represents a graph from ASCII parsed by `drawdag`.
+///
+/// Vertexes are alphanumeric names. Edges are drawn using `-`, `|`, `/`, `\`
+/// and point from parents (left or bottom) to children (right or top).
+/// Implements [`Parents`] so it can be used as the parent function of
+/// `add_heads`.
+///
+/// ```
+/// use dag::drawdag::DrawDag;
+/// use dag::ops::DagAlgorithm;
+/// # use nonblocking::non_blocking_result as r;
+///
+/// let draw = DrawDag::from(
+///     r#"
+///     A--B--D
+///         \
+///          C"#,
+/// );
+/// assert_eq!(format!("{:?}", draw.heads()), "[C, D]");
+///
+/// let dag = draw.to_mem_dag().unwrap();
+/// let parents = r(dag.parent_names("C".into())).unwrap();
+/// assert_eq!(format!("{:?}", parents), "[B]");
+/// ```
 pub struct DrawDag {
     parents: HashMap<Vertex, Vec<Vertex>>,
 }
@@ -43,6 +75,18 @@ impl DrawDag {
         heads.sort();
         heads
     }
+
+    /// Parents of vertexes in the graph.
+    pub fn parents(&self) -> &HashMap<Vertex, Vec<Vertex>> {
+        &self.parents
+    }
+
+    /// Insert the graph into a new [`MemNameDag`], heads in sorted order.
+    pub fn to_mem_dag(&self) -> Result<MemNameDag> {
+        let mut dag = MemNameDag::new();
+        non_blocking_result(dag.add_heads(self, &self.heads().into()))?;
+        Ok(dag)
+    }
 }
 
 #[async_trait::async_trait]
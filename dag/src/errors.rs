@@ -6,6 +6,7 @@
  */
 
 use std::io;
+use std::path::Path;
 
 use thiserror::Error;
 
@@ -46,6 +47,56 @@ pub enum DagError {
     IdOverflow(Group),
 }
 
+/// Classification of a [`DagError`]. See [`DagError::kind`].
+///
+/// Errors from the indexedlog backend keep their classification. See
+/// `indexedlog::Error::kind`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Data corruption. Repairing the backend might help.
+    Corruption,
+
+    /// A vertex, an Id, or a file cannot be found.
+    NotFound,
+
+    /// A lock is held by others. The operation might succeed if retried
+    /// later.
+    Busy,
+
+    /// The data or the operation is not supported. For example, data written
+    /// by a newer version.
+    Unsupported,
+
+    /// API misuse, or a bug in this crate.
+    Programming,
+
+    /// Other errors. For example, IO errors like "disk is full".
+    Other,
+}
+
+impl DagError {
+    /// Classify the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DagError::VertexNotFound(_) | DagError::IdNotFound(_) => ErrorKind::NotFound,
+            DagError::NeedSlowPath(_) => ErrorKind::Unsupported,
+            DagError::Programming(_) | DagError::Bug(_) => ErrorKind::Programming,
+            DagError::Backend(err) => err.kind(),
+            DagError::IdOverflow(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Return the path of the file or directory related to the error, if
+    /// known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            DagError::Backend(err) => err.path(),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum BackendError {
     #[error("{0}")]
@@ -64,6 +115,74 @@ pub enum BackendError {
     Other(#[from] anyhow::Error),
 }
 
+impl BackendError {
+    /// Classify the error. See [`DagError::kind`].
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            BackendError::Generic(_) => ErrorKind::Other,
+            BackendError::Io(err) => io_error_kind(err),
+            #[cfg(any(test, feature = "indexedlog-backend"))]
+            BackendError::IndexedLog(err) => indexedlog_error_kind(err),
+            BackendError::Other(err) => {
+                if let Some(err) = err.downcast_ref::<DagError>() {
+                    return err.kind();
+                }
+                #[cfg(any(test, feature = "indexedlog-backend"))]
+                if let Some(err) = err.downcast_ref::<indexedlog::Error>() {
+                    return indexedlog_error_kind(err);
+                }
+                match err.downcast_ref::<io::Error>() {
+                    Some(err) => io_error_kind(err),
+                    None => ErrorKind::Other,
+                }
+            }
+        }
+    }
+
+    /// Return the path of the file or directory related to the error, if
+    /// known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            #[cfg(any(test, feature = "indexedlog-backend"))]
+            BackendError::IndexedLog(err) => err.path(),
+            BackendError::Other(err) => {
+                if let Some(err) = err.downcast_ref::<DagError>() {
+                    return err.path();
+                }
+                #[cfg(any(test, feature = "indexedlog-backend"))]
+                if let Some(err) = err.downcast_ref::<indexedlog::Error>() {
+                    return err.path();
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+fn io_error_kind(err: &io::Error) -> ErrorKind {
+    match err.kind() {
+        io::ErrorKind::NotFound => ErrorKind::NotFound,
+        io::ErrorKind::WouldBlock => ErrorKind::Busy,
+        io::ErrorKind::Unsupported => ErrorKind::Unsupported,
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => ErrorKind::Corruption,
+        _ => ErrorKind::Other,
+    }
+}
+
+#[cfg(any(test, feature = "indexedlog-backend"))]
+fn indexedlog_error_kind(err: &indexedlog::Error) -> ErrorKind {
+    use indexedlog::ErrorKind as K;
+    match err.kind() {
+        K::Corruption => ErrorKind::Corruption,
+        K::Busy => ErrorKind::Busy,
+        K::Unsupported => ErrorKind::Unsupported,
+        K::NotFound => ErrorKind::NotFound,
+        K::Programming => ErrorKind::Programming,
+        _ => ErrorKind::Other,
+    }
+}
+
 impl From<BackendError> for DagError {
     fn from(err: BackendError) -> DagError {
         DagError::Backend(Box::new(err))
@@ -114,3 +233,44 @@ impl NotFoundError for VertexName {
         DagError::VertexNotFound(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        let err = Id(1).not_found_error();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(err.path(), None);
+        assert_eq!(
+            programming::<()>("x").unwrap_err().kind(),
+            ErrorKind::Programming
+        );
+
+        let io_err = |kind| DagError::from(io::Error::new(kind, "x"));
+        assert_eq!(io_err(io::ErrorKind::WouldBlock).kind(), ErrorKind::Busy);
+        assert_eq!(
+            io_err(io::ErrorKind::InvalidData).kind(),
+            ErrorKind::Corruption
+        );
+        assert_eq!(io_err(io::ErrorKind::Other).kind(), ErrorKind::Other);
+
+        // Errors from indexedlog keep their kind and path.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index");
+        std::fs::write(&path, b"corrupted").unwrap();
+        let index_err = indexedlog::index::OpenOptions::new()
+            .open(&path)
+            .unwrap_err();
+        assert_eq!(index_err.kind(), indexedlog::ErrorKind::Corruption);
+        let err = DagError::from(index_err);
+        assert_eq!(err.kind(), ErrorKind::Corruption);
+        assert_eq!(err.path(), Some(path.as_path()));
+
+        // Also when wrapped by anyhow.
+        let err = DagError::from(BackendError::from(anyhow::Error::new(err)));
+        assert_eq!(err.kind(), ErrorKind::Corruption);
+        assert_eq!(err.path(), Some(path.as_path()));
+    }
+}
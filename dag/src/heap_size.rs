@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! # heap_size
+//!
+//! Estimate heap memory used by in-memory structures. See [`HeapSize`].
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+use indexmap::IndexSet;
+
+use crate::Group;
+use crate::Id;
+use crate::VertexName;
+
+/// Heap memory used by a value, not including `size_of_val(self)`.
+///
+/// The result is an estimate. It is meant for cache layers that evict by
+/// size, not for exact accounting. Buffers shared with other values, like
+/// `Bytes` slices, are counted in full. Data behind `Arc`s that are shared
+/// by design (ex. the IdMap and IdDag referred by sets) are not counted.
+pub trait HeapSize {
+    /// Estimated heap memory in bytes.
+    fn heap_size(&self) -> usize;
+}
+
+impl HeapSize for Id {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for Group {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for VertexName {
+    fn heap_size(&self) -> usize {
+        self.as_ref().len()
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(|v| v.heap_size()).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for VecDeque<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(|v| v.heap_size()).sum::<usize>()
+    }
+}
+
+impl<K: HeapSize, V: HeapSize> HeapSize for BTreeMap<K, V> {
+    fn heap_size(&self) -> usize {
+        // Ignore the overhead of tree nodes.
+        self.len() * (size_of::<K>() + size_of::<V>())
+            + self
+                .iter()
+                .map(|(k, v)| k.heap_size() + v.heap_size())
+                .sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for BTreeSet<T> {
+    fn heap_size(&self) -> usize {
+        self.len() * size_of::<T>() + self.iter().map(|v| v.heap_size()).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize, S> HeapSize for HashSet<T, S> {
+    fn heap_size(&self) -> usize {
+        // Each bucket has a control byte.
+        self.capacity() * (size_of::<T>() + 1) + self.iter().map(|v| v.heap_size()).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize, S> HeapSize for IndexSet<T, S> {
+    fn heap_size(&self) -> usize {
+        // Entries store the hash next to the value. The hash table stores
+        // indexes to entries.
+        self.capacity() * (size_of::<T>() + 2 * size_of::<usize>())
+            + self.iter().map(|v| v.heap_size()).sum::<usize>()
+    }
+}
@@ -26,12 +26,14 @@ use tracing::trace;
 
 use crate::errors::bug;
 use crate::errors::NotFoundError;
+use crate::heap_size::HeapSize;
 use crate::id::Group;
 use crate::id::Id;
 use crate::iddagstore::IdDagStore;
 use crate::iddagstore::InProcessStore;
 #[cfg(any(test, feature = "indexedlog-backend"))]
 use crate::iddagstore::IndexedLogStore;
+use crate::instrument::OpSpan;
 use crate::ops::Persist;
 #[cfg(any(test, feature = "indexedlog-backend"))]
 use crate::ops::TryClone;
@@ -594,10 +596,20 @@ pub trait IdDagAlgorithm: IdDagStore {
     /// ```plain,ignore
     /// union(ancestors(i) for i in set)
     /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "dag::iddag::ancestors",
+            level = "info",
+            skip_all,
+            fields(set_len = set.count(), result_len = tracing::field::Empty, duration_us = tracing::field::Empty)
+        )
+    )]
     fn ancestors(&self, mut set: IdSet) -> Result<IdSet> {
         fn trace(msg: &dyn Fn() -> String) {
             trace!(target: "dag::algo::ancestors", "{}", msg());
         }
+        let op = OpSpan::current();
         debug!(target: "dag::algo::ancestors", "ancestors({:?})", &set);
         if set.count() > 2 {
             // Try to (greatly) reduce the size of the `set` to make calculation cheaper.
@@ -651,6 +663,7 @@ pub trait IdDagAlgorithm: IdDagStore {
         }
 
         trace(&|| format!(" result: {:?}", &result));
+        op.record("result_len", || result.count());
 
         Ok(result)
     }
@@ -1267,15 +1280,37 @@ pub trait IdDagAlgorithm: IdDagStore {
     /// If there are no common ancestors, return None.
     /// If there are multiple greatest common ancestors, pick one arbitrarily.
     /// Use `gca_all` to get all of them.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "dag::iddag::gca_one",
+            level = "info",
+            skip_all,
+            fields(set_len = set.count(), duration_us = tracing::field::Empty)
+        )
+    )]
     fn gca_one(&self, set: IdSet) -> Result<Option<Id>> {
+        let _op = OpSpan::current();
         // The set is sorted in DESC order. Therefore its first item can be used as the result.
         Ok(self.common_ancestors(set)?.max())
     }
 
     /// Calculate all "greatest common ancestor"s of the given set.
     /// `gca_one` is faster if an arbitrary answer is ok.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "dag::iddag::gca_all",
+            level = "info",
+            skip_all,
+            fields(set_len = set.count(), result_len = tracing::field::Empty, duration_us = tracing::field::Empty)
+        )
+    )]
     fn gca_all(&self, set: IdSet) -> Result<IdSet> {
-        self.heads_ancestors(self.common_ancestors(set)?)
+        let op = OpSpan::current();
+        let result = self.heads_ancestors(self.common_ancestors(set)?)?;
+        op.record("result_len", || result.count());
+        Ok(result)
     }
 
     /// Calculate all common ancestors of the given set.
@@ -1341,7 +1376,22 @@ pub trait IdDagAlgorithm: IdDagStore {
     /// ```
     ///
     /// This is O(flat segments), or O(merges).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "dag::iddag::range",
+            level = "info",
+            skip_all,
+            fields(
+                roots_len = roots.count(),
+                heads_len = heads.count(),
+                result_len = tracing::field::Empty,
+                duration_us = tracing::field::Empty
+            )
+        )
+    )]
     fn range(&self, roots: IdSet, mut heads: IdSet) -> Result<IdSet> {
+        let op = OpSpan::current();
         if roots.is_empty() {
             return Ok(IdSet::empty());
         }
@@ -1373,6 +1423,7 @@ pub trait IdDagAlgorithm: IdDagStore {
         }
 
         trace(&|| format!(" result: {:?}", &result));
+        op.record("result_len", || result.count());
         Ok(result)
     }
 
@@ -1457,8 +1508,8 @@ pub trait IdDagAlgorithm: IdDagStore {
             result.push_span_asc(result_span);
         }
 
-        // For the non-master group, only check flat segments covered by
-        // `ancestors`.
+        // For the non-master and virtual groups, only check flat segments
+        // covered by `ancestors`.
         //
         // This is usually more efficient, because the non-master group can
         // have lots of heads (created in the past) that are no longer visible
@@ -1466,7 +1517,7 @@ pub trait IdDagAlgorithm: IdDagStore {
         // a few heads in the non-master group. It's a waste of time to iterate
         // through lots of invisible segments.
         let non_master_spans = ancestors.intersection(
-            &IdSpan::from(Group::NON_MASTER.min_id()..=Group::NON_MASTER.max_id()).into(),
+            &IdSpan::from(Group::NON_MASTER.min_id()..=Group::VIRTUAL.max_id()).into(),
         );
         // Visit in ascending order.
         let mut span_iter = non_master_spans.as_spans().iter().rev().cloned();
@@ -1666,6 +1717,13 @@ pub trait IdDagAlgorithm: IdDagStore {
 
 impl<S: IdDagStore> IdDagAlgorithm for S {}
 
+impl<Store: HeapSize> HeapSize for IdDag<Store> {
+    /// Heap size of the in-memory state of the store.
+    fn heap_size(&self) -> usize {
+        self.store.heap_size()
+    }
+}
+
 impl<Store: IdDagStore> Deref for IdDag<Store> {
     type Target = dyn IdDagAlgorithm;
 
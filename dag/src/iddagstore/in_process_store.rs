@@ -22,6 +22,7 @@ use serde::Serializer;
 
 use super::IdDagStore;
 use crate::errors::bug;
+use crate::heap_size::HeapSize;
 use crate::id::Group;
 use crate::id::Id;
 use crate::ops::Persist;
@@ -214,15 +215,17 @@ impl IdDagStore for InProcessStore {
                 .get_mut(level as usize)
                 .map(|head_index| head_index.remove(&head));
         }
-        let group = Group::NON_MASTER;
-        for (_key, children) in self
-            .parent_index
-            .range_mut((group, group.min_id())..=(group, group.max_id()))
-        {
-            children.clear();
+        // `non_master_segments` also has segments in the virtual group.
+        for group in [Group::NON_MASTER, Group::VIRTUAL] {
+            for (_key, children) in self
+                .parent_index
+                .range_mut((group, group.min_id())..=(group, group.max_id()))
+            {
+                children.clear();
+            }
+            self.id_set_by_group[group.0] = IdSet::empty();
         }
         self.non_master_segments = Vec::new();
-        self.id_set_by_group[Group::NON_MASTER.0] = IdSet::empty();
         Ok(())
     }
 
@@ -327,8 +330,32 @@ impl IdDagStore for InProcessStore {
                 }
             }
         };
-        let iter = get_iter(Group::MASTER)?.chain(get_iter(Group::NON_MASTER)?);
-        Ok(Box::new(iter))
+        let mut iter: Box<dyn Iterator<Item = Result<_>> + 'a> = Box::new(iter::empty());
+        for group in Group::ALL {
+            iter = Box::new(iter.chain(get_iter(group)?));
+        }
+        Ok(iter)
+    }
+}
+
+impl HeapSize for InProcessStore {
+    fn heap_size(&self) -> usize {
+        self.master_segments.heap_size()
+            + self.non_master_segments.heap_size()
+            + self.level_head_index.heap_size()
+            + self.parent_index.heap_size()
+            + self
+                .id_set_by_group
+                .iter()
+                .map(|s| s.heap_size())
+                .sum::<usize>()
+            + self.removed_store_ids.heap_size()
+    }
+}
+
+impl HeapSize for StoreId {
+    fn heap_size(&self) -> usize {
+        0
     }
 }
 
@@ -383,7 +410,7 @@ impl InProcessStore {
             non_master_segments: Vec::new(),
             level_head_index: Vec::new(),
             parent_index: BTreeMap::new(),
-            id_set_by_group: [IdSet::empty(), IdSet::empty()],
+            id_set_by_group: Default::default(),
             removed_store_ids: Default::default(),
         }
     }
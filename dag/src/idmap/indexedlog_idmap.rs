@@ -16,6 +16,7 @@ use byteorder::BigEndian;
 use byteorder::ReadBytesExt;
 use fs2::FileExt;
 use indexedlog::log;
+use vlqencoding::FrameWrite;
 use vlqencoding::VLQDecode;
 use vlqencoding::VLQEncode;
 
@@ -351,8 +352,7 @@ fn encode_deletion_entry(items: &[(Id, &[u8])]) -> Vec<u8> {
     data.write_vlq(items.len()).unwrap();
     for (id, name) in items {
         data.write_vlq(id.0).unwrap();
-        data.write_vlq(name.len()).unwrap();
-        data.extend_from_slice(name);
+        data.write_frame(name).unwrap();
     }
     data
 }
@@ -367,12 +367,11 @@ fn decode_deletion_entry(data: &[u8]) -> Result<Vec<(Id, &[u8])>> {
     for _ in 0..n {
         let id: u64 = data.read_vlq()?;
         let id = Id(id);
-        let name_len: usize = data.read_vlq()?;
-        if name_len > data.len() {
-            return bug("decode_deletion_id_names got incomplete input");
-        }
-        let (name, rest) = data.split_at(name_len);
-        data = rest;
+        let (name, len) = match vlqencoding::read_frame_at(data, 0, data.len()) {
+            Ok(v) => v,
+            Err(_) => return bug("decode_deletion_id_names got incomplete input"),
+        };
+        data = &data[len..];
         items.push((id, name));
     }
     Ok(items)
@@ -11,6 +11,7 @@ use std::sync::atomic::AtomicU64;
 
 use super::IdMapWrite;
 use crate::errors::NotFoundError;
+use crate::heap_size::HeapSize;
 use crate::id::Group;
 use crate::id::Id;
 use crate::id::VertexName;
@@ -113,6 +114,18 @@ impl CoreMemIdMap {
     }
 }
 
+impl HeapSize for MemIdMap {
+    fn heap_size(&self) -> usize {
+        self.core.heap_size() + self.map_id.capacity()
+    }
+}
+
+impl HeapSize for CoreMemIdMap {
+    fn heap_size(&self) -> usize {
+        self.id2name.heap_size() + self.name2id.heap_size()
+    }
+}
+
 #[async_trait::async_trait]
 impl IdConvert for MemIdMap {
     async fn vertex_id(&self, name: VertexName) -> Result<Id> {
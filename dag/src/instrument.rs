@@ -0,0 +1,62 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Spans for the `tracing` feature.
+//!
+//! Entry points are annotated with `tracing::instrument` when the feature is
+//! enabled. They declare `duration_us` and other fields as empty, and fill
+//! them using [`OpSpan`].
+
+use std::time::Instant;
+
+use tracing::field::Value;
+use tracing::Span;
+
+/// The current span of an instrumented entry point. Records how long it was
+/// alive as `duration_us`.
+///
+/// Unlike an entered span, this is `Send` and can be kept across `await`s.
+pub(crate) struct OpSpan {
+    span: Span,
+    start: Option<Instant>,
+}
+
+impl OpSpan {
+    /// Take the current span. Without the `tracing` feature, the span does
+    /// not belong to us and is not used.
+    pub(crate) fn current() -> Self {
+        let span = if cfg!(feature = "tracing") {
+            Span::current()
+        } else {
+            Span::none()
+        };
+        // Avoid reading the clock if nobody is interested.
+        let start = if span.is_disabled() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+        Self { span, start }
+    }
+
+    /// Record a field declared by the entry point, like the result size.
+    /// `value` is only evaluated if the span is enabled.
+    pub(crate) fn record<V: Value>(&self, field: &str, value: impl FnOnce() -> V) {
+        if self.start.is_some() {
+            self.span.record(field, value());
+        }
+    }
+}
+
+impl Drop for OpSpan {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let duration = start.elapsed().as_micros() as u64;
+            self.span.record("duration_us", duration);
+        }
+    }
+}
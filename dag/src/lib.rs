@@ -11,15 +11,27 @@
 //! # dag
 //!
 //! Building blocks for the commit graph used by source control.
+//!
+//! With the `tracing` feature, queries like `ancestors`, `range` and `gca`
+//! on `NameDag` and [`IdDag`], and `NameDag` flushes emit `info` spans
+//! with set sizes and `duration_us`.
+//!
+//! With the `drawdag` feature, `drawdag::DrawDag` parses graphs drawn in
+//! ASCII, for declaring test graphs visually.
 
 mod bsearch;
+mod bulk_import;
 mod default_impl;
 mod delegate;
+#[cfg(any(test, feature = "drawdag", feature = "indexedlog-backend"))]
+pub mod drawdag;
 pub mod errors;
 mod fmt;
+mod heap_size;
 pub mod iddag;
 pub mod iddagstore;
 pub mod idmap;
+mod instrument;
 mod integrity;
 pub mod namedag;
 pub mod nameset;
@@ -29,11 +41,15 @@ pub mod protocol;
 pub mod render;
 pub mod segment;
 mod spanset;
+mod stats;
 pub(crate) mod types_ext;
 pub mod utils;
 mod verlink;
 mod vertex_options;
 
+pub use bulk_import::bulk_import;
+pub use bulk_import::BulkImportOptions;
+pub use bulk_import::BulkImportProgress;
 pub use dag_types::clone;
 pub use dag_types::id;
 pub use dag_types::CloneData;
@@ -41,6 +57,7 @@ pub use dag_types::Group;
 pub use dag_types::Id;
 pub use dag_types::Location;
 pub use dag_types::VertexName;
+pub use heap_size::HeapSize;
 pub use iddag::FirstAncestorConstraint;
 pub use iddag::IdDag;
 pub use iddag::IdDagAlgorithm;
@@ -55,6 +72,7 @@ pub use ops::DagAlgorithm;
 pub use segment::FlatSegment;
 pub use segment::IdSegment;
 pub use segment::PreparedFlatSegments;
+pub use stats::GraphStats;
 pub use verlink::VerLink;
 pub use vertex_options::VertexListWithOptions;
 pub use vertex_options::VertexOptions;
@@ -82,6 +100,7 @@ pub use iddagstore::indexedlog_store::describe_indexedlog_entry;
 pub mod tests;
 
 pub use errors::DagError as Error;
+pub use errors::ErrorKind;
 pub type Result<T> = std::result::Result<T, Error>;
 
 // Re-export
@@ -40,6 +40,7 @@ use crate::iddagstore::IdDagStore;
 use crate::idmap::CoreMemIdMap;
 use crate::idmap::IdMapAssignHead;
 use crate::idmap::IdMapWrite;
+use crate::instrument::OpSpan;
 use crate::nameset::hints::Flags;
 use crate::nameset::hints::Hints;
 use crate::nameset::NameSet;
@@ -82,6 +83,7 @@ mod builder;
 #[cfg(any(test, feature = "indexedlog-backend"))]
 mod indexedlog_namedag;
 mod mem_namedag;
+mod same_graph;
 
 pub use builder::NameDagBuilder;
 #[cfg(any(test, feature = "indexedlog-backend"))]
@@ -90,6 +92,7 @@ pub use indexedlog_namedag::IndexedLogNameDagPath;
 pub use indexedlog_namedag::NameDag;
 pub use mem_namedag::MemNameDag;
 pub use mem_namedag::MemNameDagPath;
+pub use same_graph::same_graph;
 
 pub struct AbstractNameDag<I, M, P, S>
 where
@@ -187,6 +190,13 @@ where
                 &self.pending_heads.vertexes(),
             ));
         }
+        let virtual_heads = heads.vertexes_by_group(Group::VIRTUAL);
+        if !virtual_heads.is_empty() {
+            return programming(format!(
+                "add_heads_and_flush called with virtual heads ({:?}). Use add_heads instead.",
+                virtual_heads
+            ));
+        }
 
         // Take lock.
         //
@@ -238,14 +248,24 @@ where
     /// overrides the `VertexOptions` provided to `add_head`. If `heads`
     /// is empty, then `VertexOptions` provided to `add_head` will be
     /// used.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "dag::namedag::flush",
+            level = "info",
+            skip_all,
+            fields(heads_len = heads.len(), duration_us = tracing::field::Empty)
+        )
+    )]
     async fn flush(&mut self, heads: &VertexListWithOptions) -> Result<()> {
+        let _op = OpSpan::current();
         // Sanity check.
         for result in self.vertex_id_batch(&heads.vertexes()).await? {
             result?;
         }
         // Previous version of the API requires `master_heads: &[Vertex]`.
         // Warn about possible misuses.
-        if !heads.vertexes_by_group(Group::NON_MASTER).is_empty() {
+        if heads.vertexes_by_group(Group::MASTER).len() != heads.len() {
             return programming(format!(
                 "NameDag::flush({:?}) is probably misused (group is not master)",
                 heads
@@ -259,13 +279,23 @@ where
         let mut new_name_dag: Self = self.path.open()?;
 
         let parents: &(dyn DagAlgorithm + Send + Sync) = self;
-        let non_master_heads: VertexListWithOptions = self.pending_heads.clone();
+        // The virtual group is not written to disk. Re-insert it in memory.
+        let (virtual_heads, non_master_heads): (Vec<_>, Vec<_>) = self
+            .pending_heads
+            .vertex_options()
+            .into_iter()
+            .partition(|(_, opts)| opts.highest_group == Group::VIRTUAL);
         let seg_size = self.dag.get_new_segment_size();
         new_name_dag.dag.set_new_segment_size(seg_size);
         new_name_dag.set_remote_protocol(self.remote_protocol.clone());
         new_name_dag.maybe_reuse_caches_from(self);
         let heads = heads.clone().chain(non_master_heads);
         new_name_dag.add_heads_and_flush(&parents, &heads).await?;
+        if !virtual_heads.is_empty() {
+            new_name_dag
+                .add_heads(&parents, &virtual_heads.into())
+                .await?;
+        }
         *self = new_name_dag;
         Ok(())
     }
@@ -406,6 +436,10 @@ where
     /// Note: heads with `reserve_size > 0` must be passed in even if they
     /// already exist and are not being added to the graph for the id
     /// reservation to work correctly.
+    ///
+    /// Heads with `highest_group = VIRTUAL` are never written to disk.
+    /// Other heads cannot be added if the VIRTUAL group is not empty. Use
+    /// `remove_virtual_group` to remove the VIRTUAL group first.
     async fn add_heads(
         &mut self,
         parents: &dyn Parents,
@@ -446,6 +480,17 @@ where
             }
         }
 
+        // Similarly, vertexes in the VIRTUAL group cannot be parents of other
+        // groups without id reassignment.
+        if heads.vertexes_by_group(Group::VIRTUAL).len() != heads.len()
+            && !self.dag.all_ids_in_groups(&[Group::VIRTUAL])?.is_empty()
+        {
+            return programming(concat!(
+                "add_heads() called with highest_group != VIRTUAL but VIRTUAL group is not empty. ",
+                "Call remove_virtual_group() first, or add virtual heads last.",
+            ));
+        }
+
         // Performance-wise, add_heads + flush is slower than
         // add_heads_and_flush.
         //
@@ -582,6 +627,28 @@ where
 
         Ok(())
     }
+
+    /// Remove all vertexes in the VIRTUAL group from the in-memory graph.
+    ///
+    /// Vertexes in other groups are not affected, since they cannot have
+    /// parents in the VIRTUAL group. The VIRTUAL group is never written to
+    /// disk. So this does not change the disk.
+    pub async fn remove_virtual_group(&mut self) -> Result<()> {
+        let id_set = self.dag.all_ids_in_groups(&[Group::VIRTUAL])?;
+        let removed_id_set = self.dag.strip(id_set)?;
+        for span in removed_id_set.iter_span_desc() {
+            self.map.remove_range(span.low, span.high).await?;
+        }
+        let heads: Vec<_> = self
+            .pending_heads
+            .vertex_options()
+            .into_iter()
+            .filter(|(_, opts)| opts.highest_group != Group::VIRTUAL)
+            .collect();
+        self.pending_heads = heads.into();
+        self.invalidate_snapshot();
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
@@ -700,7 +767,12 @@ where
                 &self.pending_heads.vertexes(),
             ));
         }
-        let non_master_heads = heads.vertexes_by_group(Group::NON_MASTER);
+        let non_master_heads: Vec<VertexName> = heads
+            .vertex_options()
+            .into_iter()
+            .filter(|(_, opts)| opts.highest_group != Group::MASTER)
+            .map(|(v, _)| v)
+            .collect();
         if !non_master_heads.is_empty() {
             return programming(format!(
                 concat!(
@@ -1286,7 +1358,7 @@ where
             // as a remote "contains" check.
             if root_parents_id_set
                 .iter_desc()
-                .all(|i| i.group() == Group::NON_MASTER)
+                .all(|i| i.group() != Group::MASTER)
             {
                 tracing::debug!(target: "dag::definitelymissing", "root {:?} is not assigned (non-lazy parent)", &root);
                 unassigned_roots.push(root);
@@ -1693,14 +1765,26 @@ where
     }
 
     /// Calculates all ancestors reachable from any name from the given set.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "dag::namedag::ancestors",
+            level = "info",
+            skip_all,
+            fields(set_len = tracing::field::Empty, result_len = tracing::field::Empty, duration_us = tracing::field::Empty)
+        )
+    )]
     async fn ancestors(&self, set: NameSet) -> Result<NameSet> {
         if set.hints().contains(Flags::ANCESTORS)
             && set.hints().dag_version() <= Some(self.dag_version())
         {
             return Ok(set);
         }
+        let op = OpSpan::current();
         let spans = self.to_id_set(&set).await?;
+        op.record("set_len", || spans.count());
         let spans = self.dag().ancestors(spans)?;
+        op.record("result_len", || spans.count());
         let result = NameSet::from_spans_dag(spans, self)?;
         result.hints().add_flags(Flags::ANCESTORS);
         Ok(result)
@@ -1813,8 +1897,20 @@ where
     /// If there are no common ancestors, return None.
     /// If there are multiple greatest common ancestors, pick one arbitrarily.
     /// Use `gca_all` to get all of them.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "dag::namedag::gca_one",
+            level = "info",
+            skip_all,
+            fields(set_len = tracing::field::Empty, duration_us = tracing::field::Empty)
+        )
+    )]
     async fn gca_one(&self, set: NameSet) -> Result<Option<VertexName>> {
-        let result: Option<VertexName> = match self.dag().gca_one(self.to_id_set(&set).await?)? {
+        let op = OpSpan::current();
+        let spans = self.to_id_set(&set).await?;
+        op.record("set_len", || spans.count());
+        let result: Option<VertexName> = match self.dag().gca_one(spans)? {
             None => None,
             Some(id) => Some(self.vertex_name(id).await?),
         };
@@ -1827,8 +1923,21 @@ where
 
     /// Calculates all "greatest common ancestor"s of the given set.
     /// `gca_one` is faster if an arbitrary answer is ok.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "dag::namedag::gca_all",
+            level = "info",
+            skip_all,
+            fields(set_len = tracing::field::Empty, result_len = tracing::field::Empty, duration_us = tracing::field::Empty)
+        )
+    )]
     async fn gca_all(&self, set: NameSet) -> Result<NameSet> {
-        let spans = self.dag().gca_all(self.to_id_set(&set).await?)?;
+        let op = OpSpan::current();
+        let spans = self.to_id_set(&set).await?;
+        op.record("set_len", || spans.count());
+        let spans = self.dag().gca_all(spans)?;
+        op.record("result_len", || spans.count());
         let result = NameSet::from_spans_dag(spans, self)?;
         #[cfg(test)]
         {
@@ -1888,10 +1997,23 @@ where
     }
 
     /// Calculates the "dag range" - vertexes reachable from both sides.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "dag::namedag::range",
+            level = "info",
+            skip_all,
+            fields(roots_len = tracing::field::Empty, heads_len = tracing::field::Empty, result_len = tracing::field::Empty, duration_us = tracing::field::Empty)
+        )
+    )]
     async fn range(&self, roots: NameSet, heads: NameSet) -> Result<NameSet> {
+        let op = OpSpan::current();
         let roots = self.to_id_set(&roots).await?;
         let heads = self.to_id_set(&heads).await?;
+        op.record("roots_len", || roots.count());
+        op.record("heads_len", || heads.count());
         let spans = self.dag().range(roots, heads)?;
+        op.record("result_len", || spans.count());
         let result = NameSet::from_spans_dag(spans, self)?;
         Ok(result)
     }
@@ -2030,12 +2152,12 @@ where
                 if max_group == Group::MASTER
                     && self
                         .map
-                        .vertex_id_with_max_group(name, Group::NON_MASTER)
+                        .vertex_id_with_max_group(name, Group::VIRTUAL)
                         .await?
                         .is_some()
                 {
-                    // If the vertex exists in the non-master group. Then it must be missing in the
-                    // master group.
+                    // If the vertex exists in the non-master or virtual group. Then it must be
+                    // missing in the master group.
                     return Ok(None);
                 }
                 match self.resolve_vertexes_remotely(&[name.clone()]).await {
@@ -2308,7 +2430,7 @@ where
             let mut outcome = PreparedFlatSegments::default();
             let mut covered = self.dag().all_ids_in_groups(&Group::ALL)?;
             let mut reserved = calculate_initial_reserved(self, &covered, heads).await?;
-            for group in [Group::MASTER, Group::NON_MASTER] {
+            for group in Group::PERSIST {
                 for (vertex, opts) in heads.vertex_options() {
                     if opts.highest_group != group {
                         continue;
@@ -5,9 +5,11 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
 
+use futures::TryStreamExt;
 use indexedlog::multi;
 use indexedlog::DefaultOpenOptions;
 use indexedlog::OpenWithRepair;
@@ -15,19 +17,32 @@ use indexedlog::OpenWithRepair;
 use super::AbstractNameDag;
 use super::NameDagBuilder;
 use crate::errors::bug;
+use crate::errors::programming;
+use crate::id::Group;
+use crate::id::VertexName;
 use crate::iddag::IdDag;
 use crate::iddagstore::IndexedLogStore;
 use crate::idmap::IdMap;
+use crate::nameset::NameSet;
+use crate::ops::DagAlgorithm;
+use crate::ops::DagPersistent;
 use crate::ops::IntVersion;
 use crate::ops::Open;
 use crate::ops::Persist;
 use crate::ops::TryClone;
 use crate::Result;
+use crate::VertexListWithOptions;
 
 /// A DAG that uses VertexName instead of ids as vertexes.
 ///
 /// A high-level wrapper structure. Combination of [`IdMap`] and [`Dag`].
 /// Maintains consistency of dag and map internally.
+///
+/// The on-disk logs keep the full history. Dropping old entries, like what
+/// `RotateLog` does, is not supported, since segments and ids refer to
+/// their ancestors. For short-lived graphs, like previews, use
+/// [`MemNameDag`](super::MemNameDag), or a [`NameDag`] in a temporary
+/// directory.
 pub type NameDag =
     AbstractNameDag<IdDag<IndexedLogStore>, IdMap, IndexedLogNameDagPath, NameDagState>;
 
@@ -81,6 +96,50 @@ impl NameDag {
         let path = IndexedLogNameDagPath(path);
         path.open()
     }
+
+    /// Write vertexes in `set` to a new [`NameDag`] at `path`.
+    ///
+    /// The new graph only contains vertexes in `set`, with new ids and
+    /// segments. Only edges between vertexes in `set` are kept. Parents
+    /// outside `set` are dropped, so a vertex without parents in `set`
+    /// becomes a root. Vertexes in the master group stay in the master group.
+    ///
+    /// `path` should not contain a non-empty graph.
+    pub async fn export_subset(&self, path: impl AsRef<Path>, set: NameSet) -> Result<NameDag> {
+        let mut dag = NameDag::open(path)?;
+        if !dag.all().await?.is_empty().await? {
+            return programming("export_subset requires an empty destination graph");
+        }
+
+        let set = self.sort(&set).await?;
+        let mut parents: HashMap<VertexName, Vec<VertexName>> = HashMap::new();
+        let mut iter = set.iter().await?;
+        while let Some(vertex) = iter.try_next().await? {
+            let mut vertex_parents = Vec::new();
+            for parent in self.parent_names(vertex.clone()).await? {
+                if set.contains(&parent).await? {
+                    vertex_parents.push(parent);
+                }
+            }
+            parents.insert(vertex, vertex_parents);
+        }
+
+        // `heads(set)` are heads of the subgraph since parents outside `set`
+        // are dropped.
+        let master_group = self.master_group().await?;
+        let master_heads = self.heads(set.intersection(&master_group)).await?;
+        let non_master_heads = self.heads(set).await?.difference(&master_group);
+        // Insert in ASC order so the new graph might preserve the order of
+        // this graph.
+        let master_heads: Vec<VertexName> = master_heads.iter_rev().await?.try_collect().await?;
+        let non_master_heads: Vec<VertexName> =
+            non_master_heads.iter_rev().await?.try_collect().await?;
+        let heads = VertexListWithOptions::from(master_heads)
+            .with_highest_group(Group::MASTER)
+            .chain(non_master_heads);
+        dag.add_heads_and_flush(&parents, &heads).await?;
+        Ok(dag)
+    }
 }
 
 impl Persist for NameDagState {
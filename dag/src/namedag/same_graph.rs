@@ -0,0 +1,131 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+
+use crate::iddag::IdDagAlgorithm;
+use crate::nameset::NameSet;
+use crate::ops::DagAlgorithm;
+use crate::ops::IdConvert;
+use crate::Error::VertexNotFound;
+use crate::Id;
+use crate::IdSet;
+use crate::Result;
+
+/// Number of vertexes to check per batch of vertex lookups.
+const BATCH_SIZE: usize = 10000;
+
+/// Test if ancestors of `heads` are the same vertexes in both graphs, and
+/// each of them has the same parents, in the same order.
+///
+/// Ids are not compared. For example, a graph imported from clone data is
+/// the same as its source graph, even if ids are assigned differently.
+/// Return `false` if some of `heads` are missing in either graph.
+///
+/// If both graphs use ids, parents are read from the [`IdDagAlgorithm`]s,
+/// and vertexes are resolved in batches. This avoids a round-trip per
+/// vertex for graphs with lazy vertexes. Otherwise, `parent_names` is
+/// called for each vertex.
+///
+/// Unlike [`CheckIntegrity::check_isomorphic_graph`](crate::ops::CheckIntegrity::check_isomorphic_graph),
+/// vertex names are compared, and no details about differences are
+/// reported.
+pub async fn same_graph(
+    a: &dyn DagAlgorithm,
+    b: &dyn DagAlgorithm,
+    heads: NameSet,
+) -> Result<bool> {
+    let (a_set, b_set) = match (a.ancestors(heads.clone()).await, b.ancestors(heads).await) {
+        (Ok(a_set), Ok(b_set)) => (a_set, b_set),
+        (Err(VertexNotFound(_)), _) | (_, Err(VertexNotFound(_))) => return Ok(false),
+        (Err(e), _) | (_, Err(e)) => return Err(e),
+    };
+    if a_set.count().await? != b_set.count().await? {
+        return Ok(false);
+    }
+    match (IdGraph::new(a, &a_set), IdGraph::new(b, &b_set)) {
+        (Some(a), Some(b)) => same_id_graph(&a, &b).await,
+        _ => same_graph_by_parent_names(a, &a_set, b, &b_set).await,
+    }
+}
+
+/// Ancestors of heads in a graph using ids.
+struct IdGraph {
+    id_set: IdSet,
+    id_map: Arc<dyn IdConvert + Send + Sync>,
+    id_dag: Arc<dyn IdDagAlgorithm + Send + Sync>,
+}
+
+impl IdGraph {
+    fn new(dag: &dyn DagAlgorithm, set: &NameSet) -> Option<Self> {
+        let (id_set, id_map) = set.to_id_set_and_id_map_in_o1()?;
+        let id_dag = dag.id_dag_snapshot().ok()?;
+        Some(Self {
+            id_set,
+            id_map,
+            id_dag,
+        })
+    }
+}
+
+/// `same_graph` for graphs with the same number of vertexes, using ids.
+async fn same_id_graph(a: &IdGraph, b: &IdGraph) -> Result<bool> {
+    let ids: Vec<Id> = a.id_set.iter_desc().collect();
+    for chunk in ids.chunks(BATCH_SIZE) {
+        // Vertexes in the chunk, followed by their parents.
+        let mut a_ids: Vec<Id> = chunk.to_vec();
+        let mut parent_counts: Vec<usize> = Vec::with_capacity(chunk.len());
+        for &id in chunk {
+            let parent_ids = a.id_dag.parent_ids(id)?;
+            parent_counts.push(parent_ids.len());
+            a_ids.extend(parent_ids);
+        }
+        let names = a.id_map.vertex_name_batch(&a_ids).await?;
+        let names = names.into_iter().collect::<Result<Vec<_>>>()?;
+        let b_ids = b.id_map.vertex_id_batch(&names).await?;
+        let b_ids = match b_ids.into_iter().collect::<Result<Vec<_>>>() {
+            Ok(ids) => ids,
+            Err(VertexNotFound(_)) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        // Both graphs have the same number of vertexes. So they have the
+        // same vertexes if vertexes in `a` are also in `b`.
+        let (b_vertex_ids, mut b_parent_ids) = b_ids.split_at(chunk.len());
+        for (&b_id, &count) in b_vertex_ids.iter().zip(&parent_counts) {
+            let (expected_parent_ids, rest) = b_parent_ids.split_at(count);
+            if !b.id_set.contains(b_id) || b.id_dag.parent_ids(b_id)? != expected_parent_ids {
+                return Ok(false);
+            }
+            b_parent_ids = rest;
+        }
+    }
+    Ok(true)
+}
+
+/// `same_graph` for graphs with the same number of vertexes, using
+/// `parent_names`.
+async fn same_graph_by_parent_names(
+    a: &dyn DagAlgorithm,
+    a_set: &NameSet,
+    b: &dyn DagAlgorithm,
+    b_set: &NameSet,
+) -> Result<bool> {
+    let mut iter = a_set.iter().await?;
+    while let Some(vertex) = iter.next().await {
+        let vertex = vertex?;
+        if !b_set.contains(&vertex).await? {
+            return Ok(false);
+        }
+        if a.parent_names(vertex.clone()).await? != b.parent_names(vertex).await? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
@@ -16,6 +16,7 @@ use super::BoxVertexStream;
 use super::Hints;
 use super::NameSet;
 use crate::fmt::write_debug;
+use crate::heap_size::HeapSize;
 use crate::Result;
 use crate::VertexName;
 
@@ -55,6 +56,12 @@ impl DifferenceSet {
     }
 }
 
+impl HeapSize for DifferenceSet {
+    fn heap_size(&self) -> usize {
+        self.lhs.heap_size() + self.rhs.heap_size()
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncNameSetQuery for DifferenceSet {
     async fn iter(&self) -> Result<BoxVertexStream> {
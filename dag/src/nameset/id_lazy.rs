@@ -19,6 +19,7 @@ use super::id_static::IdStaticSet;
 use super::AsyncNameSetQuery;
 use super::BoxVertexStream;
 use super::Hints;
+use crate::heap_size::HeapSize;
 use crate::ops::DagAlgorithm;
 use crate::ops::IdConvert;
 use crate::protocol::disable_remote_protocol;
@@ -223,6 +224,16 @@ impl IdLazySet {
     }
 }
 
+impl HeapSize for IdLazySet {
+    /// Ids visited so far. 0 if the set is being iterated.
+    fn heap_size(&self) -> usize {
+        match self.inner.try_lock() {
+            Ok(inner) => inner.visited.heap_size(),
+            Err(_) => 0,
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncNameSetQuery for IdLazySet {
     async fn iter(&self) -> Result<BoxVertexStream> {
@@ -279,7 +290,7 @@ impl AsyncNameSetQuery for IdLazySet {
     async fn contains(&self, name: &VertexName) -> Result<bool> {
         let id = match self
             .map
-            .vertex_id_with_max_group(name, Group::NON_MASTER)
+            .vertex_id_with_max_group(name, Group::VIRTUAL)
             .await?
         {
             None => {
@@ -323,7 +334,7 @@ impl AsyncNameSetQuery for IdLazySet {
     async fn contains_fast(&self, name: &VertexName) -> Result<Option<bool>> {
         let id = match self
             .map
-            .vertex_id_with_max_group(name, Group::NON_MASTER)
+            .vertex_id_with_max_group(name, Group::VIRTUAL)
             .await?
         {
             None => {
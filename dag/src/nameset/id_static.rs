@@ -15,6 +15,7 @@ use super::hints::Flags;
 use super::AsyncNameSetQuery;
 use super::BoxVertexStream;
 use super::Hints;
+use crate::heap_size::HeapSize;
 use crate::ops::DagAlgorithm;
 use crate::ops::IdConvert;
 use crate::protocol::disable_remote_protocol;
@@ -182,6 +183,13 @@ impl IdStaticSet {
     }
 }
 
+impl HeapSize for IdStaticSet {
+    fn heap_size(&self) -> usize {
+        // `map` and `dag` are shared.
+        self.spans.heap_size()
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncNameSetQuery for IdStaticSet {
     async fn iter(&self) -> Result<BoxVertexStream> {
@@ -239,7 +247,7 @@ impl AsyncNameSetQuery for IdStaticSet {
     async fn contains(&self, name: &VertexName) -> Result<bool> {
         let result = match self
             .map
-            .vertex_id_with_max_group(name, Group::NON_MASTER)
+            .vertex_id_with_max_group(name, Group::VIRTUAL)
             .await?
         {
             Some(id) => self.spans.contains(id),
@@ -17,6 +17,7 @@ use super::BoxVertexStream;
 use super::Hints;
 use super::NameSet;
 use crate::fmt::write_debug;
+use crate::heap_size::HeapSize;
 use crate::Id;
 use crate::Result;
 use crate::VertexName;
@@ -149,6 +150,12 @@ impl IntersectionSet {
     }
 }
 
+impl HeapSize for IntersectionSet {
+    fn heap_size(&self) -> usize {
+        self.lhs.heap_size() + self.rhs.heap_size()
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncNameSetQuery for IntersectionSet {
     async fn iter(&self) -> Result<BoxVertexStream> {
@@ -17,6 +17,7 @@ use indexmap::IndexSet;
 use super::AsyncNameSetQuery;
 use super::BoxVertexStream;
 use super::Hints;
+use crate::heap_size::HeapSize;
 use crate::Result;
 use crate::VertexName;
 
@@ -158,6 +159,16 @@ impl LazySet {
     }
 }
 
+impl HeapSize for LazySet {
+    /// Vertexes visited so far. 0 if the set is being iterated.
+    fn heap_size(&self) -> usize {
+        match self.inner.try_lock() {
+            Some(inner) => inner.visited.heap_size(),
+            None => 0,
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncNameSetQuery for LazySet {
     async fn iter(&self) -> Result<BoxVertexStream> {
@@ -15,6 +15,7 @@ use super::AsyncNameSetQuery;
 use super::BoxVertexStream;
 use super::Hints;
 use super::NameSet;
+use crate::heap_size::HeapSize;
 use crate::Result;
 use crate::VertexName;
 
@@ -109,6 +110,16 @@ impl MetaSet {
     }
 }
 
+impl HeapSize for MetaSet {
+    /// The evaluated set. 0 if not evaluated.
+    fn heap_size(&self) -> usize {
+        match self.evaluated.read() {
+            Ok(evaluated) => evaluated.as_ref().map_or(0, |s| s.heap_size()),
+            Err(_) => 0,
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncNameSetQuery for MetaSet {
     async fn iter(&self) -> Result<BoxVertexStream> {
@@ -26,6 +26,7 @@ use futures::StreamExt;
 use nonblocking::non_blocking;
 
 use crate::default_impl;
+use crate::heap_size::HeapSize;
 use crate::ops::DagAlgorithm;
 use crate::ops::IdConvert;
 use crate::ops::IdMapSnapshot;
@@ -510,6 +511,35 @@ impl Deref for NameSet {
     }
 }
 
+impl HeapSize for NameSet {
+    /// Heap size of the concrete set. Sets implemented outside this crate
+    /// are counted as 0.
+    fn heap_size(&self) -> usize {
+        let any = self.as_any();
+        macro_rules! dispatch {
+            ($($ty:ty),*) => {
+                $(
+                    if let Some(set) = any.downcast_ref::<$ty>() {
+                        return set.heap_size();
+                    }
+                )*
+            };
+        }
+        dispatch!(
+            StaticSet,
+            IdStaticSet,
+            MetaSet,
+            id_lazy::IdLazySet,
+            lazy::LazySet,
+            union::UnionSet,
+            intersection::IntersectionSet,
+            difference::DifferenceSet,
+            slice::SliceSet
+        );
+        0
+    }
+}
+
 impl fmt::Debug for NameSet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.0.fmt(f)
@@ -592,6 +622,10 @@ pub trait AsyncNameSetQuery: Any + Debug + Send + Sync {
 }
 
 /// Sync version of `AsyncNameSetQuery`.
+///
+/// Methods do not block. They return a `WouldBlock` error if the async
+/// version needs to wait, ex. for resolving vertexes remotely. Use
+/// [`AsyncNameSetQuery`] in async contexts.
 pub trait SyncNameSetQuery {
     /// Iterate through the set in defined order.
     fn iter(&self) -> Result<Box<dyn NameIter>>;
@@ -27,6 +27,7 @@ use super::BoxVertexStream;
 use super::Hints;
 use super::NameSet;
 use crate::fmt::write_debug;
+use crate::heap_size::HeapSize;
 use crate::Result;
 use crate::VertexName;
 
@@ -215,6 +216,15 @@ impl TakeCacheRevIter {
     }
 }
 
+impl HeapSize for SliceSet {
+    /// The inner set, and vertexes cached so far.
+    fn heap_size(&self) -> usize {
+        let skip_size = self.skip_cache.try_lock().map_or(0, |c| c.heap_size());
+        let take_size = self.take_cache.try_lock().map_or(0, |c| c.heap_size());
+        self.inner.heap_size() + skip_size + take_size
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncNameSetQuery for SliceSet {
     async fn iter(&self) -> Result<BoxVertexStream> {
@@ -14,6 +14,7 @@ use super::hints::Flags;
 use super::AsyncNameSetQuery;
 use super::BoxVertexStream;
 use super::Hints;
+use crate::heap_size::HeapSize;
 use crate::Result;
 use crate::VertexName;
 
@@ -38,6 +39,12 @@ impl StaticSet {
     }
 }
 
+impl HeapSize for StaticSet {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size()
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncNameSetQuery for StaticSet {
     async fn iter(&self) -> Result<BoxVertexStream> {
@@ -16,6 +16,7 @@ use super::BoxVertexStream;
 use super::Hints;
 use super::NameSet;
 use crate::fmt::write_debug;
+use crate::heap_size::HeapSize;
 use crate::Result;
 use crate::VertexName;
 
@@ -50,6 +51,12 @@ impl UnionSet {
     }
 }
 
+impl HeapSize for UnionSet {
+    fn heap_size(&self) -> usize {
+        self.sets.iter().map(|s| s.heap_size()).sum()
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncNameSetQuery for UnionSet {
     async fn iter(&self) -> Result<BoxVertexStream> {
@@ -315,6 +315,11 @@ pub trait DagStrip {
     ///
     /// After strip, the `self` graph might contain new vertexes because of
     /// the reload.
+    ///
+    /// Ids of the remaining vertexes are not changed. Ids of the stripped
+    /// vertexes might be reused by vertexes added later. So the graph gets
+    /// a new version, and sets using ids from before the strip are not
+    /// considered compatible with it.
     async fn strip(&mut self, set: &NameSet) -> Result<()>;
 }
 
@@ -439,7 +444,7 @@ pub trait IdConvert: PrefixLookup + Sync {
     async fn contains_vertex_name_locally(&self, name: &[VertexName]) -> Result<Vec<bool>>;
 
     async fn vertex_id_optional(&self, name: &VertexName) -> Result<Option<Id>> {
-        self.vertex_id_with_max_group(name, Group::NON_MASTER).await
+        self.vertex_id_with_max_group(name, Group::VIRTUAL).await
     }
 
     /// Convert [`Id`]s to [`VertexName`]s in batch.
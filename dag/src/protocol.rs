@@ -28,14 +28,21 @@ use crate::id::VertexName;
 use crate::iddag::FirstAncestorConstraint;
 use crate::iddag::IdDag;
 use crate::iddagstore::IdDagStore;
+use crate::idmap::IdMapWrite;
 use crate::ops::IdConvert;
+use crate::segment::PreparedFlatSegments;
 use crate::Group;
 use crate::Id;
 #[cfg(any(test, feature = "indexedlog-backend"))]
 use crate::IdMap;
 use crate::IdSet;
+use crate::IdSpan;
 use crate::Result;
 
+mod wire;
+
+pub use wire::WireFormat;
+
 // Request and Response structures -------------------------------------------
 
 /// Request for locating names (commit hashes) in a IdDag.
@@ -64,6 +71,61 @@ pub struct ResponseIdNamePair {
     pub path_names: Vec<(AncestorPath, Vec<VertexName>)>,
 }
 
+/// Request for flat segments covering a span of ids.
+/// Useful for a lazy client to fetch part of the graph.
+#[derive(Debug, Clone)]
+pub struct RequestFlatSegments {
+    pub span: IdSpan,
+}
+
+/// Response for [`RequestFlatSegments`].
+/// Segments are cut to fit the requested span.
+#[derive(Debug, Clone)]
+pub struct ResponseFlatSegments {
+    pub segments: PreparedFlatSegments,
+}
+
+/// Request for `(id, name)` pairs of ids in a span.
+/// Useful for a lazy client to fill its IdMap in bulk.
+#[derive(Debug, Clone)]
+pub struct RequestIdMapChunk {
+    pub span: IdSpan,
+}
+
+/// Response for [`RequestIdMapChunk`].
+/// Ids in the span that are not in the graph are skipped.
+#[derive(Debug, Clone)]
+pub struct ResponseIdMapChunk {
+    pub pairs: Vec<(Id, VertexName)>,
+}
+
+impl ResponseFlatSegments {
+    /// Answer `request` using a complete graph. Server-side.
+    pub async fn from_request<M: IdConvert, DagStore: IdDagStore>(
+        map: &M,
+        dag: &IdDag<DagStore>,
+        request: RequestFlatSegments,
+    ) -> Result<Self> {
+        (map, dag).process(request).await
+    }
+}
+
+impl ResponseIdMapChunk {
+    /// Answer `request` using a complete IdMap. Server-side.
+    pub async fn from_request<M: IdConvert, DagStore: IdDagStore>(
+        map: &M,
+        dag: &IdDag<DagStore>,
+        request: RequestIdMapChunk,
+    ) -> Result<Self> {
+        (map, dag).process(request).await
+    }
+
+    /// Insert the pairs to a local IdMap. Client-side.
+    pub async fn apply_to<M: IdMapWrite + Send>(self, map: &mut M) -> Result<()> {
+        map.process(self).await
+    }
+}
+
 /// The `n`-th first ancestor of `x`. `x~n` in hg revset syntax.
 /// Usually, `x` is commonly known by the client and the server.
 ///
@@ -97,6 +159,15 @@ impl fmt::Debug for AncestorPath {
 // Async Remote Protocols ----------------------------------------------------
 
 /// Abstraction of network protocols.
+///
+/// Used by a lazy graph to resolve ids and names missing in its IdMap. See
+/// [`NameDag::set_remote_protocol`](crate::namedag::AbstractNameDag::set_remote_protocol).
+/// Resolved vertexes are cached locally, and vertexes confirmed missing by
+/// the server are not asked again.
+///
+/// Ids are not exchanged directly, since ids in the client and the server
+/// can differ. Vertexes are located by [`AncestorPath`]s relative to
+/// vertexes known by both sides instead.
 #[async_trait::async_trait]
 pub trait RemoteIdConvertProtocol: Send + Sync + 'static {
     /// Ask the server to convert names to "x~n" relative paths.
@@ -435,6 +506,55 @@ impl<'a, DagStore: IdDagStore> Process<ResponseIdNamePair, ()>
     }
 }
 
+// Flat segments: RequestFlatSegments -> ResponseFlatSegments
+// Works on a complete IdDag, server-side.
+#[async_trait::async_trait]
+impl<M: IdConvert, DagStore: IdDagStore> Process<RequestFlatSegments, ResponseFlatSegments>
+    for (&M, &IdDag<DagStore>)
+{
+    async fn process(self, request: RequestFlatSegments) -> Result<ResponseFlatSegments> {
+        let dag = &self.1;
+        let segments = dag.idset_to_flat_segments(IdSet::from(request.span))?;
+        Ok(ResponseFlatSegments { segments })
+    }
+}
+
+// IdMap chunk: RequestIdMapChunk -> ResponseIdMapChunk
+// Works on a complete IdMap, server-side.
+#[async_trait::async_trait]
+impl<M: IdConvert, DagStore: IdDagStore> Process<RequestIdMapChunk, ResponseIdMapChunk>
+    for (&M, &IdDag<DagStore>)
+{
+    async fn process(self, request: RequestIdMapChunk) -> Result<ResponseIdMapChunk> {
+        let map = &self.0;
+        let dag = &self.1;
+        let ids: Vec<Id> = dag
+            .all()?
+            .intersection(&IdSet::from(request.span))
+            .iter_asc()
+            .collect();
+        let fallible_names = map.vertex_name_batch(&ids).await?;
+        let mut pairs = Vec::with_capacity(ids.len());
+        for (id, name) in ids.into_iter().zip(fallible_names) {
+            pairs.push((id, name?));
+        }
+        Ok(ResponseIdMapChunk { pairs })
+    }
+}
+
+// IdMap chunk: Apply ResponseIdMapChunk to a local IdMap.
+// Works on an incomplete IdMap, client-side.
+#[async_trait::async_trait]
+impl<M: IdMapWrite + Send> Process<ResponseIdMapChunk, ()> for &mut M {
+    async fn process(self, res: ResponseIdMapChunk) -> Result<()> {
+        for (id, name) in res.pairs.iter() {
+            tracing::trace!(" insert {:?} = {:?}", id, &name);
+            self.insert(*id, name.as_ref()).await?;
+        }
+        Ok(())
+    }
+}
+
 // Disable remote protocol temporarily ---------------------------------------
 // This can be useful for Debug::fmt to disable remote fetching which might
 // panic (ex. calling tokio without tokio runtime) when executing futures
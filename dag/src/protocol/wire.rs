@@ -0,0 +1,328 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Compact binary serialization for protocol structures.
+//!
+//! Integers are VLQ-encoded. Names are frames (VLQ length, then bytes).
+//! Lists start with their VLQ length. Spans and segments are encoded as
+//! `low` followed by `high - low`. Id sets are encoded as their span count,
+//! then spans in DESC order, each as the gap after the previous span
+//! (`high` for the first span) followed by `high - low`.
+
+use std::io;
+
+use vlqencoding::FrameWrite;
+use vlqencoding::VLQDecode;
+use vlqencoding::VLQEncode;
+
+use super::AncestorPath;
+use super::RequestFlatSegments;
+use super::RequestIdMapChunk;
+use super::RequestLocationToName;
+use super::RequestNameToLocation;
+use super::ResponseFlatSegments;
+use super::ResponseIdMapChunk;
+use super::ResponseIdNamePair;
+use crate::id::VertexName;
+use crate::segment::FlatSegment;
+use crate::segment::PreparedFlatSegments;
+use crate::Id;
+use crate::IdSet;
+use crate::IdSpan;
+use crate::Result;
+
+/// Names longer than this are rejected when decoding.
+const MAX_NAME_LEN: usize = 1 << 16;
+
+/// Serialization used to exchange protocol structures.
+pub trait WireFormat: Sized {
+    /// Append the encoded form of `self` to `out`.
+    fn write_wire(&self, out: &mut Vec<u8>);
+
+    /// Decode from the start of `input` and advance it past the decoded bytes.
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self>;
+
+    /// Encode `self` into a new buffer.
+    fn to_wire_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_wire(&mut out);
+        out
+    }
+
+    /// Decode from `bytes`. Trailing bytes are an error.
+    fn from_wire_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut input = bytes;
+        let value = Self::read_wire(&mut input)?;
+        if !input.is_empty() {
+            return Err(invalid(format!("{} trailing bytes", input.len())).into());
+        }
+        Ok(value)
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl WireFormat for u64 {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        out.write_vlq(*self).unwrap();
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        input.read_vlq()
+    }
+}
+
+impl WireFormat for Id {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        self.0.write_wire(out)
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let id = u64::read_wire(input)?;
+        if id > Id::MAX.0 {
+            return Err(invalid(format!("id {} is out of range", id)));
+        }
+        Ok(Id(id))
+    }
+}
+
+impl WireFormat for VertexName {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        out.write_frame(self.as_ref()).unwrap();
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let (name, len) = vlqencoding::read_frame_at(input, 0, MAX_NAME_LEN)?;
+        let name = VertexName::copy_from(name);
+        *input = &input[len..];
+        Ok(name)
+    }
+}
+
+impl<T: WireFormat> WireFormat for Vec<T> {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        out.write_vlq(self.len()).unwrap();
+        for item in self {
+            item.write_wire(out);
+        }
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let len: usize = input.read_vlq()?;
+        // Every item takes at least 1 byte. Do not trust `len` for allocation.
+        let mut items = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            items.push(T::read_wire(input)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<A: WireFormat, B: WireFormat> WireFormat for (A, B) {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        self.0.write_wire(out);
+        self.1.write_wire(out);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let a = A::read_wire(input)?;
+        let b = B::read_wire(input)?;
+        Ok((a, b))
+    }
+}
+
+/// Write `low`, `high - low`.
+fn write_low_high(out: &mut Vec<u8>, low: Id, high: Id) {
+    low.write_wire(out);
+    (high.0 - low.0).write_wire(out);
+}
+
+/// Read `low`, `high - low` written by `write_low_high`.
+fn read_low_high(input: &mut &[u8]) -> io::Result<(Id, Id)> {
+    let low = Id::read_wire(input)?;
+    let delta = u64::read_wire(input)?;
+    match low.0.checked_add(delta) {
+        Some(high) if high <= Id::MAX.0 => Ok((low, Id(high))),
+        _ => Err(invalid(format!("span {}+{} is out of range", low, delta))),
+    }
+}
+
+impl WireFormat for IdSpan {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        write_low_high(out, self.low, self.high);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let (low, high) = read_low_high(input)?;
+        Ok(IdSpan::new(low, high))
+    }
+}
+
+impl WireFormat for IdSet {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        let spans = self.as_spans();
+        out.write_vlq(spans.len()).unwrap();
+        let mut prev_low: Option<Id> = None;
+        for span in spans {
+            let gap = match prev_low {
+                None => span.high.0,
+                // Spans are not adjacent. So `high + 1 < prev_low`.
+                Some(prev_low) => prev_low.0 - span.high.0 - 2,
+            };
+            gap.write_wire(out);
+            (span.high.0 - span.low.0).write_wire(out);
+            prev_low = Some(span.low);
+        }
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let len: usize = input.read_vlq()?;
+        // Every span takes at least 2 bytes. Do not trust `len` for allocation.
+        let mut spans = Vec::with_capacity(len.min(input.len() / 2));
+        let mut prev_low: Option<Id> = None;
+        for i in 0..len {
+            let gap = u64::read_wire(input)?;
+            let delta = u64::read_wire(input)?;
+            let high = match prev_low {
+                None => Some(gap),
+                Some(prev_low) => prev_low.0.checked_sub(gap).and_then(|v| v.checked_sub(2)),
+            };
+            let span = match (high, high.and_then(|v| v.checked_sub(delta))) {
+                (Some(high), Some(low)) if high <= Id::MAX.0 => IdSpan::new(Id(low), Id(high)),
+                _ => return Err(invalid(format!("span #{} is out of range", i))),
+            };
+            prev_low = Some(span.low);
+            spans.push(span);
+        }
+        Ok(IdSet::from_sorted_spans(spans))
+    }
+}
+
+impl WireFormat for FlatSegment {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        write_low_high(out, self.low, self.high);
+        self.parents.write_wire(out);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let (low, high) = read_low_high(input)?;
+        let parents = Vec::read_wire(input)?;
+        Ok(FlatSegment { low, high, parents })
+    }
+}
+
+impl WireFormat for PreparedFlatSegments {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        out.write_vlq(self.segments.len()).unwrap();
+        for segment in &self.segments {
+            segment.write_wire(out);
+        }
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let segments: Vec<FlatSegment> = Vec::read_wire(input)?;
+        Ok(PreparedFlatSegments {
+            segments: segments.into_iter().collect(),
+        })
+    }
+}
+
+impl WireFormat for AncestorPath {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        self.x.write_wire(out);
+        self.n.write_wire(out);
+        self.batch_size.write_wire(out);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let x = VertexName::read_wire(input)?;
+        let n = u64::read_wire(input)?;
+        let batch_size = u64::read_wire(input)?;
+        Ok(AncestorPath { x, n, batch_size })
+    }
+}
+
+impl WireFormat for RequestNameToLocation {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        self.names.write_wire(out);
+        self.heads.write_wire(out);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let names = Vec::read_wire(input)?;
+        let heads = Vec::read_wire(input)?;
+        Ok(RequestNameToLocation { names, heads })
+    }
+}
+
+impl WireFormat for RequestLocationToName {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        self.paths.write_wire(out);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let paths = Vec::read_wire(input)?;
+        Ok(RequestLocationToName { paths })
+    }
+}
+
+impl WireFormat for ResponseIdNamePair {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        self.path_names.write_wire(out);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let path_names = Vec::read_wire(input)?;
+        Ok(ResponseIdNamePair { path_names })
+    }
+}
+
+impl WireFormat for RequestFlatSegments {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        self.span.write_wire(out);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let span = IdSpan::read_wire(input)?;
+        Ok(RequestFlatSegments { span })
+    }
+}
+
+impl WireFormat for ResponseFlatSegments {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        self.segments.write_wire(out);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let segments = PreparedFlatSegments::read_wire(input)?;
+        Ok(ResponseFlatSegments { segments })
+    }
+}
+
+impl WireFormat for RequestIdMapChunk {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        self.span.write_wire(out);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let span = IdSpan::read_wire(input)?;
+        Ok(RequestIdMapChunk { span })
+    }
+}
+
+impl WireFormat for ResponseIdMapChunk {
+    fn write_wire(&self, out: &mut Vec<u8>) {
+        self.pairs.write_wire(out);
+    }
+
+    fn read_wire(input: &mut &[u8]) -> io::Result<Self> {
+        let pairs = Vec::read_wire(input)?;
+        Ok(ResponseIdMapChunk { pairs })
+    }
+}
@@ -5,6 +5,13 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+//! # render
+//!
+//! Render graphs as text. Renderers and glyph sets are re-exported from
+//! [`renderdag`]. [`render_namedag`] renders a graph using box drawing
+//! characters. [`render_namedag_structured`] produces [`GraphRow`]s, for
+//! other renderers or output formats.
+
 #[cfg(any(test, feature = "indexedlog-backend"))]
 use std::cmp::Ordering;
 #[cfg(any(test, feature = "indexedlog-backend"))]
@@ -30,6 +30,7 @@ use vlqencoding::VLQEncode;
 
 use crate::errors::bug;
 use crate::errors::programming;
+use crate::heap_size::HeapSize;
 use crate::id::Id;
 use crate::IdSpan;
 use crate::Level;
@@ -147,25 +148,29 @@ impl Segment {
     }
 
     pub(crate) fn parent_count(&self) -> Result<usize> {
-        let mut cur = Cursor::new(&self.0);
-        cur.set_position(Self::OFFSET_DELTA as u64);
-        let _: u64 = cur.read_vlq()?;
-        let parent_count: usize = cur.read_vlq()?;
+        let (parent_count, _) = self.parent_count_and_offset()?;
         Ok(parent_count)
     }
 
     pub(crate) fn parents(&self) -> Result<Vec<Id>> {
-        let mut cur = Cursor::new(&self.0);
-        cur.set_position(Self::OFFSET_DELTA as u64);
-        let _: u64 = cur.read_vlq()?;
-        let parent_count: usize = cur.read_vlq()?;
+        let (parent_count, mut offset) = self.parent_count_and_offset()?;
         let mut result = Vec::with_capacity(parent_count);
         for _ in 0..parent_count {
-            result.push(Id(cur.read_vlq()?));
+            let (parent, len) = self.0.read_vlq_at(offset)?;
+            offset += len;
+            result.push(Id(parent));
         }
         Ok(result)
     }
 
+    // (parent_count, offset of the first parent)
+    fn parent_count_and_offset(&self) -> Result<(usize, usize)> {
+        let (_, delta_len): (u64, _) = self.0.read_vlq_at(Self::OFFSET_DELTA)?;
+        let offset = Self::OFFSET_DELTA + delta_len;
+        let (parent_count, len) = self.0.read_vlq_at(offset)?;
+        Ok((parent_count, offset + len))
+    }
+
     /// Duplicate the segment with `high` set to a new value.
     pub(crate) fn with_high(&self, high: Id) -> Result<Self> {
         let span = self.span()?;
@@ -202,6 +207,12 @@ impl Segment {
     }
 }
 
+impl HeapSize for Segment {
+    fn heap_size(&self) -> usize {
+        self.0.len()
+    }
+}
+
 impl PartialEq for Segment {
     fn eq(&self, other: &Self) -> bool {
         self.0[..] == other.0[..]
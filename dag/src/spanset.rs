@@ -27,6 +27,7 @@ use serde::Deserialize;
 use serde::Serialize;
 
 use crate::bsearch::BinarySearchBy;
+use crate::heap_size::HeapSize;
 use crate::id::Id;
 
 /// Range `low..=high`. `low` must be <= `high`.
@@ -39,12 +40,43 @@ pub struct Span {
 }
 
 /// A set of integer spans.
+///
+/// [`WireFormat`](crate::protocol::WireFormat) provides a compact binary
+/// encoding that does not expand spans.
 #[derive(Clone, Serialize, Deserialize, Default)]
+#[serde(try_from = "UncheckedSpanSet")]
 pub struct SpanSet {
     /// `spans` are sorted in DESC order.
     spans: VecDeque<Span>,
 }
 
+/// Deserialized [`SpanSet`] before checking its invariants.
+#[derive(Deserialize)]
+struct UncheckedSpanSet {
+    spans: VecDeque<Span>,
+}
+
+impl TryFrom<UncheckedSpanSet> for SpanSet {
+    type Error = String;
+
+    fn try_from(unchecked: UncheckedSpanSet) -> Result<Self, Self::Error> {
+        let spans = unchecked.spans;
+        for (i, span) in spans.iter().enumerate() {
+            if span.low > span.high || span.high > Id::MAX {
+                let (low, high) = (span.low.0, span.high.0);
+                return Err(format!("invalid span {}..={} (#{})", low, high, i));
+            }
+            if i > 0 && span.high.0.saturating_add(1) >= spans[i - 1].low.0 {
+                return Err(format!(
+                    "spans are not in DESC order or have mergable adjacent spans (around #{})",
+                    i
+                ));
+            }
+        }
+        Ok(SpanSet { spans })
+    }
+}
+
 impl PartialOrd for Span {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(match self.high.cmp(&other.high) {
@@ -274,6 +306,18 @@ impl From<(Id, Id)> for SpanSet {
     }
 }
 
+impl HeapSize for Span {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for SpanSet {
+    fn heap_size(&self) -> usize {
+        self.spans.heap_size()
+    }
+}
+
 impl SpanSet {
     /// Construct a [`SpanSet`] containing given spans.
     /// Overlapped or adjacent spans will be merged automatically.
@@ -1412,4 +1456,24 @@ mod tests {
         assert_eq!(format!("{:2?}", &set), "1..=10 20 and 1 span");
         assert_eq!(format!("{:1?}", &set), "1..=10 and 2 spans");
     }
+
+    #[test]
+    fn test_deserialize_validates() {
+        let set = SpanSet::from_spans(vec![1..=5, 10..=20]);
+        let bytes = mincode::serialize(&set).unwrap();
+        let set2: SpanSet = mincode::deserialize(&bytes).unwrap();
+        assert_eq!(format!("{:?}", set2), format!("{:?}", set));
+
+        let deserialize = |spans: Vec<(u64, u64)>| -> Option<SpanSet> {
+            let bytes = mincode::serialize(&spans).unwrap();
+            mincode::deserialize(&bytes).ok()
+        };
+        assert!(deserialize(vec![(10, 20), (1, 5)]).is_some());
+        assert!(deserialize(vec![(20, 10)]).is_none());
+        assert!(deserialize(vec![(1, 5), (10, 20)]).is_none());
+        assert!(deserialize(vec![(6, 10), (1, 5)]).is_none());
+        assert!(deserialize(vec![(Id::MAX.0, Id::MAX.0), (0, 0)]).is_some());
+        assert!(deserialize(vec![(1, 1), (Id::MAX.0, Id::MAX.0)]).is_none());
+        assert!(deserialize(vec![(u64::MAX, u64::MAX)]).is_none());
+    }
 }
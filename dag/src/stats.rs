@@ -0,0 +1,182 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! # stats
+//!
+//! Shape statistics of a graph. See [`GraphStats`].
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use crate::iddag::IdDag;
+use crate::iddagstore::IdDagStore;
+use crate::namedag::AbstractNameDag;
+use crate::segment::FlatSegment;
+use crate::Group;
+use crate::Id;
+use crate::Result;
+
+/// Statistics about the shape of a graph and how well it is compressed
+/// into segments.
+///
+/// Useful for deciding where to cut the master group, or whether a repo
+/// should be re-cloned.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphStats {
+    /// Number of vertexes.
+    pub vertex_count: u64,
+
+    /// Number of vertexes keyed by their number of children.
+    pub children_distribution: BTreeMap<usize, u64>,
+
+    /// Number of vertexes with more than one parent.
+    pub merge_count: u64,
+
+    /// Length of the longest chain of vertexes, in which each vertex is the
+    /// only child of the previous vertex, and the previous vertex is its
+    /// only parent.
+    pub longest_linear_run: u64,
+
+    /// Number of heads in each group.
+    pub heads_per_group: BTreeMap<Group, u64>,
+
+    /// Number of segments at each level. Level 0 has flat segments.
+    pub segments_per_level: Vec<u64>,
+}
+
+impl GraphStats {
+    /// Average number of children of vertexes that have children.
+    pub fn branching_factor(&self) -> f64 {
+        let (vertexes, children) = self
+            .children_distribution
+            .iter()
+            .filter(|(&n, _)| n > 0)
+            .fold((0, 0), |(v, c), (&n, &count)| {
+                (v + count, c + count * n as u64)
+            });
+        ratio(children, vertexes)
+    }
+
+    /// Merges per vertex.
+    pub fn merge_rate(&self) -> f64 {
+        ratio(self.merge_count, self.vertex_count)
+    }
+
+    /// Vertexes per flat segment. Higher means the graph is more linear and
+    /// cheaper to store and query.
+    pub fn segment_compression_ratio(&self) -> f64 {
+        let flat_segments = self.segments_per_level.first().copied().unwrap_or(0);
+        ratio(self.vertex_count, flat_segments)
+    }
+}
+
+fn ratio(a: u64, b: u64) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+impl<Store: IdDagStore> IdDag<Store> {
+    /// Calculate [`GraphStats`] from segments.
+    ///
+    /// This scans all flat segments. Its cost is proportional to the number
+    /// of segments, not vertexes.
+    pub fn graph_stats(&self) -> Result<GraphStats> {
+        let mut stats = GraphStats::default();
+
+        let mut segments: Vec<FlatSegment> = Vec::new();
+        for &group in Group::ALL.iter() {
+            segments.extend(self.flat_segments(group)?.segments);
+            let heads = self.heads(self.all_ids_in_groups(&[group])?)?;
+            stats.heads_per_group.insert(group, heads.count());
+        }
+
+        // Within a flat segment, each vertex except `high` has one child in
+        // the segment. Other parent-child edges are in `parents`.
+        let mut extra_children: BTreeMap<Id, usize> = BTreeMap::new();
+        for seg in &segments {
+            for &p in &seg.parents {
+                *extra_children.entry(p).or_default() += 1;
+            }
+            if seg.parents.len() > 1 {
+                stats.merge_count += 1;
+            }
+            let count = seg.high.0 - seg.low.0 + 1;
+            stats.vertex_count += count;
+            *stats.children_distribution.entry(0).or_default() += 1;
+            if count > 1 {
+                *stats.children_distribution.entry(1).or_default() += count - 1;
+            }
+        }
+        segments.sort_unstable_by_key(|s| s.low);
+        let is_high = |id: Id| -> Option<bool> {
+            let i = segments.partition_point(|s| s.high < id);
+            let seg = segments.get(i).filter(|s| s.low <= id)?;
+            Some(seg.high == id)
+        };
+        let mut children_count: HashMap<Id, usize> = HashMap::new();
+        for (&id, &extra) in &extra_children {
+            let base = match is_high(id) {
+                Some(true) => 0,
+                Some(false) => 1,
+                None => continue,
+            };
+            let distribution = &mut stats.children_distribution;
+            *distribution.entry(base).or_default() -= 1;
+            *distribution.entry(base + extra).or_default() += 1;
+            children_count.insert(id, base + extra);
+        }
+        stats.children_distribution.retain(|_, count| *count > 0);
+
+        // Parents have smaller ids. Visit segments in id order and extend the
+        // run ending at a parent if the parent has only one child.
+        let mut run_ending_at: HashMap<Id, u64> = HashMap::new();
+        for seg in &segments {
+            let mut run = match seg.parents[..] {
+                [p] if children_count.get(&p) == Some(&1) => {
+                    run_ending_at.get(&p).copied().unwrap_or(0)
+                }
+                _ => 0,
+            };
+            let mut low = seg.low;
+            // Vertexes with extra children fork the run.
+            for (&fork, _) in extra_children.range(seg.low..seg.high) {
+                run += fork.0 - low.0 + 1;
+                stats.longest_linear_run = stats.longest_linear_run.max(run);
+                run = 0;
+                low = fork + 1;
+            }
+            run += seg.high.0 - low.0 + 1;
+            stats.longest_linear_run = stats.longest_linear_run.max(run);
+            if children_count.get(&seg.high) == Some(&1) {
+                run_ending_at.insert(seg.high, run);
+            }
+        }
+
+        for level in 0..=self.max_level()? {
+            let count = self.iter_segments_ascending(Id::MIN, level)?.count();
+            stats.segments_per_level.push(count as u64);
+        }
+
+        Ok(stats)
+    }
+}
+
+impl<IS, M, P, S> AbstractNameDag<IdDag<IS>, M, P, S>
+where
+    IS: IdDagStore,
+    M: Send + Sync,
+    P: Send + Sync,
+    S: Send + Sync,
+{
+    /// Calculate [`GraphStats`] of the graph.
+    pub fn graph_stats(&self) -> Result<GraphStats> {
+        self.dag.graph_stats()
+    }
+}
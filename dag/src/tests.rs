@@ -9,7 +9,7 @@ use nonblocking::non_blocking_result as r;
 use tempfile::tempdir;
 pub use test_dag::TestDag;
 
-pub use self::drawdag::DrawDag;
+pub use crate::drawdag::DrawDag;
 use crate::id::Group;
 use crate::id::VertexName;
 use crate::nameset::SyncNameSetQuery;
@@ -24,8 +24,8 @@ use crate::IdSet;
 use crate::NameDag;
 use crate::NameSet;
 use crate::Result;
+use crate::VertexListWithOptions;
 
-mod drawdag;
 mod test_dag;
 
 #[cfg(test)]
@@ -59,15 +59,23 @@ use crate::ops::IdConvert;
 #[cfg(test)]
 use crate::protocol::Process;
 #[cfg(test)]
+use crate::protocol::RequestFlatSegments;
+#[cfg(test)]
+use crate::protocol::RequestIdMapChunk;
+#[cfg(test)]
 use crate::protocol::RequestLocationToName;
 #[cfg(test)]
 use crate::protocol::RequestNameToLocation;
 #[cfg(test)]
+use crate::protocol::ResponseFlatSegments;
+#[cfg(test)]
+use crate::protocol::ResponseIdMapChunk;
+#[cfg(test)]
+use crate::protocol::WireFormat;
+#[cfg(test)]
 use crate::render::render_segment_dag;
 #[cfg(test)]
 use crate::Id;
-#[cfg(test)]
-use crate::VertexListWithOptions;
 
 // Example from segmented-changelog.pdf
 // - DAG1: page 10
@@ -496,6 +504,88 @@ Lv1: R0-0[] R1-8[0]"#
     );
 }
 
+#[test]
+fn test_protocol_wire_format() {
+    let built = build_segments(ASCII_DAG1, "A C E L", 3);
+    let map = &built.name_dag.map;
+    let dag = &built.name_dag.dag;
+
+    // Encode, decode, and check every truncation is rejected.
+    fn roundtrip<T: WireFormat + std::fmt::Debug>(value: &T) -> String {
+        let bytes = value.to_wire_bytes();
+        for len in 0..bytes.len() {
+            assert!(T::from_wire_bytes(&bytes[..len]).is_err());
+        }
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(T::from_wire_bytes(&extra).is_err());
+        let decoded = T::from_wire_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}", &decoded), format!("{:?}", value));
+        format!("{:?} ({} bytes)", decoded, bytes.len())
+    }
+
+    let ids = IdSet::from_spans(vec![Id(0), Id(4), Id(5), Id(6), Id(7), Id(10)]);
+    let request1: RequestLocationToName = r((map, dag).process(ids)).unwrap();
+    let response1 = r((map, dag).process(request1.clone())).unwrap();
+    let names = vec![VertexName::copy_from(b"A"), VertexName::copy_from(b"K")];
+    let request2: RequestNameToLocation = r((map, dag).process(names)).unwrap();
+    let response2 = r((map, dag).process(request2.clone())).unwrap();
+    roundtrip(&request1);
+    roundtrip(&response1);
+    roundtrip(&request2);
+    roundtrip(&response2);
+
+    let request = RequestFlatSegments {
+        span: (Id(2)..=Id(9)).into(),
+    };
+    assert_eq!(
+        roundtrip(&request),
+        "RequestFlatSegments { span: Span { low: 2, high: 9 } } (2 bytes)"
+    );
+    let response = r(ResponseFlatSegments::from_request(map, dag, request)).unwrap();
+    assert_eq!(
+        roundtrip(&response),
+        concat!(
+            "ResponseFlatSegments { segments: PreparedFlatSegments { segments: {",
+            "FlatSegment { low: 2, high: 2, parents: [1] }, ",
+            "FlatSegment { low: 3, high: 3, parents: [0] }, ",
+            "FlatSegment { low: 4, high: 8, parents: [3, 2] }, ",
+            "FlatSegment { low: 9, high: 9, parents: [6] }} } } (18 bytes)"
+        )
+    );
+
+    let request = RequestIdMapChunk {
+        span: (Id(9)..=Id(20)).into(),
+    };
+    let response = r(ResponseIdMapChunk::from_request(map, dag, request)).unwrap();
+    assert_eq!(
+        roundtrip(&response),
+        "ResponseIdMapChunk { pairs: [(9, H), (10, K), (11, L)] } (10 bytes)"
+    );
+
+    // Apply the chunk to an empty IdMap on the client side.
+    let mut client_map = crate::idmap::MemIdMap::new();
+    r(response.apply_to(&mut client_map)).unwrap();
+    let name = r(client_map.vertex_name(Id(10))).unwrap();
+    assert_eq!(format!("{:?}", name), "K");
+
+    // Id sets with a few large spans are small.
+    let ids = IdSet::from_spans(vec![Id(0)..=Id(3_000_000), Id(3_000_002)..=Id(3_000_100)]);
+    assert_eq!(roundtrip(&ids), "0..=3000000 3000002..=3000100 (11 bytes)");
+    assert_eq!(roundtrip(&IdSet::empty()), " (1 bytes)");
+    let ids = IdSet::from_spans(vec![Id(1), Id(3), Id::MAX]);
+    roundtrip(&ids);
+
+    // Invalid input.
+    assert!(RequestFlatSegments::from_wire_bytes(&[
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, 0
+    ])
+    .is_err());
+    assert!(ResponseIdMapChunk::from_wire_bytes(&[0xff, 0xff, 0xff, 0xff, 0x0f]).is_err());
+    // The second span (gap 5) is below 0.
+    assert!(IdSet::from_wire_bytes(&[2, 3, 0, 5, 0]).is_err());
+}
+
 #[test]
 fn test_segment_non_master() {
     let ascii = r#"
@@ -1323,6 +1413,373 @@ async fn test_subdag() {
     assert_eq!(render(&s1), render(&s2));
 }
 
+#[cfg_attr(test, tokio::test)]
+async fn test_virtual_group() {
+    let mut t = TestDag::draw("A-B-C # master: C");
+    let parents = DrawDag::from("C-D-E B-X");
+    let virtual_heads = VertexListWithOptions::from(vec![VertexName::copy_from(b"E")])
+        .with_highest_group(Group::VIRTUAL);
+    t.dag.add_heads(&parents, &virtual_heads).await.unwrap();
+    assert_eq!(
+        format!("{:?}", t.dag.ancestors(nameset("E")).await.unwrap()),
+        "<spans [D:E+V0:V1, A:C+0:2]>"
+    );
+    assert_eq!(
+        format!("{:?}", t.dag.descendants(nameset("B")).await.unwrap()),
+        "<spans [D:E+V0:V1, B:C+1:2]>"
+    );
+    assert_eq!(
+        format!("{:?}", t.dag.dirty().await.unwrap()),
+        "<spans [D:E+V0:V1]>"
+    );
+
+    // Other groups cannot depend on the virtual group.
+    let heads = VertexListWithOptions::from(vec![VertexName::copy_from(b"X")]);
+    assert!(t.dag.add_heads(&parents, &heads).await.is_err());
+    assert!(t
+        .dag
+        .add_heads_and_flush(&parents, &virtual_heads)
+        .await
+        .is_err());
+
+    // The virtual group is kept in memory, but not written to disk.
+    t.flush("C").await;
+    assert_eq!(
+        format!("{:?}", t.dag.all().await.unwrap()),
+        "<spans [D:E+V0:V1, A:C+0:2]>"
+    );
+    let dag = NameDag::open(t.dir.path().join("n")).unwrap();
+    assert_eq!(
+        format!("{:?}", dag.all().await.unwrap()),
+        "<spans [A:C+0:2]>"
+    );
+
+    // After removing the virtual group, other groups can be changed.
+    t.dag.remove_virtual_group().await.unwrap();
+    assert_eq!(
+        format!("{:?}", t.dag.all().await.unwrap()),
+        "<spans [A:C+0:2]>"
+    );
+    assert!(!t
+        .dag
+        .contains_vertex_name(&VertexName::copy_from(b"E"))
+        .await
+        .unwrap());
+    t.dag.add_heads(&parents, &heads).await.unwrap();
+    assert_eq!(
+        format!("{:?}", t.dag.all().await.unwrap()),
+        "<spans [X+N0, A:C+0:2]>"
+    );
+}
+
+#[cfg_attr(test, tokio::test)]
+async fn test_export_subset() {
+    let t = TestDag::draw("A-B-C-D-E B-X-Y # master: E");
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("subset");
+    let set = nameset("A C D X Y");
+    t.dag.export_subset(&path, set.clone()).await.unwrap();
+
+    // Only edges within the set are kept. C and X lose their parent B.
+    let dag = NameDag::open(&path).unwrap();
+    assert_eq!(
+        format!(
+            "{:?}",
+            dag.parent_names(VertexName::copy_from(b"C")).await.unwrap()
+        ),
+        "[]"
+    );
+    assert_eq!(
+        format!(
+            "{:?}",
+            dag.parent_names(VertexName::copy_from(b"D")).await.unwrap()
+        ),
+        "[C]"
+    );
+    assert_eq!(
+        format!(
+            "{:?}",
+            dag.parent_names(VertexName::copy_from(b"X")).await.unwrap()
+        ),
+        "[]"
+    );
+    assert_eq!(
+        format!("{:?}", dag.master_group().await.unwrap()),
+        "<spans [A:D+0:2]>"
+    );
+    assert_eq!(
+        format!("{:?}", dag.all().await.unwrap()),
+        "<spans [X:Y+N0:N1, A:D+0:2]>"
+    );
+
+    // A is in the master group, even if it is only an ancestor of non-master
+    // vertexes in the original graph.
+    let path2 = dir.path().join("subset2");
+    let dag = t.dag.export_subset(&path2, nameset("A X Y")).await.unwrap();
+    assert_eq!(
+        format!("{:?}", dag.master_group().await.unwrap()),
+        "<spans [A+0]>"
+    );
+    assert_eq!(
+        format!("{:?}", dag.all().await.unwrap()),
+        "<spans [X:Y+N0:N1, A+0]>"
+    );
+
+    // The destination must be empty.
+    let err = t.dag.export_subset(&path, set).await.unwrap_err();
+    assert!(err.to_string().contains("empty destination"));
+}
+
+#[test]
+fn test_graph_stats() {
+    let t = TestDag::draw(
+        r#"
+        A--B--C--D--G--H--I--J--K
+            \      /
+             E----F
+        X--Y
+         \
+          Z # master: K"#,
+    );
+    let stats = t.dag.graph_stats().unwrap();
+    assert_eq!(
+        format!("{:?}", &stats),
+        concat!(
+            "GraphStats { vertex_count: 14, children_distribution: {0: 3, 1: 9, 2: 2}, ",
+            "merge_count: 1, longest_linear_run: 5, heads_per_group: {Group(0): 1, Group(1): 2, Group(2): 0}, ",
+            "segments_per_level: [5, 1] }"
+        )
+    );
+    assert_eq!(stats.branching_factor(), 13.0 / 11.0);
+    assert_eq!(stats.merge_rate(), 1.0 / 14.0);
+    assert_eq!(stats.segment_compression_ratio(), 14.0 / 5.0);
+
+    let stats = MemNameDag::new().graph_stats().unwrap();
+    assert_eq!(stats.vertex_count, 0);
+    assert_eq!(stats.branching_factor(), 0.0);
+}
+
+#[test]
+fn test_heap_size() {
+    use crate::HeapSize;
+
+    let small = IdSet::from_spans(vec![Id(1)]);
+    let large = IdSet::from_spans((0..100).map(|i| Id(i * 2)));
+    assert!(large.heap_size() > small.heap_size());
+
+    let names = nameset("A B C D");
+    let static_size = names.heap_size();
+    assert!(static_size >= 4);
+    let union = names.clone() | nameset("E");
+    assert!(union.heap_size() > static_size);
+
+    let dag = MemNameDag::new();
+    let empty_size = dag.dag().heap_size();
+    let mut dag = dag;
+    dag.import_ascii("A-B-C-D B-E").unwrap();
+    assert!(dag.dag().heap_size() > empty_size);
+    assert!(dag.map().heap_size() >= 5);
+
+    // Sets backed by ids do not count the shared IdMap and IdDag.
+    let all = r(dag.all()).unwrap();
+    assert!(all.heap_size() < dag.map().heap_size());
+}
+
+#[cfg_attr(test, tokio::test)]
+async fn test_bulk_import() {
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::SeqCst;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use crate::bulk_import;
+    use crate::errors::NotFoundError;
+    use crate::BulkImportOptions;
+
+    // A linear graph with a merge every 10 vertexes.
+    let name = |i: usize| VertexName::copy_from(format!("{}", i).as_bytes());
+    let graph: HashMap<VertexName, Vec<VertexName>> = (0..3000)
+        .map(|i| {
+            let parents = match i {
+                0 => vec![],
+                i if i % 10 == 0 && i > 7 => vec![name(i - 1), name(i - 7)],
+                i => vec![name(i - 1)],
+            };
+            (name(i), parents)
+        })
+        .collect();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let parents_func = {
+        let graph = graph.clone();
+        let calls = calls.clone();
+        move |v: VertexName| -> Result<Vec<VertexName>> {
+            calls.fetch_add(1, SeqCst);
+            Ok(graph[&v].clone())
+        }
+    };
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let options = BulkImportOptions::new()
+        .threads(4)
+        .queue_size(16)
+        .progress({
+            let progress = progress.clone();
+            move |p| progress.lock().unwrap().push(*p)
+        });
+
+    let master_heads =
+        |i: usize| VertexListWithOptions::from(vec![name(i)]).with_highest_group(Group::MASTER);
+    let dir = tempdir().unwrap();
+    let mut dag = NameDag::open(dir.path().join("a")).unwrap();
+    let heads = master_heads(1999);
+    bulk_import(&mut dag, parents_func.clone(), &heads, &options)
+        .await
+        .unwrap();
+    assert_eq!(calls.load(SeqCst), 2000);
+
+    // Ancestors in the graph are not resolved again.
+    let heads = master_heads(2999);
+    bulk_import(&mut dag, parents_func, &heads, &options)
+        .await
+        .unwrap();
+    assert_eq!(calls.load(SeqCst), 3000);
+    let progress = progress.lock().unwrap().clone();
+    let inserted: Vec<u64> = progress.iter().map(|p| p.inserted).collect();
+    assert_eq!(inserted, [0, 0, 2000, 0, 1000]);
+    let resolved: Vec<u64> = progress.iter().map(|p| p.resolved).collect();
+    assert_eq!(resolved, [1000, 2000, 2000, 1000, 1000]);
+
+    // Same result as add_heads_and_flush.
+    let mut expected = NameDag::open(dir.path().join("b")).unwrap();
+    r(expected.add_heads_and_flush(&graph, &master_heads(1999))).unwrap();
+    r(expected.add_heads_and_flush(&graph, &master_heads(2999))).unwrap();
+    assert_eq!(format!("{:?}", dag.dag()), format!("{:?}", expected.dag()));
+    let all: Vec<VertexName> = r(dag.all())
+        .unwrap()
+        .iter()
+        .unwrap()
+        .map(|v| v.unwrap())
+        .collect();
+    let expected_all: Vec<VertexName> = r(expected.all())
+        .unwrap()
+        .iter()
+        .unwrap()
+        .map(|v| v.unwrap())
+        .collect();
+    assert_eq!(all, expected_all);
+
+    // Errors from the parents function are returned.
+    let heads = VertexListWithOptions::from(vec![name(3001)]);
+    let parents_func = |v: VertexName| -> Result<Vec<VertexName>> { v.not_found() };
+    let err = bulk_import(&mut dag, parents_func, &heads, &BulkImportOptions::new())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, crate::Error::VertexNotFound(v) if v == name(3001)));
+    assert_eq!(r(dag.all()).unwrap().count().unwrap(), 3000);
+
+    // VIRTUAL heads are rejected before other groups are written.
+    let heads = master_heads(3001)
+        .chain(VertexListWithOptions::from(vec![name(3002)]).with_highest_group(Group::VIRTUAL));
+    let parents_func = move |v: VertexName| -> Result<Vec<VertexName>> {
+        Ok(graph.get(&v).cloned().unwrap_or_else(|| vec![name(2999)]))
+    };
+    let err = bulk_import(&mut dag, parents_func, &heads, &BulkImportOptions::new())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, crate::Error::Programming(_)));
+    assert_eq!(r(dag.all()).unwrap().count().unwrap(), 3000);
+}
+
+#[cfg_attr(test, tokio::test)]
+async fn test_bulk_import_batches() {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use crate::bulk_import;
+    use crate::BulkImportOptions;
+
+    // Branches of different lengths. Shorter branches are inserted before
+    // longer branches are resolved.
+    let name = |b: usize, i: usize| VertexName::copy_from(format!("{}-{}", b, i).as_bytes());
+    let mut graph: HashMap<VertexName, Vec<VertexName>> = HashMap::new();
+    let mut heads = Vec::new();
+    for b in 0..10 {
+        let len = (b + 1) * 20;
+        for i in 0..len {
+            let parents = if i == 0 { vec![] } else { vec![name(b, i - 1)] };
+            graph.insert(name(b, i), parents);
+        }
+        heads.push(name(b, len - 1));
+    }
+    let parents_func = {
+        let graph = graph.clone();
+        move |v: VertexName| -> Result<Vec<VertexName>> { Ok(graph[&v].clone()) }
+    };
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let options = BulkImportOptions::new()
+        .threads(2)
+        .queue_size(4)
+        .batch_size(50)
+        .progress({
+            let progress = progress.clone();
+            move |p| progress.lock().unwrap().push(*p)
+        });
+
+    let dir = tempdir().unwrap();
+    let mut dag = NameDag::open(dir.path()).unwrap();
+    let heads = VertexListWithOptions::from(heads);
+    bulk_import(&mut dag, parents_func, &heads, &options)
+        .await
+        .unwrap();
+
+    let progress = progress.lock().unwrap().clone();
+    let first_insert = progress.iter().find(|p| p.inserted > 0).unwrap();
+    assert!(first_insert.resolved < 1100);
+    assert_eq!(progress.last().unwrap().inserted, 1100);
+
+    assert_eq!(dag.all().await.unwrap().count().unwrap(), 1100);
+    for (v, parents) in graph {
+        assert_eq!(dag.parent_names(v).await.unwrap(), parents);
+    }
+
+    // Panics in the parents function are returned as errors.
+    let heads = VertexListWithOptions::from(vec![name(0, 1000)]);
+    let parents_func = |_: VertexName| -> Result<Vec<VertexName>> { panic!("parents") };
+    let err = bulk_import(&mut dag, parents_func, &heads, &BulkImportOptions::new())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, crate::Error::Programming(_)));
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_tracing_spans() {
+    let lines = dev_logger::traced("info", || {
+        let t = TestDag::draw("A-B-C-D B-E # master: D");
+        r(t.dag.ancestors(nameset("C"))).unwrap();
+        r(t.dag.range(nameset("B"), nameset("D E"))).unwrap();
+        r(t.dag.gca_all(nameset("D E"))).unwrap();
+    });
+    let has_exit = |name: &str, field: &str| {
+        lines
+            .iter()
+            .any(|l| l.contains(name) && l.contains(field) && l.ends_with("exit"))
+    };
+    assert!(has_exit("dag::namedag::flush{", "heads_len=1 duration_us="));
+    assert!(has_exit(
+        "dag::namedag::ancestors{",
+        "set_len=1 result_len=3 duration_us="
+    ));
+    assert!(has_exit(
+        "dag::namedag::range{",
+        "roots_len=1 heads_len=2 result_len=4 duration_us="
+    ));
+    assert!(has_exit("dag::namedag::gca_all{", "set_len=2 result_len=1"));
+    assert!(has_exit("dag::iddag::ancestors{", "set_len=1 result_len=3"));
+    assert!(has_exit("dag::iddag::gca_all{", "set_len=2 result_len=1"));
+}
+
 // Test utilities
 
 fn expand(set: NameSet) -> String {
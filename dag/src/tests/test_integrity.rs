@@ -5,7 +5,10 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+use super::dummy_dag::DummyDag;
+use super::nameset;
 use super::TestDag;
+use crate::namedag::same_graph;
 use crate::ops::CheckIntegrity;
 use crate::ops::DagAlgorithm;
 use crate::Group;
@@ -98,3 +101,36 @@ async fn quick_check_graphs(ascii1: &str, ascii2: &str) -> Vec<String> {
         .await
         .unwrap()
 }
+
+#[tokio::test]
+async fn test_same_graph() {
+    async fn same(ascii1: &str, ascii2: &str, heads: &str) -> bool {
+        let dag1 = TestDag::draw(ascii1);
+        let dag2 = TestDag::draw(ascii2);
+        same_graph(&dag1.dag, &dag2.dag, nameset(heads))
+            .await
+            .unwrap()
+    }
+
+    // Ids are not compared.
+    let ascii = "A-B-C A-D-E C-F E-F";
+    assert!(same(ascii, &format!("{} # master: E F", ascii), "F").await);
+    // Only ancestors of heads are compared.
+    assert!(same("A-B-C A-X", "A-B-C A-Y", "C").await);
+
+    assert!(!same("A-B-C-D", "A-C-B-D", "D").await);
+    assert!(!same("A-B-C", "X-B-C", "C").await);
+    assert!(!same("A-B-C", "Z-A-B-C", "C").await);
+    assert!(!same("A-B-C", "A-B", "C").await);
+
+    // Vertexes are lazy in the client.
+    let server = TestDag::draw(ascii);
+    let client = TestDag::draw_client(ascii).await;
+    let heads = nameset("F");
+    assert!(same_graph(&server.dag, &client.dag, heads).await.unwrap());
+
+    // Graphs without ids.
+    let dummy = DummyDag::new();
+    assert!(same_graph(&dummy, &server.dag, nameset("A")).await.unwrap());
+    assert!(!same_graph(&dummy, &server.dag, nameset("B")).await.unwrap());
+}
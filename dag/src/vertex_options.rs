@@ -110,6 +110,11 @@ impl VertexListWithOptions {
             .collect()
     }
 
+    /// Count vertexes in this list.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
     /// Test if this list is empty.
     pub fn is_empty(&self) -> bool {
         self.list.is_empty()
@@ -0,0 +1,25 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+#![no_main]
+
+use dag::IdSet;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(set) = mincode::deserialize::<IdSet>(data) {
+        // Deserialized sets are valid. Operations on them should not panic.
+        let _ = set.count();
+        let _ = set.max();
+        let _ = set.min();
+        let union = set.union(&set);
+        assert_eq!(union.count(), set.count());
+        let bytes = mincode::serialize(&set).unwrap();
+        let set2 = mincode::deserialize::<IdSet>(&bytes).unwrap();
+        assert_eq!(set2.count(), set.count());
+    }
+});
@@ -0,0 +1,37 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+#![no_main]
+
+use indexedlog::index::OpenOptions;
+use libfuzzer_sys::fuzz_target;
+use minibytes::Bytes;
+
+fuzz_target!(|data: &[u8]| {
+    let index = match OpenOptions::new().open_from_bytes(Bytes::copy_from_slice(data)) {
+        Ok(index) => index,
+        Err(_) => return,
+    };
+    let _ = index.verify();
+    let _ = index.get_meta();
+    let iter = match index.range(..) {
+        Ok(iter) => iter,
+        Err(_) => return,
+    };
+    for item in iter.take(100) {
+        let (key, link) = match item {
+            Ok(item) => item,
+            Err(_) => break,
+        };
+        let _ = index.get(&key);
+        for value in link.values(&index).take(100) {
+            if value.is_err() {
+                break;
+            }
+        }
+    }
+});
@@ -0,0 +1,22 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+#![no_main]
+
+use indexedlog::log::LogMetadata;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The checksum in the header rejects almost all inputs. Parse the body
+    // directly to reach the fields.
+    let _ = LogMetadata::read(data);
+    if let Ok(meta) = LogMetadata::read_body(data) {
+        let mut buf = Vec::new();
+        meta.write(&mut buf).unwrap();
+        assert_eq!(LogMetadata::read(&buf[..]).unwrap(), meta);
+    }
+});
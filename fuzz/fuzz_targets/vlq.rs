@@ -0,0 +1,37 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use vlqencoding::read_vlq_at;
+use vlqencoding::VLQDecode;
+
+fuzz_target!(|data: &[u8]| {
+    // The slice decoder agrees with the reader decoder.
+    let at: Option<(u64, usize)> = read_vlq_at(data, 0).ok();
+    let mut cursor = Cursor::new(data);
+    let read: Option<u64> = cursor.read_vlq().ok();
+    assert_eq!(at.map(|(v, _)| v), read);
+    if let Some((_, len)) = at {
+        assert_eq!(len as u64, cursor.position());
+    }
+
+    let _: Option<(i64, usize)> = read_vlq_at(data, 0).ok();
+    let _: Option<(u32, usize)> = read_vlq_at(data, 0).ok();
+    let _: Option<(i16, usize)> = read_vlq_at(data, 0).ok();
+
+    let mut frames = vlqencoding::frames(data, data.len());
+    for frame in &mut frames {
+        if frame.is_err() {
+            break;
+        }
+    }
+    assert!(frames.offset() <= data.len());
+});
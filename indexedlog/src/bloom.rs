@@ -0,0 +1,265 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Bloom filter used by [`Index`] to skip lookups of missing keys.
+//!
+//! Format:
+//!
+//! ```plain,ignore
+//! BLOOM := HEADER + VLQ(SOURCE_LEN) + SOURCE_HASH (8B) + VLQ(HASH_COUNT) +
+//!          VLQ(CAPACITY) + VLQ(LEN) + VLQ(WORD_COUNT) + WORD_LIST +
+//!          XXHASH64(everything before) (8B)
+//! HEADER := 'bloom\0'
+//! WORD_LIST := A list of 64-bit words.
+//! ```
+//!
+//! `SOURCE_LEN` and `SOURCE_HASH` identify the [`Index`] content the filter
+//! was built for. Fixed-size integers use LittleEndian encoding.
+
+use std::f64::consts::LN_2;
+use std::io::Cursor;
+use std::io::Read;
+
+use byteorder::LittleEndian;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use vlqencoding::VLQDecode;
+use vlqencoding::VLQEncode;
+
+#[cfg(doc)]
+use crate::index::Index;
+use crate::utils::xxhash;
+
+const HEADER: &[u8] = b"bloom\0";
+
+// Avoid rebuilding too often for small indexes.
+const MIN_CAPACITY: u64 = 1024;
+
+/// A bloom filter over keys. False positives are possible. False negatives
+/// are not.
+#[derive(Clone)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+    hash_count: u32,
+    // Number of keys the filter is sized for.
+    capacity: u64,
+    // Number of keys inserted. Keys that might already exist are not counted.
+    len: u64,
+    false_positive_rate: f64,
+}
+
+impl BloomFilter {
+    /// Create an empty filter sized for `capacity` keys.
+    pub(crate) fn new(capacity: u64, false_positive_rate: f64) -> Self {
+        let capacity = capacity.max(MIN_CAPACITY);
+        let (word_count, hash_count) = Self::parameters(capacity, false_positive_rate);
+        Self {
+            bits: vec![0; word_count],
+            hash_count,
+            capacity,
+            len: 0,
+            false_positive_rate,
+        }
+    }
+
+    /// Create a filter from hashes of keys. See [`BloomFilter::hash`].
+    pub(crate) fn from_hashes(hashes: &[u64], false_positive_rate: f64) -> Self {
+        // Leave room for keys inserted later.
+        let mut filter = Self::new(hashes.len() as u64 * 2, false_positive_rate);
+        for &hash in hashes {
+            filter.insert_hash(hash);
+        }
+        filter
+    }
+
+    /// Hash a key.
+    pub(crate) fn hash(key: &[u8]) -> u64 {
+        xxhash(key)
+    }
+
+    /// Insert a key.
+    pub(crate) fn insert(&mut self, key: &[u8]) {
+        self.insert_hash(Self::hash(key))
+    }
+
+    /// Test if a key might exist.
+    pub(crate) fn contains(&self, key: &[u8]) -> bool {
+        let bit_count = self.bits.len() as u64 * 64;
+        self.bit_indexes(Self::hash(key), bit_count)
+            .all(|i| self.bits[(i / 64) as usize] & (1 << (i % 64)) != 0)
+    }
+
+    /// Test if more keys were inserted than the filter was sized for.
+    /// The false positive rate is higher than configured if so.
+    pub(crate) fn is_full(&self) -> bool {
+        self.len > self.capacity
+    }
+
+    /// The false positive rate the filter was created with.
+    pub(crate) fn false_positive_rate(&self) -> f64 {
+        self.false_positive_rate
+    }
+
+    /// Serialize the filter. `source` identifies the [`Index`] content.
+    pub(crate) fn to_bytes(&self, source: (u64, u64)) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER.len() + self.bits.len() * 8 + 64);
+        buf.extend_from_slice(HEADER);
+        // Writing to a Vec is infallible.
+        buf.write_vlq(source.0).unwrap();
+        buf.write_u64::<LittleEndian>(source.1).unwrap();
+        buf.write_vlq(self.hash_count).unwrap();
+        buf.write_vlq(self.capacity).unwrap();
+        buf.write_vlq(self.len).unwrap();
+        buf.write_vlq(self.bits.len()).unwrap();
+        for &word in &self.bits {
+            buf.write_u64::<LittleEndian>(word).unwrap();
+        }
+        let checksum = xxhash(&buf);
+        buf.write_u64::<LittleEndian>(checksum).unwrap();
+        buf
+    }
+
+    /// Deserialize a filter written by [`BloomFilter::to_bytes`].
+    ///
+    /// Return `None` if `buf` is invalid, or the filter was not created for
+    /// `source` with `false_positive_rate`. The filter is a cache, so the
+    /// caller can rebuild it in those cases.
+    pub(crate) fn from_bytes(
+        buf: &[u8],
+        source: (u64, u64),
+        false_positive_rate: f64,
+    ) -> Option<Self> {
+        let body_len = buf.len().checked_sub(8)?;
+        let (body, mut checksum) = buf.split_at(body_len);
+        if checksum.read_u64::<LittleEndian>().ok()? != xxhash(body) {
+            return None;
+        }
+        let mut cur = Cursor::new(body);
+        let mut header = [0; HEADER.len()];
+        cur.read_exact(&mut header).ok()?;
+        if header != HEADER {
+            return None;
+        }
+        let source_len: u64 = cur.read_vlq().ok()?;
+        let source_hash = cur.read_u64::<LittleEndian>().ok()?;
+        if (source_len, source_hash) != source {
+            return None;
+        }
+        let hash_count: u32 = cur.read_vlq().ok()?;
+        let capacity: u64 = cur.read_vlq().ok()?;
+        let len: u64 = cur.read_vlq().ok()?;
+        let word_count: usize = cur.read_vlq().ok()?;
+        if (word_count, hash_count) != Self::parameters(capacity, false_positive_rate) {
+            return None;
+        }
+        let mut bits = Vec::with_capacity(word_count);
+        for _ in 0..word_count {
+            bits.push(cur.read_u64::<LittleEndian>().ok()?);
+        }
+        if cur.position() != body.len() as u64 {
+            return None;
+        }
+        Some(Self {
+            bits,
+            hash_count,
+            capacity,
+            len,
+            false_positive_rate,
+        })
+    }
+
+    fn insert_hash(&mut self, hash: u64) {
+        let bit_count = self.bits.len() as u64 * 64;
+        let mut existed = true;
+        for i in self.bit_indexes(hash, bit_count) {
+            let word = &mut self.bits[(i / 64) as usize];
+            let mask = 1 << (i % 64);
+            existed &= *word & mask != 0;
+            *word |= mask;
+        }
+        if !existed {
+            self.len += 1;
+        }
+    }
+
+    /// Bit indexes for a hash, using double hashing.
+    fn bit_indexes(&self, hash: u64, bit_count: u64) -> impl Iterator<Item = u64> {
+        let step = hash.rotate_left(32) | 1;
+        (0..self.hash_count as u64)
+            .map(move |i| hash.wrapping_add(i.wrapping_mul(step)) % bit_count)
+    }
+
+    /// Calculate `(word_count, hash_count)` for the given capacity and false
+    /// positive rate.
+    fn parameters(capacity: u64, false_positive_rate: f64) -> (usize, u32) {
+        let rate = if false_positive_rate.is_nan() {
+            0.01
+        } else {
+            false_positive_rate.clamp(1e-9, 0.5)
+        };
+        let bit_count = -(capacity as f64) * rate.ln() / (LN_2 * LN_2);
+        let word_count = ((bit_count / 64.0).ceil() as usize).max(1);
+        let hash_count = (word_count as f64 * 64.0 / capacity as f64 * LN_2).round();
+        (word_count, hash_count.clamp(1.0, 32.0) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_false_positive_rate() {
+        let mut filter = BloomFilter::new(10000, 0.01);
+        for i in 0..10000u64 {
+            filter.insert(&i.to_be_bytes());
+        }
+        assert!(!filter.is_full());
+        for i in 0..10000u64 {
+            assert!(filter.contains(&i.to_be_bytes()));
+        }
+        let false_positives = (10000..20000u64)
+            .filter(|i| filter.contains(&i.to_be_bytes()))
+            .count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+
+        // Keys that might exist are not counted.
+        let len = filter.len;
+        filter.insert(&0u64.to_be_bytes());
+        assert_eq!(filter.len, len);
+        for i in 20000..20200u64 {
+            filter.insert(&i.to_be_bytes());
+        }
+        assert!(filter.is_full());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let hashes: Vec<u64> = [&b"a"[..], b"b", b"c"]
+            .iter()
+            .map(|k| BloomFilter::hash(k))
+            .collect();
+        let filter = BloomFilter::from_hashes(&hashes, 0.01);
+        let buf = filter.to_bytes((10, 20));
+
+        let loaded = BloomFilter::from_bytes(&buf, (10, 20), 0.01).unwrap();
+        assert_eq!(loaded.bits, filter.bits);
+        assert_eq!(loaded.len, 3);
+        assert!(loaded.contains(b"a") && loaded.contains(b"c"));
+
+        // Mismatched source or parameters.
+        assert!(BloomFilter::from_bytes(&buf, (10, 21), 0.01).is_none());
+        assert!(BloomFilter::from_bytes(&buf, (10, 20), 0.001).is_none());
+
+        // Corrupted or truncated.
+        let mut corrupted = buf.clone();
+        corrupted[HEADER.len() + 5] ^= 1;
+        assert!(BloomFilter::from_bytes(&corrupted, (10, 20), 0.01).is_none());
+        assert!(BloomFilter::from_bytes(&buf[..buf.len() - 1], (10, 20), 0.01).is_none());
+        assert!(BloomFilter::from_bytes(b"", (10, 20), 0.01).is_none());
+    }
+}
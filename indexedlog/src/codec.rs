@@ -0,0 +1,70 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Pluggable transformation of on-disk bytes.
+//!
+//! See [`Codec`] for details.
+
+/// Transforms bytes before they are written to disk, and after they are
+/// read from disk. For example, to encrypt data at rest.
+///
+/// A [`Codec`] is set by [`log::OpenOptions::codec`](crate::log::OpenOptions::codec)
+/// or [`index::OpenOptions::codec`](crate::index::OpenOptions::codec). Key
+/// material is owned by the implementation and is never written to disk by
+/// this crate.
+///
+/// Checksums are calculated on the decoded bytes. So integrity checks still
+/// work as usual, and a wrong key will be reported as data corruption.
+///
+/// Files are append-only. A [`Codec`] must be able to encode or decode an
+/// arbitrary byte range of a file independently, given the offset of the
+/// range in the file. Therefore the transformation must preserve length,
+/// and must only depend on the byte values and their offsets. A stream cipher
+/// that derives its keystream position from `offset` (ex. AES-CTR, or
+/// ChaCha20 with the block counter derived from `offset`) satisfies this.
+pub trait Codec: Send + Sync + 'static {
+    /// Encode `data` in-place. `data` will be written at `offset` of a file.
+    fn encode(&self, offset: u64, data: &mut [u8]);
+
+    /// Decode `data` in-place. `data` was read at `offset` of a file.
+    fn decode(&self, offset: u64, data: &mut [u8]);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Simple XOR "cipher" for testing. Do not use it in production.
+    pub(crate) struct XorCodec(pub(crate) u8);
+
+    impl Codec for XorCodec {
+        fn encode(&self, offset: u64, data: &mut [u8]) {
+            for (i, b) in data.iter_mut().enumerate() {
+                *b ^= self.0.wrapping_add((offset as u8).wrapping_add(i as u8));
+            }
+        }
+
+        fn decode(&self, offset: u64, data: &mut [u8]) {
+            self.encode(offset, data)
+        }
+    }
+
+    #[test]
+    fn test_xor_codec_roundtrip() {
+        let codec = XorCodec(42);
+        let data = b"0123456789".to_vec();
+
+        let mut encoded = data.clone();
+        codec.encode(100, &mut encoded);
+        assert_ne!(encoded, data);
+
+        // Decoding a sub-range works independently.
+        let mut tail = encoded[3..].to_vec();
+        codec.decode(103, &mut tail);
+        assert_eq!(&tail[..], &data[3..]);
+    }
+}
@@ -12,6 +12,7 @@
 use std::fmt;
 use std::io;
 use std::path::Path;
+use std::path::PathBuf;
 
 // Error design goals:
 // - Callsites can test whether an error is caused by data corruption or other
@@ -23,6 +24,7 @@ use std::path::Path;
 //   to the error object are via public methods instead of struct or enum
 //   fields. `Error` is the only opaque public error type.
 // - Compatible with std Error. Therefore anyhow::Error is supported too.
+// - Callsites can classify errors without parsing messages. See `kind`.
 
 /// Represents all possible errors that can occur when using indexedlog.
 pub struct Error {
@@ -32,12 +34,57 @@ pub struct Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Classification of an [`Error`]. See [`Error::kind`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Data corruption. See [`Error::is_corruption`].
+    Corruption,
+
+    /// A lock is held by others. See [`Error::is_busy`].
+    Busy,
+
+    /// An entry exceeds the size limit. See [`Error::is_entry_too_large`].
+    EntryTooLarge,
+
+    /// The [`Log`](crate::log::Log) is poisoned. See [`Error::poison_reason`].
+    Poisoned,
+
+    /// The size limit of a [`Log`](crate::log::Log) is exceeded. See
+    /// [`Error::is_quota_exceeded`].
+    QuotaExceeded,
+
+    /// The data or the operation is not supported by this version of the
+    /// crate. For example, a [`Log`](crate::log::Log) written using a newer
+    /// format version. See [`Error::is_unsupported`].
+    Unsupported,
+
+    /// Permission denied by the operating system.
+    PermissionDenied,
+
+    /// A file or directory does not exist.
+    NotFound,
+
+    /// API misuse, or a bug in this crate.
+    Programming,
+
+    /// Other errors. For example, other IO errors like "disk is full".
+    Other,
+}
+
 #[derive(Default)]
 struct Inner {
     sources: Vec<Box<dyn std::error::Error + Send + Sync + 'static>>,
     messages: Vec<String>,
     is_corruption: bool,
+    is_busy: bool,
+    is_entry_too_large: bool,
+    poison_reason: Option<String>,
+    is_quota_exceeded: bool,
+    is_unsupported: bool,
+    is_programming: bool,
     io_error_kind: Option<io::ErrorKind>,
+    path: Option<PathBuf>,
 }
 
 impl Error {
@@ -79,10 +126,85 @@ impl Error {
         self.inner.is_corruption
     }
 
+    /// Return `true` if the error is caused by a lock held by others. For
+    /// example, a non-blocking lock attempt, or a destructive operation
+    /// like `repair` while there are active readers.
+    ///
+    /// The operation might succeed if retried later.
+    pub fn is_busy(&self) -> bool {
+        self.inner.is_busy
+    }
+
+    /// Return `true` if an entry was rejected because it exceeds
+    /// `max_entry_size` set by [`OpenOptions`](crate::log::OpenOptions).
+    pub fn is_entry_too_large(&self) -> bool {
+        self.inner.is_entry_too_large
+    }
+
+    /// Return the reason passed to [`Log::poison`](crate::log::Log::poison)
+    /// if the error is caused by using a poisoned [`Log`](crate::log::Log).
+    pub fn poison_reason(&self) -> Option<&str> {
+        self.inner.poison_reason.as_deref()
+    }
+
+    /// Return `true` if new entries were rejected because the
+    /// [`Log`](crate::log::Log) would exceed `max_total_bytes` set by
+    /// [`OpenOptions`](crate::log::OpenOptions).
+    pub fn is_quota_exceeded(&self) -> bool {
+        self.inner.is_quota_exceeded
+    }
+
+    /// Return `true` if the data or the operation is not supported by this
+    /// version of the crate. Upgrading the crate might help.
+    pub fn is_unsupported(&self) -> bool {
+        self.inner.is_unsupported || self.inner.io_error_kind == Some(io::ErrorKind::Unsupported)
+    }
+
+    /// Return `true` if the error is caused by API misuse, or a bug in this
+    /// crate.
+    pub fn is_programming(&self) -> bool {
+        self.inner.is_programming
+    }
+
+    /// Return the kind of the underlying [`io::Error`], or
+    /// [`io::ErrorKind::Other`] if the error is not caused by IO.
     pub fn io_error_kind(&self) -> io::ErrorKind {
         self.inner.io_error_kind.unwrap_or(io::ErrorKind::Other)
     }
 
+    /// Return the path of the file or directory related to the error, if
+    /// known.
+    pub fn path(&self) -> Option<&Path> {
+        self.inner.path.as_deref()
+    }
+
+    /// Classify the error.
+    ///
+    /// If an error matches multiple kinds, the first one in the order of
+    /// [`ErrorKind`] variants is used.
+    pub fn kind(&self) -> ErrorKind {
+        if self.is_corruption() {
+            ErrorKind::Corruption
+        } else if self.is_busy() {
+            ErrorKind::Busy
+        } else if self.is_entry_too_large() {
+            ErrorKind::EntryTooLarge
+        } else if self.poison_reason().is_some() {
+            ErrorKind::Poisoned
+        } else if self.is_quota_exceeded() {
+            ErrorKind::QuotaExceeded
+        } else if self.is_unsupported() {
+            ErrorKind::Unsupported
+        } else {
+            match self.inner.io_error_kind {
+                Some(io::ErrorKind::PermissionDenied) => ErrorKind::PermissionDenied,
+                Some(io::ErrorKind::NotFound) => ErrorKind::NotFound,
+                _ if self.is_programming() => ErrorKind::Programming,
+                _ => ErrorKind::Other,
+            }
+        }
+    }
+
     // Following methods are used by this crate only.
     // External code should not construct or modify `Error`.
 
@@ -96,11 +218,30 @@ impl Error {
     }
 
     fn source_dyn(mut self, source: Box<dyn std::error::Error + Send + Sync + 'static>) -> Self {
-        // Inherit the data corruption flag.
+        // Inherit the classification flags.
         if let Some(err) = source.downcast_ref::<Error>() {
             if err.is_corruption() {
                 self = self.mark_corruption();
             }
+            let (inner, source_inner) = (&mut self.inner, &err.inner);
+            inner.is_busy |= source_inner.is_busy;
+            inner.is_entry_too_large |= source_inner.is_entry_too_large;
+            if inner.poison_reason.is_none() {
+                inner.poison_reason = source_inner.poison_reason.clone();
+            }
+            inner.is_quota_exceeded |= source_inner.is_quota_exceeded;
+            inner.is_unsupported |= source_inner.is_unsupported;
+            inner.is_programming |= source_inner.is_programming;
+            if inner.io_error_kind.is_none() {
+                inner.io_error_kind = source_inner.io_error_kind;
+            }
+            if inner.path.is_none() {
+                inner.path = source_inner.path.clone();
+            }
+        } else if let Some(err) = source.downcast_ref::<io::Error>() {
+            if self.inner.io_error_kind.is_none() {
+                self.inner.io_error_kind = Some(err.kind());
+            }
         }
 
         self.inner.sources.push(source);
@@ -122,7 +263,43 @@ impl Error {
     /// For example, passing an invalid parameter to an API.
     #[inline(never)]
     pub(crate) fn programming(message: impl ToString) -> Self {
-        Self::blank().message(format!("ProgrammingError: {}", message.to_string()))
+        let mut err = Self::blank().message(format!("ProgrammingError: {}", message.to_string()));
+        err.inner.is_programming = true;
+        err
+    }
+
+    /// An entry of `len` bytes exceeds the `max` size.
+    #[inline(never)]
+    pub(crate) fn entry_too_large(len: u64, max: u64) -> Self {
+        let message = format!("entry size {} exceeds max_entry_size {}", len, max);
+        let mut err = Self::blank().message(message);
+        err.inner.is_entry_too_large = true;
+        err
+    }
+
+    /// Using a [`Log`](crate::log::Log) poisoned with `reason`.
+    #[inline(never)]
+    pub(crate) fn poisoned(reason: &str) -> Self {
+        let mut err = Self::blank().message(format!("Log is poisoned: {}", reason));
+        err.inner.poison_reason = Some(reason.to_string());
+        err
+    }
+
+    /// The data or the operation is not supported by this version.
+    #[inline(never)]
+    pub(crate) fn unsupported(message: impl ToString) -> Self {
+        let mut err = Self::blank().message(message);
+        err.inner.is_unsupported = true;
+        err
+    }
+
+    /// A [`Log`](crate::log::Log) of `size` bytes exceeds the `max` size.
+    #[inline(never)]
+    pub(crate) fn quota_exceeded(size: u64, max: u64) -> Self {
+        let message = format!("Log size {} exceeds max_total_bytes {}", size, max);
+        let mut err = Self::blank().message(message);
+        err.inner.is_quota_exceeded = true;
+        err
     }
 
     /// A data corruption error with path.
@@ -131,16 +308,24 @@ impl Error {
     #[inline(never)]
     pub(crate) fn corruption(path: &Path, message: impl ToString) -> Self {
         let message = format!("{:?}: {}", path, message.to_string());
-        Self::blank().mark_corruption().message(message)
+        Self::blank()
+            .mark_corruption()
+            .message(message)
+            .with_path(path)
     }
 
     /// An error with a path that is not a data corruption.
     ///
     /// If there is an [`IOError`], use [`IoResultExt::context`] instead.
     #[inline(never)]
-    pub(crate) fn path(path: &Path, message: impl ToString) -> Self {
+    pub(crate) fn at_path(path: &Path, message: impl ToString) -> Self {
         let message = format!("{:?}: {}", path, message.to_string());
-        Self::blank().message(message)
+        Self::blank().message(message).with_path(path)
+    }
+
+    fn with_path(mut self, path: &Path) -> Self {
+        self.inner.path = Some(path.to_path_buf());
+        self
     }
 
     /// Wrap a dynamic stdlib error.
@@ -194,6 +379,9 @@ impl fmt::Debug for Error {
         if self.is_corruption() {
             lines.push("(This error is considered as a data corruption)".to_string())
         }
+        if self.is_busy() {
+            lines.push("(This error is caused by a lock held by others)".to_string())
+        }
         if !self.inner.sources.is_empty() {
             lines.push(format!("Caused by {} errors:", self.inner.sources.len()));
             for source in &self.inner.sources {
@@ -245,11 +433,15 @@ impl<T> ResultExt<T> for Result<T> {
 }
 
 impl std::error::Error for Error {
-    // This 'Error' type is designed to be opaque (internal states are
-    // private, including inner errors), and takes responsibility
-    // of displaying a -chain- tree of errors. So it might be desirable
-    // not implementing `source` here, and expose public APIs for all
-    // use-needs.
+    // This 'Error' type takes responsibility of displaying a tree of
+    // errors. `source` only exposes the first source, for callsites that
+    // walk the chain to find a specific error type (ex. `io::Error`).
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner
+            .sources
+            .first()
+            .map(|e| e.as_ref() as &(dyn std::error::Error + 'static))
+    }
 }
 
 pub(crate) trait IoResultExt<T> {
@@ -278,6 +470,8 @@ impl<T> IoResultExt<T> for std::io::Result<T> {
                 ErrorKind::UnexpectedEof | ErrorKind::InvalidData => true,
                 _ => false,
             };
+            // For example, a non-blocking lock attempt.
+            let busy = kind == ErrorKind::WouldBlock;
             let is_eperm = kind == ErrorKind::PermissionDenied;
 
             let mut err = Error::blank().source(err).message(format!(
@@ -288,7 +482,9 @@ impl<T> IoResultExt<T> for std::io::Result<T> {
             if corruption {
                 err = err.mark_corruption();
             }
+            err.inner.is_busy = busy;
             err.inner.io_error_kind = Some(kind);
+            err.inner.path = Some(path.to_path_buf());
 
             // Provide more context for PermissionDenied
             if is_eperm {
@@ -442,6 +638,69 @@ Caused by 1 errors:
         );
     }
 
+    #[test]
+    fn test_error_kind() {
+        let path = Path::new("a.txt");
+        let io_error = |kind| Err::<(), _>(io::Error::new(kind, "io::Error"));
+
+        let err = io_error(io::ErrorKind::WouldBlock)
+            .context(path, "cannot lock")
+            .unwrap_err();
+        assert!(err.is_busy());
+        assert_eq!(err.kind(), ErrorKind::Busy);
+        assert_eq!(err.path(), Some(path));
+
+        let err = io_error(io::ErrorKind::PermissionDenied)
+            .context(path, "cannot open")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+
+        let err = io_error(io::ErrorKind::InvalidData)
+            .context(path, "cannot read")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Corruption);
+
+        assert_eq!(Error::programming("misuse").kind(), ErrorKind::Programming);
+        let err = Error::from(("cannot append", Error::entry_too_large(10, 5)));
+        assert_eq!(err.kind(), ErrorKind::EntryTooLarge);
+
+        let err = Error::from(("cannot open", Error::poisoned("migrating")));
+        assert_eq!(err.kind(), ErrorKind::Poisoned);
+        assert_eq!(err.poison_reason(), Some("migrating"));
+
+        let err = Error::from(("cannot sync", Error::quota_exceeded(10, 5)));
+        assert_eq!(err.kind(), ErrorKind::QuotaExceeded);
+
+        let err = Error::from(("cannot open", Error::unsupported("format 2")));
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        let err = io_error(io::ErrorKind::Unsupported)
+            .context(path, "cannot read")
+            .unwrap_err();
+        assert!(err.is_unsupported());
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+        assert_eq!(Error::blank().kind(), ErrorKind::Other);
+        assert_eq!(Error::blank().path(), None);
+
+        // Classification is inherited from sources.
+        let inner = io_error(io::ErrorKind::NotFound)
+            .context(path, "cannot open")
+            .unwrap_err();
+        let err = Error::from(("cannot load", inner));
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(err.io_error_kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.path(), Some(path));
+
+        // The source chain can be walked using std Error.
+        use std::error::Error as StdError;
+        let source = StdError::source(&err).unwrap();
+        let source = source.downcast_ref::<Error>().unwrap();
+        let io_source = StdError::source(source)
+            .unwrap()
+            .downcast_ref::<io::Error>();
+        assert_eq!(io_source.unwrap().kind(), io::ErrorKind::NotFound);
+    }
+
     fn io_result() -> std::io::Result<()> {
         Err(std::io::Error::new(
             std::io::ErrorKind::Other,
@@ -0,0 +1,80 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Failpoints in write paths to simulate crashes for crash-consistency
+//! testing. Requires the `failpoints` feature, which is meant for tests.
+//!
+//! Failpoints are enabled per thread, so tests running in parallel do not
+//! affect each other.
+//!
+//! Available failpoints:
+//! - `log::sync::mid_append`: half of the new entries were written to the
+//!   primary log.
+//! - `log::sync::before_meta_write`: the primary log and indexes were
+//!   written, the metadata was not.
+//! - `log::sync::after_meta_write`: the metadata was written.
+//! - `index::flush::mid_write`: half of the new data was written to the
+//!   index file.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Names of all failpoints.
+pub const FAILPOINTS: &[&str] = &[
+    "log::sync::mid_append",
+    "log::sync::before_meta_write",
+    "log::sync::after_meta_write",
+    "index::flush::mid_write",
+];
+
+/// What to do when an enabled failpoint is reached.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FailAction {
+    /// Return an error. Later steps of the operation are skipped, as if the
+    /// process crashed.
+    Error,
+
+    /// Panic. Useful with [`std::panic::catch_unwind`] or a child process.
+    Panic,
+}
+
+thread_local! {
+    static ENABLED: RefCell<HashMap<&'static str, FailAction>> = Default::default();
+}
+
+/// Enable a failpoint for the current thread.
+///
+/// Panic if `name` is not in [`FAILPOINTS`].
+pub fn enable(name: &str, action: FailAction) {
+    let name = match FAILPOINTS.iter().find(|n| **n == name) {
+        Some(name) => *name,
+        None => panic!("unknown failpoint: {}", name),
+    };
+    ENABLED.with(|enabled| enabled.borrow_mut().insert(name, action));
+}
+
+/// Disable a failpoint for the current thread.
+pub fn disable(name: &str) {
+    ENABLED.with(|enabled| enabled.borrow_mut().remove(name));
+}
+
+/// Disable all failpoints for the current thread.
+pub fn disable_all() {
+    ENABLED.with(|enabled| enabled.borrow_mut().clear());
+}
+
+/// Check the failpoint `name`. If it is enabled, call `before` to simulate
+/// partial work, then panic or return the error. Used by `fail_point!`.
+pub(crate) fn eval(name: &'static str, before: impl FnOnce()) -> Option<crate::Error> {
+    let action = ENABLED.with(|enabled| enabled.borrow().get(name).copied())?;
+    before();
+    let message = format!("injected failure at failpoint {}", name);
+    match action {
+        FailAction::Error => Some(crate::Error::from(message.as_str())),
+        FailAction::Panic => panic!("{}", message),
+    }
+}
@@ -0,0 +1,85 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! File locking and space allocation.
+//!
+//! With the `std-fs` feature, this uses `fs2`. Otherwise, this uses file
+//! locks from `std`, and allocation extends the file. On `wasm32`, which has
+//! no file locking, locks are no-ops. That is only suitable for a single
+//! process.
+//!
+//! Call lock methods as `FileExt::lock_shared(&file)`, not
+//! `file.lock_shared()`. The latter picks the inherent `File` methods
+//! in newer Rust, which fail on platforms without file locking.
+
+#[cfg(feature = "std-fs")]
+pub(crate) use fs2::lock_contended_error;
+#[cfg(feature = "std-fs")]
+pub(crate) use fs2::FileExt;
+
+#[cfg(not(feature = "std-fs"))]
+pub(crate) use self::fallback::*;
+
+#[cfg(not(feature = "std-fs"))]
+mod fallback {
+    use std::fs::File;
+    use std::io;
+
+    pub(crate) trait FileExt {
+        fn lock_shared(&self) -> io::Result<()>;
+        fn lock_exclusive(&self) -> io::Result<()>;
+        fn try_lock_shared(&self) -> io::Result<()>;
+        fn try_lock_exclusive(&self) -> io::Result<()>;
+        fn unlock(&self) -> io::Result<()>;
+        fn allocate(&self, len: u64) -> io::Result<()>;
+    }
+
+    impl FileExt for File {
+        fn lock_shared(&self) -> io::Result<()> {
+            #[cfg(not(target_arch = "wasm32"))]
+            File::lock_shared(self)?;
+            Ok(())
+        }
+
+        fn lock_exclusive(&self) -> io::Result<()> {
+            #[cfg(not(target_arch = "wasm32"))]
+            File::lock(self)?;
+            Ok(())
+        }
+
+        fn try_lock_shared(&self) -> io::Result<()> {
+            #[cfg(not(target_arch = "wasm32"))]
+            File::try_lock_shared(self)?;
+            Ok(())
+        }
+
+        fn try_lock_exclusive(&self) -> io::Result<()> {
+            #[cfg(not(target_arch = "wasm32"))]
+            File::try_lock(self)?;
+            Ok(())
+        }
+
+        fn unlock(&self) -> io::Result<()> {
+            #[cfg(not(target_arch = "wasm32"))]
+            File::unlock(self)?;
+            Ok(())
+        }
+
+        fn allocate(&self, len: u64) -> io::Result<()> {
+            if self.metadata()?.len() < len {
+                self.set_len(len)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// The error returned by `try_lock_*` if the lock is held by others.
+    /// Never returned by the no-op locks on `wasm32`.
+    pub(crate) fn lock_contended_error() -> io::Error {
+        io::ErrorKind::WouldBlock.into()
+    }
+}
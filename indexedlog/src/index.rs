@@ -8,6 +8,77 @@
 //! Index support for `log`.
 //!
 //! See [`Index`] for the main structure.
+//!
+//! [`Index`] does not depend on [`Log`](crate::log::Log). It can be used
+//! on its own, for example, to map keys to offsets of an external file. Keys
+//! can be stored in the index, or refer to an external buffer set by
+//! [`OpenOptions::key_buf`].
+//!
+//! ```
+//! use indexedlog::index::OpenOptions;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let mut index = OpenOptions::new().open(dir.path().join("index"))?;
+//! index.insert(b"key1", 10)?;
+//! index.insert(b"key1", 20)?;
+//! index.insert(b"key2", 30)?;
+//! index.flush()?;
+//!
+//! // Values of a key are in reverse insertion order.
+//! let link = index.get(b"key1")?;
+//! let values = link.values(&index).collect::<indexedlog::Result<Vec<u64>>>()?;
+//! assert_eq!(values, [20, 10]);
+//! assert!(index.get(b"key3")?.is_null());
+//!
+//! // Keys are sorted.
+//! let keys = index
+//!     .range(&b"key"[..]..)?
+//!     .map(|item| item.map(|(key, _link)| key.to_vec()))
+//!     .collect::<indexedlog::Result<Vec<_>>>()?;
+//! assert_eq!(keys, [b"key1", b"key2"]);
+//! # Ok::<(), indexedlog::Error>(())
+//! ```
+//!
+//! Values are not interpreted by [`Index`]. To index the content of an
+//! external file, use the file as the key buffer, so keys are stored as
+//! references. Use [`Index::set_meta`] to record what the index was built
+//! from, for example, the length of the file:
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use indexedlog::index::InsertKey;
+//! use indexedlog::index::InsertValue;
+//! use indexedlog::index::OpenOptions;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let data_path = dir.path().join("data");
+//! std::fs::write(&data_path, b"apple banana").unwrap();
+//! let file = std::fs::File::open(&data_path).unwrap();
+//! let data = indexedlog::utils::mmap_bytes(&file, None).unwrap();
+//!
+//! let mut index = OpenOptions::new()
+//!     .key_buf(Some(Arc::new(data.clone())))
+//!     .open(dir.path().join("index"))?;
+//! // Map words to their offsets in "data".
+//! index.insert_advanced(InsertKey::Reference((0, 5)), InsertValue::Prepend(0))?;
+//! index.insert_advanced(InsertKey::Reference((6, 6)), InsertValue::Prepend(6))?;
+//! index.set_meta(&(data.len() as u64).to_be_bytes());
+//! index.flush()?;
+//!
+//! let link = index.get(b"banana")?;
+//! assert_eq!(link.values(&index).next().unwrap()?, 6);
+//! assert_eq!(index.get_meta(), &12u64.to_be_bytes());
+//! # Ok::<(), indexedlog::Error>(())
+//! ```
+//!
+//! # Stability
+//!
+//! Public items of this module follow semantic versioning of this crate,
+//! like other modules. The file format is append-only, and stays readable
+//! by newer versions. [`Index::insert_advanced`], [`InsertKey`] and
+//! [`InsertValue`] are lower level than other APIs, and might change more
+//! often.
 
 // File format:
 //
@@ -54,6 +125,9 @@
 //   long.
 // - The "INLINE_LEAF" type is basically an inlined version of EXT_KEY and LINK, to save space.
 // - The "ROOT_LEN" is reversed so it can be read byte-by-byte from the end of a file.
+// - Integers are compact for small indexes and logs. "PTR" and "VALUE" are VLQ-encoded.
+//   "PTR2" uses 4 bytes, unless an offset in the radix entry does not fit in 32 bits. So
+//   there is no separate 32-bit format.
 
 use std::borrow::Cow;
 use std::cmp::Ordering::Equal;
@@ -76,6 +150,7 @@ use std::ops::Bound::Excluded;
 use std::ops::Bound::Included;
 use std::ops::Bound::Unbounded;
 use std::ops::Deref;
+use std::ops::Range;
 use std::ops::RangeBounds;
 use std::path::Path;
 use std::path::PathBuf;
@@ -89,7 +164,6 @@ use byteorder::ByteOrder;
 use byteorder::LittleEndian;
 use byteorder::ReadBytesExt;
 use byteorder::WriteBytesExt;
-use fs2::FileExt;
 use minibytes::Bytes;
 use tracing::debug_span;
 use twox_hash::XxHash;
@@ -99,6 +173,8 @@ use vlqencoding::VLQEncode;
 use crate::base16::base16_to_base256;
 use crate::base16::single_hex_to_base16;
 use crate::base16::Base16Iter;
+use crate::bloom::BloomFilter;
+use crate::codec::Codec;
 use crate::config;
 use crate::errors::IoResultExt;
 use crate::errors::ResultExt;
@@ -240,6 +316,9 @@ const RADIX_BITMAP_BYTES: usize = 2;
 const RADIX_FLAG_USE_64BIT: u8 = 1;
 const RADIX_FLAG_HAVE_LINK: u8 = 1 << 7;
 
+// Number of entries visited to estimate sizes of subtrees.
+const ESTIMATE_BUDGET: usize = 1024;
+
 /// Offset to an entry. The type of the entry is yet to be resolved.
 #[derive(Copy, Clone, PartialEq, PartialOrd, Default)]
 pub struct Offset(u64);
@@ -352,7 +431,7 @@ impl Offset {
 
     /// Test whether the offset is null (0).
     #[inline]
-    fn is_null(self) -> bool {
+    pub fn is_null(self) -> bool {
         self.0 == 0
     }
 
@@ -473,6 +552,15 @@ impl RadixOffset {
                     (child_offset + int_size - flag_start) as u64,
                 )?;
                 let raw_offset = Self::read_raw_int_unchecked(index, int_size, child_offset)?;
+                // Children are written before their parent. Checking this
+                // prevents cycles in corrupted data.
+                if raw_offset >= u64::from(self) {
+                    return Err(index.corruption(format!(
+                        "radix entry at {} has invalid child offset {}",
+                        usize::from(self),
+                        raw_offset
+                    )));
+                }
                 Ok(Offset::from_disk(index, raw_offset)?)
             } else {
                 index.verify_checksum(bitmap_start as u64, RADIX_BITMAP_BYTES as u64)?;
@@ -658,6 +746,30 @@ impl LeafOffset {
         }
     }
 
+    /// Offset of the key entry of a leaf entry. The key entry is not checked.
+    fn key_offset(self, index: &Index) -> crate::Result<Offset> {
+        if self.is_dirty() {
+            Ok(index.dirty_leafs[self.dirty_index()].key_offset)
+        } else {
+            let raw_key_offset = match index.buf[usize::from(self)] {
+                TYPE_INLINE_LEAF => u64::from(self) + TYPE_BYTES as u64,
+                TYPE_LEAF => {
+                    let (raw_key_offset, _): (u64, _) = index
+                        .buf
+                        .read_vlq_at(usize::from(self) + TYPE_BYTES)
+                        .context(
+                            index.path(),
+                            "cannot read key_offset in LeafOffset::key_offset",
+                        )
+                        .corruption()?;
+                    raw_key_offset
+                }
+                _ => unreachable!("bug: LeafOffset constructed with non-leaf types"),
+            };
+            Offset::from_disk(index, raw_key_offset)
+        }
+    }
+
     /// Create a new in-memory leaf entry. The key entry cannot be null.
     #[inline]
     fn create(index: &mut Index, link_offset: LinkOffset, key_offset: Offset) -> LeafOffset {
@@ -913,6 +1025,14 @@ impl LinkOffset {
                 )
                 .corruption()?;
             index.verify_checksum(u64::from(self), (TYPE_BYTES + vlq_len + vlq_len2) as u64)?;
+            // Like radix children, the next link is written before this link.
+            if next_link >= u64::from(self) {
+                return Err(index.corruption(format!(
+                    "link entry at {} has invalid next offset {}",
+                    usize::from(self),
+                    next_link
+                )));
+            }
             let next_link = LinkOffset::from_offset(Offset::from_disk(index, next_link)?, index)?;
             Ok((value, next_link))
         }
@@ -948,7 +1068,7 @@ impl KeyOffset {
                 )
                 .corruption()?;
             let start = usize::from(self) + TYPE_BYTES + vlq_len;
-            let end = start + key_len;
+            let end = start.saturating_add(key_len);
             index.verify_checksum(u64::from(self), end as u64 - u64::from(self))?;
             if end > index.buf.len() {
                 Err(index.range_error(start, end - start))
@@ -963,6 +1083,7 @@ impl KeyOffset {
     fn create(index: &mut Index, key: &[u8]) -> KeyOffset {
         debug_assert!(!key.is_empty());
         let len = index.dirty_keys.len();
+        index.dirty_key_bytes += key.len();
         index.dirty_keys.push(MemKey {
             key: Vec::from(key).into_boxed_slice(),
         });
@@ -973,7 +1094,9 @@ impl KeyOffset {
     /// No effect on an on-disk entry.
     fn mark_unused(self, index: &mut Index) {
         if self.is_dirty() {
-            index.dirty_keys[self.dirty_index()].mark_unused();
+            let entry = &mut index.dirty_keys[self.dirty_index()];
+            index.dirty_key_bytes -= entry.key.len();
+            entry.mark_unused();
         }
     }
 }
@@ -995,7 +1118,28 @@ impl ExtKeyOffset {
         self,
         index: &Index,
     ) -> crate::Result<(&[u8], Option<usize>)> {
-        let (start, len, entry_size) = if self.is_dirty() {
+        let (start, len, entry_size) = self.start_len_and_entry_size_unchecked(index)?;
+        let key_buf = index.key_buf.as_ref();
+        let key_content = match key_buf.slice(start, len) {
+            Some(k) => k,
+            None => {
+                return Err(index.corruption(format!(
+                    "key buffer is invalid when reading referred keys at {}",
+                    start
+                )));
+            }
+        };
+        Ok((key_content, entry_size))
+    }
+
+    /// Start and length of the key in the key buffer, and key entry size.
+    /// Used internally.
+    #[inline]
+    fn start_len_and_entry_size_unchecked(
+        self,
+        index: &Index,
+    ) -> crate::Result<(u64, u64, Option<usize>)> {
+        let result = if self.is_dirty() {
             let e = &index.dirty_ext_keys[self.dirty_index()];
             (e.start, e.len, None)
         } else {
@@ -1017,17 +1161,7 @@ impl ExtKeyOffset {
                 .corruption()?;
             (start, len, Some(TYPE_BYTES + vlq_len1 + vlq_len2))
         };
-        let key_buf = index.key_buf.as_ref();
-        let key_content = match key_buf.slice(start, len) {
-            Some(k) => k,
-            None => {
-                return Err(index.corruption(format!(
-                    "key buffer is invalid when reading referred keys at {}",
-                    start
-                )));
-            }
-        };
-        Ok((key_content, entry_size))
+        Ok(result)
     }
 
     /// Create a new in-memory external key entry. The key cannot be empty.
@@ -1365,9 +1499,10 @@ impl MemKey {
             .read_vlq_at(offset + 1)
             .context(index.path(), "cannot read key_len in MemKey::read_from")
             .corruption()?;
+        let start = offset + TYPE_BYTES + len;
         let key = Vec::from(
-            buf.get(offset + TYPE_BYTES + len..offset + TYPE_BYTES + len + key_len)
-                .ok_or_else(|| index.range_error(offset + TYPE_BYTES + len, key_len))?,
+            buf.get(start..start.saturating_add(key_len))
+                .ok_or_else(|| index.range_error(start, key_len))?,
         )
         .into_boxed_slice();
         index.verify_checksum(offset as u64, (TYPE_BYTES + len + key_len) as u64)?;
@@ -1459,7 +1594,7 @@ impl MemRoot {
 
         let meta = index
             .buf()
-            .get(cur..cur + meta_len)
+            .get(cur..cur.saturating_add(meta_len))
             .ok_or_else(|| index.range_error(cur, meta_len))?;
         cur += meta_len;
 
@@ -1504,6 +1639,15 @@ impl MemChecksum {
                 .read_vlq_at(cur)
                 .context(index.path(), "cannot read previous_checksum_offset")
                 .corruption()?;
+            if previous_offset >= offset as u64 {
+                return Err(crate::Error::corruption(
+                    index.path(),
+                    format!(
+                        "invalid previous_checksum_offset {} at {}",
+                        previous_offset, offset
+                    ),
+                ));
+            }
             cur += vlq_len;
 
             let (chunk_size_logarithm, vlq_len): (u32, _) = index
@@ -1512,7 +1656,12 @@ impl MemChecksum {
                 .context(index.path(), "cannot read chunk_size_logarithm")
                 .corruption()?;
 
-            if chunk_size_logarithm > 31 {
+            let is_initial_checksum = start_offset == offset as u64;
+
+            // Checksum entries in a chain share the same chunk size.
+            if chunk_size_logarithm > 31
+                || (!is_initial_checksum && chunk_size_logarithm != result.chunk_size_logarithm)
+            {
                 return Err(crate::Error::corruption(
                     index.path(),
                     format!(
@@ -1526,8 +1675,6 @@ impl MemChecksum {
             let chunk_size = 1usize << chunk_size_logarithm;
             let chunk_needed = (offset + chunk_size - 1) >> chunk_size_logarithm;
 
-            let is_initial_checksum = start_offset == offset as u64;
-
             // Initialize our Self result in our first iteration.
             if is_initial_checksum {
                 result.set_chunk_size_logarithm(index.buf(), chunk_size_logarithm)?;
@@ -1558,7 +1705,10 @@ impl MemChecksum {
                 // since the "next" checksum (i.e. previous loop iteration) will
                 // have already written the complete chunk's hash.
                 if is_initial_checksum || !incomplete_chunk {
-                    result.xxhash_list[i] = (&index.buf()[cur..])
+                    result.xxhash_list[i] = index
+                        .buf()
+                        .get(cur..)
+                        .unwrap_or_default()
                         .read_u64::<LittleEndian>()
                         .context(index.path(), "cannot read xxhash for checksum")?;
                 }
@@ -1566,7 +1716,10 @@ impl MemChecksum {
             }
 
             // Check the checksum buffer itself.
-            let xx32_read = (&index.buf()[cur..])
+            let xx32_read = index
+                .buf()
+                .get(cur..)
+                .unwrap_or_default()
                 .read_u32::<LittleEndian>()
                 .context(index.path(), "cannot read xxhash32 for checksum")?;
             let xx32_self = xxhash32(&index.buf()[offset as usize..cur as usize]);
@@ -1601,6 +1754,7 @@ impl MemChecksum {
         file: &mut File,
         file_len: u64,
         append_buf: &[u8],
+        codec: Option<&dyn Codec>,
     ) -> io::Result<()> {
         let start_chunk_index = (self.end >> self.chunk_size_logarithm) as usize;
         let start_chunk_offset = (start_chunk_index as u64) << self.chunk_size_logarithm;
@@ -1647,6 +1801,9 @@ impl MemChecksum {
             let mut file_buf = vec![0; (file_len - start_chunk_offset) as usize];
             file.seek(SeekFrom::Start(start_chunk_offset))?;
             file.read_exact(&mut file_buf)?;
+            if let Some(codec) = codec {
+                codec.decode(start_chunk_offset, &mut file_buf);
+            }
             file_buf
         };
 
@@ -1793,6 +1950,26 @@ impl MemChecksum {
     fn is_enabled(&self) -> bool {
         self.end > 0
     }
+
+    /// Byte ranges of chunks that fail the check. Adjacent chunks are merged.
+    fn damaged_ranges(&self, buf: &[u8]) -> Vec<Range<u64>> {
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        if !self.is_enabled() {
+            return ranges;
+        }
+        for i in 0..self.xxhash_list.len() {
+            if self.check_chunk(buf, i) {
+                continue;
+            }
+            let start = (i as u64) << self.chunk_size_logarithm;
+            let end = (((i + 1) as u64) << self.chunk_size_logarithm).min(self.end);
+            match ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+        ranges
+    }
 }
 
 fn write_reversed_vlq(mut writer: impl Write, value: usize) -> io::Result<()> {
@@ -1991,7 +2168,7 @@ pub struct Index {
     // Options
     checksum_enabled: bool,
     checksum_max_chain_len: u32,
-    fsync: bool,
+    pub(crate) fsync: bool,
     write: Option<bool>,
 
     // Used by `clear_dirty`.
@@ -2004,12 +2181,22 @@ pub struct Index {
     dirty_links: Vec<MemLink>,
     dirty_keys: Vec<MemKey>,
     dirty_ext_keys: Vec<MemExtKey>,
+    // Sum of key lengths in `dirty_keys`. Used by `memory_used`.
+    dirty_key_bytes: usize,
 
     checksum: MemChecksum,
 
     // Additional buffer for external keys.
     // Log::sync needs write access to this field.
     pub(crate) key_buf: Arc<dyn ReadonlyBuffer + Send + Sync>,
+
+    // Transforms on-disk bytes.
+    codec: Option<Arc<dyn Codec>>,
+
+    // Skip lookups of missing keys. See `OpenOptions::bloom_filter`.
+    bloom: Option<BloomFilter>,
+    // Whether `bloom` differs from the one stored on disk.
+    bloom_changed: bool,
 }
 
 /// Abstraction of the "external key buffer".
@@ -2024,7 +2211,8 @@ pub trait ReadonlyBuffer {
 impl<T: AsRef<[u8]>> ReadonlyBuffer for T {
     #[inline]
     fn slice(&self, start: u64, len: u64) -> Option<&[u8]> {
-        self.as_ref().get(start as usize..(start + len) as usize)
+        let end = start.checked_add(len)?;
+        self.as_ref().get(start as usize..end as usize)
     }
 }
 
@@ -2033,7 +2221,7 @@ pub enum InsertKey<'a> {
     /// Embedded key.
     Embed(&'a [u8]),
 
-    /// Reference (`[start, end)`) to `key_buf`.
+    /// Reference (`(start, len)`) to `key_buf`.
     Reference((u64, u64)),
 }
 
@@ -2051,6 +2239,8 @@ pub struct OpenOptions {
     len: Option<u64>,
     write: Option<bool>,
     key_buf: Option<Arc<dyn ReadonlyBuffer + Send + Sync>>,
+    codec: Option<Arc<dyn Codec>>,
+    bloom_filter: Option<f64>,
 }
 
 impl OpenOptions {
@@ -2062,6 +2252,8 @@ impl OpenOptions {
     /// - no fsync
     /// - read root entry from the end of the file
     /// - open as read-write but fallback to read-only
+    /// - no codec
+    /// - no bloom filter
     pub fn new() -> OpenOptions {
         OpenOptions {
             checksum_max_chain_len: config::INDEX_CHECKSUM_MAX_CHAIN_LEN.load(Acquire),
@@ -2071,6 +2263,8 @@ impl OpenOptions {
             len: None,
             write: None,
             key_buf: None,
+            codec: None,
+            bloom_filter: None,
         }
     }
 
@@ -2086,6 +2280,12 @@ impl OpenOptions {
     }
 
     /// Set checksum chunk size as `1 << checksum_chunk_size_logarithm`.
+    ///
+    /// Chunks are verified lazily, when bytes in them are read for the first
+    /// time. [`Index::verify`] verifies all chunks.
+    ///
+    /// This only affects new index files. Existing index files keep the chunk
+    /// size they were created with.
     pub fn checksum_chunk_size_logarithm(
         &mut self,
         checksum_chunk_size_logarithm: u32,
@@ -2145,6 +2345,29 @@ impl OpenOptions {
         self
     }
 
+    /// Specify the codec used to transform on-disk bytes.
+    ///
+    /// With a codec, the index file is read into memory and decoded instead
+    /// of being mmapped. Checksums are calculated on the decoded bytes.
+    ///
+    /// See [`Codec`] for details.
+    pub fn codec(&mut self, codec: Option<Arc<dyn Codec>>) -> &mut Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Keep a bloom filter of keys with the given false positive rate.
+    ///
+    /// [`Index::get`] checks the filter before walking the radix tree, making
+    /// lookups of missing keys cheaper. The filter is stored in a separate
+    /// file next to the index on [`Index::flush`]. If that file is missing or
+    /// does not match the index, the filter is rebuilt by scanning all keys
+    /// at open time.
+    pub fn bloom_filter(&mut self, false_positive_rate: Option<f64>) -> &mut Self {
+        self.bloom_filter = false_positive_rate;
+        self
+    }
+
     /// Open the index file with given options.
     ///
     /// Driven by the "immutable by default" idea, together with append-only
@@ -2205,6 +2428,7 @@ impl OpenOptions {
                     }
                 }
             };
+            let bytes = utils::decode_bytes(bytes, self.codec.as_deref(), 0);
 
             let (dirty_radixes, clean_root, mut checksum) = if bytes.is_empty() {
                 // Empty file. Create root radix entry as an dirty entry, and
@@ -2228,7 +2452,7 @@ impl OpenOptions {
             let key_buf = self.key_buf.clone();
             let dirty_root = clean_root.clone();
 
-            let index = Index {
+            let mut index = Index {
                 file: Some(file),
                 buf: bytes,
                 path: path.to_path_buf(),
@@ -2246,8 +2470,15 @@ impl OpenOptions {
                 dirty_leafs: vec![],
                 dirty_keys: vec![],
                 dirty_ext_keys: vec![],
+                dirty_key_bytes: 0,
                 key_buf: key_buf.unwrap_or_else(|| Arc::new(&b""[..])),
+                codec: open_options.codec,
+                bloom: None,
+                bloom_changed: false,
             };
+            if let Some(rate) = open_options.bloom_filter {
+                index.load_bloom(rate);
+            }
 
             Ok(index)
         })();
@@ -2286,11 +2517,105 @@ impl OpenOptions {
                 dirty_leafs: vec![],
                 dirty_keys: vec![],
                 dirty_ext_keys: vec![],
+                dirty_key_bytes: 0,
                 key_buf: key_buf.unwrap_or_else(|| Arc::new(&b""[..])),
+                codec: self.codec.clone(),
+                bloom: self.bloom_filter.map(|rate| BloomFilter::new(0, rate)),
+                bloom_changed: false,
             })
         })();
         result.context("in index::OpenOptions::create_in_memory")
     }
+
+    /// Create an in-memory [`Index`] backed by the given bytes, which use the
+    /// same format as an index file on disk.
+    ///
+    /// Like [`OpenOptions::create_in_memory`], flushing the returned [`Index`]
+    /// does not write anything. This is useful to inspect index data that is
+    /// not stored in a file, or to drive the parser from arbitrary bytes.
+    pub fn open_from_bytes(&self, bytes: Bytes) -> crate::Result<Index> {
+        let result: crate::Result<_> = (|| {
+            let path = Path::new("");
+            let bytes = utils::decode_bytes(bytes, self.codec.as_deref(), 0);
+
+            let (dirty_radixes, clean_root, mut checksum) = if bytes.is_empty() {
+                let radix_offset = RadixOffset::from_dirty_index(0);
+                let meta = Default::default();
+                let root = MemRoot { radix_offset, meta };
+                (vec![MemRadix::default()], root, MemChecksum::default())
+            } else {
+                let (root, mut checksum) = read_root_checksum_at_end(path, &bytes, bytes.len())?;
+                if !self.checksum_enabled {
+                    checksum = MemChecksum::default();
+                }
+                (vec![], root, checksum)
+            };
+
+            checksum.set_chunk_size_logarithm(&bytes, self.checksum_chunk_size_logarithm)?;
+            let key_buf = self.key_buf.clone();
+            let dirty_root = clean_root.clone();
+
+            let mut index = Index {
+                file: None,
+                buf: bytes,
+                path: PathBuf::new(),
+                checksum_enabled: self.checksum_enabled,
+                checksum_max_chain_len: self.checksum_max_chain_len,
+                fsync: self.fsync,
+                write: self.write,
+                clean_root,
+                dirty_root,
+                checksum,
+                dirty_radixes,
+                dirty_links: vec![],
+                dirty_leafs: vec![],
+                dirty_keys: vec![],
+                dirty_ext_keys: vec![],
+                dirty_key_bytes: 0,
+                key_buf: key_buf.unwrap_or_else(|| Arc::new(&b""[..])),
+                codec: self.codec.clone(),
+                bloom: None,
+                bloom_changed: false,
+            };
+            if let Some(rate) = self.bloom_filter {
+                index.rebuild_bloom(rate);
+            }
+
+            Ok(index)
+        })();
+        result.context("in index::OpenOptions::open_from_bytes")
+    }
+}
+
+/// Path of the bloom filter file for the index at `path`.
+/// See [`OpenOptions::bloom_filter`].
+pub(crate) fn bloom_filter_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".bloom");
+    PathBuf::from(path)
+}
+
+/// Identify the index content. Used to tell whether a stored bloom filter
+/// matches the index.
+fn bloom_source(buf: &[u8]) -> (u64, u64) {
+    // The end of the index has the root entry and the checksum entry, which
+    // are different for different versions of the index.
+    let tail = &buf[buf.len().saturating_sub(64)..];
+    (buf.len() as u64, xxhash(tail))
+}
+
+/// Write the bloom filter for the index at `path`. Return `true` on success.
+///
+/// Errors are not fatal since the filter can be rebuilt.
+fn write_bloom(path: &Path, bloom: &BloomFilter, source: (u64, u64), fsync: bool) -> bool {
+    let path = bloom_filter_path(path);
+    match utils::atomic_write_plain(&path, &bloom.to_bytes(source), fsync) {
+        Ok(()) => true,
+        Err(err) => {
+            tracing::warn!("cannot write bloom filter: {}", err);
+            false
+        }
+    }
 }
 
 /// Load root and checksum from the logical end.
@@ -2314,17 +2639,21 @@ fn read_root_checksum_at_end(
     // Verify the header byte.
     check_type(&buf, 0, TYPE_HEAD)?;
 
-    if end < root_checksum_size as usize + vlq_size {
-        return Err(crate::Error::corruption(
-            path,
-            format!(
-                "data corrupted at {} (invalid size: {})",
-                end, root_checksum_size
-            ),
-        ));
-    }
-
-    let root_offset = end - root_checksum_size as usize - vlq_size;
+    let root_offset = match (root_checksum_size as usize)
+        .checked_add(vlq_size)
+        .and_then(|size| end.checked_sub(size))
+    {
+        Some(offset) => offset,
+        None => {
+            return Err(crate::Error::corruption(
+                path,
+                format!(
+                    "data corrupted at {} (invalid size: {})",
+                    end, root_checksum_size
+                ),
+            ));
+        }
+    };
     let (root, root_size) = MemRoot::read_from(&buf, root_offset as u64)?;
 
     let checksum = if root_offset + root_size + vlq_size == end {
@@ -2367,7 +2696,13 @@ impl fmt::Debug for OpenOptions {
             Some(ref _buf) => "Some(_)",
             None => "None",
         };
-        write!(f, "key_buf: {} }}", key_buf_desc)?;
+        write!(f, "key_buf: {}, ", key_buf_desc)?;
+        let codec_desc = match self.codec {
+            Some(ref _codec) => "Some(_)",
+            None => "None",
+        };
+        write!(f, "codec: {}, ", codec_desc)?;
+        write!(f, "bloom_filter: {:?} }}", self.bloom_filter)?;
         Ok(())
     }
 }
@@ -2388,7 +2723,7 @@ trait IndexBuf {
         self.corruption(format!(
             "byte range {}..{} is unavailable",
             start,
-            start + length
+            start.saturating_add(length)
         ))
     }
 
@@ -2461,7 +2796,7 @@ impl Index {
 
     pub(crate) fn try_clone_internal(&self, copy_dirty: bool) -> crate::Result<Index> {
         let file = match &self.file {
-            Some(f) => Some(f.duplicate().context(self.path(), "cannot duplicate")?),
+            Some(f) => Some(f.try_clone().context(self.path(), "cannot duplicate")?),
             None => None,
         };
 
@@ -2479,10 +2814,14 @@ impl Index {
                 checksum: self.checksum.clone(),
                 dirty_keys: self.dirty_keys.clone(),
                 dirty_ext_keys: self.dirty_ext_keys.clone(),
+                dirty_key_bytes: self.dirty_key_bytes,
                 dirty_leafs: self.dirty_leafs.clone(),
                 dirty_links: self.dirty_links.clone(),
                 dirty_radixes: self.dirty_radixes.clone(),
                 key_buf: self.key_buf.clone(),
+                codec: self.codec.clone(),
+                bloom: self.bloom.clone(),
+                bloom_changed: self.bloom_changed,
             }
         } else {
             Index {
@@ -2498,6 +2837,7 @@ impl Index {
                 checksum: self.checksum.clone(),
                 dirty_keys: Vec::new(),
                 dirty_ext_keys: Vec::new(),
+                dirty_key_bytes: 0,
                 dirty_leafs: Vec::new(),
                 dirty_links: Vec::new(),
                 dirty_radixes: if self.clean_root.radix_offset.is_dirty() {
@@ -2507,6 +2847,9 @@ impl Index {
                     Vec::new()
                 },
                 key_buf: self.key_buf.clone(),
+                codec: self.codec.clone(),
+                bloom: self.bloom.clone(),
+                bloom_changed: self.bloom_changed,
             }
         };
 
@@ -2551,6 +2894,22 @@ impl Index {
         self.dirty_links.clear();
         self.dirty_keys.clear();
         self.dirty_ext_keys.clear();
+        self.dirty_key_bytes = 0;
+    }
+
+    /// Approximate bytes used by in-memory entries. They are written and
+    /// released by [`Index::flush`] or [`Index::clear_dirty`].
+    ///
+    /// Memory mapped on-disk entries, and the bloom filter, are not counted.
+    ///
+    /// This is O(1) and cheap enough to call after every insertion.
+    pub fn memory_used(&self) -> usize {
+        self.dirty_radixes.len() * size_of::<MemRadix>()
+            + self.dirty_leafs.len() * size_of::<MemLeaf>()
+            + self.dirty_links.len() * size_of::<MemLink>()
+            + self.dirty_keys.len() * size_of::<MemKey>()
+            + self.dirty_key_bytes
+            + self.dirty_ext_keys.len() * size_of::<MemExtKey>()
     }
 
     /// Flush changes to disk.
@@ -2582,11 +2941,16 @@ impl Index {
     /// unless read-only was set at open time.
     pub fn flush(&mut self) -> crate::Result<u64> {
         let result: crate::Result<_> = (|| {
+            let op = op_span!(
+                "indexedlog::index::flush",
+                path = ?self.path,
+                bytes_written = tracing::field::Empty
+            );
             let span = debug_span!("Index::flush", path = self.path.to_string_lossy().as_ref());
             let _guard = span.enter();
 
             if self.write == Some(false) {
-                return Err(crate::Error::path(
+                return Err(crate::Error::at_path(
                     self.path(),
                     "cannot flush: Index opened with read-only mode",
                 ));
@@ -2608,7 +2972,9 @@ impl Index {
             let old_len = self.buf.len() as u64;
             let mut new_len = old_len;
             if self.dirty_root == self.clean_root && !self.dirty_root.radix_offset.is_dirty() {
-                // Nothing changed
+                // Nothing changed. The bloom filter might be rebuilt at open
+                // time. Write it so it won't be rebuilt next time.
+                self.write_bloom();
                 return Ok(new_len);
             }
 
@@ -2632,7 +2998,7 @@ impl Index {
                     // file, potentially recreating it. We haven't checked the
                     // new content, so it's not considered as "data corruption".
                     // TODO: Review this decision.
-                    let err = crate::Error::path(&path, message);
+                    let err = crate::Error::at_path(&path, message);
                     return Err(err);
                 }
 
@@ -2717,7 +3083,7 @@ impl Index {
                 let mut new_checksum = self.checksum.clone();
                 let checksum_len = if self.checksum_enabled {
                     new_checksum
-                        .update(&self.buf, lock.as_mut(), len, &buf)
+                        .update(&self.buf, lock.as_mut(), len, &buf, self.codec.as_deref())
                         .context(&path, "cannot read and update checksum")?;
                     // Optionally merge the checksum entry for optimization.
                     if self.checksum_max_chain_len > 0
@@ -2736,9 +3102,16 @@ impl Index {
                 lock.as_mut()
                     .seek(SeekFrom::Start(len))
                     .context(&path, "cannot seek")?;
+                if let Some(codec) = self.codec.as_deref() {
+                    codec.encode(len, &mut buf);
+                }
+                fail_point!("index::flush::mid_write", || {
+                    let _ = lock.as_mut().write_all(&buf[..buf.len() / 2]);
+                });
                 lock.as_mut()
                     .write_all(&buf)
                     .context(&path, "cannot write new data to index")?;
+                op.record("bytes_written", buf.len() as u64);
 
                 if self.fsync || config::get_global_fsync() {
                     lock.as_mut().sync_all().context(&path, "cannot sync")?;
@@ -2746,7 +3119,7 @@ impl Index {
 
                 // Remap and update root since length has changed
                 let bytes = mmap_bytes(lock.as_ref(), None).context(&path, "cannot mmap")?;
-                self.buf = bytes;
+                self.buf = utils::decode_bytes(bytes, self.codec.as_deref(), 0);
 
                 // 'path' should not have changed.
                 debug_assert_eq!(&self.path, &path);
@@ -2768,6 +3141,13 @@ impl Index {
                 debug_assert_eq!(&checksum.xxhash_list, &new_checksum.xxhash_list);
                 self.checksum = checksum;
                 self.clean_root = root;
+
+                // Write the bloom filter while locked, so it is not replaced
+                // by a filter of an older version of the index.
+                if let Some(bloom) = &self.bloom {
+                    let source = bloom_source(&self.buf);
+                    self.bloom_changed = !write_bloom(&path, bloom, source, self.fsync);
+                }
             }
 
             // Outside critical section
@@ -2785,12 +3165,76 @@ impl Index {
             .context(|| format!("  Index.path = {:?}", self.path))
     }
 
+    /// Load the bloom filter stored next to the index file. Rebuild it if
+    /// it does not match the index.
+    fn load_bloom(&mut self, false_positive_rate: f64) {
+        let source = bloom_source(&self.buf);
+        let bloom = fs::read(bloom_filter_path(&self.path))
+            .ok()
+            .and_then(|buf| BloomFilter::from_bytes(&buf, source, false_positive_rate));
+        match bloom {
+            Some(bloom) => {
+                self.bloom = Some(bloom);
+                self.bloom_changed = false;
+            }
+            None => self.rebuild_bloom(false_positive_rate),
+        }
+    }
+
+    /// Rebuild the bloom filter from all keys in the index.
+    ///
+    /// If keys cannot be read, disable the filter. Lookups will report the
+    /// error instead.
+    fn rebuild_bloom(&mut self, false_positive_rate: f64) {
+        let hashes: crate::Result<Vec<u64>> = (|| {
+            let mut hashes = Vec::new();
+            for item in self.range(..)? {
+                let (key, _link_offset) = item?;
+                hashes.push(BloomFilter::hash(&key));
+            }
+            Ok(hashes)
+        })();
+        self.bloom = match hashes {
+            Ok(hashes) => Some(BloomFilter::from_hashes(&hashes, false_positive_rate)),
+            Err(_) => None,
+        };
+        self.bloom_changed = true;
+    }
+
+    /// Add a key to the bloom filter. Grow the filter if it is full.
+    fn insert_bloom(&mut self, key: &[u8]) {
+        if let Some(bloom) = &self.bloom {
+            if bloom.is_full() {
+                self.rebuild_bloom(bloom.false_positive_rate());
+            }
+        }
+        if let Some(bloom) = self.bloom.as_mut() {
+            bloom.insert(key);
+            self.bloom_changed = true;
+        }
+    }
+
+    /// Write the bloom filter next to the index file if it has changed.
+    fn write_bloom(&mut self) {
+        if let Some(bloom) = &self.bloom {
+            if self.bloom_changed && self.file.is_some() {
+                let source = bloom_source(&self.buf);
+                self.bloom_changed = !write_bloom(&self.path, bloom, source, self.fsync);
+            }
+        }
+    }
+
     /// Lookup by `key`. Return [`LinkOffset`].
     ///
     /// To test if the key exists or not, use [Offset::is_null].
     /// To obtain all values, use [`LinkOffset::values`].
     pub fn get<K: AsRef<[u8]>>(&self, key: &K) -> crate::Result<LinkOffset> {
         let result: crate::Result<_> = (|| {
+            if let Some(bloom) = &self.bloom {
+                if !bloom.contains(key.as_ref()) {
+                    return Ok(LinkOffset::default());
+                }
+            }
             let mut offset: Offset = self.dirty_root.radix_offset.into();
             let mut iter = Base16Iter::from_base256(key);
 
@@ -2833,6 +3277,7 @@ impl Index {
 
     /// Scan entries which match the given prefix in base16 form.
     /// Return [`RangeIter`] which allows accesses to keys and values.
+    /// The iterator is double-ended. Use `rev()` to get larger keys first.
     pub fn scan_prefix_base16(
         &self,
         mut base16: impl Iterator<Item = u8>,
@@ -2892,6 +3337,7 @@ impl Index {
 
     /// Scan entries which match the given prefix in base256 form.
     /// Return [`RangeIter`] which allows accesses to keys and values.
+    /// The iterator is double-ended. Use `rev()` to get larger keys first.
     pub fn scan_prefix<B: AsRef<[u8]>>(&self, prefix: B) -> crate::Result<RangeIter> {
         self.scan_prefix_base16(Base16Iter::from_base256(&prefix))
             .context(|| format!("in Index::scan_prefix({:?})", prefix.as_ref()))
@@ -2900,6 +3346,7 @@ impl Index {
 
     /// Scan entries which match the given prefix in hex form.
     /// Return [`RangeIter`] which allows accesses to keys and values.
+    /// The iterator is double-ended. Use `rev()` to get larger keys first.
     pub fn scan_prefix_hex<B: AsRef<[u8]>>(&self, prefix: B) -> crate::Result<RangeIter> {
         // Invalid hex chars will be caught by `radix.child`
         let base16 = prefix.as_ref().iter().cloned().map(single_hex_to_base16);
@@ -2943,6 +3390,202 @@ impl Index {
             .context(|| format!("  Index.path = {:?}", self.path))
     }
 
+    /// Estimate the fraction of keys that are less than `key`, from 0.0 to
+    /// 1.0.
+    ///
+    /// Sizes of subtrees are estimated by visiting a bounded number of
+    /// entries, so this is fast but inexact. The estimation works best if
+    /// keys are evenly distributed, like hashes.
+    pub fn estimate_rank<K: AsRef<[u8]>>(&self, key: &K) -> crate::Result<f64> {
+        let result: crate::Result<_> = (|| {
+            let key = key.as_ref();
+            let base16: Vec<u8> = Base16Iter::from_base256(&key).collect();
+            let root = self.dirty_root.radix_offset.into();
+            let (below, total) = self.estimate_rank_in(root, key, &base16, ESTIMATE_BUDGET)?;
+            if total > 0.0 {
+                Ok((below / total).clamp(0.0, 1.0))
+            } else {
+                Ok(0.0)
+            }
+        })();
+
+        result
+            .context(|| format!("in Index::estimate_rank({:?})", key.as_ref()))
+            .context(|| format!("  Index.path = {:?}", self.path))
+    }
+
+    /// Find a key at approximately `p` (0.0 to 1.0) of all keys in order.
+    /// `nth_key_approx(0.0)` is the smallest key.
+    ///
+    /// See [`Index::estimate_rank`] for the accuracy. Return `None` if the
+    /// index is empty.
+    pub fn nth_key_approx(&self, p: f64) -> crate::Result<Option<Cow<'_, [u8]>>> {
+        let result: crate::Result<_> = (|| {
+            let mut offset: Offset = self.dirty_root.radix_offset.into();
+            let mut prefix: Vec<u8> = Vec::new();
+            let mut p = if p.is_nan() { 0.0 } else { p.clamp(0.0, 1.0) };
+            loop {
+                match offset.to_typed(self)? {
+                    TypedOffset::Radix(radix) => {
+                        let has_link = !radix.link_offset(self)?.is_null();
+                        let children = self.radix_children(radix)?;
+                        let budget = ESTIMATE_BUDGET / children.len().max(1);
+                        let mut sizes = Vec::with_capacity(children.len());
+                        for &(_, child) in &children {
+                            sizes.push(self.estimate_key_count(child, budget)?);
+                        }
+                        let link_count = if has_link { 1.0 } else { 0.0 };
+                        let total: f64 = link_count + sizes.iter().sum::<f64>();
+                        if total == 0.0 {
+                            return Ok(None);
+                        }
+                        let mut target = p * total;
+                        if has_link && target < 1.0 {
+                            // The key ends at this radix entry.
+                            return Ok(Some(Cow::Owned(base16_to_base256(&prefix))));
+                        }
+                        target -= link_count;
+                        // Pick the child containing the target. Fallback to
+                        // the last non-empty child for rounding errors.
+                        let mut picked = None;
+                        for (i, &size) in sizes.iter().enumerate() {
+                            if size > 0.0 {
+                                picked = Some((i, 1.0));
+                                if target < size {
+                                    picked = Some((i, (target / size).max(0.0)));
+                                    break;
+                                }
+                            }
+                            target -= size;
+                        }
+                        let i = match picked {
+                            Some((i, child_p)) => {
+                                p = child_p;
+                                i
+                            }
+                            None => return Ok(None),
+                        };
+                        prefix.push(children[i].0);
+                        offset = children[i].1;
+                    }
+                    TypedOffset::Leaf(leaf) => {
+                        let (key, link_offset) = leaf.key_and_link_offset(self)?;
+                        if link_offset.is_null() {
+                            return Ok(None);
+                        }
+                        return Ok(Some(Cow::Borrowed(key)));
+                    }
+                    _ => return Err(self.corruption("unexpected type during key estimation")),
+                }
+            }
+        })();
+
+        result
+            .context(|| format!("in Index::nth_key_approx({})", p))
+            .context(|| format!("  Index.path = {:?}", self.path))
+    }
+
+    /// Non-null children of a radix entry, with their positions.
+    fn radix_children(&self, radix: RadixOffset) -> crate::Result<Vec<(u8, Offset)>> {
+        let mut children = Vec::new();
+        for i in 0..16 {
+            let child = radix.child(self, i)?;
+            if !child.is_null() {
+                children.push((i, child));
+            }
+        }
+        Ok(children)
+    }
+
+    /// Estimate the number of keys in a subtree, visiting about `budget`
+    /// entries. Unvisited subtrees are assumed to have one key per child.
+    fn estimate_key_count(&self, offset: Offset, budget: usize) -> crate::Result<f64> {
+        match offset.to_typed(self)? {
+            TypedOffset::Radix(radix) => {
+                let mut count = if radix.link_offset(self)?.is_null() {
+                    0.0
+                } else {
+                    1.0
+                };
+                let children = self.radix_children(radix)?;
+                if budget == 0 {
+                    return Ok(count + children.len() as f64);
+                }
+                let budget = (budget - 1) / children.len().max(1);
+                for (_, child) in children {
+                    count += self.estimate_key_count(child, budget)?;
+                }
+                Ok(count)
+            }
+            TypedOffset::Leaf(leaf) => {
+                let (_, link_offset) = leaf.key_and_link_offset(self)?;
+                Ok(if link_offset.is_null() { 0.0 } else { 1.0 })
+            }
+            _ => Err(self.corruption("unexpected type during key estimation")),
+        }
+    }
+
+    /// Estimate `(keys less than key, all keys)` in a subtree. `base16` is
+    /// `key` in base16.
+    fn estimate_rank_in(
+        &self,
+        offset: Offset,
+        key: &[u8],
+        base16: &[u8],
+        budget: usize,
+    ) -> crate::Result<(f64, f64)> {
+        let mut depth = 0;
+        let mut offset = offset;
+        let mut below = 0.0;
+        let mut total = 0.0;
+        loop {
+            match offset.to_typed(self)? {
+                TypedOffset::Radix(radix) => {
+                    let nibble = base16.get(depth).cloned();
+                    if !radix.link_offset(self)?.is_null() {
+                        total += 1.0;
+                        if nibble.is_some() {
+                            // The key of the link is a prefix of `key`.
+                            below += 1.0;
+                        }
+                    }
+                    let children = self.radix_children(radix)?;
+                    let budget = budget.saturating_sub(1) / children.len().max(1);
+                    let mut next = None;
+                    for (i, child) in children {
+                        match nibble {
+                            Some(n) if n == i => next = Some(child),
+                            Some(n) if i < n => {
+                                let size = self.estimate_key_count(child, budget)?;
+                                below += size;
+                                total += size;
+                            }
+                            _ => total += self.estimate_key_count(child, budget)?,
+                        }
+                    }
+                    match next {
+                        Some(child) => {
+                            offset = child;
+                            depth += 1;
+                        }
+                        None => return Ok((below, total)),
+                    }
+                }
+                TypedOffset::Leaf(leaf) => {
+                    let (stored_key, link_offset) = leaf.key_and_link_offset(self)?;
+                    if !link_offset.is_null() {
+                        total += 1.0;
+                        if stored_key < key {
+                            below += 1.0;
+                        }
+                    }
+                    return Ok((below, total));
+                }
+                _ => return Err(self.corruption("unexpected type during key estimation")),
+            }
+        }
+    }
+
     /// Insert a key-value pair. The value will be the head of the linked list.
     /// That is, `get(key).values().first()` will return the newly inserted
     /// value.
@@ -3005,6 +3648,9 @@ impl Index {
                 (detached_key, Some((start, len)))
             }
         };
+        if let InsertValue::Prepend(_) | InsertValue::PrependReplace(..) = value {
+            self.insert_bloom(key);
+        }
         let mut iter = Base16Iter::from_base256(&key);
 
         let mut last_radix = RadixOffset::default();
@@ -3150,6 +3796,87 @@ impl Index {
         self.verify_checksum(0, self.checksum.end)
     }
 
+    /// Byte ranges of the on-disk buffer that fail the checksum check.
+    ///
+    /// Ranges are aligned to checksum chunks. See
+    /// [`OpenOptions::checksum_chunk_size_logarithm`]. Return an empty list if
+    /// checksum is disabled.
+    pub fn damaged_ranges(&self) -> Vec<Range<u64>> {
+        self.checksum.damaged_ranges(&self.buf)
+    }
+
+    /// Read keys and values that are not affected by checksum errors.
+    ///
+    /// A subtree that cannot be read completely is skipped, and its key
+    /// prefix is recorded as damaged. Used to rebuild only the damaged
+    /// part of an index.
+    pub(crate) fn salvage(&self) -> Salvage {
+        let mut salvage = Salvage::default();
+        let root: Offset = self.dirty_root.radix_offset.into();
+        self.salvage_subtree(root, &mut Vec::new(), &mut salvage);
+        salvage
+    }
+
+    /// Salvage a subtree. On error, drop what was read from the subtree and
+    /// mark `prefix` as damaged.
+    fn salvage_subtree(&self, offset: Offset, prefix: &mut Vec<u8>, salvage: &mut Salvage) {
+        let entries_len = salvage.entries.len();
+        let damaged_len = salvage.damaged_prefixes.len();
+        if self
+            .salvage_subtree_unchecked(offset, prefix, salvage)
+            .is_err()
+        {
+            salvage.entries.truncate(entries_len);
+            salvage.damaged_prefixes.truncate(damaged_len);
+            salvage.damaged_prefixes.push(prefix.clone());
+        }
+    }
+
+    fn salvage_subtree_unchecked(
+        &self,
+        offset: Offset,
+        prefix: &mut Vec<u8>,
+        salvage: &mut Salvage,
+    ) -> crate::Result<()> {
+        let values = |link_offset: LinkOffset| -> crate::Result<Vec<u64>> {
+            let mut values = link_offset
+                .values(self)
+                .collect::<crate::Result<Vec<u64>>>()?;
+            values.reverse();
+            Ok(values)
+        };
+        match offset.to_typed(self)? {
+            TypedOffset::Radix(radix) => {
+                let values = values(radix.link_offset(self)?)?;
+                if !values.is_empty() {
+                    let key = base16_to_base256(prefix).into_boxed_slice();
+                    salvage.entries.push((SalvagedKey::Embed(key), values));
+                }
+                for (i, child) in self.radix_children(radix)? {
+                    prefix.push(i);
+                    self.salvage_subtree(child, prefix, salvage);
+                    prefix.pop();
+                }
+            }
+            TypedOffset::Leaf(leaf) => {
+                let (key_content, link_offset) = leaf.key_and_link_offset(self)?;
+                let values = values(link_offset)?;
+                if !values.is_empty() {
+                    let key = match leaf.key_offset(self)?.to_typed(self)? {
+                        TypedOffset::ExtKey(key) => {
+                            let (start, len, _) = key.start_len_and_entry_size_unchecked(self)?;
+                            SalvagedKey::Reference(start, len)
+                        }
+                        _ => SalvagedKey::Embed(key_content.into()),
+                    };
+                    salvage.entries.push((key, values));
+                }
+            }
+            _ => return Err(self.corruption("unexpected type during salvage")),
+        }
+        Ok(())
+    }
+
     // Internal function used by [`Index::range`].
     // Calculate the [`IterState`] stack used by [`RangeIter`].
     // `side` is the side of the `bound`, starting side of the iteration,
@@ -3351,6 +4078,50 @@ impl Index {
     }
 }
 
+/// Keys and values read from an [`Index`] with checksum errors.
+/// See [`Index::salvage`].
+#[derive(Default)]
+pub(crate) struct Salvage {
+    /// Readable keys and their values, oldest value first.
+    pub(crate) entries: Vec<(SalvagedKey, Vec<u64>)>,
+
+    /// Base16 prefixes of keys in subtrees that cannot be read.
+    pub(crate) damaged_prefixes: Vec<Vec<u8>>,
+}
+
+/// A key read by [`Index::salvage`].
+pub(crate) enum SalvagedKey {
+    /// The key content.
+    Embed(Box<[u8]>),
+
+    /// Start and length of the key in the key buffer.
+    Reference(u64, u64),
+}
+
+impl Salvage {
+    /// Test if `key` is in a damaged subtree.
+    pub(crate) fn is_damaged(&self, key: &[u8]) -> bool {
+        self.damaged_prefixes.iter().any(|prefix| {
+            let base16 = Base16Iter::from_base256(&key);
+            prefix.len() <= base16.len() && base16.take(prefix.len()).eq(prefix.iter().copied())
+        })
+    }
+
+    /// Insert the salvaged entries to `index`.
+    pub(crate) fn insert_into(&self, index: &mut Index) -> crate::Result<()> {
+        for (key, values) in &self.entries {
+            for &value in values {
+                let key = match key {
+                    SalvagedKey::Embed(key) => InsertKey::Embed(key),
+                    SalvagedKey::Reference(start, len) => InsertKey::Reference((*start, *len)),
+                };
+                index.insert_advanced(key, InsertValue::Prepend(value))?;
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Specify value to insert. Used by `insert_advanced`.
 #[derive(Copy, Clone)]
 pub enum InsertValue {
@@ -4370,6 +5141,53 @@ Disk[201]: Checksum { start: 126, end: 201, chunk_size_logarithm: 4, checksums.l
         }
     }
 
+    #[test]
+    fn test_salvage() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a");
+        let opts = open_opts().checksum_chunk_size_logarithm(4).clone();
+        let mut index = opts.open(&path).unwrap();
+        for i in 0..=255u8 {
+            index.insert(&[i], i as u64).unwrap();
+            index.insert(&[i], i as u64 + 256).unwrap();
+        }
+        index.flush().unwrap();
+        assert!(index.damaged_ranges().is_empty());
+        let salvage = index.salvage();
+        assert!(salvage.damaged_prefixes.is_empty());
+        assert_eq!(salvage.entries.len(), 256);
+        drop(index);
+
+        let mut bytes = fs::read(&path).unwrap();
+        let offset = bytes.len() / 2;
+        bytes[offset] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let index = opts.open(&path).unwrap();
+        let chunk_start = offset as u64 & !15;
+        let damaged_ranges = index.damaged_ranges();
+        assert_eq!(damaged_ranges.len(), 1);
+        assert_eq!(damaged_ranges[0], chunk_start..chunk_start + 16);
+
+        // Keys are either salvaged with all values, or in damaged subtrees.
+        let salvage = index.salvage();
+        assert!(!salvage.damaged_prefixes.is_empty());
+        let mut new_index = open_opts().create_in_memory().unwrap();
+        salvage.insert_into(&mut new_index).unwrap();
+        for i in 0..=255u8 {
+            let values: Vec<u64> = new_index
+                .get(&[i])
+                .unwrap()
+                .values(&new_index)
+                .map(|v| v.unwrap())
+                .collect();
+            if salvage.is_damaged(&[i]) {
+                assert!(values.is_empty());
+            } else {
+                assert_eq!(values, [i as u64 + 256, i as u64]);
+            }
+        }
+    }
+
     #[test]
     fn test_checksum_toggle() {
         let dir = tempdir().unwrap();
@@ -4711,6 +5529,171 @@ Disk[410]: Root { radix: Disk[402] }
         );
     }
 
+    #[test]
+    fn test_estimate_rank() {
+        let mut index = in_memory_index();
+        assert_eq!(index.estimate_rank(b"x").unwrap(), 0.0);
+        assert!(index.nth_key_approx(0.5).unwrap().is_none());
+
+        // Hash-like keys.
+        let mut keys: Vec<[u8; 8]> = (0..20000u64)
+            .map(|i| i.wrapping_mul(0x9e3779b97f4a7c15).to_be_bytes())
+            .collect();
+        for key in &keys {
+            index.insert(key, 1).unwrap();
+        }
+        index.flush().unwrap();
+        keys.sort_unstable();
+        let true_rank =
+            |key: &[u8]| keys.partition_point(|k| &k[..] < key) as f64 / keys.len() as f64;
+        for &p in &[0.0, 0.1, 0.25, 0.5, 0.75, 0.9, 1.0] {
+            let key = keys[((keys.len() - 1) as f64 * p) as usize];
+            let rank = index.estimate_rank(&key).unwrap();
+            assert!((rank - p).abs() < 0.02, "rank {} != {}", rank, p);
+            let key = index.nth_key_approx(p).unwrap().unwrap();
+            assert!((true_rank(&key) - p).abs() < 0.02);
+        }
+        assert_eq!(index.nth_key_approx(0.0).unwrap().unwrap(), &keys[0][..]);
+        assert_eq!(
+            index.nth_key_approx(1.0).unwrap().unwrap(),
+            &keys[keys.len() - 1][..]
+        );
+
+        // Keys ending at radix entries, and removed keys.
+        let mut index = in_memory_index();
+        for key in [&b"a"[..], b"ab", b"abc", b"b", b"c"] {
+            index.insert(&key, 1).unwrap();
+        }
+        index.remove(b"b").unwrap();
+        assert_eq!(index.estimate_rank(b"a").unwrap(), 0.0);
+        assert_eq!(index.estimate_rank(b"abc").unwrap(), 0.5);
+        assert_eq!(index.estimate_rank(b"z").unwrap(), 1.0);
+        assert_eq!(index.nth_key_approx(0.0).unwrap().unwrap(), &b"a"[..]);
+        assert_eq!(index.nth_key_approx(0.3).unwrap().unwrap(), &b"ab"[..]);
+        assert_eq!(index.nth_key_approx(1.0).unwrap().unwrap(), &b"c"[..]);
+    }
+
+    #[test]
+    fn test_bloom_filter() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a");
+        let bloom_path = bloom_filter_path(&path);
+        let mut opts = open_opts();
+        opts.bloom_filter(Some(0.01));
+
+        let mut index = opts.open(&path).unwrap();
+        for i in 0..100u64 {
+            index.insert(&i.to_be_bytes(), i).unwrap();
+        }
+        let present = |index: &Index, i: u64| !index.get(&i.to_be_bytes()).unwrap().is_null();
+        assert!((0..100).all(|i| present(&index, i)));
+        assert!(!present(&index, 100));
+        assert!(!bloom_path.exists());
+
+        // Flush writes the filter. Reopen loads it without rebuilding.
+        let len = index.flush().unwrap();
+        assert!(bloom_path.exists());
+        let index = opts.open(&path).unwrap();
+        assert!(!index.bloom_changed);
+        assert!((0..100).all(|i| present(&index, i)));
+        assert!(!present(&index, 100));
+        let stored = index.bloom.as_ref().unwrap();
+        assert!(!stored.contains(&100u64.to_be_bytes()));
+
+        // Keys written without the filter are still found. The stale filter
+        // is rebuilt.
+        let mut index = open_opts().open(&path).unwrap();
+        index.insert(&100u64.to_be_bytes(), 100).unwrap();
+        index.flush().unwrap();
+        let mut index = opts.open(&path).unwrap();
+        assert!(index.bloom_changed);
+        assert!(present(&index, 100));
+        // Flush without changes writes the rebuilt filter.
+        index.flush().unwrap();
+        let index = opts.open(&path).unwrap();
+        assert!(!index.bloom_changed);
+        assert!(present(&index, 100));
+
+        // A filter for another version of the index is not used.
+        let index = opts.clone().logical_len(Some(len)).open(&path).unwrap();
+        assert!(index.bloom_changed);
+        assert!(!present(&index, 100));
+
+        // A corrupted filter is rebuilt.
+        fs::write(&bloom_path, b"x").unwrap();
+        let index = opts.open(&path).unwrap();
+        assert!(index.bloom_changed);
+        assert!((0..101).all(|i| present(&index, i)));
+
+        // The filter grows with more keys.
+        let mut index = opts.create_in_memory().unwrap();
+        for i in 0..5000u64 {
+            index.insert(&i.to_be_bytes(), i).unwrap();
+        }
+        assert!(!index.bloom.as_ref().unwrap().is_full());
+        assert!((0..5000).all(|i| present(&index, i)));
+    }
+
+    #[test]
+    fn test_open_from_bytes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a");
+        let mut index = open_opts().open(&path).unwrap();
+        index.insert(&"foo", 1).unwrap();
+        index.insert(&"bar", 2).unwrap();
+        index.set_meta([42]);
+        index.flush().unwrap();
+
+        let bytes = Bytes::from(fs::read(&path).unwrap());
+        let mut index = open_opts().open_from_bytes(bytes.clone()).unwrap();
+        assert_eq!(index.get_meta(), [42]);
+        assert_eq!(
+            index.get(&"foo").unwrap().value_and_next(&index).unwrap().0,
+            1
+        );
+        assert_eq!(
+            index.get(&"bar").unwrap().value_and_next(&index).unwrap().0,
+            2
+        );
+        assert!(index.get(&"baz").unwrap().is_null());
+        index.verify().unwrap();
+
+        // Changes stay in memory.
+        index.insert(&"baz", 3).unwrap();
+        index.flush().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), bytes.as_ref());
+
+        // Empty bytes are an empty index.
+        let index = open_opts().open_from_bytes(Bytes::new()).unwrap();
+        assert!(index.get(&"foo").unwrap().is_null());
+
+        // Corrupted bytes are reported as errors.
+        assert!(open_opts()
+            .open_from_bytes(Bytes::from_static(b"x"))
+            .is_err());
+        let truncated = bytes.slice(..bytes.len() - 1);
+        assert!(open_opts().open_from_bytes(truncated).is_err());
+        let huge_size = b"\x00\x01\xff\x00\xff\xff\xff\xff\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff";
+        assert!(open_opts()
+            .open_from_bytes(Bytes::from_static(huge_size))
+            .is_err());
+
+        // Without checksum, corrupted bytes do not cause panics or loops.
+        let mut opts = open_opts();
+        opts.checksum_enabled(false);
+        for i in 0..bytes.len() {
+            for byte in [0, 1, 0x7f, 0xff] {
+                let mut corrupted = bytes.to_vec();
+                corrupted[i] = byte;
+                if let Ok(index) = opts.open_from_bytes(corrupted.into()) {
+                    for (_key, link) in index.range(..).into_iter().flatten().flatten().take(10) {
+                        link.values(&index).take(10).for_each(drop);
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_clear_dirty() {
         let dir = tempdir().unwrap();
@@ -4736,6 +5719,26 @@ Disk[410]: Root { radix: Disk[402] }
         assert!(index.get(&"bar").unwrap().is_null());
     }
 
+    #[test]
+    fn test_memory_used() {
+        let dir = tempdir().unwrap();
+        let mut index = open_opts().open(dir.path().join("a")).unwrap();
+        let key_bytes =
+            |index: &Index| -> usize { index.dirty_keys.iter().map(|k| k.key.len()).sum() };
+
+        for i in 0..100u64 {
+            index.insert(&format!("key{}", i), i).unwrap();
+        }
+        index.remove("key4").unwrap();
+        index.remove_prefix("key1").unwrap();
+        assert_eq!(index.dirty_key_bytes, key_bytes(&index));
+        assert!(index.memory_used() > index.dirty_key_bytes);
+
+        index.flush().unwrap();
+        assert_eq!(index.dirty_key_bytes, 0);
+        assert_eq!(index.memory_used(), 0);
+    }
+
     #[test]
     fn test_meta_only_flush() {
         let dir = tempdir().unwrap();
@@ -0,0 +1,51 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Spans for the `tracing` feature. See `op_span!`.
+
+use std::time::Instant;
+
+use tracing::field::Value;
+use tracing::span::EnteredSpan;
+use tracing::Span;
+
+/// An entered span that records how long it was entered as `duration_us`.
+pub(crate) struct OpSpan {
+    span: EnteredSpan,
+    start: Option<Instant>,
+}
+
+impl OpSpan {
+    pub(crate) fn new(span: Span) -> Self {
+        // Avoid reading the clock if nobody is interested.
+        let start = if span.is_disabled() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+        Self {
+            span: span.entered(),
+            start,
+        }
+    }
+
+    /// Record a field declared by `op_span!`, like bytes written.
+    pub(crate) fn record(&self, field: &str, value: impl Value) {
+        if self.start.is_some() {
+            self.span.record(field, value);
+        }
+    }
+}
+
+impl Drop for OpSpan {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let duration = start.elapsed().as_micros() as u64;
+            self.span.record("duration_us", duration);
+        }
+    }
+}
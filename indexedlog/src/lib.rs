@@ -14,22 +14,45 @@
 //!
 //! See [log::Log] for the main structure. The index can be used independently.
 //! See [index::Index] for details.
+//!
+//! With the `tracing` feature, expensive operations like syncing, flushing
+//! indexes, rebuilding indexes, taking locks and repairing emit `info`
+//! spans with fields like the path, bytes written and `duration_us`.
+//!
+//! The `std-fs` feature, enabled by default, memory-maps files and uses file
+//! locks. Without it, files are read into memory and locks are no-ops. That
+//! suits single-process environments without those APIs, like
+//! `wasm32-unknown-unknown` using in-memory logs and indexes.
+//!
+//! The `stress` feature adds the `stress` module, which runs reader, writer
+//! and repairer processes against a log and checks invariants.
 
 #[macro_use]
 mod macros;
 
 pub mod base16;
+mod bloom;
+pub mod codec;
 pub mod config;
 mod errors;
+#[cfg(feature = "failpoints")]
+pub mod failpoint;
+#[cfg(not(feature = "failpoints"))]
+mod failpoint;
+mod fs_ext;
 pub mod index;
+mod instrument;
 pub mod lock;
 pub mod log;
 pub mod multi;
 mod repair;
 pub mod rotate;
+#[cfg(feature = "stress")]
+pub mod stress;
 pub mod utils;
 
 pub use errors::Error;
+pub use errors::ErrorKind;
 pub use errors::Result;
 pub use repair::DefaultOpenOptions;
 pub use repair::OpenWithRepair;
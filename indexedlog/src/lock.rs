@@ -5,17 +5,34 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+//! Advisory file and directory locks that work across processes.
+//!
+//! [`ScopedDirLock`] is the lock used by [`Log`](crate::log::Log) and
+//! [`RotateLog`](crate::rotate::RotateLog) to serialize writes. Applications
+//! can use it to coordinate their own operations across multiple logs.
+//!
+//! Locks are exclusive or shared, like `flock`. Open logs, including
+//! read-only ones, hold a shared lock on a separate lock file, so
+//! destructive operations like repair can tell whether readers are alive
+//! without blocking each other or normal writes.
+
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::path::Path;
 use std::path::PathBuf;
-
-use fs2::FileExt;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
 use crate::errors::IoResultExt;
+use crate::fs_ext::lock_contended_error;
+use crate::fs_ext::FileExt;
 use crate::utils;
 
+// Upper bound of the interval between attempts, for locks with a timeout.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// RAII style file locking.
 pub struct ScopedFileLock<'a> {
     file: &'a mut File,
@@ -24,9 +41,9 @@ pub struct ScopedFileLock<'a> {
 impl<'a> ScopedFileLock<'a> {
     pub fn new(file: &'a mut File, exclusive: bool) -> io::Result<Self> {
         if exclusive {
-            file.lock_exclusive()?;
+            FileExt::lock_exclusive(file)?;
         } else {
-            file.lock_shared()?;
+            FileExt::lock_shared(file)?;
         }
         Ok(ScopedFileLock { file })
     }
@@ -46,7 +63,7 @@ impl<'a> AsMut<File> for ScopedFileLock<'a> {
 
 impl<'a> Drop for ScopedFileLock<'a> {
     fn drop(&mut self) {
-        self.file.unlock().expect("unlock");
+        FileExt::unlock(&*self.file).expect("unlock");
     }
 }
 
@@ -54,12 +71,20 @@ impl<'a> Drop for ScopedFileLock<'a> {
 pub struct ScopedDirLock {
     file: File,
     path: PathBuf,
+    waited: bool,
 }
 
-/// Options for directory locking.
+/// Options for directory locking. See [`ScopedDirLock::new_with_options`].
 pub struct DirLockOptions {
+    /// Exclusive lock, or shared lock.
     pub exclusive: bool,
+
+    /// Return an error instead of waiting if the lock is not available.
     pub non_blocking: bool,
+
+    /// Name of the lock file in the directory. An empty name locks the
+    /// directory itself, which is the lock used by [`Log`](crate::log::Log)
+    /// writes.
     pub file_name: &'static str,
 }
 
@@ -83,6 +108,24 @@ pub(crate) static READER_LOCK_OPTS: DirLockOptions = DirLockOptions {
     file_name: "rlock",
 };
 
+/// Take the shared reader lock for a read-only open.
+///
+/// Read-only opens do not write to the directory. So this does not create
+/// the lock file, and returns `None` if the lock file does not exist or
+/// cannot be opened (ex. no permission).
+pub(crate) fn read_only_reader_lock(dir: &Path) -> Option<ScopedDirLock> {
+    if !dir.join(READER_LOCK_OPTS.file_name).exists() {
+        return None;
+    }
+    match ScopedDirLock::new_with_options(dir, &READER_LOCK_OPTS) {
+        Ok(lock) => Some(lock),
+        Err(err) => {
+            tracing::debug!("read-only open without reader lock: {}", err);
+            None
+        }
+    }
+}
+
 impl ScopedDirLock {
     /// Lock the given directory with default options (exclusive, blocking).
     pub fn new(path: &Path) -> crate::Result<Self> {
@@ -104,6 +147,27 @@ impl ScopedDirLock {
     ///   for the (dir, file_name); if false, allow other non-exclusive locks
     ///   to co-exist.
     pub fn new_with_options(dir: &Path, opts: &DirLockOptions) -> crate::Result<Self> {
+        Self::new_internal(dir, opts, None)
+    }
+
+    /// Lock the given directory with advanced options. Wait at most `timeout`
+    /// for the lock to be available.
+    ///
+    /// Return an error that [`crate::Error::is_busy`] on timeout.
+    /// `opts.non_blocking` is ignored.
+    pub fn new_with_timeout(
+        dir: &Path,
+        opts: &DirLockOptions,
+        timeout: Duration,
+    ) -> crate::Result<Self> {
+        Self::new_internal(dir, opts, Some(timeout))
+    }
+
+    fn new_internal(
+        dir: &Path,
+        opts: &DirLockOptions,
+        timeout: Option<Duration>,
+    ) -> crate::Result<Self> {
         let (path, file) = if opts.file_name.is_empty() {
             let file = utils::open_dir(dir).context(dir, "cannot open for locking")?;
             (dir.to_path_buf(), file)
@@ -131,20 +195,67 @@ impl ScopedDirLock {
         };
 
         // Lock
-        match (opts.exclusive, opts.non_blocking) {
-            (true, false) => file.lock_exclusive(),
-            (true, true) => file.try_lock_exclusive(),
-            (false, false) => file.lock_shared(),
-            (false, true) => file.try_lock_shared(),
-        }
-        .context(&path, || {
-            format!(
-                "cannot lock (exclusive: {}, non_blocking: {})",
-                opts.exclusive, opts.non_blocking,
-            )
-        })?;
-
-        let result = Self { file, path };
+        let _op = op_span!(
+            "indexedlog::lock",
+            path = ?path,
+            exclusive = opts.exclusive,
+            non_blocking = opts.non_blocking
+        );
+        // Try without blocking first to tell whether the lock is contended.
+        let try_lock = || match opts.exclusive {
+            true => FileExt::try_lock_exclusive(&file),
+            false => FileExt::try_lock_shared(&file),
+        };
+        let is_contended =
+            |e: &io::Error| e.raw_os_error() == lock_contended_error().raw_os_error();
+        let mut waited = false;
+        try_lock()
+            .or_else(|e| {
+                if !is_contended(&e) {
+                    return Err(e);
+                }
+                match timeout {
+                    None if opts.non_blocking => Err(e),
+                    None => {
+                        waited = true;
+                        match opts.exclusive {
+                            true => FileExt::lock_exclusive(&file),
+                            false => FileExt::lock_shared(&file),
+                        }
+                    }
+                    Some(timeout) => {
+                        // There is no portable blocking lock with timeout.
+                        // Poll with exponential backoff.
+                        waited = true;
+                        let deadline = Instant::now() + timeout;
+                        let mut interval = Duration::from_millis(1);
+                        loop {
+                            let now = Instant::now();
+                            if now >= deadline {
+                                return Err(e);
+                            }
+                            thread::sleep(interval.min(deadline - now));
+                            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+                            match try_lock() {
+                                Err(e) if is_contended(&e) => continue,
+                                result => return result,
+                            }
+                        }
+                    }
+                }
+            })
+            .context(&path, || match timeout {
+                None => format!(
+                    "cannot lock (exclusive: {}, non_blocking: {})",
+                    opts.exclusive, opts.non_blocking,
+                ),
+                Some(timeout) => format!(
+                    "cannot lock (exclusive: {}, timeout: {:?})",
+                    opts.exclusive, timeout,
+                ),
+            })?;
+
+        let result = Self { file, path, waited };
         Ok(result)
     }
 
@@ -152,15 +263,21 @@ impl ScopedDirLock {
     pub fn path(&self) -> &Path {
         &self.path
     }
+
+    /// Whether the lock was held by others, and had to be waited for.
+    pub fn waited(&self) -> bool {
+        self.waited
+    }
 }
 
 impl Drop for ScopedDirLock {
     fn drop(&mut self) {
-        self.file.unlock().expect("unlock");
+        FileExt::unlock(&self.file).expect("unlock");
     }
 }
 
-#[cfg(test)]
+// The tests need real file locks.
+#[cfg(all(test, feature = "std-fs"))]
 mod tests {
     use std::fs::OpenOptions;
     use std::io::Read;
@@ -328,4 +445,36 @@ mod tests {
 
         drop(l4);
     }
+
+    #[test]
+    fn test_dir_lock_with_timeout() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let opts = DirLockOptions {
+            file_name: "",
+            exclusive: true,
+            non_blocking: false,
+        };
+        let timeout = Duration::from_millis(50);
+
+        let l1 = ScopedDirLock::new_with_timeout(path, &opts, timeout).unwrap();
+        assert!(!l1.waited());
+
+        // Time out while the lock is held.
+        let start = Instant::now();
+        let err = ScopedDirLock::new_with_timeout(path, &opts, timeout)
+            .err()
+            .unwrap();
+        assert!(err.is_busy());
+        assert!(start.elapsed() >= timeout);
+
+        // Obtain the lock after it is released.
+        let thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            drop(l1);
+        });
+        let l2 = ScopedDirLock::new_with_timeout(path, &opts, Duration::from_secs(10)).unwrap();
+        assert!(l2.waited());
+        thread.join().unwrap();
+    }
 }
@@ -0,0 +1,270 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::fs;
+use std::io;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::PathBuf;
+
+use byteorder::LittleEndian;
+use byteorder::WriteBytesExt;
+use tempfile::NamedTempFile;
+use vlqencoding::VLQEncode;
+
+use super::entry_checksum;
+use super::entry_checksum_width;
+use super::entry_format_version;
+use super::preallocate_primary;
+use super::ENTRY_CHECKSUM_CHUNK_SIZE;
+use super::ENTRY_FLAG_CHUNKED;
+use super::ENTRY_FLAG_HAS_CRC32C;
+use super::ENTRY_FLAG_HAS_TIMESTAMP;
+use super::ENTRY_FLAG_HAS_XXH3;
+use super::ENTRY_FLAG_HAS_XXHASH32;
+use super::ENTRY_FLAG_HAS_XXHASH64;
+use super::ENTRY_FLAG_NO_CHECKSUM;
+use crate::config;
+use crate::errors::IoResultExt;
+use crate::errors::ResultExt;
+use crate::lock::ScopedDirLock;
+use crate::log::ChecksumType;
+use crate::log::GenericPath;
+use crate::log::Log;
+use crate::log::LogMetrics;
+use crate::log::PRIMARY_FILE;
+use crate::utils;
+
+/// Writer of a single large entry. Created by [`Log::append_writer`].
+///
+/// Content is streamed to a temporary file next to the primary log, and
+/// checksummed in 64KB chunks. Call [`LogAppendWriter::finish`] to append
+/// the entry. Dropping the writer without calling `finish` discards the
+/// content.
+pub struct LogAppendWriter<'a> {
+    log: &'a mut Log,
+    dir: PathBuf,
+    file: io::BufWriter<NamedTempFile>,
+    len: u64,
+    entry_flags: u32,
+    chunk: Vec<u8>,
+    checksums: Vec<u64>,
+}
+
+impl Log {
+    /// Append an entry by streaming its content, without buffering the
+    /// entire entry in memory. Useful for very large entries.
+    ///
+    /// The entry is appended by [`LogAppendWriter::finish`]. Unlike
+    /// [`Log::append`], it is written to disk directly.
+    ///
+    /// Only works for on-disk [`Log`]s without a codec.
+    pub fn append_writer(&mut self) -> crate::Result<LogAppendWriter<'_>> {
+        let result: crate::Result<_> = (|| {
+            self.check_writable()?;
+            let dir = match &self.dir {
+                GenericPath::Filesystem(dir) if self.open_options.codec.is_none() => dir.clone(),
+                _ => {
+                    return Err(crate::Error::programming(
+                        "append_writer() only supports on-disk logs without codecs",
+                    ));
+                }
+            };
+            let file = tempfile::Builder::new()
+                .prefix("append")
+                .tempfile_in(&dir)
+                .context(&dir, "cannot create temporary file for append_writer")?;
+            Ok((dir, file))
+        })();
+        let (dir, file) = result
+            .context("in Log::append_writer")
+            .context(|| format!("  Log.dir = {:?}", self.dir))?;
+
+        let mut entry_flags = if self.open_options.skip_checksum {
+            ENTRY_FLAG_NO_CHECKSUM
+        } else {
+            match self.open_options.checksum_type {
+                // Large entries prefer xxhash64. See `append_in_memory`.
+                ChecksumType::Auto | ChecksumType::Xxhash64 => ENTRY_FLAG_HAS_XXHASH64,
+                ChecksumType::Xxhash32 => ENTRY_FLAG_HAS_XXHASH32,
+                ChecksumType::Xxh3 => ENTRY_FLAG_HAS_XXH3,
+                ChecksumType::Crc32c => ENTRY_FLAG_HAS_CRC32C,
+            }
+        };
+        let entry_timestamp = self.open_options.entry_timestamp;
+        if entry_timestamp {
+            entry_flags |= ENTRY_FLAG_HAS_TIMESTAMP;
+        }
+        let mut writer = LogAppendWriter {
+            log: self,
+            dir,
+            file: io::BufWriter::new(file),
+            len: 0,
+            entry_flags,
+            chunk: Vec::with_capacity(ENTRY_CHECKSUM_CHUNK_SIZE),
+            checksums: Vec::new(),
+        };
+        if entry_timestamp {
+            // The timestamp is the start of the payload.
+            let timestamp = utils::now_millis().to_le_bytes();
+            writer
+                .write_all(&timestamp)
+                .context(&writer.dir, "cannot write timestamp")?;
+        }
+        Ok(writer)
+    }
+}
+
+impl<'a> LogAppendWriter<'a> {
+    /// Append the entry. Return its offset.
+    ///
+    /// Update indexes for the entry. Pending in-memory entries are written
+    /// before the entry, as if by [`Log::sync`].
+    pub fn finish(mut self) -> crate::Result<u64> {
+        let dir = self.dir.clone();
+        let result: crate::Result<_> = (|| {
+            self.flush_chunk();
+            if self.entry_flags & ENTRY_FLAG_NO_CHECKSUM == 0 {
+                if self.checksums.is_empty() {
+                    // Empty entry.
+                    self.checksums.push(entry_checksum(self.entry_flags, &[]));
+                }
+                if self.checksums.len() > 1 {
+                    self.entry_flags |= ENTRY_FLAG_CHUNKED;
+                }
+            }
+
+            let mut header = Vec::new();
+            header.write_vlq(self.entry_flags).infallible()?;
+            header.write_vlq(self.len).infallible()?;
+            let width = entry_checksum_width(self.entry_flags);
+            for &checksum in &self.checksums {
+                match width {
+                    Some(8) => header.write_u64::<LittleEndian>(checksum),
+                    Some(4) => header.write_u32::<LittleEndian>(checksum as u32),
+                    _ => unreachable!(),
+                }
+                .infallible()?;
+            }
+
+            let mut content = self
+                .file
+                .into_inner()
+                .map_err(|e| e.into_error())
+                .context(&dir, "cannot write temporary file for append_writer")?;
+            content
+                .seek(SeekFrom::Start(0))
+                .context(content.path(), "cannot seek")?;
+
+            // Write pending in-memory entries first, so they stay before the
+            // entry and their offsets do not change.
+            let log = self.log;
+            log.sync()?;
+
+            // Similar to `Log::sync`, but write the entry directly.
+            let offset = {
+                let lock = ScopedDirLock::new(&dir)?;
+                let metrics = &log.open_options.metrics;
+                metrics.record_lock(&lock);
+                let mut meta = Log::load_or_create_meta(&log.dir, false)?;
+                log.check_quota(&meta, header.len() as u64 + self.len)?;
+                let primary_path = dir.join(PRIMARY_FILE);
+                let mut primary_file = fs::OpenOptions::new()
+                    .write(true)
+                    .open(&primary_path)
+                    .context(&primary_path, "cannot open for write")?;
+                // Overwrite (broken) data after the length in metadata.
+                // See `Log::sync` for details.
+                let pos = primary_file
+                    .seek(SeekFrom::Start(meta.primary_len))
+                    .context(&primary_path, "cannot seek")?;
+                if pos != meta.primary_len {
+                    let msg = format!(
+                        "log file has {} bytes, expect at least {} bytes",
+                        pos, meta.primary_len
+                    );
+                    return Err(crate::Error::at_path(&primary_path, msg));
+                }
+                primary_file
+                    .write_all(&header)
+                    .context(&primary_path, "cannot write entry header")?;
+                io::copy(content.as_file_mut(), &mut primary_file)
+                    .context(&primary_path, "cannot write entry content")?;
+                if let Some(chunk_size) = log.open_options.preallocate_chunk_size {
+                    let len = meta.primary_len + header.len() as u64 + self.len;
+                    preallocate_primary(&primary_file, &primary_path, len, chunk_size);
+                }
+                let fsync = log.open_options.fsync || config::get_global_fsync();
+                if fsync {
+                    primary_file
+                        .sync_all()
+                        .context(&primary_path, "cannot fsync")?;
+                }
+                let offset = meta.primary_len;
+                meta.primary_len += header.len() as u64 + self.len;
+                meta.entry_count = meta.entry_count.map(|n| n + 1);
+                meta.format_version = meta
+                    .format_version
+                    .max(entry_format_version(self.entry_flags));
+                log.dir.write_meta(&meta, fsync)?;
+                LogMetrics::add(&metrics.appends, 1);
+                LogMetrics::add(&metrics.bytes_written, header.len() as u64 + self.len);
+                offset
+            };
+
+            // Load the entry and update indexes.
+            log.sync()?;
+            Ok(offset)
+        })();
+
+        result
+            .context("in LogAppendWriter::finish")
+            .context(|| format!("  Log.dir = {:?}", dir))
+    }
+
+    /// Calculate checksum of the pending chunk.
+    fn flush_chunk(&mut self) {
+        if !self.chunk.is_empty() {
+            if self.entry_flags & ENTRY_FLAG_NO_CHECKSUM == 0 {
+                let checksum = entry_checksum(self.entry_flags, &self.chunk);
+                self.checksums.push(checksum);
+            }
+            self.chunk.clear();
+        }
+    }
+}
+
+impl<'a> Write for LogAppendWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(max) = self.log.open_options.max_entry_size {
+            // `self.len` includes the timestamp.
+            let timestamp_len = match self.entry_flags & ENTRY_FLAG_HAS_TIMESTAMP {
+                0 => 0,
+                _ => 8,
+            };
+            let len = (self.len + buf.len() as u64).saturating_sub(timestamp_len);
+            if len > max {
+                let err = crate::Error::entry_too_large(len, max);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, err));
+            }
+        }
+        let size = buf.len().min(ENTRY_CHECKSUM_CHUNK_SIZE - self.chunk.len());
+        let buf = &buf[..size];
+        self.file.write_all(buf)?;
+        self.chunk.extend_from_slice(buf);
+        self.len += size as u64;
+        if self.chunk.len() == ENTRY_CHECKSUM_CHUNK_SIZE {
+            self.flush_chunk();
+        }
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
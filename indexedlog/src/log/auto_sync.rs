@@ -0,0 +1,145 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Background [`Log::sync`] used by [`OpenOptions::auto_sync_interval`].
+
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use std::time::Duration;
+
+use crate::log::Log;
+#[cfg(doc)]
+use crate::log::OpenOptions;
+
+/// A [`Log`] that is synced periodically by a background thread.
+/// Created by [`Log::into_auto_sync`].
+///
+/// Use [`AutoSyncLog::lock`] to access the [`Log`]. The background thread
+/// takes the same lock to call [`Log::sync`].
+///
+/// Dropping the [`AutoSyncLog`] stops the background thread, and syncs
+/// pending changes.
+pub struct AutoSyncLog {
+    shared: Option<Arc<Shared>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+struct Shared {
+    log: Mutex<Log>,
+    stopped: Mutex<bool>,
+    condvar: Condvar,
+    error: Mutex<Option<crate::Error>>,
+}
+
+impl Log {
+    /// Start a background thread that calls [`Log::sync`] every
+    /// [`OpenOptions::auto_sync_interval`] if there are pending changes.
+    pub fn into_auto_sync(self) -> crate::Result<AutoSyncLog> {
+        let interval = match self.open_options.auto_sync_interval {
+            Some(interval) => interval,
+            None => {
+                return Err(crate::Error::programming(
+                    "into_auto_sync() requires auto_sync_interval",
+                ));
+            }
+        };
+        let shared = Arc::new(Shared {
+            log: Mutex::new(self),
+            stopped: Mutex::new(false),
+            condvar: Condvar::new(),
+            error: Mutex::new(None),
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("indexedlog-sync".to_string())
+                .spawn(move || worker(&shared, interval))
+                .map_err(|e| crate::Error::from(("cannot spawn auto sync thread", e)))?
+        };
+        Ok(AutoSyncLog {
+            shared: Some(shared),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl AutoSyncLog {
+    /// Lock the [`Log`] for reading or writing. Background sync is blocked
+    /// while the lock is held.
+    pub fn lock(&self) -> MutexGuard<'_, Log> {
+        self.shared().log.lock().unwrap()
+    }
+
+    /// Take the error of the last failed background sync, if any.
+    ///
+    /// A failed sync is retried in the next interval.
+    pub fn take_error(&self) -> Option<crate::Error> {
+        self.shared().error.lock().unwrap().take()
+    }
+
+    /// Stop the background thread. Return the [`Log`] without syncing it.
+    pub fn into_inner(mut self) -> Log {
+        self.stop();
+        let shared = self.shared.take().unwrap();
+        match Arc::try_unwrap(shared) {
+            Ok(shared) => shared.log.into_inner().unwrap_or_else(|e| e.into_inner()),
+            Err(_) => unreachable!("background thread has stopped"),
+        }
+    }
+
+    fn shared(&self) -> &Shared {
+        self.shared.as_ref().unwrap()
+    }
+
+    fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let shared = self.shared();
+            *shared.stopped.lock().unwrap() = true;
+            shared.condvar.notify_all();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for AutoSyncLog {
+    fn drop(&mut self) {
+        self.stop();
+        if let Some(shared) = self.shared.take() {
+            if let Ok(mut log) = shared.log.lock() {
+                if log.has_pending_changes() {
+                    if let Err(err) = log.sync() {
+                        tracing::warn!("auto sync on drop failed: {}", err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn worker(shared: &Shared, interval: Duration) {
+    let mut stopped = shared.stopped.lock().unwrap();
+    while !*stopped {
+        stopped = shared.condvar.wait_timeout(stopped, interval).unwrap().0;
+        if *stopped {
+            break;
+        }
+        let mut log = match shared.log.lock() {
+            Ok(log) => log,
+            // A writer panicked. Do not sync potentially inconsistent state.
+            Err(_) => break,
+        };
+        if log.has_pending_changes() {
+            if let Err(err) = log.sync() {
+                tracing::warn!("auto sync failed: {}", err);
+                *shared.error.lock().unwrap() = Some(err);
+            }
+        }
+    }
+}
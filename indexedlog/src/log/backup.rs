@@ -0,0 +1,101 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use crate::errors::IoResultExt;
+use crate::errors::ResultExt;
+use crate::log::Log;
+use crate::log::META_FILE;
+use crate::log::PRIMARY_FILE;
+use crate::utils;
+
+impl Log {
+    /// Copy the on-disk state of the [`Log`] to another directory.
+    ///
+    /// The copy matches the state when the [`Log`] was opened or last synced.
+    /// In-memory entries are not copied. Since files are append-only, this
+    /// does not take the write lock. Other writers can continue appending
+    /// during the copy. Destructive operations like `repair` or `rewrite`
+    /// are blocked because this [`Log`] is an active reader.
+    ///
+    /// `dir` must not contain a [`Log`]. Indexes not defined by the
+    /// [`OpenOptions`](crate::log::OpenOptions) of this [`Log`] are not
+    /// copied.
+    pub fn copy_to(&self, dir: impl AsRef<Path>) -> crate::Result<()> {
+        let dest_dir = dir.as_ref();
+        let result: crate::Result<_> = (|| {
+            let src_dir = match self.dir.as_opt_path() {
+                Some(dir) => dir,
+                None => {
+                    return Err(crate::Error::programming(
+                        "copy_to() does not support in-memory logs",
+                    ));
+                }
+            };
+            let dest_meta_path = dest_dir.join(META_FILE);
+            // Use symlink_metadata since the meta file can be a symlink.
+            // See `atomic_write`.
+            if fs::symlink_metadata(&dest_meta_path).is_ok() {
+                return Err(crate::Error::at_path(
+                    dest_dir,
+                    "cannot copy to a directory that already contains a Log",
+                ));
+            }
+            utils::mkdir_p(dest_dir)?;
+
+            let fsync = self.open_options.fsync;
+            let mut meta = self.meta.clone();
+            copy_prefix(
+                &src_dir.join(PRIMARY_FILE),
+                &dest_dir.join(PRIMARY_FILE),
+                meta.primary_len,
+                fsync,
+            )?;
+            let mut indexes = std::mem::take(&mut meta.indexes);
+            for def in self.open_options.index_defs.iter() {
+                if let Some((name, len)) = indexes.remove_entry(&def.metaname()) {
+                    let filename = def.filename();
+                    copy_prefix(
+                        &src_dir.join(&filename),
+                        &dest_dir.join(&filename),
+                        len,
+                        fsync,
+                    )?;
+                    meta.indexes.insert(name, len);
+                }
+            }
+
+            // Write metadata last, so an interrupted copy is not a valid Log.
+            meta.write_file(&dest_meta_path, fsync)?;
+            Ok(())
+        })();
+
+        result
+            .context(|| format!("in Log::copy_to({:?})", dest_dir))
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+}
+
+/// Copy the first `len` bytes of `src` to `dest`.
+fn copy_prefix(src: &Path, dest: &Path, len: u64, fsync: bool) -> crate::Result<()> {
+    let src_file = fs::File::open(src).context(src, "cannot open for copying")?;
+    let mut dest_file = fs::File::create(dest).context(dest, "cannot create")?;
+    let copied = io::copy(&mut src_file.take(len), &mut dest_file)
+        .context(dest, || format!("cannot copy from {:?}", src))?;
+    if copied != len {
+        let msg = format!("file has {} bytes, expect at least {} bytes", copied, len);
+        return Err(crate::Error::corruption(src, msg));
+    }
+    if fsync {
+        dest_file.sync_all().context(dest, "cannot fsync")?;
+    }
+    Ok(())
+}
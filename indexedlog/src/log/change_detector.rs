@@ -0,0 +1,93 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use crate::errors::ResultExt;
+use crate::log::GenericPath;
+use crate::log::Log;
+use crate::log::LogMetadata;
+
+/// Detect changes of a [`Log`] without keeping the [`Log`] open.
+/// Created by [`Log::change_detector`].
+///
+/// This is useful for invalidating caches derived from a [`Log`]. Checking
+/// only reads the small metadata file.
+#[derive(Clone, Debug)]
+pub struct ChangeDetector {
+    dir: GenericPath,
+    meta: LogMetadata,
+}
+
+impl ChangeDetector {
+    /// Test if entries or metadata of the [`Log`] changed since the last
+    /// call, or since the [`Log`] snapshot this detector was created from.
+    ///
+    /// Changes only affecting indexes are ignored. Return `false` if the
+    /// metadata cannot be read.
+    pub fn is_changed(&mut self) -> bool {
+        if let GenericPath::Nothing = self.dir {
+            return false;
+        }
+        match self.dir.read_meta() {
+            Ok(meta) if !meta.has_same_content(&self.meta) => {
+                self.meta = meta;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The epoch seen by the last [`ChangeDetector::is_changed`] call.
+    /// See [`Log::epoch`].
+    pub fn epoch(&self) -> u64 {
+        self.meta.epoch
+    }
+}
+
+impl Log {
+    /// Return the epoch of the [`Log`].
+    ///
+    /// The epoch changes on non-append-only changes, like [`Log::rewrite`],
+    /// `repair` truncating corrupted entries, or [`Log::bump_epoch`]. If the
+    /// epoch is unchanged, the [`Log`] was only appended, and data derived
+    /// from existing entries (ex. offsets) is still valid.
+    pub fn epoch(&self) -> u64 {
+        self.meta.epoch
+    }
+
+    /// Change the epoch to declare a non-append-only change, so other
+    /// [`Log`]s and caches depending on [`Log::epoch`] can invalidate their
+    /// states.
+    ///
+    /// Pending changes of this [`Log`] are written first. Entries are not
+    /// changed. Other [`Log`]s reload on their next [`Log::sync`], as if the
+    /// [`Log`] was rewritten. Return the new epoch.
+    pub fn bump_epoch(&mut self) -> crate::Result<u64> {
+        let result: crate::Result<_> = (|| {
+            self.check_writable()?;
+            // Write pending changes first. Offsets used by them are still
+            // valid before the bump.
+            self.sync()?;
+            self.modify_meta_locked(|meta| meta.epoch = meta.epoch.wrapping_add(1))?;
+            // Reload with the new epoch.
+            self.sync()?;
+            Ok(self.meta.epoch)
+        })();
+
+        result
+            .context("in Log::bump_epoch")
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+
+    /// Create a [`ChangeDetector`] to detect changes made after this
+    /// [`Log`] was loaded or synced.
+    pub fn change_detector(&self) -> ChangeDetector {
+        ChangeDetector {
+            dir: self.dir.clone(),
+            meta: self.meta.clone(),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Describe how entries are stored in the primary log, for external tools.
+//! See [`Log::describe_entries`].
+
+use vlqencoding::VLQDecodeAt;
+
+use crate::errors::ResultExt;
+use crate::log::ChecksumType;
+use crate::log::IndexOutput;
+use crate::log::Log;
+use crate::log::ENTRY_FLAG_CHUNKED;
+use crate::log::ENTRY_FLAG_HAS_CRC32C;
+use crate::log::ENTRY_FLAG_HAS_XXH3;
+use crate::log::ENTRY_FLAG_HAS_XXHASH32;
+use crate::log::ENTRY_FLAG_HAS_XXHASH64;
+use crate::log::PRIMARY_START_OFFSET;
+
+/// How an entry is stored in the primary log.
+///
+/// Offsets are positions in the primary log file (`log`), after decoding
+/// if [`OpenOptions::codec`](crate::log::OpenOptions::codec) is set.
+/// Offsets of in-memory entries are where they will be written by
+/// [`Log::sync`].
+///
+/// # Entry framing
+///
+/// The framing below is stable. Future versions only add entry flags.
+///
+/// The primary log starts with the 12-byte header `indexedlog0\0`. Entries
+/// follow without padding. Each entry is:
+///
+/// 1. `FLAGS`: VLQ encoded bit flags.
+///    - `1`, `2`, `4`, `8`: the checksum function is xxhash64, xxhash32,
+///      XXH3 (64-bit) or CRC-32C. Exactly one is set, unless `32` is set.
+///    - `16`: the payload is covered by one checksum per 64KB chunk. The
+///      last chunk can be shorter.
+///    - `32`: the entry has no checksum.
+///    - `64`: the payload starts with a timestamp.
+/// 2. `LEN`: VLQ encoded length of the payload.
+/// 3. `CHECKSUMS`: checksums of the payload, or of its chunks, in
+///    LittleEndian. A checksum has 8 bytes for xxhash64 and XXH3, and 4
+///    bytes for xxhash32 and CRC-32C. Empty if the entry has no checksum.
+/// 4. `PAYLOAD`: an optional LittleEndian `u64` timestamp in milliseconds
+///    since UNIX epoch, followed by the entry data.
+///
+/// The metadata file decides how much of the primary log is valid, and
+/// which entries are deleted. Bytes after the valid length are not
+/// entries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryDescription {
+    /// Offset of the entry. This is the offset used by [`Log::delete`].
+    pub offset: u64,
+
+    /// Length of the entry, including the header, checksums and timestamp.
+    pub len: u64,
+
+    /// Offset of the entry data. The timestamp, if any, is skipped.
+    pub data_offset: u64,
+
+    /// Length of the entry data.
+    pub data_len: u64,
+
+    /// The checksum function. `None` if the entry has no checksum.
+    pub checksum: Option<ChecksumType>,
+
+    /// Whether the entry has one checksum per 64KB chunk.
+    pub chunked: bool,
+
+    /// Whether the entry has a timestamp.
+    pub has_timestamp: bool,
+
+    /// Whether any index function outputs a key to insert for the entry.
+    pub has_index_keys: bool,
+
+    /// Whether the entry is deleted by [`Log::delete`].
+    pub deleted: bool,
+}
+
+impl Log {
+    /// Describe all entries, including deleted and in-memory entries, in
+    /// offset order.
+    ///
+    /// Checksums are not verified. Use [`Log::verify`] for that. The
+    /// iteration stops after the first error, ex. a corrupted entry header.
+    ///
+    /// See [`EntryDescription`] for the framing of entries.
+    pub fn describe_entries(&self) -> impl Iterator<Item = crate::Result<EntryDescription>> + '_ {
+        let mut offset = Some(PRIMARY_START_OFFSET);
+        std::iter::from_fn(move || {
+            let result = self.describe_entry(offset?);
+            offset = match &result {
+                Ok(Some(desc)) => Some(desc.offset + desc.len),
+                _ => None,
+            };
+            result.transpose()
+        })
+    }
+
+    /// Describe the entry at `offset`. Return `None` at the end of the log.
+    fn describe_entry(&self, offset: u64) -> crate::Result<Option<EntryDescription>> {
+        let result: crate::Result<_> = (|| {
+            let entry = match self.read_entry_with_verify(offset, false)? {
+                Some(entry) => entry,
+                None => return Ok(None),
+            };
+            // The header was checked by `read_entry_with_verify`.
+            let (buf, buf_offset) = if offset < self.meta.primary_len {
+                (&self.disk_buf[..], offset)
+            } else {
+                (&self.mem_buf[..], offset - self.meta.primary_len)
+            };
+            let (entry_flags, _): (u32, _) = buf.read_vlq_at(buf_offset as usize).unwrap();
+            let checksum = if entry_flags & ENTRY_FLAG_HAS_XXHASH64 != 0 {
+                Some(ChecksumType::Xxhash64)
+            } else if entry_flags & ENTRY_FLAG_HAS_XXHASH32 != 0 {
+                Some(ChecksumType::Xxhash32)
+            } else if entry_flags & ENTRY_FLAG_HAS_XXH3 != 0 {
+                Some(ChecksumType::Xxh3)
+            } else if entry_flags & ENTRY_FLAG_HAS_CRC32C != 0 {
+                Some(ChecksumType::Crc32c)
+            } else {
+                None
+            };
+            let has_index_keys = self.open_options.index_defs.iter().any(|def| {
+                (def.func)(entry.data).iter().any(|output| {
+                    matches!(output, IndexOutput::Reference(_) | IndexOutput::Owned(_))
+                })
+            });
+            Ok(Some(EntryDescription {
+                offset,
+                len: entry.next_offset - offset,
+                // `data_offset` is relative to `buf`.
+                data_offset: entry.data_offset + offset - buf_offset,
+                data_len: entry.data.len() as u64,
+                checksum,
+                chunked: entry_flags & ENTRY_FLAG_CHUNKED != 0,
+                has_timestamp: entry.timestamp.is_some(),
+                has_index_keys,
+                deleted: self.is_deleted(offset),
+            }))
+        })();
+        result.context(|| format!("in Log::describe_entry({})", offset))
+    }
+}
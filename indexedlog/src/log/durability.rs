@@ -0,0 +1,181 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Background `fsync` used by [`Durability::FsyncBatched`] and
+//! [`Log::flush_async`].
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::Once;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use once_cell::sync::Lazy;
+
+use crate::errors::IoResultExt;
+#[cfg(doc)]
+use crate::log::Log;
+
+/// How [`Log::sync`] makes written data durable.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Durability {
+    /// Do not use `fsync`. Data written by [`Log::sync`] might be lost on
+    /// power loss or OS crash.
+    None,
+
+    /// Use `fsync` before [`Log::sync`] returns.
+    Fsync,
+
+    /// Use `fsync` in a background thread, at most once per the given
+    /// interval. [`Log::sync`]s within the interval share a single `fsync`.
+    ///
+    /// Data written within the last interval might be lost on power loss or
+    /// OS crash.
+    FsyncBatched(Duration),
+}
+
+/// Handle of a background `fsync` started by [`Log::flush_async`].
+pub struct FsyncHandle {
+    receiver: mpsc::Receiver<crate::Result<()>>,
+}
+
+impl FsyncHandle {
+    /// Block until the background `fsync` completes.
+    pub fn wait(self) -> crate::Result<()> {
+        self.receiver.recv().unwrap_or_else(|_| {
+            Err(crate::Error::programming(
+                "background fsync thread exited unexpectedly",
+            ))
+        })
+    }
+
+    /// A handle that is already completed.
+    pub(crate) fn completed() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let _ = sender.send(Ok(()));
+        Self { receiver }
+    }
+}
+
+/// Pending `fsync` of files in a directory.
+struct Request {
+    deadline: Instant,
+    names: BTreeSet<String>,
+    waiters: Vec<mpsc::Sender<crate::Result<()>>>,
+}
+
+#[derive(Default)]
+struct Queue {
+    pending: Mutex<BTreeMap<PathBuf, Request>>,
+    condvar: Condvar,
+}
+
+static QUEUE: Lazy<Queue> = Lazy::new(Default::default);
+
+/// Schedule `fsync` for the given files in `dir`, and the directory itself.
+///
+/// The `fsync` happens after `delay`, or earlier if another request for the
+/// same directory is due earlier.
+pub(crate) fn schedule(dir: &Path, names: Vec<String>, delay: Duration) -> FsyncHandle {
+    static START: Once = Once::new();
+    START.call_once(|| {
+        thread::Builder::new()
+            .name("indexedlog-fsync".to_string())
+            .spawn(worker)
+            .expect("spawn fsync thread");
+    });
+
+    let (sender, receiver) = mpsc::channel();
+    let deadline = Instant::now() + delay;
+    let mut pending = QUEUE.pending.lock().unwrap();
+    let request = pending.entry(dir.to_path_buf()).or_insert_with(|| Request {
+        deadline,
+        names: BTreeSet::new(),
+        waiters: Vec::new(),
+    });
+    request.deadline = request.deadline.min(deadline);
+    request.names.extend(names);
+    request.waiters.push(sender);
+    QUEUE.condvar.notify_one();
+    FsyncHandle { receiver }
+}
+
+fn worker() {
+    let mut pending = QUEUE.pending.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        match pending.values().map(|r| r.deadline).min() {
+            None => pending = QUEUE.condvar.wait(pending).unwrap(),
+            Some(deadline) if deadline > now => {
+                pending = QUEUE
+                    .condvar
+                    .wait_timeout(pending, deadline - now)
+                    .unwrap()
+                    .0
+            }
+            Some(_) => {
+                let due: Vec<PathBuf> = pending
+                    .iter()
+                    .filter(|(_, r)| r.deadline <= now)
+                    .map(|(dir, _)| dir.clone())
+                    .collect();
+                let requests: Vec<(PathBuf, Request)> = due
+                    .into_iter()
+                    .filter_map(|dir| pending.remove(&dir).map(|r| (dir, r)))
+                    .collect();
+
+                // Do not block new requests while running fsync.
+                drop(pending);
+                for (dir, request) in requests {
+                    let result = fsync_files(&dir, &request.names);
+                    if let Err(err) = &result {
+                        tracing::warn!("background fsync failed: {}", err);
+                    }
+                    let result = result.map_err(|e| e.to_string());
+                    for waiter in request.waiters {
+                        let result = result.clone().map_err(|e| crate::Error::at_path(&dir, e));
+                        let _ = waiter.send(result);
+                    }
+                }
+                pending = QUEUE.pending.lock().unwrap();
+            }
+        }
+    }
+}
+
+fn fsync_files(dir: &Path, names: &BTreeSet<String>) -> crate::Result<()> {
+    for name in names {
+        let path = dir.join(name);
+        // Skip missing files, and symlinks used by `atomic_write`. Symlinks
+        // are covered by syncing the directory.
+        match fs::symlink_metadata(&path) {
+            Ok(meta) if meta.file_type().is_file() => {}
+            _ => continue,
+        }
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .context(&path, "cannot open for fsync")?;
+        file.sync_all().context(&path, "cannot fsync")?;
+    }
+
+    #[cfg(unix)]
+    {
+        let file = fs::File::open(dir).context(dir, "cannot open directory for fsync")?;
+        file.sync_all().context(dir, "cannot fsync directory")?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,274 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Portable single-file archive of a [`Log`].
+//!
+//! Format:
+//!
+//! ```plain,ignore
+//! ARCHIVE := HEADER + METADATA + RECORDS + XXHASH64(HEADER + METADATA + RECORDS)
+//! HEADER := 'indexedlog-archive\0'
+//! METADATA := LEN(PAIRS) + PAIRS
+//! PAIRS := '' | PAIRS + LEN(KEY) + KEY + LEN(VALUE) + VALUE
+//! RECORDS := RECORD_END | RECORD + RECORDS
+//! RECORD := RECORD_ENTRY + LEN(DATA) + DATA
+//!         | RECORD_ENTRY_WITH_TIMESTAMP + TIMESTAMP + LEN(DATA) + DATA
+//! ```
+//!
+//! `RECORD_*` are single bytes. Integers are VLQ encoded, except for
+//! `TIMESTAMP` and `XXHASH64`, which use LittleEndian encoding.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use byteorder::LittleEndian;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use twox_hash::XxHash;
+use vlqencoding::VLQDecode;
+use vlqencoding::VLQEncode;
+
+use crate::errors::IoResultExt;
+use crate::errors::ResultExt;
+use crate::log::Log;
+use crate::log::OpenOptions;
+use crate::log::META_FILE;
+use crate::utils;
+
+const ARCHIVE_HEADER: &[u8] = b"indexedlog-archive\0";
+const RECORD_END: u8 = 0;
+const RECORD_ENTRY: u8 = 1;
+const RECORD_ENTRY_WITH_TIMESTAMP: u8 = 2;
+
+// Sync periodically during import to limit memory usage.
+const IMPORT_SYNC_THRESHOLD: usize = 64 << 20;
+
+impl Log {
+    /// Write entries, and metadata set by [`Log::update_meta`] to a single
+    /// portable archive. Entries deleted by [`Log::delete`] are skipped.
+    ///
+    /// The archive does not contain indexes or paths. Use [`Log::import_from`]
+    /// to create a [`Log`] from it.
+    pub fn export_to(&self, writer: impl Write) -> crate::Result<()> {
+        let result: crate::Result<_> = (|| {
+            let mut writer = HashWriter {
+                inner: writer,
+                hasher: XxHash::default(),
+            };
+            let write_error = |e| crate::Error::from(("cannot write archive", e));
+
+            writer.write_all(ARCHIVE_HEADER).map_err(write_error)?;
+            let mut user_meta: BTreeMap<&str, &[u8]> = self
+                .meta
+                .user
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_slice()))
+                .collect();
+            for (key, value) in self.dirty_user.iter() {
+                match value {
+                    Some(value) => user_meta.insert(key, value),
+                    None => user_meta.remove(key.as_str()),
+                };
+            }
+            writer.write_vlq(user_meta.len()).map_err(write_error)?;
+            for (key, value) in user_meta {
+                writer.write_vlq(key.len()).map_err(write_error)?;
+                writer.write_all(key.as_bytes()).map_err(write_error)?;
+                writer.write_vlq(value.len()).map_err(write_error)?;
+                writer.write_all(value).map_err(write_error)?;
+            }
+
+            for item in self.iter().with_offsets() {
+                let (offset, data) = item?;
+                let timestamp = self.read_entry(offset)?.and_then(|e| e.timestamp);
+                match timestamp {
+                    None => writer.write_u8(RECORD_ENTRY),
+                    Some(timestamp) => writer
+                        .write_u8(RECORD_ENTRY_WITH_TIMESTAMP)
+                        .and_then(|_| writer.write_u64::<LittleEndian>(timestamp)),
+                }
+                .map_err(write_error)?;
+                writer.write_vlq(data.len()).map_err(write_error)?;
+                writer.write_all(data).map_err(write_error)?;
+            }
+            writer.write_u8(RECORD_END).map_err(write_error)?;
+
+            let checksum = writer.hasher.finish();
+            writer
+                .inner
+                .write_u64::<LittleEndian>(checksum)
+                .map_err(write_error)?;
+            writer.inner.flush().map_err(write_error)?;
+            Ok(())
+        })();
+
+        result
+            .context("in Log::export_to")
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+
+    /// Create a [`Log`] at `dir` from an archive written by
+    /// [`Log::export_to`]. Indexes defined by `options` are built from
+    /// scratch. Entry timestamps are preserved if `options` records them.
+    ///
+    /// `dir` must not exist, or be an empty directory. The [`Log`] is
+    /// created in a temporary directory, then renamed to `dir`, so a
+    /// truncated or corrupted archive does not leave a partial [`Log`].
+    pub fn import_from(
+        reader: impl Read,
+        dir: impl AsRef<Path>,
+        options: &OpenOptions,
+    ) -> crate::Result<Log> {
+        let dir = dir.as_ref();
+        let result: crate::Result<_> = (|| {
+            if fs::symlink_metadata(dir.join(META_FILE)).is_ok() {
+                return Err(crate::Error::at_path(
+                    dir,
+                    "cannot import to a directory that already contains a Log",
+                ));
+            }
+            let parent = match dir.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            utils::mkdir_p(parent)?;
+            let tmp = tempfile::Builder::new()
+                .prefix("import")
+                .tempdir_in(parent)
+                .context(parent, "cannot create tempdir for import")?;
+
+            let mut log = options
+                .clone()
+                .create(true)
+                .auto_sync_threshold(None)
+                .open(tmp.path())?;
+            read_archive(reader, &mut log)?;
+            log.sync()?;
+            drop(log);
+
+            fs::rename(tmp.path(), dir)
+                .context(dir, || format!("cannot rename from {:?}", tmp.path()))?;
+            options.clone().create(false).open(dir)
+        })();
+
+        result.context(|| format!("in Log::import_from(_, {:?})", dir))
+    }
+
+    /// Create an in-memory [`Log`] from an archive written by
+    /// [`Log::export_to`]. Similar to [`Log::import_from`], but nothing is
+    /// written to disk. The [`Log`] cannot [`Log::sync`] to a directory.
+    pub fn import_in_memory(reader: impl Read, options: &OpenOptions) -> crate::Result<Log> {
+        let result: crate::Result<_> = (|| {
+            let mut log = options.clone().auto_sync_threshold(None).open(())?;
+            read_archive(reader, &mut log)?;
+            Ok(log)
+        })();
+
+        result.context("in Log::import_in_memory")
+    }
+}
+
+/// Append entries and metadata of an archive to `log`. Sync periodically.
+fn read_archive(reader: impl Read, log: &mut Log) -> crate::Result<()> {
+    let mut reader = HashReader {
+        inner: reader,
+        hasher: XxHash::default(),
+    };
+    let read_error = |e| crate::Error::from(("cannot read archive", e));
+
+    let mut header = vec![0; ARCHIVE_HEADER.len()];
+    reader.read_exact(&mut header).map_err(read_error)?;
+    if header != ARCHIVE_HEADER {
+        return Err(crate::Error::from("invalid archive header"));
+    }
+    let count: usize = reader.read_vlq().map_err(read_error)?;
+    let mut user_meta = Vec::with_capacity(count);
+    for _ in 0..count {
+        let key = read_bytes(&mut reader).map_err(read_error)?;
+        let key = String::from_utf8(key)
+            .map_err(|e| crate::Error::from(("non-utf8 metadata key in archive", e)))?;
+        let value = read_bytes(&mut reader).map_err(read_error)?;
+        user_meta.push((key, Some(value)));
+    }
+    log.update_meta(user_meta)?;
+
+    loop {
+        let timestamp = match reader.read_u8().map_err(read_error)? {
+            RECORD_END => break,
+            RECORD_ENTRY => None,
+            RECORD_ENTRY_WITH_TIMESTAMP => {
+                Some(reader.read_u64::<LittleEndian>().map_err(read_error)?)
+            }
+            record => {
+                let msg = format!("unknown record type {} in archive", record);
+                return Err(crate::Error::from(msg.as_str()));
+            }
+        };
+        let data = read_bytes(&mut reader).map_err(read_error)?;
+        log.append_in_memory(&data, timestamp)?;
+        if log.mem_buf.len() >= IMPORT_SYNC_THRESHOLD {
+            log.sync()?;
+        }
+    }
+
+    let checksum = reader.hasher.finish();
+    let expected = reader
+        .inner
+        .read_u64::<LittleEndian>()
+        .map_err(read_error)?;
+    if checksum != expected {
+        return Err(crate::Error::from("archive checksum mismatch"));
+    }
+    Ok(())
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len: usize = reader.read_vlq()?;
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+/// Calculate xxhash of written bytes.
+struct HashWriter<W> {
+    inner: W,
+    hasher: XxHash,
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.inner.write(buf)?;
+        self.hasher.write(&buf[..size]);
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Calculate xxhash of read bytes.
+struct HashReader<R> {
+    inner: R,
+    hasher: XxHash,
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.hasher.write(&buf[..size]);
+        Ok(size)
+    }
+}
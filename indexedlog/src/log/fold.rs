@@ -200,7 +200,7 @@ impl FoldState {
 
         // Set self state as up-to-date, and write to disk.
         self.offset = log.disk_buf.len() as u64;
-        if let Some(path) = &opt_path {
+        if let Some(path) = opt_path.as_ref().filter(|_| !log.open_options.read_only) {
             if let Err(e) = self.save_to_file(path) {
                 tracing::warn!("cannot save FoldState: {}", e);
             }
@@ -241,7 +241,7 @@ impl FoldState {
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use tempfile::tempdir;
 
     use super::*;
@@ -274,7 +274,7 @@ mod test {
     }
 
     #[derive(Debug, Default)]
-    struct CountFold(u64);
+    pub(crate) struct CountFold(pub(crate) u64);
 
     impl Fold for CountFold {
         fn load(&mut self, state_bytes: &[u8]) -> io::Result<()> {
@@ -0,0 +1,246 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Background building of indexes marked by [`IndexDef::build_in_background`].
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::Arc;
+use std::thread;
+
+use crate::errors::IoResultExt;
+use crate::errors::ResultExt;
+use crate::index;
+use crate::lock::ScopedDirLock;
+use crate::log::GenericPath;
+use crate::log::IndexDef;
+use crate::log::Log;
+use crate::log::OpenOptions;
+use crate::utils;
+
+/// A background build of indexes. Created by
+/// [`Log::build_indexes_in_background`].
+///
+/// Built indexes are used by [`Log`]s after their next [`Log::sync`].
+/// Dropping the [`IndexBuild`] does not stop the build.
+pub struct IndexBuild {
+    progress: Arc<Progress>,
+    thread: Option<thread::JoinHandle<crate::Result<()>>>,
+}
+
+#[derive(Default)]
+struct Progress {
+    // Bytes of the primary log processed, summed over indexes.
+    done: AtomicU64,
+    total: AtomicU64,
+}
+
+impl Log {
+    /// Test if the index can be used for lookups.
+    ///
+    /// An index marked by [`IndexDef::build_in_background`] is not ready
+    /// until it is built by [`Log::build_indexes_in_background`], and picked
+    /// up by [`Log::sync`]. Lookups using an index that is not ready return
+    /// errors.
+    pub fn is_index_ready(&self, index_id: usize) -> bool {
+        match self.open_options.index_defs.get(index_id) {
+            Some(def) => !Self::is_index_pending(&self.dir, &self.meta, def),
+            None => false,
+        }
+    }
+
+    /// Build indexes that are not ready in a background thread.
+    /// See [`IndexDef::build_in_background`].
+    ///
+    /// The directory is not locked during most of the build, so other
+    /// [`Log`]s can still append entries. The lock is taken to index the
+    /// entries appended in the meantime, and to replace the index file.
+    /// The build fails if the [`Log`] is rewritten in the meantime.
+    pub fn build_indexes_in_background(&self) -> crate::Result<IndexBuild> {
+        let result: crate::Result<_> = (|| {
+            self.check_writable()?;
+            let defs: Vec<IndexDef> = self
+                .open_options
+                .index_defs
+                .iter()
+                .filter(|def| Self::is_index_pending(&self.dir, &self.meta, def))
+                .cloned()
+                .collect();
+            let progress = Arc::new(Progress::default());
+            let thread = match self.dir.as_opt_path() {
+                Some(dir) if !defs.is_empty() => {
+                    let dir = dir.to_path_buf();
+                    let options = self.open_options.clone();
+                    let progress = progress.clone();
+                    let thread = thread::Builder::new()
+                        .name("indexedlog-index-build".to_string())
+                        .spawn(move || build_indexes(&dir, &defs, &options, &progress))
+                        .map_err(|e| crate::Error::from(("cannot spawn index build thread", e)))?;
+                    Some(thread)
+                }
+                _ => None,
+            };
+            Ok(IndexBuild { progress, thread })
+        })();
+
+        result
+            .context("in Log::build_indexes_in_background")
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+}
+
+impl IndexBuild {
+    /// Fraction of the work done, from 0.0 to 1.0.
+    pub fn progress(&self) -> f64 {
+        let total = self.progress.total.load(Relaxed);
+        if total == 0 {
+            return if self.is_finished() { 1.0 } else { 0.0 };
+        }
+        let done = self.progress.done.load(Relaxed).min(total);
+        done as f64 / total as f64
+    }
+
+    /// Test if the build has stopped, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        match &self.thread {
+            Some(thread) => thread.is_finished(),
+            None => true,
+        }
+    }
+
+    /// Wait for the build to stop. Return the error if it failed.
+    pub fn wait(mut self) -> crate::Result<()> {
+        match self.thread.take() {
+            Some(thread) => match thread.join() {
+                Ok(result) => result,
+                Err(_) => Err(crate::Error::programming(
+                    "background index build thread panicked",
+                )),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+/// Build indexes from scratch. Similar to `Log::rebuild_indexes`, but only
+/// take the lock to catch up and replace the index file.
+fn build_indexes(
+    dir: &Path,
+    defs: &[IndexDef],
+    options: &OpenOptions,
+    progress: &Progress,
+) -> crate::Result<()> {
+    let path = GenericPath::from(dir);
+    let meta = Log::load_or_create_meta(&path, false)?;
+    meta.check_format_version()?;
+    let disk_buf = Log::load_primary(dir, meta.primary_len, options.codec.as_deref())?;
+    progress
+        .total
+        .store(meta.primary_len * defs.len() as u64, Relaxed);
+
+    for (i, def) in defs.iter().enumerate() {
+        let base = meta.primary_len * i as u64;
+        let tmp_path = tempfile::Builder::new()
+            .prefix(&def.rebuild_prefix())
+            .tempfile_in(dir)
+            .context(dir, "cannot create temporary index file")?
+            .into_temp_path();
+        let result = (|| -> crate::Result<()> {
+            // Index entries on disk without the lock.
+            let mut index = open_index(&tmp_path, def, options, disk_buf.clone())?;
+            Log::update_index_for_on_disk_entry_unchecked(
+                &path,
+                &mut index,
+                def,
+                &disk_buf,
+                meta.primary_len,
+                &mut |offset| progress.done.store(base + offset, Relaxed),
+            )?;
+            let mut index_len = index.flush()?;
+            drop(index);
+
+            // Index entries appended in the meantime, then replace the index.
+            let lock = ScopedDirLock::new(dir)?;
+            options.metrics.record_lock(&lock);
+            let mut latest = Log::load_or_create_meta(&path, false)?;
+            latest.check_format_version()?;
+            latest.check_poison()?;
+            if latest.index_len(def).is_some() {
+                // Built by others.
+                return Ok(());
+            }
+            if latest.epoch != meta.epoch {
+                return Err(crate::Error::at_path(
+                    dir,
+                    "log was rewritten during the background index build",
+                ));
+            }
+            if latest.primary_len > meta.primary_len {
+                let disk_buf =
+                    Log::load_primary(dir, latest.primary_len, options.codec.as_deref())?;
+                let mut index = open_index(&tmp_path, def, options, disk_buf.clone())?;
+                Log::update_index_for_on_disk_entry_unchecked(
+                    &path,
+                    &mut index,
+                    def,
+                    &disk_buf,
+                    latest.primary_len,
+                    &mut |_| {},
+                )?;
+                index_len = index.flush()?;
+            }
+
+            let _ = utils::fix_perm_path(&tmp_path, false);
+            let index_path = dir.join(def.filename());
+            fs::rename(&tmp_path, &index_path).context(&index_path, || {
+                format!("cannot rename from {:?} to replace index", &tmp_path)
+            })?;
+            if def.bloom_filter.is_some() {
+                // The bloom filter is a cache. It will be rebuilt if this
+                // fails.
+                let _ = fs::rename(
+                    index::bloom_filter_path(&tmp_path),
+                    index::bloom_filter_path(&index_path),
+                );
+            }
+            if options.fsync {
+                // Make the rename durable before the metadata points to the
+                // new index.
+                #[cfg(unix)]
+                fs::File::open(dir)
+                    .and_then(|f| f.sync_all())
+                    .context(dir, "cannot fsync directory")?;
+            }
+            latest.set_index_len(def, index_len);
+            path.write_meta(&latest, options.fsync)?;
+            Ok(())
+        })();
+        let _ = fs::remove_file(index::bloom_filter_path(&tmp_path));
+        result.context(|| format!("  building index {:?}", def.name.as_str()))?;
+    }
+
+    progress.done.store(progress.total.load(Relaxed), Relaxed);
+    Ok(())
+}
+
+/// Open an index file for building.
+fn open_index(
+    path: &Path,
+    def: &IndexDef,
+    options: &OpenOptions,
+    disk_buf: minibytes::Bytes,
+) -> crate::Result<index::Index> {
+    index::OpenOptions::new()
+        .key_buf(Some(Arc::new(disk_buf)))
+        .codec(options.codec.clone())
+        .fsync(options.fsync)
+        .checksum_chunk_size_logarithm(def.checksum_chunk_size_logarithm)
+        .bloom_filter(def.bloom_filter)
+        .open(path)
+}
@@ -0,0 +1,134 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Partial recovery of indexes with checksum errors.
+//!
+//! Instead of rebuilding a damaged index from scratch, keys in undamaged
+//! subtrees are copied from the old index. Only keys in damaged subtrees
+//! are re-indexed from the primary log.
+
+use std::ops::Range;
+
+use crate::index::Index;
+use crate::index::InsertKey;
+use crate::index::InsertValue;
+use crate::index::Salvage;
+use crate::log::GenericPath;
+use crate::log::IndexDef;
+use crate::log::IndexOutput;
+use crate::log::Log;
+use crate::log::PRIMARY_START_OFFSET;
+
+/// Summary of an index recovered from partial damage.
+///
+/// See [`RepairReport::recovered_indexes`](crate::log::RepairReport).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexRecovery {
+    /// Name of the index.
+    pub name: String,
+
+    /// Byte ranges of the old index file that failed checksum checks.
+    pub damaged_ranges: Vec<Range<u64>>,
+
+    /// Number of keys copied from undamaged parts of the old index.
+    pub kept_keys: u64,
+
+    /// Number of values re-indexed from the primary log, for keys in
+    /// damaged parts of the old index.
+    pub reindexed_values: u64,
+}
+
+/// What can be read from an index with checksum errors.
+pub(crate) struct DamagedIndex {
+    salvage: Salvage,
+    damaged_ranges: Vec<Range<u64>>,
+    /// Length of the primary log covered by the old index.
+    log_len: u64,
+}
+
+impl Log {
+    /// Read the undamaged part of an index covering `log_len` bytes of the
+    /// primary log. Return `None` if nothing can be kept.
+    pub(crate) fn salvage_index(index: &Index, log_len: u64) -> Option<DamagedIndex> {
+        let salvage = index.salvage();
+        if salvage.entries.is_empty() {
+            // Rebuilding from scratch is cheaper.
+            return None;
+        }
+        Some(DamagedIndex {
+            salvage,
+            damaged_ranges: index.damaged_ranges(),
+            log_len,
+        })
+    }
+
+    /// Fill an empty `index` using the undamaged part of the old index, and
+    /// entries in the primary log for keys in the damaged part.
+    ///
+    /// The index covers the same part of the log as the old index
+    /// afterwards. Use `update_index_for_on_disk_entry_unchecked` to index
+    /// the rest of the log.
+    pub(crate) fn recover_index_unchecked(
+        path: &GenericPath,
+        index: &mut Index,
+        def: &IndexDef,
+        disk_buf: &[u8],
+        damaged: &DamagedIndex,
+    ) -> crate::Result<IndexRecovery> {
+        let salvage = &damaged.salvage;
+        let mut reindexed_values = 0;
+        let mut offset = PRIMARY_START_OFFSET;
+
+        // Re-index keys in the damaged part first. Removals also affect
+        // keys in the undamaged part, but those keys are not inserted yet
+        // and the old index has the removals applied.
+        while offset < damaged.log_len {
+            let entry = match Self::read_entry_from_buf(path, disk_buf, offset, true)? {
+                Some(entry) => entry,
+                None => break,
+            };
+            let data = entry.data;
+            for index_output in (def.func)(data) {
+                match index_output {
+                    IndexOutput::Reference(range) => {
+                        let key = &data[range.start as usize..range.end as usize];
+                        if salvage.is_damaged(key) {
+                            let start = range.start + entry.data_offset;
+                            let key = InsertKey::Reference((start, range.end - range.start));
+                            index.insert_advanced(key, InsertValue::Prepend(offset))?;
+                            reindexed_values += 1;
+                        }
+                    }
+                    IndexOutput::Owned(key) => {
+                        if salvage.is_damaged(&key) {
+                            let key = InsertKey::Embed(&key);
+                            index.insert_advanced(key, InsertValue::Prepend(offset))?;
+                            reindexed_values += 1;
+                        }
+                    }
+                    IndexOutput::Remove(key) => {
+                        index.remove(key)?;
+                    }
+                    IndexOutput::RemovePrefix(key) => {
+                        index.remove_prefix(key)?;
+                    }
+                }
+            }
+            offset = entry.next_offset;
+        }
+
+        salvage.insert_into(index)?;
+        Self::set_index_log_len(std::iter::once(index), damaged.log_len);
+
+        Ok(IndexRecovery {
+            name: def.name.to_string(),
+            damaged_ranges: damaged.damaged_ranges.clone(),
+            kept_keys: salvage.entries.len() as u64,
+            reindexed_values,
+        })
+    }
+}
@@ -0,0 +1,116 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Deferred loading of indexes. See
+//! [`OpenOptions::lazy_indexes`](crate::log::OpenOptions::lazy_indexes).
+//!
+//! A lazy index has an empty placeholder in `Log::indexes`. Lookups load
+//! it into `Log::lazy_indexes`. Functions that change indexes move loaded
+//! indexes into `Log::indexes` by `load_lazy_indexes` first. So lazy
+//! indexes only exist when there are no in-memory entries.
+
+use crate::errors::ResultExt;
+use crate::index::Index;
+use crate::log::GenericPath;
+use crate::log::IndexDef;
+use crate::log::Log;
+use crate::log::LogMetadata;
+
+impl Log {
+    /// Test if loading the index can be deferred.
+    ///
+    /// Indexes of in-memory Logs are built on open. Indexes pending
+    /// background builds are empty and not worth deferring.
+    pub(crate) fn can_defer_index_load(
+        dir: &GenericPath,
+        meta: &LogMetadata,
+        def: &IndexDef,
+    ) -> bool {
+        dir.as_opt_path().is_some() && !Self::is_index_pending(dir, meta, def)
+    }
+
+    /// Test if the index is not loaded into `indexes` yet.
+    pub(crate) fn is_index_lazy(&self, index_id: usize) -> bool {
+        matches!(self.lazy_indexes.get(index_id), Some(Some(_)))
+    }
+
+    /// Test if any index is not loaded into `indexes` yet.
+    pub(crate) fn has_lazy_indexes(&self) -> bool {
+        self.lazy_indexes.iter().any(Option::is_some)
+    }
+
+    /// Get the specified index. Load it if it is lazy.
+    ///
+    /// Return `None` if `index_id` is out of bound.
+    pub(crate) fn load_index_by_id(&self, index_id: usize) -> crate::Result<Option<&Index>> {
+        if let Some(Some(lazy)) = self.lazy_indexes.get(index_id) {
+            if let Some(index) = lazy.get() {
+                return Ok(Some(index));
+            }
+            // If another thread loaded the index first, its index is used.
+            let index = self.load_lazy_index(index_id)?;
+            return Ok(Some(lazy.get_or_init(|| index)));
+        }
+        Ok(self.indexes.get(index_id))
+    }
+
+    /// Move all lazy indexes into `indexes`. Load them if needed.
+    pub(crate) fn load_lazy_indexes(&mut self) -> crate::Result<()> {
+        if !self.has_lazy_indexes() {
+            return Ok(());
+        }
+        let result = (0..self.lazy_indexes.len()).try_for_each(|i| self.take_lazy_index(i));
+        self.maybe_set_index_error(result)?;
+        self.lazy_indexes.clear();
+        Ok(())
+    }
+
+    /// Move the lazy index into `indexes`. Load it if needed.
+    ///
+    /// The index is no longer lazy even if loading fails. The empty
+    /// placeholder is left in `indexes` in that case.
+    pub(crate) fn take_lazy_index(&mut self, index_id: usize) -> crate::Result<()> {
+        if let Some(lazy) = self.lazy_indexes.get_mut(index_id).and_then(Option::take) {
+            let index = match lazy.into_inner() {
+                Some(index) => index,
+                None => self.load_lazy_index(index_id)?,
+            };
+            self.indexes[index_id] = index;
+        }
+        Ok(())
+    }
+
+    /// Load an index from disk, and update it for lagging on-disk entries.
+    fn load_lazy_index(&self, index_id: usize) -> crate::Result<Index> {
+        let def = &self.open_options.index_defs[index_id];
+        let result: crate::Result<_> = (|| {
+            let opts = &self.open_options;
+            // The placeholder has the key buffer.
+            let key_buf = self.indexes[index_id].key_buf.clone();
+            let index_len = self.meta.index_len(def).unwrap_or(0);
+            let mut index = Self::load_index(
+                &self.dir,
+                def,
+                index_len,
+                key_buf,
+                opts.fsync,
+                opts.codec.as_ref(),
+                opts.read_only,
+            )?;
+            Self::update_index_for_on_disk_entry_unchecked(
+                &self.dir,
+                &mut index,
+                def,
+                &self.disk_buf,
+                self.meta.primary_len,
+                &mut |_| {},
+            )?;
+            Ok(index)
+        })();
+        result.context(|| format!("in Log::load_lazy_index({:?})", def.name.as_str()))
+    }
+}
@@ -0,0 +1,302 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+use minibytes::Bytes;
+
+use crate::errors::ResultExt;
+use crate::log::ExternalKeyBuffer;
+use crate::log::FlushFilterContext;
+use crate::log::FlushFilterOutput;
+use crate::log::GenericPath;
+use crate::log::Log;
+use crate::log::LogMetadata;
+use crate::log::LogMetrics;
+use crate::log::PRIMARY_HEADER;
+use crate::log::PRIMARY_START_OFFSET;
+
+/// In-memory replacement of a directory. Useful for tests, or platforms
+/// without filesystem or mmap support (ex. WebAssembly).
+///
+/// [`Log`]s opened on clones of the same [`MemoryDir`] share storage. They
+/// behave like [`Log`]s opened on the same directory: changes are buffered
+/// until [`Log::sync`], and each [`Log`] reads a snapshot taken at open or
+/// [`Log::sync`] time.
+///
+/// Indexes are not stored. They are built in memory when opening the
+/// [`Log`].
+#[derive(Clone, Default)]
+pub struct MemoryDir {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    // Serialize writers. Similar to the directory lock.
+    lock: Mutex<()>,
+    state: Mutex<Option<State>>,
+}
+
+struct State {
+    meta: LogMetadata,
+    primary: Bytes,
+}
+
+impl MemoryDir {
+    /// Create an empty [`MemoryDir`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Test if a [`Log`] was created in this [`MemoryDir`].
+    pub fn exists(&self) -> bool {
+        self.state().is_some()
+    }
+
+    fn state(&self) -> MutexGuard<'_, Option<State>> {
+        self.inner.state.lock().unwrap()
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, ()> {
+        self.inner.lock.lock().unwrap()
+    }
+
+    pub(crate) fn read_meta(&self) -> crate::Result<LogMetadata> {
+        match self.state().as_ref() {
+            Some(state) => Ok(state.meta.clone()),
+            None => Err(crate::Error::from((
+                "Log does not exist in MemoryDir",
+                io::Error::from(io::ErrorKind::NotFound),
+            ))),
+        }
+    }
+
+    pub(crate) fn write_meta(&self, meta: &LogMetadata) -> crate::Result<()> {
+        match self.state().as_mut() {
+            Some(state) => {
+                state.meta = meta.clone();
+                Ok(())
+            }
+            None => Err(crate::Error::programming(
+                "write_meta() requires an existing Log in MemoryDir",
+            )),
+        }
+    }
+
+    /// Read the metadata. If `create` is `true`, create an empty [`Log`] on
+    /// demand.
+    pub(crate) fn load_or_create_meta(&self, create: bool) -> crate::Result<LogMetadata> {
+        let mut state = self.state();
+        if state.is_none() && create {
+            *state = Some(State {
+                meta: LogMetadata::new_with_primary_len(PRIMARY_START_OFFSET),
+                primary: Bytes::from_static(PRIMARY_HEADER),
+            });
+        }
+        drop(state);
+        self.read_meta()
+    }
+
+    /// Read the first `len` bytes of the primary log.
+    pub(crate) fn read_primary(&self, len: u64) -> crate::Result<Bytes> {
+        let state = self.state();
+        let primary = match state.as_ref() {
+            Some(state) => &state.primary,
+            None => return Ok(Bytes::new()),
+        };
+        if primary.len() < len as usize {
+            let msg = format!(
+                "primary log in MemoryDir has {} bytes, expect at least {} bytes",
+                primary.len(),
+                len
+            );
+            return Err(crate::Error::programming(msg));
+        }
+        Ok(primary.slice(..len as usize))
+    }
+
+    /// Replace the content of the [`Log`].
+    pub(crate) fn write_primary_and_meta(&self, primary: Bytes, meta: LogMetadata) {
+        *self.state() = Some(State { meta, primary });
+    }
+
+    /// Append `data` to the primary log of length `len`, and replace the
+    /// metadata. The primary log is extended in place, unless it is still
+    /// read by other [`Log`]s. In that case, it is copied.
+    pub(crate) fn append_primary_and_meta(
+        &self,
+        len: u64,
+        data: &[u8],
+        meta: LogMetadata,
+    ) -> crate::Result<()> {
+        let mut state = self.state();
+        let state = match state.as_mut() {
+            Some(state) => state,
+            None => {
+                return Err(crate::Error::programming(
+                    "append_primary_and_meta() requires an existing Log in MemoryDir",
+                ));
+            }
+        };
+        let mut primary = std::mem::take(&mut state.primary).into_vec();
+        primary.truncate(len as usize);
+        primary.extend_from_slice(data);
+        state.primary = Bytes::from(primary);
+        state.meta = meta;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MemoryDir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MemoryDir({:p})", Arc::as_ptr(&self.inner))
+    }
+}
+
+impl Log {
+    /// [`Log::sync`] for [`Log`]s backed by [`MemoryDir`].
+    pub(crate) fn sync_memory(&mut self, dir: &MemoryDir) -> crate::Result<u64> {
+        let _lock = dir.lock();
+        let meta = dir.read_meta()?;
+        meta.check_format_version()?;
+        if !self.open_options.read_only {
+            meta.check_poison()?;
+        }
+        self.check_quota(&meta, 0)?;
+        let changed = self.meta != meta;
+        let truncated = self.meta.epoch != meta.epoch;
+        if !truncated && self.meta.primary_len > meta.primary_len {
+            let msg = format!(
+                "log in MemoryDir is unexpectedly smaller ({} bytes) than its previous version ({} bytes)",
+                meta.primary_len, self.meta.primary_len
+            );
+            return Err(crate::Error::programming(msg));
+        }
+
+        if !self.has_pending_changes() {
+            if changed {
+                *self = self.open_options.create_in_memory(self.dir.clone())?;
+            }
+            return Ok(self.meta.primary_len);
+        }
+
+        if changed {
+            // Reload, and re-insert entries. Similar to `Log::sync` when the
+            // log was changed by others.
+            let disk_deleted: BTreeSet<u64> = if truncated {
+                BTreeSet::new()
+            } else {
+                self.dirty_deleted
+                    .range(..self.meta.primary_len)
+                    .cloned()
+                    .collect()
+            };
+            let dirty_user = std::mem::take(&mut self.dirty_user);
+            let mut log = self
+                .open_options
+                .create_in_memory(self.dir.clone())
+                .context("re-open since MemoryDir has changed")?;
+            for entry in self.iter_dirty() {
+                let content = entry?;
+                match self.open_options.flush_filter {
+                    Some(filter) => {
+                        let context = FlushFilterContext { log: &log };
+                        match filter(&context, content).map_err(|err| {
+                            crate::Error::wrap(err, "failed to run filter function")
+                        })? {
+                            FlushFilterOutput::Drop => {}
+                            FlushFilterOutput::Keep => log.append_in_memory(content, None)?,
+                            FlushFilterOutput::Replace(content) => {
+                                log.append_in_memory(&content, None)?
+                            }
+                        }
+                    }
+                    None => log.append_in_memory(content, None)?,
+                }
+            }
+            *self = log;
+            self.dirty_deleted = disk_deleted;
+            self.dirty_user = dirty_user;
+        }
+
+        // Append the in-memory entries. Offsets of in-memory entries are
+        // unchanged, since `self.meta` matches the storage now.
+        let mut meta = self.meta.clone();
+        meta.deleted.extend(std::mem::take(&mut self.dirty_deleted));
+        for (key, value) in std::mem::take(&mut self.dirty_user) {
+            match value {
+                Some(value) => meta.user.insert(key, value),
+                None => meta.user.remove(&key),
+            };
+        }
+        let disk_len = meta.primary_len;
+        meta.primary_len += self.mem_buf.len() as u64;
+        meta.format_version = meta.format_version.max(self.dirty_format_version);
+        meta.entry_count = meta.entry_count.map(|n| n + self.dirty_entry_count);
+
+        // Release references to the primary log, so it can be extended in
+        // place if no other Log reads it. Both are replaced below.
+        self.disk_buf = Bytes::new();
+        let mem_buf: &Vec<u8> = &self.mem_buf;
+        let key_buf = Arc::new(ExternalKeyBuffer {
+            disk_buf: Bytes::new(),
+            disk_len: 0,
+            mem_buf: mem_buf as *const Vec<u8>,
+        });
+        for index in self.indexes.iter_mut() {
+            index.key_buf = key_buf.clone();
+        }
+        drop(key_buf);
+        dir.append_primary_and_meta(disk_len, &self.mem_buf, meta.clone())?;
+
+        let metrics = &self.open_options.metrics;
+        LogMetrics::add(&metrics.bytes_written, self.mem_buf.len() as u64);
+        self.mem_buf.clear();
+        self.dirty_format_version = 0;
+        self.dirty_entry_count = 0;
+
+        // Reload the primary log. Reuse indexes since they include all
+        // entries.
+        Self::set_index_log_len(self.indexes.iter_mut(), meta.primary_len);
+        let (disk_buf, indexes) = Self::load_log_and_indexes(
+            &self.dir,
+            &meta,
+            &self.open_options.index_defs,
+            &self.mem_buf,
+            Some(&self.indexes),
+            self.open_options.fsync,
+            self.open_options.codec.as_ref(),
+            self.open_options.read_only,
+            false,
+        )?;
+        self.disk_buf = disk_buf;
+        self.indexes = indexes;
+        self.meta = meta;
+        self.update_and_flush_disk_folds()?;
+        self.all_folds = self.disk_folds.clone();
+
+        Ok(self.meta.primary_len)
+    }
+}
+
+impl From<&MemoryDir> for GenericPath {
+    fn from(dir: &MemoryDir) -> Self {
+        Self::Memory(dir.clone())
+    }
+}
+
+impl From<MemoryDir> for GenericPath {
+    fn from(dir: MemoryDir) -> Self {
+        Self::Memory(dir)
+    }
+}
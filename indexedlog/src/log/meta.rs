@@ -6,6 +6,7 @@
  */
 
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::io;
 use std::io::Cursor;
 use std::io::Read;
@@ -15,15 +16,41 @@ use std::path::Path;
 use byteorder::LittleEndian;
 use byteorder::ReadBytesExt;
 use byteorder::WriteBytesExt;
+use vlqencoding::FrameRead;
+use vlqencoding::FrameWrite;
 use vlqencoding::VLQDecode;
 use vlqencoding::VLQEncode;
 
 use crate::errors::IoResultExt;
+use crate::log::IndexDef;
+use crate::log::PRIMARY_START_OFFSET;
 use crate::utils;
 use crate::utils::atomic_read;
 use crate::utils::atomic_write;
 use crate::utils::xxhash;
 
+/// Latest format version of [`Log`]s.
+///
+/// - 0: Version is not recorded. Written before versions were introduced.
+/// - 1: Version is recorded in the metadata. Entries only use xxhash
+///   checksums, so versions before 1 can read them.
+/// - 2: Entries may use newer entry flags, like other checksum types,
+///   chunked checksums, or timestamps. The metadata header is changed so
+///   versions before 2 refuse to open the [`Log`] instead of misreading
+///   entries.
+///
+/// Bump this when changing the on-disk layout. [`Log`]s using newer versions
+/// are refused. [`Log::migrate_to_latest`](crate::log::Log::migrate_to_latest)
+/// rewrites [`Log`]s using older versions.
+pub(crate) const LATEST_FORMAT_VERSION: u64 = 2;
+
+/// Format version of new [`Log`]s. It is bumped to
+/// [`ENTRY_FLAGS_FORMAT_VERSION`] once an entry using newer flags is written.
+pub(crate) const INITIAL_FORMAT_VERSION: u64 = 1;
+
+/// Format version required by entries using flags added after version 1.
+pub(crate) const ENTRY_FLAGS_FORMAT_VERSION: u64 = 2;
+
 /// Metadata about index names, logical [`Log`] and [`Index`] file lengths.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct LogMetadata {
@@ -36,6 +63,34 @@ pub struct LogMetadata {
     /// Used to detect non-append-only changes.
     /// Conceptually similar to "create time".
     pub(crate) epoch: u64,
+
+    /// Offsets of deleted entries in the primary log.
+    pub(crate) deleted: BTreeSet<u64>,
+
+    /// Application-defined key-value pairs. See
+    /// [`Log::update_meta`](crate::log::Log::update_meta).
+    pub(crate) user: BTreeMap<String, Vec<u8>>,
+
+    /// Reason why the [`Log`] should not be used. See
+    /// [`Log::poison`](crate::log::Log::poison).
+    pub(crate) poison: Option<String>,
+
+    /// Version of the on-disk layout. See [`LATEST_FORMAT_VERSION`].
+    pub(crate) format_version: u64,
+
+    /// Versions of index functions that built the indexes. Name => Version.
+    /// Missing versions are 0. See
+    /// [`IndexDef::version`](crate::log::IndexDef::version).
+    pub(crate) index_versions: BTreeMap<String, u64>,
+
+    /// Names of temporary files being written, like indexes being rebuilt.
+    /// Files left by crashes are removed by the next index rebuild.
+    pub(crate) temp_files: BTreeSet<String>,
+
+    /// Number of entries in the primary log, including deleted ones. `None`
+    /// if unknown, for example, if the metadata was written by an older
+    /// version, which does not keep it.
+    pub(crate) entry_count: Option<u64>,
 }
 
 impl LogMetadata {
@@ -44,26 +99,40 @@ impl LogMetadata {
         let header = HeaderVersion::from_reader(&mut reader)?;
         let hash: u64 = match header {
             HeaderVersion::V0 => reader.read_vlq()?,
-            HeaderVersion::V1 => reader.read_u64::<LittleEndian>()?,
+            HeaderVersion::V1 | HeaderVersion::V2 => reader.read_u64::<LittleEndian>()?,
         };
-        let buf_len = reader.read_vlq()?;
+        let buf_len: usize = reader.read_vlq()?;
 
-        let mut buf = vec![0; buf_len];
-        reader.read_exact(&mut buf)?;
+        // Do not trust `buf_len` for allocation. The reader might be shorter.
+        let mut buf = Vec::new();
+        reader.take(buf_len as u64).read_to_end(&mut buf)?;
+        if buf.len() != buf_len {
+            let msg = "metadata is truncated";
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg));
+        }
 
         if xxhash(&buf) != hash {
             let msg = "metadata integrity check failed";
             return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
         }
 
+        Self::read_body(&buf)
+    }
+
+    /// Parse the metadata body, which is the part after the header and the
+    /// checksum written by [`LogMetadata::write`].
+    ///
+    /// Unlike [`LogMetadata::read`], there is no integrity check. This is
+    /// mainly useful to exercise the parser with arbitrary bytes.
+    pub fn read_body(buf: &[u8]) -> io::Result<Self> {
+        // Frames inside `buf` cannot be longer than `buf`.
+        let limit = buf.len();
         let mut reader = Cursor::new(buf);
         let primary_len = reader.read_vlq()?;
         let index_count: usize = reader.read_vlq()?;
         let mut indexes = BTreeMap::new();
         for _ in 0..index_count {
-            let name_len = reader.read_vlq()?;
-            let mut name = vec![0; name_len];
-            reader.read_exact(&mut name)?;
+            let name = reader.read_frame(limit)?;
             let name = String::from_utf8(name).map_err(|_e| {
                 let msg = "non-utf8 index name";
                 io::Error::new(io::ErrorKind::InvalidData, msg)
@@ -76,16 +145,106 @@ impl LogMetadata {
         // format. So not being able to read it (because EOF) is not fatal.
         let epoch = reader.read_vlq().unwrap_or_default();
 
+        // 'deleted' is optional too. Offsets are delta-encoded.
+        let mut deleted = BTreeSet::new();
+        let deleted_count: usize = reader.read_vlq().unwrap_or_default();
+        let mut offset: u64 = 0;
+        for _ in 0..deleted_count {
+            let delta: u64 = reader.read_vlq()?;
+            offset = offset.checked_add(delta).ok_or_else(|| {
+                let msg = "deleted offset overflow";
+                io::Error::new(io::ErrorKind::InvalidData, msg)
+            })?;
+            deleted.insert(offset);
+        }
+
+        // 'user' is optional too.
+        let mut user = BTreeMap::new();
+        let user_count: usize = reader.read_vlq().unwrap_or_default();
+        for _ in 0..user_count {
+            let key = reader.read_frame(limit)?;
+            let key = String::from_utf8(key).map_err(|_e| {
+                let msg = "non-utf8 metadata key";
+                io::Error::new(io::ErrorKind::InvalidData, msg)
+            })?;
+            let value = reader.read_frame(limit)?;
+            user.insert(key, value);
+        }
+
+        // 'poison' is optional too. 0 means not poisoned. Otherwise, it is
+        // the length of the reason plus 1.
+        let poison = match reader.read_vlq().unwrap_or_default() {
+            0 => None,
+            reason_len if reason_len - 1 > limit => {
+                let msg = "poison reason is too long";
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+            reason_len => {
+                let mut reason = vec![0; reason_len - 1];
+                reader.read_exact(&mut reason)?;
+                let reason = String::from_utf8(reason).map_err(|_e| {
+                    let msg = "non-utf8 poison reason";
+                    io::Error::new(io::ErrorKind::InvalidData, msg)
+                })?;
+                Some(reason)
+            }
+        };
+
+        // 'format_version' is optional too. Checked by `check_format_version`
+        // so newer versions are not reported as corruption.
+        let format_version = reader.read_vlq().unwrap_or_default();
+
+        // 'index_versions' is optional too.
+        let mut index_versions = BTreeMap::new();
+        let index_version_count: usize = reader.read_vlq().unwrap_or_default();
+        for _ in 0..index_version_count {
+            let name = reader.read_frame(limit)?;
+            let name = String::from_utf8(name).map_err(|_e| {
+                let msg = "non-utf8 index name";
+                io::Error::new(io::ErrorKind::InvalidData, msg)
+            })?;
+            let version = reader.read_vlq()?;
+            index_versions.insert(name, version);
+        }
+
+        // 'temp_files' is optional too.
+        let mut temp_files = BTreeSet::new();
+        let temp_file_count: usize = reader.read_vlq().unwrap_or_default();
+        for _ in 0..temp_file_count {
+            let name = reader.read_frame(limit)?;
+            let name = String::from_utf8(name).map_err(|_e| {
+                let msg = "non-utf8 temporary file name";
+                io::Error::new(io::ErrorKind::InvalidData, msg)
+            })?;
+            temp_files.insert(name);
+        }
+
+        // 'entry_count' is optional too. 0 means unknown. Otherwise, it is
+        // the count plus 1.
+        let entry_count = match reader.read_vlq().unwrap_or_default() {
+            0 => None,
+            count => Some(count - 1),
+        };
+
         Ok(Self {
             primary_len,
             indexes,
             epoch,
+            deleted,
+            user,
+            poison,
+            format_version,
+            index_versions,
+            temp_files,
+            entry_count,
         })
     }
 
     /// Write metadata to a writer.
     pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
-        let header = if cfg!(test) {
+        let header = if self.format_version >= ENTRY_FLAGS_FORMAT_VERSION {
+            HeaderVersion::V2
+        } else if cfg!(test) {
             HeaderVersion::V1
         } else {
             HeaderVersion::V0
@@ -103,15 +262,67 @@ impl LogMetadata {
         buf.write_vlq(self.primary_len)?;
         buf.write_vlq(self.indexes.len())?;
         for (name, len) in self.indexes.iter() {
-            let name = name.as_bytes();
-            buf.write_vlq(name.len())?;
-            buf.write_all(name)?;
+            buf.write_frame(name.as_bytes())?;
             buf.write_vlq(*len)?;
         }
         buf.write_vlq(self.epoch)?;
+        // Optional fields. A field is written if it or any following field
+        // is not the default.
+        let write_entry_count = self.entry_count.is_some();
+        let write_temp_files = write_entry_count || !self.temp_files.is_empty();
+        let write_index_versions = write_temp_files || !self.index_versions.is_empty();
+        let write_format_version = write_index_versions || self.format_version != 0;
+        let write_poison = write_format_version || self.poison.is_some();
+        let write_user = write_poison || !self.user.is_empty();
+        let write_deleted = write_user || !self.deleted.is_empty();
+        if write_deleted {
+            buf.write_vlq(self.deleted.len())?;
+            let mut last_offset = 0;
+            for &offset in self.deleted.iter() {
+                buf.write_vlq(offset - last_offset)?;
+                last_offset = offset;
+            }
+        }
+        if write_user {
+            buf.write_vlq(self.user.len())?;
+            for (key, value) in self.user.iter() {
+                buf.write_frame(key.as_bytes())?;
+                buf.write_frame(value)?;
+            }
+        }
+        if write_poison {
+            match &self.poison {
+                Some(reason) => {
+                    buf.write_vlq(reason.len() + 1)?;
+                    buf.write_all(reason.as_bytes())?;
+                }
+                None => buf.write_vlq(0)?,
+            }
+        }
+        if write_format_version {
+            buf.write_vlq(self.format_version)?;
+        }
+        if write_index_versions {
+            buf.write_vlq(self.index_versions.len())?;
+            for (name, version) in self.index_versions.iter() {
+                buf.write_frame(name.as_bytes())?;
+                buf.write_vlq(*version)?;
+            }
+        }
+        if write_temp_files {
+            buf.write_vlq(self.temp_files.len())?;
+            for name in self.temp_files.iter() {
+                buf.write_frame(name.as_bytes())?;
+            }
+        }
+        if let Some(count) = self.entry_count {
+            buf.write_vlq(count + 1)?;
+        }
         writer.write_all(header.to_bytes())?;
         match header {
-            HeaderVersion::V1 => writer.write_u64::<LittleEndian>(xxhash(&buf))?,
+            HeaderVersion::V1 | HeaderVersion::V2 => {
+                writer.write_u64::<LittleEndian>(xxhash(&buf))?
+            }
             HeaderVersion::V0 => writer.write_vlq(xxhash(&buf))?,
         }
         writer.write_vlq(buf.len())?;
@@ -146,7 +357,38 @@ impl LogMetadata {
             primary_len: len,
             indexes: BTreeMap::new(),
             epoch: utils::rand_u64(),
+            deleted: BTreeSet::new(),
+            user: BTreeMap::new(),
+            poison: None,
+            format_version: INITIAL_FORMAT_VERSION,
+            index_versions: BTreeMap::new(),
+            temp_files: BTreeSet::new(),
+            // An empty primary log has no entries.
+            entry_count: (len == PRIMARY_START_OFFSET).then_some(0),
+        }
+    }
+
+    /// Logical length of the index file. Return `None` if the index was never
+    /// built, or was built by a different version of the index function.
+    pub(crate) fn index_len(&self, def: &IndexDef) -> Option<u64> {
+        let name = def.metaname();
+        let version = self.index_versions.get(&name).cloned().unwrap_or(0);
+        if version != def.version {
+            return None;
+        }
+        self.indexes.get(&name).cloned()
+    }
+
+    /// Set the logical length of the index file, and the version of the index
+    /// function that built it.
+    pub(crate) fn set_index_len(&mut self, def: &IndexDef, len: u64) {
+        let name = def.metaname();
+        if def.version == 0 {
+            self.index_versions.remove(&name);
+        } else {
+            self.index_versions.insert(name.clone(), def.version);
         }
+        self.indexes.insert(name, len);
     }
 
     /// Test if two Metadata is compatible, aka. having the same length
@@ -154,6 +396,43 @@ impl LogMetadata {
     pub(crate) fn is_compatible_with(&self, other: &Self) -> bool {
         self.primary_len == other.primary_len && self.epoch == other.epoch
     }
+
+    /// Test if two Metadata describe the same entries, user metadata, and
+    /// poison state. Unlike `==`, this ignores index lengths.
+    pub(crate) fn has_same_content(&self, other: &Self) -> bool {
+        self.is_compatible_with(other)
+            && self.deleted == other.deleted
+            && self.user == other.user
+            && self.poison == other.poison
+    }
+
+    /// Total bytes of the primary log and indexes.
+    pub(crate) fn total_bytes(&self) -> u64 {
+        self.primary_len + self.indexes.values().sum::<u64>()
+    }
+
+    /// Return an error if the format version is not supported.
+    ///
+    /// This is not a data corruption, so `repair` won't destroy data
+    /// written by a newer version of this crate.
+    pub(crate) fn check_format_version(&self) -> crate::Result<()> {
+        if self.format_version > LATEST_FORMAT_VERSION {
+            let msg = format!(
+                "Log format version {} is not supported (latest supported version is {}). Upgrade indexedlog to use it.",
+                self.format_version, LATEST_FORMAT_VERSION
+            );
+            return Err(crate::Error::unsupported(msg));
+        }
+        Ok(())
+    }
+
+    /// Return an error if the [`Log`] is poisoned.
+    pub(crate) fn check_poison(&self) -> crate::Result<()> {
+        match &self.poison {
+            Some(reason) => Err(crate::Error::poisoned(reason)),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -162,17 +441,24 @@ enum HeaderVersion {
 
     // V1: xxhash uses fixed 8 bytes instead of vlq.
     V1,
+
+    // V2: same as V1. Used by format version 2 and later, so older versions
+    // refuse to read the metadata.
+    V2,
 }
 
 impl HeaderVersion {
     const HEADER_V0: &'static [u8] = b"meta\0";
     const HEADER_V1: &'static [u8] = b"meta\x01";
+    const HEADER_V2: &'static [u8] = b"meta\x02";
 
     fn from_reader(reader: &mut dyn Read) -> io::Result<Self> {
         assert_eq!(Self::HEADER_V0.len(), Self::HEADER_V0.len());
         let mut header = vec![0; Self::HEADER_V0.len()];
         reader.read_exact(&mut header)?;
-        if header == Self::HEADER_V1 {
+        if header == Self::HEADER_V2 {
+            Ok(Self::V2)
+        } else if header == Self::HEADER_V1 {
             Ok(Self::V1)
         } else if header == Self::HEADER_V0 {
             Ok(Self::V0)
@@ -186,6 +472,7 @@ impl HeaderVersion {
         match self {
             Self::V0 => Self::HEADER_V0,
             Self::V1 => Self::HEADER_V1,
+            Self::V2 => Self::HEADER_V2,
         }
     }
 }
@@ -198,9 +485,20 @@ mod tests {
     use super::*;
 
     quickcheck! {
-        fn test_roundtrip_meta(primary_len: u64, indexes: BTreeMap<String, u64>, epoch: u64) -> bool {
+        fn test_roundtrip_meta(primary_len: u64, indexes: BTreeMap<String, u64>, epoch: u64, deleted: BTreeSet<u64>, user: BTreeMap<String, Vec<u8>>, poison: Option<String>, format_version: u64, index_versions: BTreeMap<String, u64>) -> bool {
             let mut buf = Vec::new();
-            let meta = LogMetadata { primary_len, indexes, epoch,  };
+            let meta = LogMetadata { primary_len, indexes, epoch, deleted, user, poison, format_version, index_versions, temp_files: Default::default(), entry_count: None };
+            meta.write(&mut buf).expect("write");
+            let mut cur = Cursor::new(buf);
+            let meta_read = LogMetadata::read(&mut cur).expect("read");
+            meta_read == meta
+        }
+
+        fn test_roundtrip_meta_temp_files(primary_len: u64, temp_files: BTreeSet<String>, entry_count: Option<u32>) -> bool {
+            let mut buf = Vec::new();
+            let mut meta = LogMetadata::new_with_primary_len(primary_len);
+            meta.temp_files = temp_files;
+            meta.entry_count = entry_count.map(|c| c as u64);
             meta.write(&mut buf).expect("write");
             let mut cur = Cursor::new(buf);
             let meta_read = LogMetadata::read(&mut cur).expect("read");
@@ -209,7 +507,7 @@ mod tests {
 
         fn test_roundtrip_meta_v0(primary_len: u64, indexes: BTreeMap<String, u64>, epoch: u64) -> bool {
             let mut buf = Vec::new();
-            let meta = LogMetadata { primary_len, indexes, epoch,  };
+            let meta = LogMetadata { primary_len, indexes, epoch, deleted: Default::default(), user: Default::default(), poison: None, format_version: 0, index_versions: Default::default(), temp_files: Default::default(), entry_count: None };
             meta.write_using_header(&mut buf, HeaderVersion::V0).expect("write");
             let mut cur = Cursor::new(buf);
             let meta_read = LogMetadata::read(&mut cur).expect("read");
@@ -218,7 +516,7 @@ mod tests {
 
         fn test_roundtrip_meta_file(primary_len: u64, indexes: BTreeMap<String, u64>, epoch: u64) -> bool {
             let dir = tempdir().unwrap();
-            let meta = LogMetadata { primary_len, indexes, epoch,  };
+            let meta = LogMetadata { primary_len, indexes, epoch, deleted: Default::default(), user: Default::default(), poison: None, format_version: 0, index_versions: Default::default(), temp_files: Default::default(), entry_count: None };
             let path = dir.path().join("meta");
             meta.write_file(&path, false).expect("write_file");
             let meta_read = LogMetadata::read_file(&path).expect("read_file");
@@ -234,6 +532,13 @@ mod tests {
             primary_len: 1,
             indexes: Default::default(),
             epoch: 42,
+            deleted: Default::default(),
+            user: Default::default(),
+            poison: None,
+            format_version: LATEST_FORMAT_VERSION,
+            index_versions: Default::default(),
+            temp_files: Default::default(),
+            entry_count: None,
         };
         let mut buf: Vec<u8> = Vec::new();
         meta.write(&mut buf).unwrap();
@@ -243,4 +548,29 @@ mod tests {
         let content = format!("{:?}", &buf);
         assert!(err.to_string().contains(&content));
     }
+
+    #[test]
+    fn test_read_untrusted_lengths() {
+        // A huge body length in the header is not allocated upfront.
+        let mut buf = HeaderVersion::HEADER_V1.to_vec();
+        buf.extend_from_slice(&[0; 8]);
+        buf.write_vlq(u64::MAX >> 1).unwrap();
+        let err = LogMetadata::read(&buf[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Body fields are checked too.
+        let mut body = Vec::new();
+        for value in [0, 0, 0, 2, u64::MAX, 1] {
+            body.write_vlq(value).unwrap();
+        }
+        let err = LogMetadata::read_body(&body).unwrap_err();
+        assert_eq!(err.to_string(), "deleted offset overflow");
+
+        let mut body = Vec::new();
+        for value in [0, 0, 0, 0, 0, u64::MAX] {
+            body.write_vlq(value).unwrap();
+        }
+        let err = LogMetadata::read_body(&body).unwrap_err();
+        assert_eq!(err.to_string(), "poison reason is too long");
+    }
 }
@@ -0,0 +1,82 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::lock::ScopedDirLock;
+use crate::log::Log;
+#[cfg(doc)]
+use crate::log::OpenOptions;
+
+/// Counters of [`Log`] operations. Obtained by [`Log::metrics`].
+///
+/// Counters are shared by [`Log`]s opened by clones of the same
+/// [`OpenOptions`], including [`Log`]s reloaded by [`Log::sync`]. They never
+/// decrease, and can be exported to monitoring systems periodically.
+#[derive(Debug, Default)]
+pub struct LogMetrics {
+    pub(crate) appends: AtomicU64,
+    pub(crate) bytes_written: AtomicU64,
+    pub(crate) lookups: AtomicU64,
+    pub(crate) index_misses: AtomicU64,
+    pub(crate) lock_waits: AtomicU64,
+    pub(crate) repairs: AtomicU64,
+}
+
+impl LogMetrics {
+    /// Number of entries appended by [`Log::append`] or [`Log::append_writer`].
+    pub fn appends(&self) -> u64 {
+        self.appends.load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes written to primary logs. Index files are not included.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// Number of [`Log::lookup`], [`Log::lookup_prefix`],
+    /// [`Log::lookup_prefix_hex`] and [`Log::lookup_range`] calls.
+    pub fn lookups(&self) -> u64 {
+        self.lookups.load(Ordering::Relaxed)
+    }
+
+    /// Number of [`Log::lookup`] calls with keys missing from the index.
+    pub fn index_misses(&self) -> u64 {
+        self.index_misses.load(Ordering::Relaxed)
+    }
+
+    /// Number of times a directory lock was held by others, and had to be
+    /// waited for.
+    pub fn lock_waits(&self) -> u64 {
+        self.lock_waits.load(Ordering::Relaxed)
+    }
+
+    /// Number of completed repairs, including auto repairs.
+    pub fn repairs(&self) -> u64 {
+        self.repairs.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Count `lock` in `lock_waits` if it was contended.
+    pub(crate) fn record_lock(&self, lock: &ScopedDirLock) {
+        if lock.waited() {
+            Self::add(&self.lock_waits, 1);
+        }
+    }
+}
+
+impl Log {
+    /// Return operation counters. See [`LogMetrics`].
+    pub fn metrics(&self) -> Arc<LogMetrics> {
+        self.open_options.metrics.clone()
+    }
+}
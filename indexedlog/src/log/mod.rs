@@ -15,25 +15,35 @@
 //
 // Primary log:
 //   LOG := HEADER + ENTRY_LIST
-//   HEADER := 'log\0'
+//   HEADER := 'indexedlog0\0'
 //   ENTRY_LIST := '' | ENTRY_LIST + ENTRY
-//   ENTRY := ENTRY_FLAGS + LEN(CONTENT) + CHECKSUM + CONTENT
-//   CHECKSUM := '' | XXHASH64(CONTENT) | XXHASH32(CONTENT)
+//   ENTRY := ENTRY_FLAGS + LEN(PAYLOAD) + CHECKSUMS + PAYLOAD
+//   CHECKSUMS := CHECKSUM(PAYLOAD) | CHUNK_CHECKSUMS (if ENTRY_FLAG_CHUNKED)
+//              | '' (if ENTRY_FLAG_NO_CHECKSUM)
+//   CHUNK_CHECKSUMS := CHECKSUM(CHUNK) | CHUNK_CHECKSUMS + CHECKSUM(CHUNK)
+//   CHECKSUM := '' | XXHASH64 | XXHASH32 | XXH3 | CRC32C
+//   CHUNK := 64KB of PAYLOAD (the last one can be shorter)
+//   PAYLOAD := CONTENT | TIMESTAMP + CONTENT (if ENTRY_FLAG_HAS_TIMESTAMP)
+//   TIMESTAMP := milliseconds since UNIX epoch (u64)
 //
 // Metadata:
 //   META := HEADER + XXHASH64(DATA) + LEN(DATA) + DATA
 //   HEADER := 'meta\0'
-//   DATA := LEN(LOG) + LEN(INDEXES) + INDEXES
+//   DATA := LEN(LOG) + LEN(INDEXES) + INDEXES + EPOCH + DELETED
 //   INDEXES := '' | INDEXES + INDEX
 //   INDEX := LEN(NAME) + NAME + INDEX_LOGIC_LEN
+//   DELETED := '' | LEN(OFFSETS) + OFFSETS
+//   OFFSETS := '' | OFFSETS + DELTA(DELETED_ENTRY_OFFSET)
 //
 // Indexes:
 //   See `index.rs`.
 //
-// Integers are VLQ encoded, except for XXHASH64 and XXHASH32, which uses
-// LittleEndian encoding.
+// Integers are VLQ encoded, except for checksums, which use LittleEndian
+// encoding.
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Formatter;
@@ -43,10 +53,16 @@ use std::io;
 use std::io::Seek;
 use std::io::SeekFrom;
 use std::io::Write;
+use std::ops::Range;
 use std::ops::RangeBounds;
 use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use byteorder::ByteOrder;
 use byteorder::LittleEndian;
@@ -57,6 +73,7 @@ use tracing::trace;
 use vlqencoding::VLQDecodeAt;
 use vlqencoding::VLQEncode;
 
+use crate::codec::Codec;
 use crate::config;
 use crate::errors::IoResultExt;
 use crate::errors::ResultExt;
@@ -70,19 +87,40 @@ use crate::index::ReadonlyBuffer;
 use crate::lock::ScopedDirLock;
 use crate::lock::READER_LOCK_OPTS;
 use crate::utils;
+use crate::utils::crc32c;
 use crate::utils::mmap_path;
+use crate::utils::xxh3;
 use crate::utils::xxhash;
 use crate::utils::xxhash32;
 
+mod append_writer;
+mod auto_sync;
+mod backup;
+mod change_detector;
+mod describe;
+mod durability;
+mod export;
 mod fold;
+mod index_build;
+mod index_recovery;
+mod lazy_index;
+mod memory;
 mod meta;
+mod metrics;
 mod open_options;
 mod path;
+mod poison;
 mod repair;
+mod rewrite;
 #[cfg(test)]
 pub(crate) mod tests;
+mod verify;
+mod watch;
 
+pub use minibytes::MmapAdvice;
+pub use open_options::ChecksumGranularity;
 pub use open_options::ChecksumType;
+pub use open_options::ChecksumVerification;
 pub use open_options::FlushFilterContext;
 pub use open_options::FlushFilterFunc;
 pub use open_options::FlushFilterOutput;
@@ -91,10 +129,27 @@ pub use open_options::IndexOutput;
 pub use open_options::OpenOptions;
 pub use path::GenericPath;
 
+pub use self::append_writer::LogAppendWriter;
+pub use self::auto_sync::AutoSyncLog;
+pub use self::change_detector::ChangeDetector;
+pub use self::describe::EntryDescription;
+pub use self::durability::Durability;
+pub use self::durability::FsyncHandle;
 pub use self::fold::Fold;
 pub use self::fold::FoldDef;
 use self::fold::FoldState;
+pub use self::index_build::IndexBuild;
+pub use self::index_recovery::IndexRecovery;
+pub use self::memory::MemoryDir;
 pub use self::meta::LogMetadata;
+pub(crate) use self::meta::ENTRY_FLAGS_FORMAT_VERSION;
+pub(crate) use self::meta::LATEST_FORMAT_VERSION;
+pub use self::metrics::LogMetrics;
+pub use self::repair::RepairReport;
+use self::verify::VerifiedRanges;
+pub use self::verify::VerifyProblem;
+pub use self::verify::VerifyProblemKind;
+pub use self::watch::LogWatcher;
 
 // Constants about file names
 pub(crate) const PRIMARY_FILE: &str = "log";
@@ -104,8 +159,21 @@ pub(crate) const META_FILE: &str = "meta";
 
 const ENTRY_FLAG_HAS_XXHASH64: u32 = 1;
 const ENTRY_FLAG_HAS_XXHASH32: u32 = 2;
+const ENTRY_FLAG_HAS_XXH3: u32 = 4;
+const ENTRY_FLAG_HAS_CRC32C: u32 = 8;
+const ENTRY_FLAG_CHUNKED: u32 = 16;
+const ENTRY_FLAG_NO_CHECKSUM: u32 = 32;
+const ENTRY_FLAG_HAS_TIMESTAMP: u32 = 64;
+const ENTRY_FLAG_CHECKSUM_MASK: u32 =
+    ENTRY_FLAG_HAS_XXHASH64 | ENTRY_FLAG_HAS_XXHASH32 | ENTRY_FLAG_HAS_XXH3 | ENTRY_FLAG_HAS_CRC32C;
+// Entry flags understood by all versions.
+const ENTRY_FLAG_LEGACY_MASK: u32 = ENTRY_FLAG_HAS_XXHASH64 | ENTRY_FLAG_HAS_XXHASH32;
+
+// Chunk size used by ChecksumGranularity::Chunk.
+const ENTRY_CHECKSUM_CHUNK_SIZE: usize = 1 << 16;
 
 // 1MB index checksum. This makes checksum file within one block (4KB) for 512MB index.
+// Default of `IndexDef::checksum_chunk_size_logarithm`.
 const INDEX_CHECKSUM_CHUNK_SIZE_LOGARITHM: u32 = 20;
 
 /// An append-only storage with indexes and integrity checks.
@@ -131,6 +199,9 @@ pub struct Log {
     pub(crate) mem_buf: Pin<Box<Vec<u8>>>,
     pub(crate) meta: LogMetadata,
     indexes: Vec<Index>,
+    // Indexes not loaded yet, by index id. `indexes` has empty placeholders
+    // for them. See `OpenOptions::lazy_indexes`.
+    lazy_indexes: Vec<Option<OnceLock<Index>>>,
     // On-demand caches of the folds defined by open_options.
     // disk_folds only includes clean (on-disk) entries.
     // all_folds includes both clean (on-disk) and dirty (in-memory) entries.
@@ -141,9 +212,22 @@ pub struct Log {
     // This could be improved to be per index. For now, it's a single state for simplicity. It's
     // probably fine considering index corruptions are rare.
     index_corrupted: bool,
+    // Offsets of entries deleted by `delete` but not yet written to disk.
+    // On-disk deletions are tracked by `meta.deleted`.
+    dirty_deleted: BTreeSet<u64>,
+    // Changes to `meta.user` not yet written to disk. `None` means removal.
+    dirty_user: BTreeMap<String, Option<Vec<u8>>>,
+    // Format version required by entries in `mem_buf`. 0 if entries only use
+    // flags understood by all versions.
+    dirty_format_version: u64,
+    // Number of entries in `mem_buf`.
+    dirty_entry_count: u64,
     open_options: OpenOptions,
     // Indicate an active reader. Destrictive writes (repair) are unsafe.
     reader_lock: Option<ScopedDirLock>,
+    // Ranges of disk_buf with verified checksums. Only used by
+    // `ChecksumVerification::FirstAccess`.
+    verified: Mutex<VerifiedRanges>,
 }
 
 /// Iterator over all entries in a [`Log`].
@@ -151,6 +235,24 @@ pub struct LogIter<'a> {
     next_offset: u64,
     errored: bool,
     log: &'a Log,
+    // Whether `iter_advice` or `release_after_iter` need handling on drop.
+    advised: bool,
+}
+
+/// Iterator over all entries in a [`Log`], with their offsets.
+///
+/// It is a wrapper around [`LogIter`]. See [`LogIter::with_offsets`].
+pub struct LogOffsetIter<'a> {
+    inner: LogIter<'a>,
+}
+
+/// Iterator over [`Log`] entries as [`Bytes`].
+///
+/// It is a wrapper around [`LogIter`] or [`LogLookupIter`]. See
+/// [`LogIter::into_bytes_iter`] and [`LogLookupIter::into_bytes_iter`].
+pub struct LogBytesIter<'a, I> {
+    inner: I,
+    log: &'a Log,
 }
 
 /// Iterator over [`Log`] entries selected by an index lookup.
@@ -164,7 +266,8 @@ pub struct LogLookupIter<'a> {
 
 /// Iterator over keys and [`LogLookupIter`], filtered by an index prefix.
 ///
-/// It is a wrapper around [index::RangeIter].
+/// It is a wrapper around [index::RangeIter]. Keys are sorted. Use `rev()`
+/// to visit the largest keys first without collecting all keys.
 pub struct LogRangeIter<'a> {
     inner_iter: RangeIter<'a>,
     errored: bool,
@@ -172,6 +275,15 @@ pub struct LogRangeIter<'a> {
     index: &'a Index,
 }
 
+/// Iterator over distinct keys of an index, filtered by a prefix.
+/// Created by [`Log::lookup_prefix_keys`].
+///
+/// It is a wrapper around [index::RangeIter]. Entries are not read.
+pub struct LogKeyIter<'a> {
+    inner_iter: RangeIter<'a>,
+    errored: bool,
+}
+
 /// Satisfy [`index::ReadonlyBuffer`] trait so [`Log`] can use external
 /// keys on [`Index`] for in-memory-only entries.
 struct ExternalKeyBuffer {
@@ -220,8 +332,9 @@ unsafe impl Send for ExternalKeyBuffer {}
 unsafe impl Sync for ExternalKeyBuffer {}
 
 // Some design notes:
-// - Public APIs do not expose internal offsets of entries. This avoids issues when an in-memory
-//   entry gets moved after `flush`.
+// - Public APIs avoid exposing internal offsets of entries. This avoids issues when an in-memory
+//   entry gets moved after `flush`. `LogOffsetIter` and `entry_at` are exceptions for external
+//   references and document when offsets are stable.
 // - The only write-to-disk operation is `flush`, aside from creating an empty `Log`. This makes it
 //   easier to verify correctness - just make sure `flush` is properly handled (ex. by locking).
 
@@ -247,81 +360,12 @@ impl Log {
     /// To write in-memory entries and indexes to disk, call [`Log::sync`].
     pub fn append<T: AsRef<[u8]>>(&mut self, data: T) -> crate::Result<()> {
         let result: crate::Result<_> = (|| {
-            let data = data.as_ref();
-
-            let checksum_type = if self.open_options.checksum_type == ChecksumType::Auto {
-                // xxhash64 is slower for smaller data. A quick benchmark on x64 platform shows:
-                //
-                // bytes  xxhash32  xxhash64 (MB/s)
-                //   32       1882      1600
-                //   40       1739      1538
-                //   48       2285      1846
-                //   56       2153      2000
-                //   64       2666      2782
-                //   72       2400      2322
-                //   80       2962      2758
-                //   88       2750      2750
-                //   96       3200      3692
-                //  104       2810      3058
-                //  112       3393      3500
-                //  120       3000      3428
-                //  128       3459      4266
-                const XXHASH64_THRESHOLD: usize = 88;
-                if data.len() >= XXHASH64_THRESHOLD {
-                    ChecksumType::Xxhash64
-                } else {
-                    ChecksumType::Xxhash32
-                }
-            } else {
-                self.open_options.checksum_type
-            };
-
-            let offset = self.meta.primary_len + self.mem_buf.len() as u64;
-
-            // Design note: Currently checksum_type is the only thing that decides
-            // entry_flags.  Entry flags is not designed to just cover different
-            // checksum types.  For example, if we'd like to introduce transparent
-            // compression (maybe not a good idea since it can be more cleanly built
-            // at an upper layer), or some other ways to store data (ex. reference
-            // to other data, or fixed length data), they can probably be done by
-            // extending the entry type.
-            let mut entry_flags = 0;
-            entry_flags |= match checksum_type {
-                ChecksumType::Xxhash64 => ENTRY_FLAG_HAS_XXHASH64,
-                ChecksumType::Xxhash32 => ENTRY_FLAG_HAS_XXHASH32,
-                ChecksumType::Auto => unreachable!(),
-            };
-
-            self.mem_buf.write_vlq(entry_flags).infallible()?;
-            self.mem_buf.write_vlq(data.len()).infallible()?;
-
-            match checksum_type {
-                ChecksumType::Xxhash64 => {
-                    self.mem_buf
-                        .write_u64::<LittleEndian>(xxhash(data))
-                        .infallible()?;
-                }
-                ChecksumType::Xxhash32 => {
-                    self.mem_buf
-                        .write_u32::<LittleEndian>(xxhash32(data))
-                        .infallible()?;
-                }
-                ChecksumType::Auto => unreachable!(),
-            };
-            let data_offset = self.meta.primary_len + self.mem_buf.len() as u64;
-
-            self.mem_buf.write_all(data).infallible()?;
-            self.update_indexes_for_in_memory_entry(data, offset, data_offset)?;
-            self.update_fold_for_in_memory_entry(data, offset, data_offset)?;
-
-            if let Some(threshold) = self.open_options.auto_sync_threshold {
-                if self.mem_buf.len() as u64 >= threshold {
-                    self.sync()
-                        .context("sync triggered by auto_sync_threshold")?;
-                }
-            }
-
-            Ok(())
+            self.check_writable()?;
+            self.check_entry_size(data.as_ref().len())?;
+            self.check_quota(&self.meta, self.encoded_entry_len(data.as_ref().len())?)?;
+            self.append_in_memory(data.as_ref(), None)?;
+            LogMetrics::add(&self.open_options.metrics.appends, 1);
+            self.maybe_auto_sync()
         })();
 
         result
@@ -336,6 +380,162 @@ impl Log {
             .context(|| format!("  Log.dir = {:?}", self.dir))
     }
 
+    /// Append an entry to the in-memory buffer. Update indexes and folds.
+    ///
+    /// `timestamp` is recorded if `entry_timestamp` is enabled. `None` means
+    /// the current time.
+    fn append_in_memory(&mut self, data: &[u8], timestamp: Option<u64>) -> crate::Result<()> {
+        let offset = self.meta.primary_len + self.mem_buf.len() as u64;
+        let entry_flags = self.entry_flags(data.len());
+        let timestamp = match entry_flags & ENTRY_FLAG_HAS_TIMESTAMP {
+            0 => None,
+            _ => Some(timestamp.unwrap_or_else(utils::now_millis)),
+        };
+        let payload_len = data.len() + if timestamp.is_some() { 8 } else { 0 };
+
+        self.dirty_format_version = self
+            .dirty_format_version
+            .max(entry_format_version(entry_flags));
+        self.dirty_entry_count += 1;
+        self.mem_buf.write_vlq(entry_flags).infallible()?;
+        self.mem_buf.write_vlq(payload_len).infallible()?;
+
+        // Reserve space for checksums. Fill them after writing the payload.
+        let (checksum_width, checksum_count) =
+            entry_checksum_layout(entry_flags, payload_len as u64).unwrap();
+        let checksums_pos = self.mem_buf.len();
+        let payload_pos = checksums_pos + (checksum_width * checksum_count) as usize;
+        self.mem_buf.resize(payload_pos, 0);
+
+        if let Some(timestamp) = timestamp {
+            self.mem_buf
+                .write_u64::<LittleEndian>(timestamp)
+                .infallible()?;
+        }
+        let data_offset = self.meta.primary_len + self.mem_buf.len() as u64;
+        self.mem_buf.write_all(data).infallible()?;
+
+        let checksums: Vec<u64> =
+            entry_checksum_chunks(&self.mem_buf[payload_pos..], checksum_count)
+                .map(|chunk| entry_checksum(entry_flags, chunk))
+                .collect();
+        for (i, checksum) in checksums.into_iter().enumerate() {
+            let pos = checksums_pos + i * checksum_width as usize;
+            let buf = &mut self.mem_buf[pos..pos + checksum_width as usize];
+            match checksum_width {
+                8 => LittleEndian::write_u64(buf, checksum),
+                4 => LittleEndian::write_u32(buf, checksum as u32),
+                _ => unreachable!(),
+            }
+        }
+
+        self.update_indexes_for_in_memory_entry(data, offset, data_offset)?;
+        self.update_fold_for_in_memory_entry(data, offset, data_offset)?;
+
+        Ok(())
+    }
+
+    /// Entry flags used by [`Log::append_in_memory`] for `data_len` bytes of
+    /// content.
+    fn entry_flags(&self, data_len: usize) -> u32 {
+        let checksum_type = if self.open_options.checksum_type == ChecksumType::Auto {
+            // xxhash64 is slower for smaller data. A quick benchmark on x64 platform shows:
+            //
+            // bytes  xxhash32  xxhash64 (MB/s)
+            //   32       1882      1600
+            //   40       1739      1538
+            //   48       2285      1846
+            //   56       2153      2000
+            //   64       2666      2782
+            //   72       2400      2322
+            //   80       2962      2758
+            //   88       2750      2750
+            //   96       3200      3692
+            //  104       2810      3058
+            //  112       3393      3500
+            //  120       3000      3428
+            //  128       3459      4266
+            const XXHASH64_THRESHOLD: usize = 88;
+            if data_len >= XXHASH64_THRESHOLD {
+                ChecksumType::Xxhash64
+            } else {
+                ChecksumType::Xxhash32
+            }
+        } else {
+            self.open_options.checksum_type
+        };
+
+        // Design note: Currently checksum_type is the only thing that decides
+        // entry_flags.  Entry flags is not designed to just cover different
+        // checksum types.  For example, if we'd like to introduce transparent
+        // compression (maybe not a good idea since it can be more cleanly built
+        // at an upper layer), or some other ways to store data (ex. reference
+        // to other data, or fixed length data), they can probably be done by
+        // extending the entry type.
+        let skip_checksum = self.open_options.skip_checksum;
+        let mut entry_flags = 0;
+        entry_flags |= match checksum_type {
+            _ if skip_checksum => ENTRY_FLAG_NO_CHECKSUM,
+            ChecksumType::Xxhash64 => ENTRY_FLAG_HAS_XXHASH64,
+            ChecksumType::Xxhash32 => ENTRY_FLAG_HAS_XXHASH32,
+            ChecksumType::Xxh3 => ENTRY_FLAG_HAS_XXH3,
+            ChecksumType::Crc32c => ENTRY_FLAG_HAS_CRC32C,
+            ChecksumType::Auto => unreachable!(),
+        };
+        let mut payload_len = data_len;
+        if self.open_options.entry_timestamp {
+            entry_flags |= ENTRY_FLAG_HAS_TIMESTAMP;
+            payload_len += 8;
+        }
+        // Keep small entries readable by older versions.
+        let chunked = !skip_checksum
+            && self.open_options.checksum_granularity == ChecksumGranularity::Chunk
+            && payload_len > ENTRY_CHECKSUM_CHUNK_SIZE;
+        if chunked {
+            entry_flags |= ENTRY_FLAG_CHUNKED;
+        }
+        entry_flags
+    }
+
+    /// Size of the entry written by [`Log::append_in_memory`] for `data_len`
+    /// bytes of content, including flags, length, checksums, and timestamp.
+    fn encoded_entry_len(&self, data_len: usize) -> crate::Result<u64> {
+        let entry_flags = self.entry_flags(data_len);
+        let payload_len = match entry_flags & ENTRY_FLAG_HAS_TIMESTAMP {
+            0 => data_len as u64,
+            _ => data_len as u64 + 8,
+        };
+        let (checksum_width, checksum_count) =
+            entry_checksum_layout(entry_flags, payload_len).unwrap();
+        const MAX_HEADER_LEN: usize = 32;
+        let mut header = [0u8; MAX_HEADER_LEN];
+        let mut cursor = &mut header[..];
+        cursor.write_vlq(entry_flags).infallible()?;
+        cursor.write_vlq(payload_len).infallible()?;
+        let header_len = (MAX_HEADER_LEN - cursor.len()) as u64;
+        Ok(header_len + checksum_width * checksum_count + payload_len)
+    }
+
+    /// Call [`Log::sync`] if the in-memory buffer exceeds `auto_sync_threshold`,
+    /// or an index exceeds its `dirty_memory_threshold`.
+    fn maybe_auto_sync(&mut self) -> crate::Result<()> {
+        if let Some(threshold) = self.open_options.auto_sync_threshold {
+            if self.mem_buf.len() as u64 >= threshold {
+                self.sync()
+                    .context("sync triggered by auto_sync_threshold")?;
+                return Ok(());
+            }
+        }
+        // Indexes are not written to disk for in-memory Logs.
+        if self.dir.as_opt_path().is_some()
+            && (0..self.indexes.len()).any(|i| self.is_index_over_memory_threshold(i))
+        {
+            self.sync()
+                .context("sync triggered by dirty_memory_threshold")?;
+        }
+        Ok(())
+    }
+
     /// Remove dirty (in-memory) state. Restore the [`Log`] to the state as
     /// if it's just loaded from disk without modifications.
     pub fn clear_dirty(&mut self) -> crate::Result<()> {
@@ -345,6 +545,10 @@ impl Log {
                 index.clear_dirty();
             }
             self.mem_buf.clear();
+            self.dirty_format_version = 0;
+            self.dirty_entry_count = 0;
+            self.dirty_deleted.clear();
+            self.dirty_user.clear();
             self.all_folds = self.disk_folds.clone();
             self.update_indexes_for_on_disk_entries()?;
             Ok(())
@@ -412,6 +616,12 @@ impl Log {
             mem_buf,
             meta: self.meta.clone(),
             indexes,
+            // Lazy indexes loaded by `self` use `self.mem_buf`. Load them again.
+            lazy_indexes: self
+                .lazy_indexes
+                .iter()
+                .map(|lazy| lazy.as_ref().map(|_| OnceLock::new()))
+                .collect(),
             disk_folds: self.disk_folds.clone(),
             all_folds: if copy_dirty {
                 &self.all_folds
@@ -420,8 +630,29 @@ impl Log {
             }
             .clone(),
             index_corrupted: false,
+            dirty_deleted: if copy_dirty {
+                self.dirty_deleted.clone()
+            } else {
+                BTreeSet::new()
+            },
+            dirty_user: if copy_dirty {
+                self.dirty_user.clone()
+            } else {
+                BTreeMap::new()
+            },
+            dirty_format_version: if copy_dirty {
+                self.dirty_format_version
+            } else {
+                0
+            },
+            dirty_entry_count: if copy_dirty {
+                self.dirty_entry_count
+            } else {
+                0
+            },
             open_options: self.open_options.clone(),
             reader_lock,
+            verified: Mutex::new(self.verified.lock().unwrap().clone()),
         };
 
         if !copy_dirty {
@@ -450,12 +681,21 @@ impl Log {
     /// For in-memory-only Logs, this function does nothing, and returns 0.
     pub fn sync(&mut self) -> crate::Result<u64> {
         let result: crate::Result<_> = (|| {
+            let op = op_span!(
+                "indexedlog::log::sync",
+                path = ?self.dir,
+                bytes_written = tracing::field::Empty
+            );
             let span = debug_span!("Log::sync", dirty_bytes = self.mem_buf.len());
             if let Some(dir) = &self.dir.as_opt_path() {
                 span.record("dir", &dir.to_string_lossy().as_ref());
             }
             let _guard = span.enter();
 
+            if let GenericPath::Memory(dir) = &self.dir {
+                let dir = dir.clone();
+                return self.sync_memory(&dir);
+            }
             if self.dir.as_opt_path().is_none() {
                 // See Index::flush for why this is not an Err.
                 return Ok(0);
@@ -464,7 +704,7 @@ impl Log {
             fn check_append_only(this: &Log, new_meta: &LogMetadata) -> crate::Result<()> {
                 let old_meta = &this.meta;
                 if old_meta.primary_len > new_meta.primary_len {
-                    Err(crate::Error::path(
+                    Err(crate::Error::at_path(
                         this.dir.as_opt_path().unwrap(),
                         format!(
                             "on-disk log is unexpectedly smaller ({} bytes) than its previous version ({} bytes)",
@@ -477,8 +717,12 @@ impl Log {
             }
 
             // Read-only fast path - no need to take directory lock.
-            if self.mem_buf.is_empty() {
+            if !self.has_pending_changes() {
                 if let Ok(meta) = Self::load_or_create_meta(&self.dir, false) {
+                    meta.check_format_version()?;
+                    if !self.open_options.read_only {
+                        meta.check_poison()?;
+                    }
                     let changed = self.meta != meta;
                     let truncated = self.meta.epoch != meta.epoch;
                     if !truncated {
@@ -490,11 +734,18 @@ impl Log {
                         // Indexes can be reused, since they do not have new in-memory
                         // entries, and the on-disk primary log is append-only (so data
                         // already present in the indexes is valid).
+                        let verified = std::mem::take(&mut self.verified);
+                        // Placeholders of lazy indexes cannot be reused.
+                        let reuse = !truncated && !self.has_lazy_indexes();
                         *self = self.open_options.clone().open_internal(
                             &self.dir,
-                            if truncated { None } else { Some(&self.indexes) },
+                            if reuse { Some(&self.indexes) } else { None },
                             None,
                         )?;
+                        // Verified entries are unchanged, for the same reason.
+                        if !truncated {
+                            self.verified = verified;
+                        }
                     }
                 } else {
                     // If meta can not be read, do not error out.
@@ -512,15 +763,32 @@ impl Log {
             // log, then update indexes.
             let dir = self.dir.as_opt_path().unwrap().to_path_buf();
             let lock = ScopedDirLock::new(&dir)?;
+            self.open_options.metrics.record_lock(&lock);
 
             // Step 1: Reload metadata to get the latest view of the files.
             let mut meta = Self::load_or_create_meta(&self.dir, false)?;
+            meta.check_format_version()?;
+            meta.check_poison()?;
+            self.check_quota(&meta, 0)?;
             let changed = self.meta != meta;
             let truncated = self.meta.epoch != meta.epoch;
             if !truncated {
                 check_append_only(self, &meta)?;
             }
 
+            // Deletions of on-disk entries can be preserved unless the log was
+            // truncated. Deletions of in-memory entries are handled by not
+            // re-inserting them, since `iter_dirty` skips deleted entries.
+            let disk_deleted: BTreeSet<u64> = if truncated {
+                BTreeSet::new()
+            } else {
+                self.dirty_deleted
+                    .range(..self.meta.primary_len)
+                    .cloned()
+                    .collect()
+            };
+            let dirty_user = std::mem::take(&mut self.dirty_user);
+
             // Cases where Log and Indexes need to be reloaded.
             if changed && self.open_options.flush_filter.is_some() {
                 let filter = self.open_options.flush_filter.unwrap();
@@ -540,13 +808,16 @@ impl Log {
                         .map_err(|err| crate::Error::wrap(err, "failed to run filter function"))?
                     {
                         FlushFilterOutput::Drop => {}
-                        FlushFilterOutput::Keep => log.append(content)?,
-                        FlushFilterOutput::Replace(content) => log.append(content)?,
+                        FlushFilterOutput::Keep => log.append_in_memory(content, None)?,
+                        FlushFilterOutput::Replace(content) => {
+                            log.append_in_memory(&content, None)?
+                        }
                     }
                 }
 
                 // Replace "self" so we can continue flushing the updated data.
                 *self = log;
+                self.dirty_deleted = disk_deleted;
             } else if truncated {
                 // Reload log and indexes, and re-insert entries.
                 let mut log = self
@@ -562,13 +833,17 @@ impl Log {
 
                 for entry in self.iter_dirty() {
                     let content = entry?;
-                    log.append(content)?;
+                    log.append_in_memory(content, None)?;
                 }
 
                 // Replace "self" so we can continue flushing the updated data.
                 *self = log;
+                self.dirty_deleted = disk_deleted;
             }
 
+            // Indexes are reused or flushed below. Load deferred ones.
+            self.load_lazy_indexes()?;
+
             // Step 2: Append to the primary log.
             let primary_path = self.dir.as_opt_path().unwrap().join(PRIMARY_FILE);
             let mut primary_file = fs::OpenOptions::new()
@@ -602,16 +877,35 @@ impl Log {
                 // This might be another process re-creating the file.
                 // Do not consider this as a corruption (?).
                 // TODO: Review this decision.
-                let err = crate::Error::path(&primary_path, msg);
+                let err = crate::Error::at_path(&primary_path, msg);
                 return Err(err);
             }
 
             // Actually write the primary log. Once it's written, we can remove the in-memory buffer.
-            primary_file
-                .write_all(&self.mem_buf)
-                .context(&primary_path, || {
-                    format!("cannot write data ({} bytes)", self.mem_buf.len())
-                })?;
+            let encoded_buf;
+            let buf: &[u8] = match self.open_options.codec.as_deref() {
+                Some(codec) => {
+                    encoded_buf = {
+                        let mut buf = self.mem_buf.to_vec();
+                        codec.encode(meta.primary_len, &mut buf);
+                        buf
+                    };
+                    &encoded_buf
+                }
+                None => &self.mem_buf,
+            };
+            fail_point!("log::sync::mid_append", || {
+                let _ = primary_file.write_all(&buf[..buf.len() / 2]);
+            });
+            primary_file.write_all(buf).context(&primary_path, || {
+                format!("cannot write data ({} bytes)", self.mem_buf.len())
+            })?;
+            op.record("bytes_written", buf.len() as u64);
+            LogMetrics::add(&self.open_options.metrics.bytes_written, buf.len() as u64);
+            if let Some(chunk_size) = self.open_options.preallocate_chunk_size {
+                let len = meta.primary_len + buf.len() as u64;
+                preallocate_primary(&primary_file, &primary_path, len, chunk_size);
+            }
 
             if self.open_options.fsync || config::get_global_fsync() {
                 primary_file
@@ -619,8 +913,31 @@ impl Log {
                     .context(&primary_path, "cannot fsync")?;
             }
 
+            // In-memory entries were written at `meta.primary_len` instead of
+            // `self.meta.primary_len`. Adjust their offsets accordingly.
+            let old_primary_len = self.meta.primary_len;
+            for offset in std::mem::take(&mut self.dirty_deleted) {
+                if offset >= old_primary_len {
+                    meta.deleted
+                        .insert(offset - old_primary_len + meta.primary_len);
+                } else {
+                    meta.deleted.insert(offset);
+                }
+            }
+
+            for (key, value) in dirty_user {
+                match value {
+                    Some(value) => meta.user.insert(key, value),
+                    None => meta.user.remove(&key),
+                };
+            }
+
             meta.primary_len += self.mem_buf.len() as u64;
+            meta.format_version = meta.format_version.max(self.dirty_format_version);
+            meta.entry_count = meta.entry_count.map(|n| n + self.dirty_entry_count);
             self.mem_buf.clear();
+            self.dirty_format_version = 0;
+            self.dirty_entry_count = 0;
 
             // Step 3: Reload primary log and indexes to get the latest view.
             let (disk_buf, indexes) = Self::load_log_and_indexes(
@@ -644,15 +961,29 @@ impl Log {
                     // This is needed because `Log::append` updated indexes in-memory but
                     // did not update their metadata for performance. This is to update
                     // the metadata stored in Indexes.
-                    Self::set_index_log_len(self.indexes.iter_mut(), meta.primary_len);
+                    //
+                    // Indexes pending background builds are empty. Do not
+                    // update them, so they get loaded once built.
+                    let defs = &self.open_options.index_defs;
+                    let ready_indexes = self.indexes.iter_mut().zip(defs).filter_map(|(i, d)| {
+                        (!Self::is_index_pending(&self.dir, &self.meta, d)).then_some(i)
+                    });
+                    Self::set_index_log_len(ready_indexes, meta.primary_len);
                     Some(&self.indexes)
                 },
                 self.open_options.fsync,
+                self.open_options.codec.as_ref(),
+                self.open_options.read_only,
+                false,
             )?;
 
             self.disk_buf = disk_buf;
             self.indexes = indexes;
             self.meta = meta;
+            if self.open_options.lookup_advice != MmapAdvice::Normal {
+                let advice = Some(self.open_options.lookup_advice);
+                self.advise_disk_buf(PRIMARY_START_OFFSET..self.meta.primary_len, advice);
+            }
 
             // Step 4: Update the indexes and folds. Optionally flush them.
             self.update_indexes_for_on_disk_entries()?;
@@ -662,7 +993,13 @@ impl Log {
             self.all_folds = self.disk_folds.clone();
 
             // Step 5: Write the updated meta file.
+            fail_point!("log::sync::before_meta_write");
             self.dir.write_meta(&self.meta, self.open_options.fsync)?;
+            fail_point!("log::sync::after_meta_write");
+
+            if let Some(interval) = self.open_options.fsync_interval {
+                durability::schedule(&dir, self.file_names(), interval);
+            }
 
             Ok(self.meta.primary_len)
         })();
@@ -682,13 +1019,13 @@ impl Log {
         _lock: &ScopedDirLock,
     ) -> crate::Result<()> {
         for &index_id in index_ids.iter() {
-            let metaname = self.open_options.index_defs[index_id].metaname();
             let new_length = self.indexes[index_id].flush();
             let new_length = self.maybe_set_index_error(new_length.map_err(Into::into))?;
-            self.meta.indexes.insert(metaname, new_length);
+            let def = &self.open_options.index_defs[index_id];
+            self.meta.set_index_len(def, new_length);
             trace!(
                 name = "Log::flush_lagging_index",
-                index_name = self.open_options.index_defs[index_id].name.as_str(),
+                index_name = def.name.as_str(),
                 new_index_length = new_length,
             );
         }
@@ -704,6 +1041,9 @@ impl Log {
             .iter()
             .enumerate()
             .filter(|(i, def)| {
+                if Self::is_index_pending(&self.dir, &self.meta, def) || self.is_index_lazy(*i) {
+                    return false;
+                }
                 let indexed_bytes = Self::get_index_log_len(&self.indexes[*i], false).unwrap_or(0);
                 let lag_bytes = log_bytes.max(indexed_bytes) - indexed_bytes;
                 let lag_threshold = def.lag_threshold;
@@ -713,12 +1053,30 @@ impl Log {
                     lag = lag_bytes,
                     threshold = lag_threshold
                 );
-                lag_bytes > lag_threshold
+                lag_bytes > lag_threshold || self.is_index_over_memory_threshold(*i)
             })
             .map(|(i, _def)| i)
             .collect()
     }
 
+    /// Test if the index was never built, and is left to
+    /// [`Log::build_indexes_in_background`]. Pending indexes are empty. They
+    /// are not updated or flushed.
+    fn is_index_pending(dir: &GenericPath, meta: &LogMetadata, def: &IndexDef) -> bool {
+        def.build_in_background
+            && matches!(dir, GenericPath::Filesystem(_))
+            && meta.index_len(def).is_none()
+    }
+
+    /// Test if in-memory entries of an index use more bytes than its
+    /// `dirty_memory_threshold`.
+    fn is_index_over_memory_threshold(&self, index_id: usize) -> bool {
+        match self.open_options.index_defs[index_id].dirty_memory_threshold {
+            Some(threshold) => self.indexes[index_id].memory_used() as u64 > threshold,
+            None => false,
+        }
+    }
+
     /// Check if the log is changed on disk.
     pub fn is_changed(&self) -> bool {
         match self.dir.read_meta() {
@@ -727,13 +1085,72 @@ impl Log {
         }
     }
 
+    /// Check if entries on disk were changed by other [`Log`]s. For example,
+    /// new entries were appended, entries were deleted, or the log was
+    /// rewritten.
+    ///
+    /// Unlike [`Log::is_changed`], this ignores changes that only affect
+    /// on-disk indexes, and returns `false` if the metadata cannot be read.
+    /// It only reads the small metadata file, so it is cheap enough to be
+    /// called periodically. Use [`Log::sync`] to load the changes, or
+    /// [`Log::watch`] to do both automatically.
+    pub fn is_changed_on_disk(&self) -> bool {
+        if matches!(self.dir, GenericPath::Nothing) {
+            return false;
+        }
+        match self.dir.read_meta() {
+            Ok(meta) => !meta.has_same_content(&self.meta),
+            Err(_) => false,
+        }
+    }
+
     /// Renamed. Use [`Log::sync`] instead.
     pub fn flush(&mut self) -> crate::Result<u64> {
         self.sync()
     }
 
+    /// Write in-memory entries to disk like [`Log::sync`], without waiting
+    /// for `fsync`.
+    ///
+    /// `fsync` happens in a background thread regardless of the
+    /// [`Durability`] setting. Use the returned [`FsyncHandle`] to wait for
+    /// it. Other [`Log`]s can see the written entries before the `fsync`
+    /// completes.
+    pub fn flush_async(&mut self) -> crate::Result<FsyncHandle> {
+        let fsync = self.open_options.fsync;
+        self.set_fsync(false);
+        let result = self.sync();
+        self.set_fsync(fsync);
+        result?;
+        Ok(match self.dir.as_opt_path() {
+            Some(dir) => durability::schedule(dir, self.file_names(), Duration::ZERO),
+            None => FsyncHandle::completed(),
+        })
+    }
+
+    /// Update the `fsync` setting used by [`Log::sync`] for both the primary
+    /// log and indexes.
+    fn set_fsync(&mut self, fsync: bool) {
+        self.open_options.fsync = fsync;
+        let lazy_indexes = self.lazy_indexes.iter_mut().flatten();
+        let loaded_lazy_indexes = lazy_indexes.filter_map(|lazy| lazy.get_mut());
+        for index in self.indexes.iter_mut().chain(loaded_lazy_indexes) {
+            index.fsync = fsync;
+        }
+    }
+
+    /// Names of files in the directory written by [`Log::sync`].
+    fn file_names(&self) -> Vec<String> {
+        let mut names = vec![PRIMARY_FILE.to_string(), META_FILE.to_string()];
+        names.extend(self.open_options.index_defs.iter().map(|d| d.filename()));
+        names
+    }
+
     /// Convert a slice to [`Bytes`].
     /// Do not copy the slice if it's from the main on-disk buffer.
+    ///
+    /// To get entries as [`Bytes`] from iterators, use
+    /// [`LogIter::into_bytes_iter`] or [`LogLookupIter::into_bytes_iter`].
     pub fn slice_to_bytes(&self, slice: &[u8]) -> Bytes {
         self.disk_buf.slice_to_bytes(slice)
     }
@@ -741,7 +1158,14 @@ impl Log {
     /// Convert a slice to [`Bytes`].
     /// Do not copy the slice if it's from the specified index buffer.
     pub fn index_slice_to_bytes(&self, index_id: usize, slice: &[u8]) -> Bytes {
-        self.indexes[index_id].slice_to_bytes(slice)
+        match self.lazy_indexes.get(index_id) {
+            // The slice cannot be from an index that is not loaded.
+            Some(Some(lazy)) => match lazy.get() {
+                Some(index) => index.slice_to_bytes(slice),
+                None => Bytes::copy_from_slice(slice),
+            },
+            _ => self.indexes[index_id].slice_to_bytes(slice),
+        }
     }
 
     /// Make sure on-disk indexes are up-to-date with the primary log, regardless
@@ -751,6 +1175,7 @@ impl Log {
     /// complete indexes before rotating.
     pub(crate) fn finalize_indexes(&mut self, _lock: &ScopedDirLock) -> crate::Result<()> {
         let result: crate::Result<_> = (|| {
+            self.load_lazy_indexes()?;
             let dir = self.dir.clone();
             if let Some(dir) = dir.as_opt_path() {
                 if !self.mem_buf.is_empty() {
@@ -759,7 +1184,8 @@ impl Log {
                     ));
                 }
 
-                let _lock = ScopedDirLock::new(&dir)?;
+                let lock = ScopedDirLock::new(dir)?;
+                self.open_options.metrics.record_lock(&lock);
 
                 let meta = Self::load_or_create_meta(&self.dir, false)?;
                 // Only check primary_len, not meta.indexes. This is because
@@ -783,14 +1209,25 @@ impl Log {
                         &self.meta, &meta
                     )));
                 }
+                // Indexes pending background builds stay pending, even if
+                // they were built after being loaded.
+                let pending: Vec<bool> = self
+                    .open_options
+                    .index_defs
+                    .iter()
+                    .map(|def| Self::is_index_pending(&self.dir, &self.meta, def))
+                    .collect();
                 self.meta = meta;
 
                 // Flush all indexes.
                 for i in 0..self.indexes.len() {
+                    if pending[i] {
+                        continue;
+                    }
                     let new_length = self.indexes[i].flush();
                     let new_length = self.maybe_set_index_error(new_length.map_err(Into::into))?;
-                    let name = self.open_options.index_defs[i].metaname();
-                    self.meta.indexes.insert(name, new_length);
+                    let def = &self.open_options.index_defs[i];
+                    self.meta.set_index_len(def, new_length);
                 }
 
                 self.dir.write_meta(&self.meta, self.open_options.fsync)?;
@@ -810,6 +1247,14 @@ impl Log {
     /// Setting `force` to `true` might reduce the size used by the index
     /// files. But that is more expensive.
     ///
+    /// If `force` is `false`, an index that fails the checksum check is
+    /// rebuilt by copying keys that can still be read, and re-indexing
+    /// entries in the primary log only for keys in the damaged parts.
+    ///
+    /// Each index is built in a temporary file, then renamed into place.
+    /// Readers never see a partially built index, even if the process
+    /// crashes.
+    ///
     /// The function consumes the [`Log`] object, since it is hard to recover
     /// from an error case.
     ///
@@ -817,9 +1262,13 @@ impl Log {
     pub fn rebuild_indexes(self, force: bool) -> crate::Result<String> {
         let dir = self.dir.clone();
         let result: crate::Result<_> = (|this: Log| {
+            this.check_writable()?;
             if let Some(dir) = this.dir.clone().as_opt_path() {
                 let lock = ScopedDirLock::new(&dir)?;
-                this.rebuild_indexes_with_lock(force, &lock)
+                this.open_options.metrics.record_lock(&lock);
+                let (message, _rebuilt, _recovered) =
+                    this.rebuild_indexes_with_lock(force, &lock)?;
+                Ok(message)
             } else {
                 Ok(String::new())
             }
@@ -830,19 +1279,52 @@ impl Log {
             .context(|| format!("  Log.dir = {:?}", dir))
     }
 
+    /// Rebuild indexes. Return the message, names of rebuilt indexes, and
+    /// details about indexes recovered from partial damage.
     fn rebuild_indexes_with_lock(
         mut self,
         force: bool,
         _lock: &ScopedDirLock,
-    ) -> crate::Result<String> {
+    ) -> crate::Result<(String, Vec<String>, Vec<IndexRecovery>)> {
+        let op = op_span!(
+            "indexedlog::log::rebuild_indexes",
+            path = ?self.dir,
+            force = force,
+            rebuilt = tracing::field::Empty
+        );
         let mut message = String::new();
+        let mut rebuilt = Vec::new();
+        let mut recovered = Vec::new();
+        // Load indexes deferred by `lazy_indexes`. Rebuild those that cannot
+        // be loaded.
+        let load_failed: Vec<bool> = (0..self.open_options.index_defs.len())
+            .map(|i| self.take_lazy_index(i).is_err())
+            .collect();
         {
             if let Some(ref dir) = self.dir.as_opt_path() {
+                // Remove temporary files left by crashed rebuilds.
+                if !self.meta.temp_files.is_empty() {
+                    for name in std::mem::take(&mut self.meta.temp_files) {
+                        let path = dir.join(name);
+                        let _ = fs::remove_file(index::bloom_filter_path(&path));
+                        let _ = fs::remove_file(&path);
+                    }
+                    self.meta
+                        .write_file(dir.join(META_FILE), self.open_options.fsync)
+                        .context("  after removing temporary files")?;
+                }
+
                 for (i, def) in self.open_options.index_defs.iter().enumerate() {
                     let name = def.name.as_str();
+                    let mut damaged = None;
+
+                    let load_failed = load_failed[i];
+                    if load_failed {
+                        message += &format!("Index {:?} cannot be loaded\n", name);
+                    }
 
                     if let Some(index) = &self.indexes.get(i) {
-                        let should_skip = if force {
+                        let should_skip = if force || load_failed {
                             false
                         } else {
                             match Self::get_index_log_len(index, true) {
@@ -861,6 +1343,7 @@ impl Log {
                                     } else {
                                         message +=
                                             &format!("Index {:?} failed integrity check\n", name);
+                                        damaged = Self::salvage_index(index, len);
                                         false
                                     }
                                 }
@@ -880,50 +1363,101 @@ impl Log {
                         }
                     }
 
-                    let tmp = tempfile::NamedTempFile::new_in(dir).context(&dir, || {
-                        format!("cannot create tempfile for rebuilding index {:?}", name)
-                    })?;
+                    // Build the index in a temporary file, then rename it into
+                    // place, so readers never see a partially built index. The
+                    // temporary file is tracked by metadata, so it is removed
+                    // by the next rebuild if the process crashes.
+                    let tmp_path = tempfile::Builder::new()
+                        .prefix(&def.rebuild_prefix())
+                        .tempfile_in(dir)
+                        .context(dir, "cannot create temporary index file")?
+                        .into_temp_path();
+                    let tmp_name = tmp_path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned();
+                    let meta_path = dir.join(META_FILE);
+                    self.meta.temp_files.insert(tmp_name.clone());
+                    self.meta
+                        .write_file(&meta_path, self.open_options.fsync)
+                        .context(|| format!("  before rebuilding index {:?}", name))?;
                     let index_len = {
                         let mut index = index::OpenOptions::new()
                             .key_buf(Some(Arc::new(self.disk_buf.clone())))
-                            .open(&tmp.path())?;
+                            .codec(self.open_options.codec.clone())
+                            .fsync(self.open_options.fsync)
+                            .checksum_chunk_size_logarithm(def.checksum_chunk_size_logarithm)
+                            .bloom_filter(def.bloom_filter)
+                            .open(&tmp_path)?;
+                        if let Some(damaged) = &damaged {
+                            let recovery = Self::recover_index_unchecked(
+                                &self.dir,
+                                &mut index,
+                                def,
+                                &self.disk_buf,
+                                damaged,
+                            )?;
+                            message += &format!(
+                                "Kept {} keys in index {:?}, re-indexed {} values\n",
+                                recovery.kept_keys, name, recovery.reindexed_values
+                            );
+                            recovered.push(recovery);
+                        }
                         Self::update_index_for_on_disk_entry_unchecked(
                             &self.dir,
                             &mut index,
                             def,
                             &self.disk_buf,
                             self.meta.primary_len,
+                            &mut |_| {},
                         )?;
                         index.flush()?
                     };
 
                     // Before replacing the index, set its "logic length" to 0 so
                     // readers won't get inconsistent view about index length and data.
-                    let meta_path = dir.join(META_FILE);
-                    self.meta.indexes.insert(def.metaname(), 0);
+                    self.meta.set_index_len(def, 0);
                     self.meta
                         .write_file(&meta_path, self.open_options.fsync)
                         .context(|| format!("  before replacing index {:?})", name))?;
 
-                    let _ = utils::fix_perm_file(tmp.as_file(), false);
+                    let _ = utils::fix_perm_path(&tmp_path, false);
 
                     let path = dir.join(def.filename());
-                    tmp.persist(&path).map_err(|e| {
-                        crate::Error::wrap(Box::new(e), || {
-                            format!("cannot persist tempfile to replace index {:?}", name)
-                        })
+                    fs::rename(&tmp_path, &path).context(&path, || {
+                        format!("cannot rename from {:?} to replace index", &tmp_path)
                     })?;
+                    if def.bloom_filter.is_some() {
+                        // The bloom filter is a cache. It will be rebuilt if
+                        // this fails.
+                        let _ = fs::rename(
+                            index::bloom_filter_path(&tmp_path),
+                            index::bloom_filter_path(&path),
+                        );
+                    }
+                    if self.open_options.fsync {
+                        // Make the rename durable before the metadata points to
+                        // the new index.
+                        #[cfg(unix)]
+                        fs::File::open(dir)
+                            .and_then(|f| f.sync_all())
+                            .context(dir, "cannot fsync directory")?;
+                    }
 
-                    self.meta.indexes.insert(def.metaname(), index_len);
+                    self.meta.set_index_len(def, index_len);
+                    self.meta.temp_files.remove(&tmp_name);
                     self.meta
                         .write_file(&meta_path, self.open_options.fsync)
                         .context(|| format!("  after replacing index {:?}", name))?;
                     message += &format!("Rebuilt index {:?}\n", name);
+                    rebuilt.push(name.to_string());
                 }
             }
         }
 
-        Ok(message)
+        op.record("rebuilt", rebuilt.len() as u64);
+        Ok((message, rebuilt, recovered))
     }
 
     /// Look up an entry using the given index. The `index_id` is the index of
@@ -933,9 +1467,15 @@ impl Log {
     pub fn lookup<K: AsRef<[u8]>>(&self, index_id: usize, key: K) -> crate::Result<LogLookupIter> {
         let result: crate::Result<_> = (|| {
             self.maybe_return_index_error()?;
-            if let Some(index) = self.indexes.get(index_id) {
+            self.check_index_ready(index_id)?;
+            if let Some(index) = self.load_index_by_id(index_id)? {
                 assert!(!key.as_ref().is_empty());
+                let metrics = &self.open_options.metrics;
+                LogMetrics::add(&metrics.lookups, 1);
                 let link_offset = index.get(&key)?;
+                if link_offset.is_null() {
+                    LogMetrics::add(&metrics.index_misses, 1);
+                }
                 let inner_iter = link_offset.values(index);
                 Ok(LogLookupIter {
                     inner_iter,
@@ -943,13 +1483,7 @@ impl Log {
                     log: self,
                 })
             } else {
-                let msg = format!(
-                    "invalid index_id {} (len={}, path={:?})",
-                    index_id,
-                    self.indexes.len(),
-                    &self.dir
-                );
-                Err(crate::Error::programming(msg))
+                Err(self.invalid_index_id(index_id))
             }
         })();
         result
@@ -957,12 +1491,36 @@ impl Log {
             .context(|| format!("  Log.dir = {:?}", self.dir))
     }
 
+    /// Count entries [`Log::lookup`] would return for the given key. The
+    /// `index_id` is the index of `index_defs` passed to [`Log::open`].
+    ///
+    /// The index does not store counts. This walks the list of all matching
+    /// offsets, so it takes O(n) time for n matching entries. It is cheaper
+    /// than counting [`Log::lookup`] results only because entries are not
+    /// read or verified. Entries removed by [`Log::delete`] are excluded.
+    pub fn count_lookup<K: AsRef<[u8]>>(&self, index_id: usize, key: K) -> crate::Result<usize> {
+        let result: crate::Result<_> = (|| {
+            let iter = self.lookup(index_id, &key)?;
+            let mut count = 0;
+            for offset in iter.inner_iter {
+                if !self.is_deleted(offset?) {
+                    count += 1;
+                }
+            }
+            Ok(count)
+        })();
+        result
+            .context(|| format!("in Log::count_lookup({}, {:?})", index_id, key.as_ref()))
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+
     /// Look up keys and entries using the given prefix.
     /// The `index_id` is the index of `index_defs` passed to [`Log::open`].
     ///
     /// Return an iterator that yields `(key, iter)`, where `key` is the full
     /// key, `iter` is [`LogLookupIter`] that allows iteration through matched
-    /// entries.
+    /// entries. Keys are sorted in ascending order. Use `rev()` for
+    /// descending order.
     pub fn lookup_prefix<K: AsRef<[u8]>>(
         &self,
         index_id: usize,
@@ -970,7 +1528,11 @@ impl Log {
     ) -> crate::Result<LogRangeIter> {
         let prefix = prefix.as_ref();
         let result: crate::Result<_> = (|| {
-            let index = self.indexes.get(index_id).unwrap();
+            self.check_index_ready(index_id)?;
+            let index = self
+                .load_index_by_id(index_id)?
+                .ok_or_else(|| self.invalid_index_id(index_id))?;
+            LogMetrics::add(&self.open_options.metrics.lookups, 1);
             let inner_iter = index.scan_prefix(prefix)?;
             Ok(LogRangeIter {
                 inner_iter,
@@ -992,6 +1554,11 @@ impl Log {
     /// Return an iterator that yields `(key, iter)`, where `key` is the full
     /// key, `iter` is [`LogLookupIter`] that allows iteration through entries
     /// matching that key.
+    ///
+    /// Keys are yielded in ascending order, as compared by bytes. Use
+    /// `rev()` to get them in descending order. For example, with keys
+    /// `foo`, `foo1`, `fop`, and `fox`, the range `b"foo"..b"fop"` yields
+    /// `foo` and `foo1`.
     pub fn lookup_range<'a>(
         &self,
         index_id: usize,
@@ -1000,7 +1567,12 @@ impl Log {
         let start = range.start_bound();
         let end = range.end_bound();
         let result: crate::Result<_> = (|| {
-            let index = self.indexes.get(index_id).unwrap();
+            self.maybe_return_index_error()?;
+            self.check_index_ready(index_id)?;
+            let index = self
+                .load_index_by_id(index_id)?
+                .ok_or_else(|| self.invalid_index_id(index_id))?;
+            LogMetrics::add(&self.open_options.metrics.lookups, 1);
             let inner_iter = index.range((start, end))?;
             Ok(LogRangeIter {
                 inner_iter,
@@ -1024,7 +1596,8 @@ impl Log {
     ///
     /// Return an iterator that yields `(key, iter)`, where `key` is the full
     /// key, `iter` is [`LogLookupIter`] that allows iteration through matched
-    /// entries.
+    /// entries. Keys are sorted in ascending order. Use `rev()` for
+    /// descending order.
     pub fn lookup_prefix_hex<K: AsRef<[u8]>>(
         &self,
         index_id: usize,
@@ -1032,7 +1605,11 @@ impl Log {
     ) -> crate::Result<LogRangeIter> {
         let prefix = hex_prefix.as_ref();
         let result: crate::Result<_> = (|| {
-            let index = self.indexes.get(index_id).unwrap();
+            self.check_index_ready(index_id)?;
+            let index = self
+                .load_index_by_id(index_id)?
+                .ok_or_else(|| self.invalid_index_id(index_id))?;
+            LogMetrics::add(&self.open_options.metrics.lookups, 1);
             let inner_iter = index.scan_prefix_hex(prefix)?;
             Ok(LogRangeIter {
                 inner_iter,
@@ -1046,12 +1623,50 @@ impl Log {
             .context(|| format!("  Log.dir = {:?}", self.dir))
     }
 
+    /// Look up distinct keys using the given prefix.
+    /// The `index_id` is the index of `index_defs` passed to [`Log::open`].
+    ///
+    /// Unlike [`Log::lookup_prefix`], entries are not read. This is cheaper
+    /// for finding candidates of an ambiguous prefix. Keys are sorted in
+    /// ascending order. Use `take(n)` to stop early.
+    pub fn lookup_prefix_keys<K: AsRef<[u8]>>(
+        &self,
+        index_id: usize,
+        prefix: K,
+    ) -> crate::Result<LogKeyIter<'_>> {
+        let prefix = prefix.as_ref();
+        let result: crate::Result<_> = (|| {
+            self.maybe_return_index_error()?;
+            self.check_index_ready(index_id)?;
+            let index = self
+                .load_index_by_id(index_id)?
+                .ok_or_else(|| self.invalid_index_id(index_id))?;
+            LogMetrics::add(&self.open_options.metrics.lookups, 1);
+            let inner_iter = index.scan_prefix(prefix)?;
+            Ok(LogKeyIter {
+                inner_iter,
+                errored: false,
+            })
+        })();
+        result
+            .context(|| format!("in Log::lookup_prefix_keys({}, {:?})", index_id, prefix))
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+
     /// Return an iterator for all entries.
     pub fn iter(&self) -> LogIter {
+        let opts = &self.open_options;
+        if opts.iter_advice != opts.lookup_advice {
+            self.advise_disk_buf(
+                PRIMARY_START_OFFSET..self.meta.primary_len,
+                Some(opts.iter_advice),
+            );
+        }
         LogIter {
             log: self,
             next_offset: PRIMARY_START_OFFSET,
             errored: false,
+            advised: opts.iter_advice != opts.lookup_advice || opts.release_after_iter,
         }
     }
 
@@ -1063,9 +1678,187 @@ impl Log {
             log: self,
             next_offset: self.meta.primary_len,
             errored: false,
+            advised: false,
         }
     }
 
+    /// Read the entry at the given offset.
+    ///
+    /// `offset` should be an offset returned by [`LogOffsetIter`]. Reading
+    /// at other offsets usually results in integrity check errors.
+    ///
+    /// Offsets of on-disk entries (offsets less than the length returned by
+    /// [`Log::sync`]) are stable until the log is rewritten, repaired, or
+    /// has its content deleted (in which case the epoch changes). Offsets of
+    /// in-memory entries can change after [`Log::sync`], if other processes
+    /// appended entries in the meantime.
+    ///
+    /// Return `None` if `offset` is at the end of the log.
+    pub fn entry_at(&self, offset: u64) -> crate::Result<Option<&[u8]>> {
+        let result: crate::Result<_> = (|| {
+            let end = self.meta.primary_len + self.mem_buf.len() as u64;
+            if offset < PRIMARY_START_OFFSET || offset > end {
+                let msg = format!(
+                    "offset {} is out of bound (valid range: {}..={})",
+                    offset, PRIMARY_START_OFFSET, end
+                );
+                return Err(crate::Error::programming(msg));
+            }
+            Ok(self.read_entry(offset)?.map(|entry| entry.data))
+        })();
+        result
+            .context(|| format!("in Log::entry_at({})", offset))
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+
+    /// Get the time the entry at the given offset was appended.
+    ///
+    /// Return `None` if the entry does not have a timestamp. See
+    /// [`OpenOptions::entry_timestamp`].
+    pub fn entry_timestamp(&self, offset: u64) -> crate::Result<Option<SystemTime>> {
+        let result: crate::Result<_> = (|| {
+            self.entry_at(offset)?;
+            let entry = self.read_entry(offset)?;
+            let timestamp = entry.and_then(|e| e.timestamp);
+            Ok(timestamp.map(|t| UNIX_EPOCH + Duration::from_millis(t)))
+        })();
+        result
+            .context(|| format!("in Log::entry_timestamp({})", offset))
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+
+    /// Delete the entry at the given offset.
+    ///
+    /// `offset` should be an offset returned by [`LogOffsetIter`]. See
+    /// [`Log::entry_at`] for when offsets are stable.
+    ///
+    /// The entry is not physically removed. A tombstone is recorded so
+    /// iteration and lookups skip the entry. Indexes and folds are not
+    /// changed. Use [`Log::vacuum`] to reclaim the space used by deleted
+    /// entries.
+    ///
+    /// Similar to [`Log::append`], the deletion is in-memory until
+    /// [`Log::sync`].
+    pub fn delete(&mut self, offset: u64) -> crate::Result<()> {
+        let result: crate::Result<_> = (|| {
+            self.check_writable()?;
+            if self.is_deleted(offset) {
+                return Ok(());
+            }
+            if self.entry_at(offset)?.is_none() {
+                let msg = format!("offset {} is the end of log", offset);
+                return Err(crate::Error::programming(msg));
+            }
+            self.dirty_deleted.insert(offset);
+            Ok(())
+        })();
+        result
+            .context(|| format!("in Log::delete({})", offset))
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+
+    /// Rewrite the log to drop deleted entries.
+    ///
+    /// This is a [`Log::rewrite`] that keeps all entries that are not deleted.
+    /// Return the rewritten [`Log`].
+    pub fn vacuum(self) -> crate::Result<Log> {
+        let options = self.open_options.clone();
+        self.rewrite(|_| Ok(FlushFilterOutput::Keep), &options)
+    }
+
+    /// Set or remove (if the value is `None`) application-defined metadata.
+    ///
+    /// Metadata is stored in the meta file, and is written atomically with
+    /// entries by [`Log::sync`]. It is suitable for small values like format
+    /// versions. Similar to [`Log::append`], the change is in-memory until
+    /// [`Log::sync`]. Changes to other keys made by other processes are
+    /// preserved.
+    pub fn update_meta<K: ToString, V: AsRef<[u8]>>(
+        &mut self,
+        items: impl IntoIterator<Item = (K, Option<V>)>,
+    ) -> crate::Result<()> {
+        self.check_writable()
+            .context("in Log::update_meta")
+            .context(|| format!("  Log.dir = {:?}", self.dir))?;
+        for (key, value) in items {
+            let value = value.map(|v| v.as_ref().to_vec());
+            self.dirty_user.insert(key.to_string(), value);
+        }
+        Ok(())
+    }
+
+    /// Get application-defined metadata set by [`Log::update_meta`],
+    /// including changes not yet written to disk.
+    pub fn meta(&self, key: &str) -> Option<&[u8]> {
+        match self.dirty_user.get(key) {
+            Some(value) => value.as_deref(),
+            None => self.meta.user.get(key).map(|v| v.as_slice()),
+        }
+    }
+
+    /// Return an error if the [`Log`] was opened in read-only mode.
+    fn check_writable(&self) -> crate::Result<()> {
+        if self.open_options.read_only {
+            Err(crate::Error::programming(
+                "Log was opened in read-only mode",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Return an error if a new entry of `len` bytes exceeds `max_entry_size`.
+    /// Existing entries, for example, those being rewritten, are not checked.
+    fn check_entry_size(&self, len: usize) -> crate::Result<()> {
+        if let Some(max) = self.open_options.max_entry_size {
+            if len as u64 > max {
+                return Err(crate::Error::entry_too_large(len as u64, max));
+            }
+        }
+        Ok(())
+    }
+
+    /// Return an error if writing pending entries and `extra` bytes to a
+    /// [`Log`] described by `meta` would exceed `max_total_bytes`.
+    fn check_quota(&self, meta: &LogMetadata, extra: u64) -> crate::Result<()> {
+        if let Some(max) = self.open_options.max_total_bytes {
+            let pending = self.mem_buf.len() as u64 + extra;
+            let size = meta.total_bytes() + pending;
+            if pending > 0 && size > max {
+                return Err(crate::Error::quota_exceeded(size, max));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of entries [`Log::iter`] would return, if the metadata records
+    /// the number of on-disk entries.
+    pub(crate) fn recorded_entry_count(&self) -> Option<usize> {
+        let total = self.meta.entry_count? + self.dirty_entry_count;
+        let deleted =
+            self.meta.deleted.len() + self.dirty_deleted.difference(&self.meta.deleted).count();
+        Some(total.saturating_sub(deleted as u64) as usize)
+    }
+
+    /// Test if there are changes not yet written to disk.
+    fn has_pending_changes(&self) -> bool {
+        !(self.mem_buf.is_empty() && self.dirty_deleted.is_empty() && self.dirty_user.is_empty())
+    }
+
+    /// Pass `advice` about the given range of the primary log to `madvise`.
+    /// Only affects memory-mapped files. See [`Bytes::advise`].
+    fn advise_disk_buf(&self, range: Range<u64>, advice: Option<MmapAdvice>) {
+        let len = self.disk_buf.len();
+        let end = (range.end as usize).min(len);
+        let start = (range.start as usize).min(end);
+        self.disk_buf.slice(start..end).advise(advice);
+    }
+
+    /// Test if the entry at the given offset is deleted.
+    fn is_deleted(&self, offset: u64) -> bool {
+        self.dirty_deleted.contains(&offset) || self.meta.deleted.contains(&offset)
+    }
+
     /// Applies the given index function to the entry data and returns the index keys.
     pub fn index_func<'a>(
         &self,
@@ -1118,6 +1911,7 @@ impl Log {
         offset: u64,
         data_offset: u64,
     ) -> crate::Result<()> {
+        self.load_lazy_indexes()?;
         let result = self.update_indexes_for_in_memory_entry_unchecked(data, offset, data_offset);
         self.maybe_set_index_error(result)
     }
@@ -1142,6 +1936,9 @@ impl Log {
         data_offset: u64,
     ) -> crate::Result<()> {
         for (index, def) in self.indexes.iter_mut().zip(&self.open_options.index_defs) {
+            if Self::is_index_pending(&self.dir, &self.meta, def) {
+                continue;
+            }
             for index_output in (def.func)(data) {
                 match index_output {
                     IndexOutput::Reference(range) => {
@@ -1196,32 +1993,50 @@ impl Log {
 
     fn update_indexes_for_on_disk_entries_unchecked(&mut self) -> crate::Result<()> {
         // It's a programming error to call this when mem_buf is not empty.
-        for (index, def) in self.indexes.iter_mut().zip(&self.open_options.index_defs) {
+        let defs = &self.open_options.index_defs;
+        for (i, (index, def)) in self.indexes.iter_mut().zip(defs).enumerate() {
+            // Lazy indexes are updated when loaded.
+            if Self::is_index_pending(&self.dir, &self.meta, def)
+                || matches!(self.lazy_indexes.get(i), Some(Some(_)))
+            {
+                continue;
+            }
             Self::update_index_for_on_disk_entry_unchecked(
                 &self.dir,
                 index,
                 def,
                 &self.disk_buf,
                 self.meta.primary_len,
+                &mut |_| {},
             )?;
         }
         Ok(())
     }
 
+    /// `progress` is called with the offset of the next entry after
+    /// indexing each entry.
     fn update_index_for_on_disk_entry_unchecked(
         path: &GenericPath,
         index: &mut Index,
         def: &IndexDef,
         disk_buf: &Bytes,
         primary_len: u64,
+        progress: &mut dyn FnMut(u64),
     ) -> crate::Result<usize> {
         // The index meta is used to store the next offset the index should be built.
         let mut offset = Self::get_index_log_len(index, true)?;
+        let op = op_span!(
+            "indexedlog::log::update_index",
+            path = ?path,
+            index = def.name.as_str(),
+            from_offset = offset,
+            entries = tracing::field::Empty
+        );
         // How many times the index function gets called?
         let mut count = 0;
         // PERF: might be worthwhile to cache xxhash verification result.
-        while let Some(entry_result) =
-            Self::read_entry_from_buf(&path, disk_buf, offset).context(|| {
+        while let Some(entry_result) = Self::read_entry_from_buf(path, disk_buf, offset, true)
+            .context(|| {
                 format!(
                     "while updating index {:?} for on-disk entry at {}",
                     def.name, offset
@@ -1253,9 +2068,11 @@ impl Log {
                 }
             }
             offset = entry_result.next_offset;
+            progress(offset);
         }
         // The index now contains all entries. Write "next_offset" as the index meta.
         Self::set_index_log_len(std::iter::once(index), primary_len);
+        op.record("entries", count as u64);
 
         Ok(count)
     }
@@ -1301,12 +2118,44 @@ impl Log {
         }
     }
 
+    /// Change the metadata in storage by `func` with the directory locked.
+    /// For [`GenericPath::Nothing`], change `self.meta` instead.
+    ///
+    /// `self.meta` is otherwise unchanged. Use [`Log::sync`] to reload it.
+    pub(crate) fn modify_meta_locked(
+        &mut self,
+        func: impl FnOnce(&mut LogMetadata),
+    ) -> crate::Result<()> {
+        match &self.dir {
+            GenericPath::Nothing => func(&mut self.meta),
+            GenericPath::Memory(dir) => {
+                let _lock = dir.lock();
+                let mut meta = dir.read_meta()?;
+                meta.check_format_version()?;
+                func(&mut meta);
+                dir.write_meta(&meta)?;
+            }
+            _ => {
+                let lock = self.dir.lock()?;
+                self.open_options.metrics.record_lock(&lock);
+                let mut meta = Self::load_or_create_meta(&self.dir, false)?;
+                meta.check_format_version()?;
+                func(&mut meta);
+                self.dir.write_meta(&meta, self.open_options.fsync)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Read `(log.disk_buf, indexes)` from the directory using the metadata.
     ///
     /// If `reuse_indexes` is not None, they are existing indexes that match `index_defs`
     /// order. This should only be used in `sync` code path when the on-disk `meta` matches
     /// the in-memory `meta`. Otherwise it is not a sound use.
     ///
+    /// If `lazy` is true, indexes that are not reused are not loaded if
+    /// `can_defer_index_load` allows. Empty placeholders are returned for them.
+    ///
     /// The indexes loaded by this function can be lagging.
     /// Use `update_indexes_for_on_disk_entries` to update them.
     fn load_log_and_indexes(
@@ -1316,10 +2165,14 @@ impl Log {
         mem_buf: &Pin<Box<Vec<u8>>>,
         reuse_indexes: Option<&Vec<Index>>,
         fsync: bool,
+        codec: Option<&Arc<dyn Codec>>,
+        read_only: bool,
+        lazy: bool,
     ) -> crate::Result<(Bytes, Vec<Index>)> {
-        let primary_buf = match dir.as_opt_path() {
-            Some(dir) => mmap_path(&dir.join(PRIMARY_FILE), meta.primary_len)?,
-            None => Bytes::new(),
+        let primary_buf = match (dir, dir.as_opt_path()) {
+            (GenericPath::Memory(dir), _) => dir.read_primary(meta.primary_len)?,
+            (_, Some(dir)) => Self::load_primary(dir, meta.primary_len, codec.map(|c| c.as_ref()))?,
+            (_, None) => Bytes::new(),
         };
 
         let mem_buf: &Vec<u8> = &mem_buf;
@@ -1335,13 +2188,23 @@ impl Log {
                 // No indexes are reused, reload them.
                 let mut indexes = Vec::with_capacity(index_defs.len());
                 for def in index_defs.iter() {
-                    let index_len = meta.indexes.get(&def.metaname()).cloned().unwrap_or(0);
+                    if lazy && Self::can_defer_index_load(dir, meta, def) {
+                        // Keep the key buffer for `load_lazy_index`.
+                        let index = index::OpenOptions::new()
+                            .key_buf(Some(key_buf.clone()))
+                            .create_in_memory()?;
+                        indexes.push(index);
+                        continue;
+                    }
+                    let index_len = meta.index_len(def).unwrap_or(0);
                     indexes.push(Self::load_index(
                         dir,
                         &def,
                         index_len,
                         key_buf.clone(),
                         fsync,
+                        codec,
+                        read_only,
                     )?);
                 }
                 indexes
@@ -1352,9 +2215,22 @@ impl Log {
                 // Avoid reloading the index from disk.
                 // Update their ExternalKeyBuffer so they have the updated meta.primary_len.
                 for (index, def) in indexes.iter().zip(index_defs) {
-                    let index_len = meta.indexes.get(&def.metaname()).cloned().unwrap_or(0);
-                    let index = if index_len > Self::get_index_log_len(index, true).unwrap_or(0) {
-                        Self::load_index(dir, &def, index_len, key_buf.clone(), fsync)?
+                    let index_len = meta.index_len(def).unwrap_or(0);
+                    // An index without metadata was never updated. For
+                    // example, it was pending a background build.
+                    let never_updated = index_len > 0 && index.get_meta().is_empty();
+                    let index = if never_updated
+                        || index_len > Self::get_index_log_len(index, true).unwrap_or(0)
+                    {
+                        Self::load_index(
+                            dir,
+                            def,
+                            index_len,
+                            key_buf.clone(),
+                            fsync,
+                            codec,
+                            read_only,
+                        )?
                     } else {
                         let mut index = index.try_clone()?;
                         index.key_buf = key_buf.clone();
@@ -1373,6 +2249,20 @@ impl Log {
         &self.dir
     }
 
+    /// Load the primary log buffer. Decode it if `codec` is set.
+    pub(crate) fn load_primary(
+        dir: &Path,
+        len: u64,
+        codec: Option<&dyn Codec>,
+    ) -> crate::Result<Bytes> {
+        let buf = mmap_path(&dir.join(PRIMARY_FILE), len)?;
+        Ok(utils::decode_bytes(
+            buf,
+            codec,
+            PRIMARY_START_OFFSET as usize,
+        ))
+    }
+
     /// Load a single index.
     fn load_index(
         dir: &GenericPath,
@@ -1380,54 +2270,96 @@ impl Log {
         len: u64,
         buf: Arc<dyn ReadonlyBuffer + Send + Sync>,
         fsync: bool,
+        codec: Option<&Arc<dyn Codec>>,
+        read_only: bool,
     ) -> crate::Result<Index> {
         match dir.as_opt_path() {
+            // The index file might not exist. Nothing to read from it anyway.
+            Some(_) if read_only && len == 0 => index::OpenOptions::new()
+                .logical_len(Some(len))
+                .key_buf(Some(buf))
+                .bloom_filter(def.bloom_filter)
+                .create_in_memory(),
             Some(dir) => {
                 let path = dir.join(def.filename());
                 index::OpenOptions::new()
-                    .checksum_chunk_size_logarithm(INDEX_CHECKSUM_CHUNK_SIZE_LOGARITHM)
+                    .checksum_chunk_size_logarithm(def.checksum_chunk_size_logarithm)
                     .logical_len(Some(len))
                     .key_buf(Some(buf))
                     .fsync(fsync)
+                    .codec(codec.cloned())
+                    .write(if read_only { Some(false) } else { None })
+                    .bloom_filter(def.bloom_filter)
                     .open(path)
             }
             None => index::OpenOptions::new()
                 .logical_len(Some(len))
                 .key_buf(Some(buf))
                 .fsync(fsync)
+                .bloom_filter(def.bloom_filter)
                 .create_in_memory(),
         }
     }
 
     /// Read the entry at the given offset. Return `None` if offset is out of bound, or the content
     /// of the data, the real offset of the data, and the next offset. Raise errors if
-    /// integrity-check failed.
+    /// integrity-check failed. Whether to check integrity is decided by
+    /// [`OpenOptions::checksum_verification`].
     fn read_entry(&self, offset: u64) -> crate::Result<Option<EntryResult>> {
+        let verify = offset >= self.meta.primary_len
+            || match self.open_options.checksum_verification {
+                ChecksumVerification::Always => true,
+                ChecksumVerification::FirstAccess => {
+                    !self.verified.lock().unwrap().contains(offset)
+                }
+                ChecksumVerification::OnDemand => false,
+            };
+        self.read_entry_with_verify(offset, verify)
+    }
+
+    /// Similar to [`Log::read_entry`], but decide whether to check integrity
+    /// by `verify`.
+    fn read_entry_with_verify(
+        &self,
+        offset: u64,
+        verify: bool,
+    ) -> crate::Result<Option<EntryResult<'_>>> {
         let result = if offset < self.meta.primary_len {
-            Self::read_entry_from_buf(&self.dir, &self.disk_buf, offset)?
+            let result = Self::read_entry_from_buf(&self.dir, &self.disk_buf, offset, verify)?;
+            if let Some(entry) = &result {
+                if verify
+                    && self.open_options.checksum_verification == ChecksumVerification::FirstAccess
+                {
+                    let mut verified = self.verified.lock().unwrap();
+                    verified.insert(offset..entry.next_offset);
+                }
+            }
+            result
         } else {
             let offset = offset - self.meta.primary_len;
             if offset >= self.mem_buf.len() as u64 {
                 return Ok(None);
             }
-            Self::read_entry_from_buf(&self.dir, &self.mem_buf, offset)?
+            Self::read_entry_from_buf(&self.dir, &self.mem_buf, offset, verify)?
                 .map(|entry_result| entry_result.offset(self.meta.primary_len))
         };
         Ok(result)
     }
 
-    /// Read an entry at the given offset of the given buffer. Verify its integrity. Return the
-    /// data, the real data offset, and the next entry offset. Return None if the offset is at
-    /// the end of the buffer.  Raise errors if there are integrity check issues.
+    /// Read an entry at the given offset of the given buffer. Verify its integrity if `verify`
+    /// is `true`. Return the data, the real data offset, and the next entry offset. Return None
+    /// if the offset is at the end of the buffer.  Raise errors if there are integrity check
+    /// issues.
     fn read_entry_from_buf<'a>(
         path: &GenericPath,
         buf: &'a [u8],
         offset: u64,
+        verify: bool,
     ) -> crate::Result<Option<EntryResult<'a>>> {
         let data_error = |msg: String| -> crate::Error {
             match path.as_opt_path() {
                 Some(path) => crate::Error::corruption(path, msg),
-                None => crate::Error::path(Path::new("<memory>"), msg),
+                None => crate::Error::at_path(Path::new("<memory>"), msg),
             }
         };
 
@@ -1459,58 +2391,71 @@ impl Log {
         })?;
         let offset = offset + vlq_len as u64;
 
-        // Depends on entry_flags, some of them have a checksum field.
-        let checksum_flags = entry_flags & (ENTRY_FLAG_HAS_XXHASH64 | ENTRY_FLAG_HAS_XXHASH32);
-        let (checksum, offset) = match checksum_flags {
-            ENTRY_FLAG_HAS_XXHASH64 => {
-                let checksum = LittleEndian::read_u64(
-                    &buf.get(offset as usize..offset as usize + 8)
-                        .ok_or_else(|| {
-                            data_error(format!("xxhash cannot be read at {}", offset))
-                        })?,
-                );
-                (checksum, offset + 8)
-            }
-            ENTRY_FLAG_HAS_XXHASH32 => {
-                let checksum = LittleEndian::read_u32(
-                    &buf.get(offset as usize..offset as usize + 4)
-                        .ok_or_else(|| {
-                            data_error(format!("xxhash32 cannot be read at {}", offset))
-                        })?,
-                ) as u64;
-                (checksum, offset + 4)
-            }
-            _ => {
+        // Depends on entry_flags, some of them have checksum fields.
+        let (checksum_width, checksum_count) = match entry_checksum_layout(entry_flags, data_len) {
+            Some(layout) => layout,
+            None => {
                 return Err(data_error(format!(
                     "entry at {} has malformed checksum metadata",
                     offset
                 )));
             }
         };
-
-        // Read the actual payload
-        let end = offset + data_len;
-        if end > buf.len() as u64 {
-            return Err(data_error(format!("incomplete entry data at {}", offset)));
-        }
-        let data = &buf[offset as usize..end as usize];
-
-        let verified = match checksum_flags {
-            0 => true,
-            ENTRY_FLAG_HAS_XXHASH64 => xxhash(&data) == checksum,
-            ENTRY_FLAG_HAS_XXHASH32 => xxhash32(&data) as u64 == checksum,
-            // Tested above. Therefore unreachable.
-            _ => unreachable!(),
+        let checksums_offset = offset;
+        let offset = checksum_width
+            .checked_mul(checksum_count)
+            .and_then(|len| len.checked_add(offset))
+            .filter(|&end| end <= buf.len() as u64)
+            .ok_or_else(|| data_error(format!("checksum cannot be read at {}", offset)))?;
+
+        // Read the actual payload. `data_len` can be absurd if the header is
+        // corrupted. Avoid overflow.
+        let end = match offset.checked_add(data_len) {
+            Some(end) if end <= buf.len() as u64 => end,
+            _ => return Err(data_error(format!("incomplete entry data at {}", offset))),
         };
-        if verified {
-            Ok(Some(EntryResult {
-                data,
-                data_offset: offset,
-                next_offset: end,
-            }))
-        } else {
-            Err(data_error(format!("integrity check failed at {}", offset)))
+        let payload = &buf[offset as usize..end as usize];
+
+        if verify {
+            let chunks = entry_checksum_chunks(payload, checksum_count);
+            for (i, chunk) in (0..).zip(chunks) {
+                let checksum_offset = (checksums_offset + i * checksum_width) as usize;
+                let checksum_buf = &buf[checksum_offset..checksum_offset + checksum_width as usize];
+                let checksum = match checksum_width {
+                    8 => LittleEndian::read_u64(checksum_buf),
+                    4 => LittleEndian::read_u32(checksum_buf) as u64,
+                    // Tested by entry_checksum_layout. Therefore unreachable.
+                    _ => unreachable!(),
+                };
+                if entry_checksum(entry_flags, chunk) != checksum {
+                    let chunk_offset = offset + i * ENTRY_CHECKSUM_CHUNK_SIZE as u64;
+                    return Err(data_error(format!(
+                        "integrity check failed at {}",
+                        chunk_offset
+                    )));
+                }
+            }
         }
+
+        // Strip the timestamp, if any.
+        let (timestamp, data, offset) = if entry_flags & ENTRY_FLAG_HAS_TIMESTAMP == 0 {
+            (None, payload, offset)
+        } else if payload.len() >= 8 {
+            let timestamp = LittleEndian::read_u64(&payload[..8]);
+            (Some(timestamp), &payload[8..], offset + 8)
+        } else {
+            return Err(data_error(format!(
+                "timestamp cannot be read at {}",
+                offset
+            )));
+        };
+
+        Ok(Some(EntryResult {
+            data,
+            data_offset: offset,
+            next_offset: end,
+            timestamp,
+        }))
     }
 
     /// Wrapper around a `Result` returned by an index write operation.
@@ -1583,7 +2528,7 @@ impl Log {
 impl Log {
     /// Get the specified index, with error handling.
     fn get_index(&self, index_id: usize) -> crate::Result<&Index> {
-        self.indexes.get(index_id).ok_or_else(|| {
+        self.load_index_by_id(index_id)?.ok_or_else(|| {
             let msg = format!(
                 "index_id {} is out of bound (len={}, dir={:?})",
                 index_id,
@@ -1594,6 +2539,32 @@ impl Log {
         })
     }
 
+    /// Return an error if the index is pending a background build.
+    fn check_index_ready(&self, index_id: usize) -> crate::Result<()> {
+        match self.open_options.index_defs.get(index_id) {
+            Some(def) if Self::is_index_pending(&self.dir, &self.meta, def) => {
+                let msg = format!(
+                    "index {:?} is not built yet (dir={:?}). See Log::is_index_ready",
+                    def.name.as_str(),
+                    &self.dir
+                );
+                Err(crate::Error::programming(msg))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Error for an `index_id` that does not refer to an index.
+    fn invalid_index_id(&self, index_id: usize) -> crate::Error {
+        let msg = format!(
+            "invalid index_id {} (len={}, path={:?})",
+            index_id,
+            self.indexes.len(),
+            &self.dir
+        );
+        crate::Error::programming(msg)
+    }
+
     /// Get the specified index, with error handling.
     fn get_index_def(&self, index_id: usize) -> crate::Result<&IndexDef> {
         self.open_options.index_defs.get(index_id).ok_or_else(|| {
@@ -1616,11 +2587,82 @@ impl Log {
     }
 }
 
+// Entry checksum utilities
+
+/// Size of a checksum in bytes, decided by `entry_flags`.
+fn entry_checksum_width(entry_flags: u32) -> Option<u64> {
+    match entry_flags & ENTRY_FLAG_CHECKSUM_MASK {
+        ENTRY_FLAG_HAS_XXHASH64 | ENTRY_FLAG_HAS_XXH3 => Some(8),
+        ENTRY_FLAG_HAS_XXHASH32 | ENTRY_FLAG_HAS_CRC32C => Some(4),
+        _ => None,
+    }
+}
+
+/// Size of a checksum, and the number of checksums of an entry.
+/// Return `None` if `entry_flags` is malformed.
+pub(crate) fn entry_checksum_layout(entry_flags: u32, data_len: u64) -> Option<(u64, u64)> {
+    if entry_flags & ENTRY_FLAG_NO_CHECKSUM != 0 {
+        let conflicting_flags = ENTRY_FLAG_CHECKSUM_MASK | ENTRY_FLAG_CHUNKED;
+        return (entry_flags & conflicting_flags == 0).then_some((0, 0));
+    }
+    let width = entry_checksum_width(entry_flags)?;
+    let count = if entry_flags & ENTRY_FLAG_CHUNKED == 0 {
+        1
+    } else {
+        data_len.div_ceil(ENTRY_CHECKSUM_CHUNK_SIZE as u64).max(1)
+    };
+    Some((width, count))
+}
+
+/// Split an entry payload into `checksum_count` chunks covered by checksums.
+fn entry_checksum_chunks(payload: &[u8], checksum_count: u64) -> impl Iterator<Item = &[u8]> {
+    let chunk_size = if checksum_count > 1 {
+        ENTRY_CHECKSUM_CHUNK_SIZE
+    } else {
+        payload.len().max(1)
+    };
+    // `chunks` yields nothing for an empty payload.
+    payload
+        .chunks(chunk_size)
+        .chain(std::iter::once(&[][..]))
+        .take(checksum_count as usize)
+}
+
+/// Calculate checksum of `data` using the algorithm decided by `entry_flags`.
+fn entry_checksum(entry_flags: u32, data: &[u8]) -> u64 {
+    match entry_flags & ENTRY_FLAG_CHECKSUM_MASK {
+        ENTRY_FLAG_HAS_XXHASH64 => xxhash(data),
+        ENTRY_FLAG_HAS_XXHASH32 => xxhash32(data) as u64,
+        ENTRY_FLAG_HAS_XXH3 => xxh3(data),
+        ENTRY_FLAG_HAS_CRC32C => crc32c(data) as u64,
+        _ => unreachable!("entry_flags should be checked by entry_checksum_width"),
+    }
+}
+
+/// Format version required to read an entry using `entry_flags`. 0 if the
+/// entry is readable by all versions.
+fn entry_format_version(entry_flags: u32) -> u64 {
+    if entry_flags & !ENTRY_FLAG_LEGACY_MASK == 0 {
+        0
+    } else {
+        ENTRY_FLAGS_FORMAT_VERSION
+    }
+}
+
+/// Preallocate the primary log for `OpenOptions::preallocate_chunk_size`.
+/// Errors are not fatal, since preallocation is only an optimization.
+fn preallocate_primary(file: &File, path: &Path, len: u64, chunk_size: u64) {
+    if let Err(err) = utils::preallocate(file, len, chunk_size) {
+        tracing::debug!("cannot preallocate {:?}: {}", path, err);
+    }
+}
+
 /// "Pointer" to an entry. Used internally.
 struct EntryResult<'a> {
     data: &'a [u8],
     data_offset: u64,
     next_offset: u64,
+    timestamp: Option<u64>,
 }
 
 impl<'a> EntryResult<'a> {
@@ -1632,6 +2674,7 @@ impl<'a> EntryResult<'a> {
             // So it does not need to be changed.
             data_offset: self.data_offset,
             next_offset: self.next_offset + offset,
+            timestamp: self.timestamp,
         }
     }
 }
@@ -1643,7 +2686,15 @@ impl<'a> Iterator for LogLookupIter<'a> {
         if self.errored {
             return None;
         }
-        match self.inner_iter.next() {
+        // Skip deleted entries.
+        let mut next = self.inner_iter.next();
+        while let Some(Ok(offset)) = next {
+            if !self.log.is_deleted(offset) {
+                break;
+            }
+            next = self.inner_iter.next();
+        }
+        match next {
             None => None,
             Some(Err(err)) => {
                 self.errored = true;
@@ -1676,32 +2727,109 @@ impl<'a> LogLookupIter<'a> {
     pub fn into_vec(self) -> crate::Result<Vec<&'a [u8]>> {
         self.collect()
     }
+
+    /// Yield entries as [`Bytes`] that can outlive the [`Log`] borrow.
+    ///
+    /// On-disk entries are not copied. See [`Log::slice_to_bytes`].
+    pub fn into_bytes_iter(self) -> LogBytesIter<'a, Self> {
+        let log = self.log;
+        LogBytesIter { inner: self, log }
+    }
+
+    /// Similar to [`LogLookupIter::into_vec`], but get data as [`Bytes`].
+    pub fn into_bytes_vec(self) -> crate::Result<Vec<Bytes>> {
+        self.into_bytes_iter().collect()
+    }
 }
 
 impl<'a> Iterator for LogIter<'a> {
     type Item = crate::Result<&'a [u8]>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.errored {
-            return None;
+        self.next_with_offset()
+            .map(|result| result.map(|(_offset, data)| data))
+    }
+}
+
+impl<'a> Drop for LogIter<'a> {
+    fn drop(&mut self) {
+        if self.advised {
+            let opts = &self.log.open_options;
+            if opts.release_after_iter {
+                self.log
+                    .advise_disk_buf(PRIMARY_START_OFFSET..self.next_offset, None);
+            }
+            if opts.iter_advice != opts.lookup_advice {
+                let len = self.log.meta.primary_len;
+                self.log
+                    .advise_disk_buf(PRIMARY_START_OFFSET..len, Some(opts.lookup_advice));
+            }
         }
-        match self
-            .log
-            .read_entry(self.next_offset)
-            .context("in LogIter::next")
-        {
-            Err(e) => {
-                self.errored = true;
-                Some(Err(e))
+    }
+}
+
+impl<'a> LogIter<'a> {
+    /// Similar to `next`, but also returns the offset of the entry.
+    fn next_with_offset(&mut self) -> Option<crate::Result<(u64, &'a [u8])>> {
+        loop {
+            if self.errored {
+                return None;
             }
-            Ok(Some(entry_result)) => {
-                assert!(entry_result.next_offset > self.next_offset);
-                self.next_offset = entry_result.next_offset;
-                Some(Ok(entry_result.data))
+            match self
+                .log
+                .read_entry(self.next_offset)
+                .context("in LogIter::next")
+            {
+                Err(e) => {
+                    self.errored = true;
+                    return Some(Err(e));
+                }
+                Ok(Some(entry_result)) => {
+                    assert!(entry_result.next_offset > self.next_offset);
+                    let offset = self.next_offset;
+                    self.next_offset = entry_result.next_offset;
+                    if self.log.is_deleted(offset) {
+                        continue;
+                    }
+                    return Some(Ok((offset, entry_result.data)));
+                }
+                Ok(None) => return None,
             }
-            Ok(None) => None,
         }
     }
+
+    /// Also yield offsets of entries. The offsets can be used by
+    /// [`Log::entry_at`] to read the entries without re-scanning.
+    pub fn with_offsets(self) -> LogOffsetIter<'a> {
+        LogOffsetIter { inner: self }
+    }
+
+    /// Yield entries as [`Bytes`] that can outlive the [`Log`] borrow.
+    ///
+    /// On-disk entries are not copied. See [`Log::slice_to_bytes`].
+    pub fn into_bytes_iter(self) -> LogBytesIter<'a, Self> {
+        let log = self.log;
+        LogBytesIter { inner: self, log }
+    }
+}
+
+impl<'a, I: Iterator<Item = crate::Result<&'a [u8]>>> Iterator for LogBytesIter<'a, I> {
+    type Item = crate::Result<Bytes>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let log = self.log;
+        self.inner
+            .next()
+            .map(|result| result.map(|data| log.slice_to_bytes(data)))
+    }
+}
+
+impl<'a> Iterator for LogOffsetIter<'a> {
+    type Item = crate::Result<(u64, &'a [u8])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next_with_offset()
+    }
 }
 
 impl<'a> LogRangeIter<'a> {
@@ -1750,17 +2878,50 @@ impl<'a> DoubleEndedIterator for LogRangeIter<'a> {
     }
 }
 
+impl<'a> Iterator for LogKeyIter<'a> {
+    type Item = crate::Result<Cow<'a, [u8]>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.errored {
+            return None;
+        }
+        match self.inner_iter.next() {
+            None => None,
+            Some(Err(err)) => {
+                self.errored = true;
+                Some(Err(err))
+            }
+            Some(Ok((key, _link_offset))) => Some(Ok(key)),
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for LogKeyIter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.errored {
+            return None;
+        }
+        match self.inner_iter.next_back() {
+            None => None,
+            Some(Err(err)) => {
+                self.errored = true;
+                Some(Err(err))
+            }
+            Some(Ok((key, _link_offset))) => Some(Ok(key)),
+        }
+    }
+}
+
 impl Debug for Log {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
         let mut count = 0;
         let mut iter = self.iter();
         let bytes_per_line = 16;
         loop {
-            let offset = iter.next_offset;
             count += 1;
-            match iter.next() {
+            match iter.next_with_offset() {
                 None => break,
-                Some(Ok(bytes)) => {
+                Some(Ok((offset, bytes))) => {
                     if count > 1 {
                         write!(f, "\n")?;
                     }
@@ -10,23 +10,33 @@ use std::fmt;
 use std::fmt::Debug;
 use std::ops::Range;
 use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 use tracing::debug_span;
 
 use super::fold::Fold;
 use super::fold::FoldDef;
 use super::fold::FoldState;
+use crate::codec::Codec;
 use crate::errors::ResultExt;
 use crate::index::Index;
+use crate::lock::read_only_reader_lock;
 use crate::lock::ScopedDirLock;
 use crate::lock::READER_LOCK_OPTS;
+use crate::log::Durability;
 use crate::log::GenericPath;
 use crate::log::Log;
 use crate::log::LogMetadata;
+use crate::log::LogMetrics;
+use crate::log::MmapAdvice;
+use crate::log::RepairReport;
+use crate::log::INDEX_CHECKSUM_CHUNK_SIZE_LOGARITHM;
 use crate::log::PRIMARY_START_OFFSET;
 
 const INDEX_FILE_PREFIX: &str = "index2-";
 const META_PREFIX: &str = "2-";
+const INDEX_REBUILD_SUFFIX: &str = ".rebuild";
 
 /// Definition of an index. It includes: name, function to extract index keys,
 /// and how much the index can lag on disk.
@@ -58,7 +68,8 @@ pub struct IndexDef {
     /// use user-generated content here. And do not abuse this by using `..` or `/`.
     ///
     /// When adding new or changing index functions, make sure a different
-    /// `name` is used so the existing index won't be reused incorrectly.
+    /// `name` or [`IndexDef::version`] is used so the existing index won't be
+    /// reused incorrectly.
     pub(crate) name: Arc<String>,
 
     /// How many bytes (as counted in the file backing [`Log`]) could be left not
@@ -73,6 +84,26 @@ pub struct IndexDef {
     ///
     /// Practically, this correlates to how fast `func` is.
     pub(crate) lag_threshold: u64,
+
+    /// False positive rate of the bloom filter of the index. `None` disables
+    /// the bloom filter.
+    ///
+    /// See [`crate::index::OpenOptions::bloom_filter`] for details.
+    pub(crate) bloom_filter: Option<f64>,
+
+    /// Checksum chunk size of the index file, as `1 << value` bytes.
+    pub(crate) checksum_chunk_size_logarithm: u32,
+
+    /// Flush the index if its in-memory entries use more bytes than this.
+    /// `None` means no limit.
+    pub(crate) dirty_memory_threshold: Option<u64>,
+
+    /// Do not build the index on open if it was never built. See
+    /// [`IndexDef::build_in_background`].
+    pub(crate) build_in_background: bool,
+
+    /// Version of `func`. See [`IndexDef::version`].
+    pub(crate) version: u64,
 }
 
 /// Output of an index function. Bytes that can be used for lookups.
@@ -93,15 +124,23 @@ pub enum IndexOutput {
     /// Remove all values associated with the key in the index.
     ///
     /// This only affects the index. The entry is not removed in the log.
+    /// Use this to drop keys of entries superseded by the current entry.
+    /// Removed keys are skipped by lookups and range scans, until they get
+    /// inserted again by later entries.
     Remove(Box<[u8]>),
 
     /// Remove all values associated with all keys with the given prefix in the index.
     ///
     /// This only affects the index. The entry is not removed in the log.
+    /// See [`IndexOutput::Remove`].
     RemovePrefix(Box<[u8]>),
 }
 
 /// What checksum function to use for an entry.
+///
+/// The checksum function is recorded in the entry header. Readers pick the
+/// right verifier regardless of their [`OpenOptions`]. A [`Log`] can contain
+/// entries using different checksum functions.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ChecksumType {
     /// Choose xxhash64 or xxhash32 automatically based on data size.
@@ -114,6 +153,45 @@ pub enum ChecksumType {
     /// platforms, but takes less space. Perhaps a good fit when entries are
     /// short.
     Xxhash32,
+
+    /// Use the 64-bit XXH3 checksum algorithm. Faster than xxhash64,
+    /// especially for short entries.
+    Xxh3,
+
+    /// Use the CRC-32C checksum algorithm. Hardware accelerated on x86_64
+    /// with SSE 4.2.
+    Crc32c,
+}
+
+/// How much data a checksum covers.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChecksumGranularity {
+    /// One checksum per entry.
+    Entry,
+
+    /// One checksum per 64KB chunk of entries larger than 64KB. Smaller
+    /// entries use one checksum per entry. Integrity errors report the
+    /// offset of the corrupted chunk.
+    Chunk,
+}
+
+/// When to verify checksums of entries read from the primary log.
+///
+/// Entries written by the current [`Log`] but not yet synced are always
+/// verified when read. Index updates always verify entries.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChecksumVerification {
+    /// Verify an entry every time it is read.
+    Always,
+
+    /// Verify an entry the first time it is read. Remember verified ranges
+    /// of the primary log so later reads skip the check. Verified ranges
+    /// are kept across [`Log::sync`] unless the [`Log`] is rewritten.
+    FirstAccess,
+
+    /// Do not verify entries when reading them. Use [`Log::verify_range`]
+    /// to verify explicitly, for example, from a background thread.
+    OnDemand,
 }
 
 /// Options used to configured how an [`Log`] is opened.
@@ -123,9 +201,26 @@ pub struct OpenOptions {
     pub(crate) fold_defs: Vec<FoldDef>,
     pub(crate) create: bool,
     pub(crate) checksum_type: ChecksumType,
+    pub(crate) checksum_granularity: ChecksumGranularity,
+    pub(crate) skip_checksum: bool,
+    pub(crate) checksum_verification: ChecksumVerification,
+    pub(crate) entry_timestamp: bool,
+    pub(crate) preallocate_chunk_size: Option<u64>,
+    pub(crate) max_entry_size: Option<u64>,
+    pub(crate) max_total_bytes: Option<u64>,
+    pub(crate) lookup_advice: MmapAdvice,
+    pub(crate) iter_advice: MmapAdvice,
+    pub(crate) release_after_iter: bool,
     pub(crate) flush_filter: Option<FlushFilterFunc>,
     pub(crate) fsync: bool,
+    pub(crate) fsync_interval: Option<Duration>,
     pub(crate) auto_sync_threshold: Option<u64>,
+    pub(crate) auto_sync_interval: Option<Duration>,
+    pub(crate) codec: Option<Arc<dyn Codec>>,
+    pub(crate) read_only: bool,
+    pub(crate) auto_repair: bool,
+    pub(crate) lazy_indexes: bool,
+    pub(crate) metrics: Arc<LogMetrics>,
 }
 
 pub type FlushFilterFunc =
@@ -180,7 +275,8 @@ impl IndexDef {
     /// use user-generated content here. And do not abuse this by using `..` or `/`.
     ///
     /// When adding new or changing index functions, make sure a different
-    /// `name` is used so the existing index won't be reused incorrectly.
+    /// `name` or [`IndexDef::version`] is used so the existing index won't be
+    /// reused incorrectly.
     pub fn new(
         name: impl ToString,
         index_func: impl Fn(&[u8]) -> Vec<IndexOutput> + Send + Sync + 'static,
@@ -195,6 +291,11 @@ impl IndexDef {
             // indexes. Users should customize the value if the default is not
             // good enough.
             lag_threshold: 25 * 500,
+            bloom_filter: None,
+            checksum_chunk_size_logarithm: INDEX_CHECKSUM_CHUNK_SIZE_LOGARITHM,
+            dirty_memory_threshold: None,
+            build_in_background: false,
+            version: 0,
         }
     }
 
@@ -214,6 +315,121 @@ impl IndexDef {
             func: self.func,
             name: self.name,
             lag_threshold,
+            bloom_filter: self.bloom_filter,
+            checksum_chunk_size_logarithm: self.checksum_chunk_size_logarithm,
+            dirty_memory_threshold: self.dirty_memory_threshold,
+            build_in_background: self.build_in_background,
+            version: self.version,
+        }
+    }
+
+    /// Keep a bloom filter of index keys with the given false positive rate.
+    ///
+    /// This makes [`Log::lookup`] cheaper for missing keys, at the cost of
+    /// about 10 bits per key for a 1% false positive rate. The filter is
+    /// stored next to the index file, and rebuilt with the index.
+    ///
+    /// See [`crate::index::OpenOptions::bloom_filter`] for details.
+    pub fn bloom_filter(self, false_positive_rate: f64) -> Self {
+        Self {
+            func: self.func,
+            name: self.name,
+            lag_threshold: self.lag_threshold,
+            bloom_filter: Some(false_positive_rate),
+            checksum_chunk_size_logarithm: self.checksum_chunk_size_logarithm,
+            dirty_memory_threshold: self.dirty_memory_threshold,
+            build_in_background: self.build_in_background,
+            version: self.version,
+        }
+    }
+
+    /// Set the checksum chunk size of the index file as
+    /// `1 << checksum_chunk_size_logarithm` bytes. The default is 1MB.
+    ///
+    /// Chunks are verified lazily, when they are read by a lookup for the
+    /// first time. Smaller chunks make the first lookups on a cold, large
+    /// index cheaper, at the cost of a larger checksum table.
+    ///
+    /// This only affects index files created after the change, like
+    /// those created by [`Log::rebuild_indexes`]. Existing index files keep
+    /// their chunk size.
+    pub fn checksum_chunk_size_logarithm(self, checksum_chunk_size_logarithm: u32) -> Self {
+        Self {
+            func: self.func,
+            name: self.name,
+            lag_threshold: self.lag_threshold,
+            bloom_filter: self.bloom_filter,
+            checksum_chunk_size_logarithm,
+            dirty_memory_threshold: self.dirty_memory_threshold,
+            build_in_background: self.build_in_background,
+            version: self.version,
+        }
+    }
+
+    /// Set how many bytes in-memory index entries can use before they are
+    /// written to disk. `None` means no limit, which is the default.
+    ///
+    /// In-memory entries are created by appending entries, or by building
+    /// the lagged part of the index on open. Normally they are written by
+    /// [`Log::sync`] once the lag exceeds `lag_threshold`. With a limit,
+    /// [`Log::append`] calls [`Log::sync`] when the in-memory entries use
+    /// more bytes than the limit, and [`Log::sync`] writes the index
+    /// regardless of `lag_threshold`. Use [`crate::index::Index::memory_used`]
+    /// to check the memory usage of an index.
+    ///
+    /// Lower this for low-memory environments, at the cost of more writes.
+    pub fn dirty_memory_threshold(self, threshold: impl Into<Option<u64>>) -> Self {
+        Self {
+            func: self.func,
+            name: self.name,
+            lag_threshold: self.lag_threshold,
+            bloom_filter: self.bloom_filter,
+            checksum_chunk_size_logarithm: self.checksum_chunk_size_logarithm,
+            dirty_memory_threshold: threshold.into(),
+            build_in_background: self.build_in_background,
+            version: self.version,
+        }
+    }
+
+    /// Build the index in background, if it was never built.
+    ///
+    /// Building a new index for a large existing [`Log`] can take a long
+    /// time. With this set, [`Log::open`] skips building the index. Lookups
+    /// using the index return errors until it is built by
+    /// [`Log::build_indexes_in_background`] and picked up by [`Log::sync`].
+    /// Use [`Log::is_index_ready`] to check.
+    ///
+    /// Indexes that were built before are updated as usual. This only
+    /// affects [`Log`]s on the filesystem.
+    pub fn build_in_background(self, enabled: bool) -> Self {
+        Self {
+            func: self.func,
+            name: self.name,
+            lag_threshold: self.lag_threshold,
+            bloom_filter: self.bloom_filter,
+            checksum_chunk_size_logarithm: self.checksum_chunk_size_logarithm,
+            dirty_memory_threshold: self.dirty_memory_threshold,
+            build_in_background: enabled,
+            version: self.version,
+        }
+    }
+
+    /// Set the version of the index function. The default is 0.
+    ///
+    /// Bump the version when changing what the index function outputs.
+    /// Indexes built by other versions are discarded, and rebuilt on open,
+    /// or in background if [`IndexDef::build_in_background`] is set. The
+    /// version is stored in the [`Log`] metadata.
+    pub fn version(self, version: u64) -> Self {
+        Self {
+            func: self.func,
+            name: self.name,
+            lag_threshold: self.lag_threshold,
+            bloom_filter: self.bloom_filter,
+            checksum_chunk_size_logarithm: self.checksum_chunk_size_logarithm,
+            dirty_memory_threshold: self.dirty_memory_threshold,
+            build_in_background: self.build_in_background,
+            version,
         }
     }
 
@@ -226,6 +442,11 @@ impl IndexDef {
     pub(crate) fn filename(&self) -> String {
         format!("{}{}", INDEX_FILE_PREFIX, self.name)
     }
+
+    /// Prefix of temporary files used to rebuild the index.
+    pub(crate) fn rebuild_prefix(&self) -> String {
+        format!("{}{}{}", INDEX_FILE_PREFIX, self.name, INDEX_REBUILD_SUFFIX)
+    }
 }
 
 impl OpenOptions {
@@ -235,16 +456,43 @@ impl OpenOptions {
     /// `create` is initially `false`.
     /// `fsync` is initially `false`.
     /// `index_defs` is initially empty.
+    /// `checksum_verification` is initially [`ChecksumVerification::Always`].
+    /// `max_entry_size` and `max_total_bytes` are initially `None`.
+    /// `lookup_advice` is initially [`MmapAdvice::Random`].
+    /// `iter_advice` is initially [`MmapAdvice::Sequential`].
+    /// `release_after_iter` is initially `false`.
     /// `auto_sync_threshold` is initially `None`.
+    /// `auto_sync_interval` is initially `None`.
+    /// `codec` is initially `None`.
+    /// `read_only` is initially `false`.
+    /// `auto_repair` is initially `false`.
+    /// `lazy_indexes` is initially `false`.
     pub fn new() -> Self {
         Self {
             create: false,
             index_defs: Vec::new(),
             fold_defs: Vec::new(),
             checksum_type: ChecksumType::Auto,
+            checksum_granularity: ChecksumGranularity::Entry,
+            skip_checksum: false,
+            checksum_verification: ChecksumVerification::Always,
+            entry_timestamp: false,
+            preallocate_chunk_size: None,
+            max_entry_size: None,
+            max_total_bytes: None,
+            lookup_advice: MmapAdvice::Random,
+            iter_advice: MmapAdvice::Sequential,
+            release_after_iter: false,
             flush_filter: None,
             fsync: false,
+            fsync_interval: None,
             auto_sync_threshold: None,
+            auto_sync_interval: None,
+            codec: None,
+            read_only: false,
+            auto_repair: false,
+            lazy_indexes: false,
+            metrics: Default::default(),
         }
     }
 
@@ -252,8 +500,24 @@ impl OpenOptions {
     ///
     /// If true, then [`Log::sync`] will use `fsync` to flush log and index
     /// data to the physical device before returning.
+    ///
+    /// This is a shortcut of [`OpenOptions::durability`] with
+    /// [`Durability::Fsync`] or [`Durability::None`].
     pub fn fsync(mut self, fsync: bool) -> Self {
         self.fsync = fsync;
+        self.fsync_interval = None;
+        self
+    }
+
+    /// Set how [`Log::sync`] makes written data durable.
+    ///
+    /// See [`Durability`] for details.
+    pub fn durability(mut self, durability: Durability) -> Self {
+        (self.fsync, self.fsync_interval) = match durability {
+            Durability::None => (false, None),
+            Durability::Fsync => (true, None),
+            Durability::FsyncBatched(interval) => (false, Some(interval)),
+        };
         self
     }
 
@@ -300,6 +564,19 @@ impl OpenOptions {
         self
     }
 
+    /// Sets the interval of [`Log::sync`] called by a background thread.
+    /// - `None`: Do not call `sync` in background.
+    /// - `Some(interval)`: Call `sync` every `interval` if there are pending
+    ///   changes.
+    ///
+    /// Only takes effect after [`Log::into_auto_sync`]. Together with
+    /// `auto_sync_threshold`, this limits the amount of in-memory changes
+    /// lost if the process crashes.
+    pub fn auto_sync_interval(mut self, interval: impl Into<Option<Duration>>) -> Self {
+        self.auto_sync_interval = interval.into();
+        self
+    }
+
     /// Sets the checksum type.
     ///
     /// See [`ChecksumType`] for details.
@@ -308,6 +585,125 @@ impl OpenOptions {
         self
     }
 
+    /// Sets the checksum granularity.
+    ///
+    /// See [`ChecksumGranularity`] for details.
+    pub fn checksum_granularity(mut self, granularity: ChecksumGranularity) -> Self {
+        self.checksum_granularity = granularity;
+        self
+    }
+
+    /// Sets when to verify checksums of entries being read.
+    ///
+    /// See [`ChecksumVerification`] for details.
+    pub fn checksum_verification(mut self, verification: ChecksumVerification) -> Self {
+        self.checksum_verification = verification;
+        self
+    }
+
+    /// Write new entries without checksums. Reading them skips integrity
+    /// checks. The entry header records the lack of checksums, so entries
+    /// written with or without checksums can be mixed.
+    ///
+    /// This is only suitable for trusted scratch data that does not need to
+    /// survive crashes, for example, logs on `tmpfs`. Torn writes and disk
+    /// corruption of such entries cannot be detected or repaired, and can
+    /// result in bad data being read. Do not use this for durable data.
+    ///
+    /// [`ChecksumType`] and [`ChecksumGranularity`] are ignored if this is
+    /// set.
+    pub fn skip_checksum(mut self, skip_checksum: bool) -> Self {
+        self.skip_checksum = skip_checksum;
+        self
+    }
+
+    /// Record the creation time of new entries, so they can be expired by
+    /// [`Log::purge_older_than`]. This takes 8 extra bytes per entry.
+    ///
+    /// Use [`Log::entry_timestamp`] to read the recorded time.
+    ///
+    /// Writing entries with timestamps bumps [`Log::format_version`], so
+    /// older versions refuse to open the [`Log`] instead of reading the
+    /// timestamps as part of the entries.
+    pub fn entry_timestamp(mut self, entry_timestamp: bool) -> Self {
+        self.entry_timestamp = entry_timestamp;
+        self
+    }
+
+    /// Sets whether to grow the primary log file in chunks.
+    /// - `None`: Grow the file by the size of written entries.
+    /// - `Some(size)`: Preallocate the file to a multiple of `size` bytes,
+    ///   using `fallocate` if possible.
+    ///
+    /// This reduces fragmentation on some filesystems if small entries are
+    /// appended frequently. The metadata still tracks the logical length.
+    /// Space after the logical length is overwritten by the next
+    /// [`Log::sync`].
+    pub fn preallocate_chunk_size(mut self, size: impl Into<Option<u64>>) -> Self {
+        self.preallocate_chunk_size = size.into();
+        self
+    }
+
+    /// Sets the maximum size of a new entry in bytes. `None` means no
+    /// limit.
+    ///
+    /// Appending a larger entry fails with an error that
+    /// [`Error::is_entry_too_large`](crate::Error::is_entry_too_large).
+    /// Existing entries are not checked.
+    pub fn max_entry_size(mut self, size: impl Into<Option<u64>>) -> Self {
+        self.max_entry_size = size.into();
+        self
+    }
+
+    /// Sets the maximum size of the primary log and indexes in bytes.
+    /// `None` means no limit.
+    ///
+    /// Appending or syncing new entries that would exceed the limit fails
+    /// with an error that
+    /// [`Error::is_quota_exceeded`](crate::Error::is_quota_exceeded). Entries
+    /// are counted by their encoded sizes, including headers and checksums.
+    /// Pending entries are kept. Use [`Log::rewrite`] or
+    /// [`Log::purge_older_than`] to free up space, then retry. They are not
+    /// limited by this option.
+    ///
+    /// Indexes are counted by their sizes on disk, so the limit can be
+    /// exceeded by in-memory index changes until the next [`Log::sync`].
+    pub fn max_total_bytes(mut self, size: impl Into<Option<u64>>) -> Self {
+        self.max_total_bytes = size.into();
+        self
+    }
+
+    /// Sets the access pattern hint for the primary log, applied when it is
+    /// loaded. This affects lookups via indexes, and reading entries by
+    /// offsets.
+    ///
+    /// The default, [`MmapAdvice::Random`], avoids reading unrelated data
+    /// ahead when a large log is queried by indexes. Use
+    /// [`MmapAdvice::Normal`] to keep the operating system default.
+    pub fn lookup_advice(mut self, advice: MmapAdvice) -> Self {
+        self.lookup_advice = advice;
+        self
+    }
+
+    /// Sets the access pattern hint for the primary log, applied during
+    /// [`Log::iter`]. After iteration, the hint set by
+    /// [`OpenOptions::lookup_advice`] is restored.
+    ///
+    /// The default, [`MmapAdvice::Sequential`], speeds up full scans of
+    /// large logs.
+    pub fn iter_advice(mut self, advice: MmapAdvice) -> Self {
+        self.iter_advice = advice;
+        self
+    }
+
+    /// Sets whether to release pages read by [`Log::iter`] from the memory
+    /// mapping after iteration. This avoids keeping a full scan of a large
+    /// log in memory.
+    pub fn release_after_iter(mut self, release: bool) -> Self {
+        self.release_after_iter = release;
+        self
+    }
+
     /// Sets the flush filter function.
     ///
     /// The function will be called at [`Log::sync`] time, if there are
@@ -320,6 +716,72 @@ impl OpenOptions {
         self
     }
 
+    /// Sets the codec used to transform on-disk bytes. For example, to
+    /// encrypt data at rest.
+    ///
+    /// The codec applies to entries in the primary log, and index files.
+    /// The file header of the primary log, and the metadata file which
+    /// only contains lengths, are not encoded.
+    ///
+    /// Checksums are calculated before encoding, and verified after
+    /// decoding. Opening an existing [`Log`] with a different codec will
+    /// cause integrity check errors.
+    ///
+    /// Encoded files are read into memory instead of being mmapped.
+    ///
+    /// See [`Codec`] for details.
+    pub fn codec(mut self, codec: Option<Arc<dyn Codec>>) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Sets whether to open the [`Log`] in read-only mode.
+    ///
+    /// A read-only [`Log`] never creates, locks, or writes files. So it can
+    /// be used on a read-only filesystem. `create` is ignored. Lagging
+    /// indexes are built in memory, without being written back to disk.
+    ///
+    /// [`Log::append`], [`Log::delete`] and other functions that write data
+    /// return errors. [`Log::sync`] can still be used to load new entries
+    /// written by other processes.
+    ///
+    /// Since no reader lock is taken, a read-only [`Log`] does not prevent
+    /// other processes from running [`Log::rewrite`] or repair.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Sets whether to repair the [`Log`] automatically if
+    /// [`OpenOptions::open`] detects data corruption.
+    ///
+    /// Repair truncates the primary log to the last valid entry, and
+    /// rebuilds corrupted indexes. See [`OpenOptions::repair`] for details.
+    /// Similar to [`OpenWithRepair`](crate::OpenWithRepair), repair is
+    /// skipped if there are other active readers.
+    ///
+    /// Use [`OpenOptions::open_with_report`] to find out what was repaired.
+    pub fn auto_repair(mut self, auto_repair: bool) -> Self {
+        self.auto_repair = auto_repair;
+        self
+    }
+
+    /// Sets whether to defer loading indexes until they are used.
+    ///
+    /// If true, [`OpenOptions::open`] does not read index files. An index is
+    /// loaded, and updated for lagging entries, on its first lookup. This
+    /// makes opening cheaper if only some of the indexes are used.
+    ///
+    /// Functions that change indexes, like [`Log::append`], or
+    /// [`Log::sync`] with pending changes, load all indexes first. Errors
+    /// reading an index are reported by lookups instead of `open`.
+    /// Lagging indexes are written back by the next [`Log::sync`] with
+    /// pending changes, instead of `open`.
+    pub fn lazy_indexes(mut self, lazy: bool) -> Self {
+        self.lazy_indexes = lazy;
+        self
+    }
+
     /// Remove index lagging.
     ///
     /// Used by `RotateLog` to make sure old logs have complete indexes.
@@ -353,24 +815,56 @@ impl OpenOptions {
     /// transaction. Dropping the [`Log`] instance is like abandoning a
     /// transaction.
     pub fn open(&self, dir: impl Into<GenericPath>) -> crate::Result<Log> {
+        self.open_with_report(dir).map(|(log, _report)| log)
+    }
+
+    /// Similar to [`OpenOptions::open`]. Also return a [`RepairReport`] if
+    /// the [`Log`] was repaired because of `auto_repair`.
+    pub fn open_with_report(
+        &self,
+        dir: impl Into<GenericPath>,
+    ) -> crate::Result<(Log, Option<RepairReport>)> {
         let dir = dir.into();
         match dir.as_opt_path() {
-            None => self.create_in_memory(dir),
+            None => Ok((self.create_in_memory(dir)?, None)),
             Some(ref fs_dir) => {
                 let span = debug_span!("Log::open", dir = &fs_dir.to_string_lossy().as_ref());
                 let _guard = span.enter();
-                self.open_internal(&dir, None, None)
-                    .context(|| format!("in log::OpenOptions::open({:?})", &dir))
+                match self.open_internal(&dir, None, None) {
+                    Err(err)
+                        if self.auto_repair
+                            && err.is_corruption()
+                            && matches!(dir, GenericPath::Filesystem(_)) =>
+                    {
+                        self.repair_on_open(fs_dir, err)
+                            .map(|(log, report)| (log, Some(report)))
+                    }
+                    result => result.map(|log| (log, None)),
+                }
+                .context(|| format!("in log::OpenOptions::open({:?})", &dir))
             }
         }
     }
 
-    /// Construct an empty in-memory [`Log`] without side-effects on the
-    /// filesystem. The in-memory [`Log`] cannot be [`sync`]ed.
+    /// Construct an in-memory [`Log`] without side-effects on the
+    /// filesystem.
+    ///
+    /// For [`GenericPath::Nothing`], the [`Log`] is empty, and cannot be
+    /// [`sync`]ed. For [`GenericPath::Memory`], the [`Log`] is loaded from
+    /// the [`MemoryDir`](crate::log::MemoryDir), and can be [`sync`]ed.
     pub(crate) fn create_in_memory(&self, dir: GenericPath) -> crate::Result<Log> {
         assert!(dir.as_opt_path().is_none());
         let result: crate::Result<_> = (|| {
-            let meta = LogMetadata::new_with_primary_len(PRIMARY_START_OFFSET);
+            let meta = match &dir {
+                GenericPath::Memory(mem_dir) => mem_dir
+                    .load_or_create_meta(self.create && !self.read_only)
+                    .context(|| format!("cannot open Log at {:?}", &dir))?,
+                _ => LogMetadata::new_with_primary_len(PRIMARY_START_OFFSET),
+            };
+            meta.check_format_version()?;
+            if !self.read_only {
+                meta.check_poison()?;
+            }
             let mem_buf = Box::pin(Vec::new());
             let (disk_buf, indexes) = Log::load_log_and_indexes(
                 &dir,
@@ -379,21 +873,37 @@ impl OpenOptions {
                 &mem_buf,
                 None,
                 self.fsync,
+                self.codec.as_ref(),
+                self.read_only,
+                false,
             )?;
             let disk_folds = self.empty_folds();
             let all_folds = disk_folds.clone();
-            Ok(Log {
+            let mut log = Log {
                 dir,
                 disk_buf,
                 mem_buf,
                 meta,
                 indexes,
+                lazy_indexes: Vec::new(),
                 disk_folds,
                 all_folds,
                 index_corrupted: false,
+                dirty_deleted: Default::default(),
+                dirty_user: Default::default(),
+                dirty_format_version: 0,
+                dirty_entry_count: 0,
                 open_options: self.clone(),
                 reader_lock: None,
-            })
+                verified: Default::default(),
+            };
+            if let GenericPath::Memory(_) = log.dir {
+                // Indexes are not stored in `MemoryDir`. Build them.
+                log.update_indexes_for_on_disk_entries()?;
+                log.update_and_flush_disk_folds()?;
+                log.all_folds = log.disk_folds.clone();
+            }
+            Ok(log)
         })();
 
         result.context("in log::OpenOptions::create_in_memory")
@@ -407,6 +917,18 @@ impl OpenOptions {
         self.open_internal(dir, None, Some(lock))
     }
 
+    /// Similar to `open_with_lock`, but do not take the shared reader lock.
+    /// `reader_lock` should be the reader lock held exclusively by the
+    /// caller. The returned [`Log`] must be dropped before `reader_lock`.
+    pub(crate) fn open_with_exclusive_reader_lock(
+        &self,
+        dir: &GenericPath,
+        lock: &ScopedDirLock,
+        _reader_lock: &ScopedDirLock,
+    ) -> crate::Result<Log> {
+        self.open_with_reader_lock(dir, None, Some(lock), None)
+    }
+
     // "Back-door" version of "open" that allows reusing indexes.
     // Used by [`Log::sync`]. See [`Log::load_log_and_indexes`] for when indexes
     // can be reused.
@@ -417,10 +939,23 @@ impl OpenOptions {
         lock: Option<&ScopedDirLock>,
     ) -> crate::Result<Log> {
         let reader_lock = match dir.as_opt_path() {
-            Some(d) => Some(ScopedDirLock::new_with_options(d, &READER_LOCK_OPTS)?),
+            Some(d) if !self.read_only => {
+                Some(ScopedDirLock::new_with_options(d, &READER_LOCK_OPTS)?)
+            }
+            Some(d) => read_only_reader_lock(d),
             None => None,
         };
-        let create = self.create;
+        self.open_with_reader_lock(dir, reuse_indexes, lock, reader_lock)
+    }
+
+    fn open_with_reader_lock(
+        &self,
+        dir: &GenericPath,
+        reuse_indexes: Option<&Vec<Index>>,
+        lock: Option<&ScopedDirLock>,
+        reader_lock: Option<ScopedDirLock>,
+    ) -> crate::Result<Log> {
+        let create = self.create && !self.read_only;
 
         // Do a lock-less load_or_create_meta to avoid the flock overhead.
         let meta = Log::load_or_create_meta(dir, false).or_else(|err| {
@@ -432,13 +967,18 @@ impl OpenOptions {
                 if lock.is_some() {
                     Log::load_or_create_meta(dir, true)
                 } else {
-                    let _lock = dir.lock()?;
+                    let lock = dir.lock()?;
+                    self.metrics.record_lock(&lock);
                     Log::load_or_create_meta(dir, true)
                 }
             } else {
                 Err(err).context(|| format!("cannot open Log at {:?}", &dir))
             }
         })?;
+        meta.check_format_version()?;
+        if !self.read_only {
+            meta.check_poison()?;
+        }
 
         let mem_buf = Box::pin(Vec::new());
         let (disk_buf, indexes) = Log::load_log_and_indexes(
@@ -448,7 +988,18 @@ impl OpenOptions {
             &mem_buf,
             reuse_indexes,
             self.fsync,
+            self.codec.as_ref(),
+            self.read_only,
+            self.lazy_indexes,
         )?;
+        let lazy_indexes = match reuse_indexes {
+            None if self.lazy_indexes => self
+                .index_defs
+                .iter()
+                .map(|def| Log::can_defer_index_load(dir, &meta, def).then(OnceLock::new))
+                .collect(),
+            _ => Vec::new(),
+        };
         let disk_folds = self.empty_folds();
         let all_folds = disk_folds.clone();
         let mut log = Log {
@@ -457,17 +1008,23 @@ impl OpenOptions {
             mem_buf,
             meta,
             indexes,
+            lazy_indexes,
             disk_folds,
             all_folds,
             index_corrupted: false,
+            dirty_deleted: Default::default(),
+            dirty_user: Default::default(),
+            dirty_format_version: 0,
+            dirty_entry_count: 0,
             open_options: self.clone(),
             reader_lock,
+            verified: Default::default(),
         };
         log.update_indexes_for_on_disk_entries()?;
         log.update_and_flush_disk_folds()?;
         log.all_folds = log.disk_folds.clone();
         let lagging_index_ids = log.lagging_index_ids();
-        if !lagging_index_ids.is_empty() {
+        if !lagging_index_ids.is_empty() && !self.read_only {
             // Update indexes.
             // NOTE: Consider ignoring failures if they are caused by permission
             // issues.
@@ -476,10 +1033,15 @@ impl OpenOptions {
                 log.dir.write_meta(&log.meta, self.fsync)?;
             } else {
                 let lock = dir.lock()?;
+                self.metrics.record_lock(&lock);
                 // At this time the Log might be changed on-disk. Reload them.
                 return self.open_internal(dir, reuse_indexes, Some(&lock));
             }
         }
+        if self.lookup_advice != MmapAdvice::Normal {
+            let len = log.meta.primary_len;
+            log.advise_disk_buf(PRIMARY_START_OFFSET..len, Some(self.lookup_advice));
+        }
         Ok(log)
     }
 
@@ -535,9 +1097,37 @@ impl fmt::Debug for OpenOptions {
             self.fold_defs.iter().map(|d| d.name).collect::<Vec<_>>()
         )?;
         write!(f, "fsync: {}, ", self.fsync)?;
+        write!(f, "fsync_interval: {:?}, ", self.fsync_interval)?;
         write!(f, "create: {}, ", self.create)?;
         write!(f, "checksum_type: {:?}, ", self.checksum_type)?;
+        write!(f, "checksum_granularity: {:?}, ", self.checksum_granularity)?;
+        write!(f, "skip_checksum: {}, ", self.skip_checksum)?;
+        write!(
+            f,
+            "checksum_verification: {:?}, ",
+            self.checksum_verification
+        )?;
+        write!(f, "entry_timestamp: {}, ", self.entry_timestamp)?;
+        write!(
+            f,
+            "preallocate_chunk_size: {:?}, ",
+            self.preallocate_chunk_size
+        )?;
+        write!(f, "max_entry_size: {:?}, ", self.max_entry_size)?;
+        write!(f, "max_total_bytes: {:?}, ", self.max_total_bytes)?;
+        write!(f, "lookup_advice: {:?}, ", self.lookup_advice)?;
+        write!(f, "iter_advice: {:?}, ", self.iter_advice)?;
+        write!(f, "release_after_iter: {}, ", self.release_after_iter)?;
         write!(f, "auto_sync_threshold: {:?}, ", self.auto_sync_threshold)?;
+        write!(f, "auto_sync_interval: {:?}, ", self.auto_sync_interval)?;
+        let codec_desc = match self.codec {
+            Some(ref _codec) => "Some(_)",
+            None => "None",
+        };
+        write!(f, "codec: {}, ", codec_desc)?;
+        write!(f, "read_only: {}, ", self.read_only)?;
+        write!(f, "auto_repair: {}, ", self.auto_repair)?;
+        write!(f, "lazy_indexes: {}, ", self.lazy_indexes)?;
         let flush_filter_desc = match self.flush_filter {
             Some(ref _buf) => "Some(_)",
             None => "None",
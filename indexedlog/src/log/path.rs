@@ -11,6 +11,7 @@ use std::sync::Mutex;
 
 use crate::lock::ScopedDirLock;
 use crate::log::LogMetadata;
+use crate::log::MemoryDir;
 use crate::log::META_FILE;
 use crate::utils;
 
@@ -18,6 +19,7 @@ use crate::utils;
 ///
 /// This defines where a [`Log`] reads and writes data.
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum GenericPath {
     /// The [`Log`] is backed by a directory on filesystem.
     Filesystem(PathBuf),
@@ -31,6 +33,10 @@ pub enum GenericPath {
 
     /// From nothing. Indicates creating from memory.
     Nothing,
+
+    /// The [`Log`] is backed by a [`MemoryDir`], which can be shared by
+    /// multiple [`Log`]s.
+    Memory(MemoryDir),
 }
 
 impl From<&std::path::Path> for GenericPath {
@@ -69,7 +75,7 @@ impl GenericPath {
         match self {
             GenericPath::Filesystem(path) => Some(&path),
             GenericPath::SharedMeta { path, .. } => path.as_opt_path(),
-            GenericPath::Nothing => None,
+            GenericPath::Nothing | GenericPath::Memory(_) => None,
         }
     }
 
@@ -112,6 +118,7 @@ impl GenericPath {
                 }
                 Ok(meta.clone())
             }
+            GenericPath::Memory(dir) => dir.read_meta(),
             GenericPath::Nothing => Err(crate::Error::programming(
                 "read_meta() does not support GenericPath::Nothing",
             )),
@@ -140,6 +147,7 @@ impl GenericPath {
                 *shared_meta = meta.clone();
                 Ok(())
             }
+            GenericPath::Memory(dir) => dir.write_meta(meta),
             GenericPath::Nothing => Err(crate::Error::programming(
                 "write_meta() does not support GenericPath::Nothing",
             )),
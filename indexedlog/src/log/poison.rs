@@ -0,0 +1,50 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use crate::errors::ResultExt;
+use crate::log::Log;
+#[cfg(doc)]
+use crate::log::OpenOptions;
+
+impl Log {
+    /// Mark the [`Log`] as "do not use", for example, while it is being
+    /// migrated. Pending changes are written first.
+    ///
+    /// After poisoning, [`OpenOptions::open`] and [`Log::sync`] fail with an
+    /// error of [`ErrorKind::Poisoned`](crate::ErrorKind::Poisoned) carrying
+    /// `reason`, in all processes, until [`Log::unpoison`] is called.
+    /// Opening with [`OpenOptions::read_only`] is still allowed, so the
+    /// entries can be read by migration tools.
+    pub fn poison(&mut self, reason: &str) -> crate::Result<()> {
+        let result: crate::Result<_> = (|| {
+            self.check_writable()?;
+            self.sync()?;
+            self.modify_meta_locked(|meta| meta.poison = Some(reason.to_string()))?;
+            self.meta.poison = Some(reason.to_string());
+            Ok(())
+        })();
+
+        result
+            .context(|| format!("in Log::poison({:?})", reason))
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+
+    /// Revert [`Log::poison`]. Reload the [`Log`] so it can be used again.
+    pub fn unpoison(&mut self) -> crate::Result<()> {
+        let result: crate::Result<_> = (|| {
+            self.check_writable()?;
+            self.modify_meta_locked(|meta| meta.poison = None)?;
+            self.meta.poison = None;
+            self.sync()?;
+            Ok(())
+        })();
+
+        result
+            .context("in Log::unpoison")
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+}
@@ -14,13 +14,21 @@ use std::io::SeekFrom;
 use std::io::Write;
 use std::path::Path;
 
+use vlqencoding::VLQDecodeAt;
+
 use crate::errors::IoResultExt;
 use crate::errors::ResultExt;
+use crate::lock::DirLockOptions;
 use crate::lock::ScopedDirLock;
+use crate::lock::READER_LOCK_OPTS;
+use crate::log::entry_checksum_layout;
 use crate::log::GenericPath;
+use crate::log::IndexRecovery;
 use crate::log::Log;
 use crate::log::LogMetadata;
+use crate::log::LogMetrics;
 use crate::log::OpenOptions;
+use crate::log::LATEST_FORMAT_VERSION;
 use crate::log::META_FILE;
 use crate::log::PRIMARY_FILE;
 use crate::log::PRIMARY_HEADER;
@@ -29,7 +37,32 @@ use crate::repair::OpenOptionsOutput;
 use crate::repair::OpenOptionsRepair;
 use crate::repair::RepairMessage;
 use crate::utils;
-use crate::utils::mmap_path;
+
+/// Summary of changes made by repairing a [`Log`].
+///
+/// See [`OpenOptions::repair_with_report`] and
+/// [`OpenOptions::open_with_report`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepairReport {
+    /// Number of bytes truncated from the end of the primary log.
+    pub dropped_bytes: u64,
+
+    /// Number of entries truncated from the end of the primary log.
+    ///
+    /// Lengths of truncated entries might be corrupted. So this is
+    /// best-effort.
+    pub dropped_entries: u64,
+
+    /// Names of rebuilt indexes.
+    pub rebuilt_indexes: Vec<String>,
+
+    /// Indexes in `rebuilt_indexes` that were recovered from partial
+    /// damage, instead of being rebuilt from scratch.
+    pub recovered_indexes: Vec<IndexRecovery>,
+
+    /// Message useful for human consumption.
+    pub message: String,
+}
 
 // Repair
 impl OpenOptions {
@@ -40,20 +73,44 @@ impl OpenOptions {
     ///
     /// Backup files are written for further investigation.
     ///
+    /// A [`Log`] marked by [`Log::poison`] is not repaired. An error
+    /// carrying the poison reason is returned instead.
+    ///
     /// Return message useful for human consumption.
     pub fn repair(&self, dir: impl Into<GenericPath>) -> crate::Result<String> {
+        self.repair_with_report(dir).map(|report| report.message)
+    }
+
+    /// Similar to [`OpenOptions::repair`]. Return a structured
+    /// [`RepairReport`].
+    pub fn repair_with_report(&self, dir: impl Into<GenericPath>) -> crate::Result<RepairReport> {
         let dir = dir.into();
         let dir = match dir.as_opt_path() {
             Some(dir) => dir,
-            None => return Ok(format!("{:?} is not on disk. Nothing to repair.\n", &dir)),
+            None => {
+                let message = format!("{:?} is not on disk. Nothing to repair.\n", &dir);
+                return Ok(RepairReport {
+                    message,
+                    ..Default::default()
+                });
+            }
         };
 
         let result: crate::Result<_> = (|| {
+            let op = op_span!(
+                "indexedlog::log::repair",
+                path = ?dir,
+                dropped_bytes = tracing::field::Empty,
+                rebuilt_indexes = tracing::field::Empty
+            );
+            let mut report = RepairReport::default();
             if !dir.exists() {
-                return Ok(format!("{:?} does not exist. Nothing to repair.\n", dir));
+                report.message = format!("{:?} does not exist. Nothing to repair.\n", dir);
+                return Ok(report);
             }
 
             let lock = ScopedDirLock::new(dir)?;
+            self.metrics.record_lock(&lock);
             let mut message = RepairMessage::new(dir);
             message += &format!("Processing IndexedLog: {:?}\n", dir);
 
@@ -106,7 +163,7 @@ impl OpenOptions {
                     Ok(meta) => {
                         // If metadata can be read, trust it.
                         if meta.primary_len > primary_len {
-                            use fs2::FileExt;
+                            use crate::fs_ext::FileExt;
                             // Log was truncated for some reason...
                             // (This should be relatively rare)
                             // Fill Log with 0s.
@@ -123,8 +180,10 @@ impl OpenOptions {
                         }
                     }
                     Err(meta_err) => {
-                        // Attempt to rebuild metadata.
-                        let meta = LogMetadata::new_with_primary_len(primary_len);
+                        // Attempt to rebuild metadata. Entries might use
+                        // newer flags, so use the latest format version.
+                        let mut meta = LogMetadata::new_with_primary_len(primary_len);
+                        meta.format_version = LATEST_FORMAT_VERSION;
                         meta.write_file(&meta_path, self.fsync)
                             .context("while recreating meta")
                             .source(meta_err)?;
@@ -146,6 +205,7 @@ impl OpenOptions {
                 .or_else(|_| {
                     self.clone()
                         .index_defs(Vec::new())
+                        .auto_repair(false)
                         .open(GenericPath::from(dir))
                 })
                 .context("cannot open log for repair")?;
@@ -159,14 +219,26 @@ impl OpenOptions {
             }
 
             let valid_len = iter.next_offset;
+            drop(iter);
             assert!(valid_len >= PRIMARY_START_OFFSET);
             assert!(valid_len <= log.meta.primary_len);
 
+            // The recorded entry count might be missing or wrong. It includes
+            // deleted entries, which are skipped by `iter`.
+            let total_count = entry_count + log.meta.deleted.range(..valid_len).count() as u64;
+            let count_changed = log.meta.entry_count != Some(total_count);
+            log.meta.entry_count = Some(total_count);
+
             if valid_len == log.meta.primary_len {
                 message += &format!(
                     "Verified {} entries, {} bytes in log\n",
                     entry_count, valid_len
                 );
+                if count_changed {
+                    log.meta
+                        .write_file(&meta_path, log.open_options.fsync)
+                        .context("while trying to update metadata with entry count")?;
+                }
             } else {
                 message += &format!(
                     "Verified first {} entries, {} of {} bytes in log\n",
@@ -212,13 +284,17 @@ impl OpenOptions {
                 })()
                 .context("while trying to backup corrupted log")?;
 
+                report.dropped_bytes = log.meta.primary_len - valid_len;
+                report.dropped_entries = count_entries_lossy(&log.disk_buf, valid_len);
+
                 // Update metadata. Invalidate indexes.
                 // Bump epoch since this is a non-append-only change.
                 // Reload disk buffer.
                 log.meta.primary_len = valid_len;
                 log.meta.indexes.clear();
                 log.meta.epoch = log.meta.epoch.wrapping_add(1);
-                log.disk_buf = mmap_path(&primary_path, valid_len)?;
+                log.disk_buf =
+                    Log::load_primary(dir, valid_len, log.open_options.codec.as_deref())?;
 
                 log.meta
                     .write_file(&meta_path, log.open_options.fsync)
@@ -230,15 +306,87 @@ impl OpenOptions {
             // Without this, indexes are empty until the next `sync`, which
             // can lead to bad performance.
             log.open_options.index_defs = self.index_defs.clone();
-            message += &log
+            let (index_message, rebuilt_indexes, recovered_indexes) = log
                 .rebuild_indexes_with_lock(false, &lock)
                 .context("while trying to update indexes with reapired log")?;
+            message += &index_message;
 
-            Ok(message.into_string())
+            report.rebuilt_indexes = rebuilt_indexes;
+            report.recovered_indexes = recovered_indexes;
+            report.message = message.into_string();
+            op.record("dropped_bytes", report.dropped_bytes);
+            op.record("rebuilt_indexes", report.rebuilt_indexes.len() as u64);
+            LogMetrics::add(&self.metrics.repairs, 1);
+            Ok(report)
         })();
 
         result.context(|| format!("in log::OpenOptions::repair({:?})", dir))
     }
+
+    /// Repair after `open` failed with a data corruption error `err`. Then
+    /// open the [`Log`] again.
+    ///
+    /// Used by [`OpenOptions::open_with_report`] if `auto_repair` is set.
+    pub(crate) fn repair_on_open(
+        &self,
+        dir: &Path,
+        err: crate::Error,
+    ) -> crate::Result<(Log, RepairReport)> {
+        // Check if it's safe to repair (no active readers).
+        // This is similar to `open_with_repair`.
+        static CHECK_READER_LOCK_OPTS: DirLockOptions = DirLockOptions {
+            exclusive: true,
+            non_blocking: true,
+            ..READER_LOCK_OPTS
+        };
+        let lock = match ScopedDirLock::new_with_options(dir, &CHECK_READER_LOCK_OPTS) {
+            Ok(lock) => lock,
+            Err(lock_err) => {
+                return Err(err.source(lock_err))
+                    .context("auto repair is skipped due to active readers");
+            }
+        };
+        drop(lock);
+
+        let report = self
+            .repair_with_report(dir)
+            .context("in auto repair")
+            .source(err)?;
+        tracing::info!("Auto-repair {:?} Result:\n{}", dir, &report.message);
+        let log = self
+            .open_internal(&dir.into(), None, None)
+            .context(|| format!("after auto repair ({})", &report.message))?;
+        Ok((log, report))
+    }
+}
+
+/// Count entries starting from `offset` without verifying checksums.
+/// Stop at the first entry that cannot be parsed, which is also counted.
+fn count_entries_lossy(buf: &[u8], mut offset: u64) -> u64 {
+    let mut count = 0;
+    while offset < buf.len() as u64 {
+        count += 1;
+        match next_entry_offset_lossy(buf, offset) {
+            Some(next_offset) => offset = next_offset,
+            None => break,
+        }
+    }
+    count
+}
+
+/// Parse the entry header at `offset`. Return the offset of the next entry.
+pub(crate) fn next_entry_offset_lossy(buf: &[u8], offset: u64) -> Option<u64> {
+    let (entry_flags, vlq_len): (u32, _) = buf.read_vlq_at(offset as usize).ok()?;
+    let offset = offset + vlq_len as u64;
+    let (data_len, vlq_len): (u64, _) = buf.read_vlq_at(offset as usize).ok()?;
+    let offset = offset + vlq_len as u64;
+    let (checksum_width, checksum_count) = entry_checksum_layout(entry_flags, data_len)?;
+    let checksum_len = checksum_width.checked_mul(checksum_count)?;
+    let next_offset = offset.checked_add(checksum_len)?.checked_add(data_len)?;
+    if next_offset > buf.len() as u64 {
+        return None;
+    }
+    Some(next_offset)
 }
 
 impl OpenOptionsRepair for OpenOptions {
@@ -0,0 +1,286 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::fs;
+use std::time::Duration;
+
+use minibytes::Bytes;
+use tracing::debug_span;
+
+use crate::errors::IoResultExt;
+use crate::errors::ResultExt;
+use crate::lock::DirLockOptions;
+use crate::lock::ScopedDirLock;
+use crate::lock::READER_LOCK_OPTS;
+use crate::log::FlushFilterOutput;
+use crate::log::GenericPath;
+use crate::log::Log;
+use crate::log::LogMetadata;
+use crate::log::OpenOptions;
+use crate::log::LATEST_FORMAT_VERSION;
+use crate::log::META_FILE;
+use crate::log::PRIMARY_FILE;
+use crate::log::PRIMARY_HEADER;
+use crate::log::PRIMARY_START_OFFSET;
+use crate::utils;
+
+// Sync periodically during rewrite to limit memory usage.
+const REWRITE_SYNC_THRESHOLD: usize = 64 << 20;
+
+// Rewrite
+impl Log {
+    /// Rewrite the [`Log`] so it only contains entries selected by `filter`.
+    ///
+    /// `filter` is called for every entry in insertion order. It decides
+    /// whether the entry is kept as-is, dropped, or replaced by different
+    /// content (ex. a compressed version of the entry). See
+    /// [`FlushFilterOutput`] for details. Entries deleted by [`Log::delete`]
+    /// are skipped.
+    ///
+    /// `options` decides how the rewritten [`Log`] is opened. Its index
+    /// definitions are used to rebuild indexes from scratch, and its checksum
+    /// type is used for the rewritten entries. Other settings like `create`
+    /// are ignored. Metadata set by [`Log::update_meta`] is preserved.
+    ///
+    /// In-memory entries are written to disk first. Then live entries are
+    /// streamed into a fresh log in a temporary directory, which is then
+    /// swapped in with a new epoch, so other [`Log`]s will reload from scratch
+    /// on their next [`Log::sync`].
+    ///
+    /// Similar to `repair`, this is not an append-only operation. It is
+    /// skipped with an error if there are other active readers.
+    ///
+    /// Timestamps recorded by [`OpenOptions::entry_timestamp`] are preserved
+    /// if `options` also records timestamps.
+    ///
+    /// The function consumes the [`Log`] object to release its mmaps.
+    /// Return the rewritten [`Log`].
+    pub fn rewrite(
+        self,
+        mut filter: impl FnMut(&[u8]) -> crate::Result<FlushFilterOutput>,
+        options: &OpenOptions,
+    ) -> crate::Result<Log> {
+        self.rewrite_with_timestamp(|data, _timestamp| filter(data), options, 0)
+    }
+
+    /// Return the on-disk format version of the [`Log`].
+    ///
+    /// [`Log`]s using older versions can be read and written. Use
+    /// [`Log::migrate_to_latest`] to upgrade them.
+    pub fn format_version(&self) -> u64 {
+        self.meta.format_version
+    }
+
+    /// Rewrite the [`Log`] using the latest on-disk format, if it uses an
+    /// older format. Entries and metadata set by [`Log::update_meta`] are
+    /// preserved. Indexes are rebuilt.
+    ///
+    /// This is a [`Log::rewrite`] using the current options. It has the same
+    /// requirements, like no other active readers. Return the migrated
+    /// [`Log`]. Older versions can no longer read it.
+    pub fn migrate_to_latest(self) -> crate::Result<Log> {
+        if self.meta.format_version >= LATEST_FORMAT_VERSION {
+            return Ok(self);
+        }
+        let options = self.open_options.clone();
+        self.rewrite_with_timestamp(
+            |_data, _timestamp| Ok(FlushFilterOutput::Keep),
+            &options,
+            LATEST_FORMAT_VERSION,
+        )
+        .context("in Log::migrate_to_latest")
+    }
+
+    /// Remove entries recorded more than `age` ago.
+    ///
+    /// Only entries with timestamps (see [`OpenOptions::entry_timestamp`])
+    /// can expire. This is a [`Log::rewrite`] using the current options.
+    /// Return the rewritten [`Log`].
+    pub fn purge_older_than(self, age: Duration) -> crate::Result<Log> {
+        let deadline = utils::now_millis().saturating_sub(age.as_millis() as u64);
+        let options = self.open_options.clone();
+        self.rewrite_with_timestamp(
+            |_data, timestamp| match timestamp {
+                Some(timestamp) if timestamp < deadline => Ok(FlushFilterOutput::Drop),
+                _ => Ok(FlushFilterOutput::Keep),
+            },
+            &options,
+            0,
+        )
+    }
+
+    /// Similar to [`Log::rewrite`], but `filter` also takes the timestamp of
+    /// the entry. The rewritten [`Log`] uses at least `format_version`.
+    fn rewrite_with_timestamp(
+        mut self,
+        mut filter: impl FnMut(&[u8], Option<u64>) -> crate::Result<FlushFilterOutput>,
+        options: &OpenOptions,
+        format_version: u64,
+    ) -> crate::Result<Log> {
+        let dir = self.dir.clone();
+        let result: crate::Result<_> = (|| {
+            self.check_writable()?;
+            // Pending entries are rewritten too. Do not reject them by
+            // `max_total_bytes`, since rewriting is the way to free up space.
+            self.open_options.max_total_bytes = None;
+            let fs_dir = match &dir {
+                GenericPath::Filesystem(fs_dir) => fs_dir.clone(),
+                GenericPath::Nothing => {
+                    let mut log = options.create_in_memory(GenericPath::Nothing)?;
+                    for offset in self.iter().with_offsets() {
+                        append_filtered(&mut log, &self, offset?.0, &mut filter)?;
+                    }
+                    log.meta.user = self.meta.user.clone();
+                    log.meta.format_version = log.meta.format_version.max(format_version);
+                    log.dirty_user = self.dirty_user.clone();
+                    return Ok(log);
+                }
+                GenericPath::Memory(mem_dir) => {
+                    self.sync()?;
+                    drop(self);
+                    let _lock = mem_dir.lock();
+                    let src = options.create_in_memory(dir.clone())?;
+                    let mut new_log = options.create_in_memory(GenericPath::Nothing)?;
+                    for offset in src.iter().with_offsets() {
+                        append_filtered(&mut new_log, &src, offset?.0, &mut filter)?;
+                    }
+
+                    // Bump epoch since this is a non-append-only change.
+                    // Existing `Log`s keep reading their own snapshots.
+                    let mut primary = PRIMARY_HEADER.to_vec();
+                    primary.extend_from_slice(&new_log.mem_buf);
+                    let mut meta = LogMetadata::new_with_primary_len(primary.len() as u64);
+                    meta.epoch = src.meta.epoch.wrapping_add(1);
+                    meta.user = src.meta.user.clone();
+                    meta.entry_count = Some(new_log.dirty_entry_count);
+                    meta.format_version = meta
+                        .format_version
+                        .max(new_log.dirty_format_version)
+                        .max(format_version);
+                    mem_dir.write_primary_and_meta(Bytes::from(primary), meta);
+                    return options.create_in_memory(dir.clone());
+                }
+                GenericPath::SharedMeta { .. } => {
+                    return Err(crate::Error::programming(
+                        "rewrite() does not support logs managed by MultiLog",
+                    ));
+                }
+            };
+
+            let span = debug_span!("Log::rewrite", dir = &fs_dir.to_string_lossy().as_ref());
+            let _guard = span.enter();
+
+            self.sync()?;
+            let src_options = self.open_options.clone();
+            drop(self);
+
+            // Check if it's safe to rewrite (no active readers).
+            // This is similar to `open_with_repair`.
+            //
+            // Keep the exclusive reader lock until files are replaced. New
+            // readers take the shared reader lock before reading metadata, so
+            // they wait instead of reading intermediate states.
+            static CHECK_READER_LOCK_OPTS: DirLockOptions = DirLockOptions {
+                exclusive: true,
+                non_blocking: true,
+                ..READER_LOCK_OPTS
+            };
+            let reader_lock = ScopedDirLock::new_with_options(&fs_dir, &CHECK_READER_LOCK_OPTS)
+                .context("rewrite is skipped due to active readers")?;
+
+            let lock = ScopedDirLock::new(&fs_dir)?;
+            src_options.metrics.record_lock(&lock);
+            let src = src_options.open_with_exclusive_reader_lock(&dir, &lock, &reader_lock)?;
+
+            // Stream live entries into a fresh log with complete indexes.
+            let tmp = tempfile::Builder::new()
+                .prefix("rewrite")
+                .tempdir_in(&fs_dir)
+                .context(&fs_dir, "cannot create tempdir for rewrite")?;
+            let mut new_log = options
+                .clone()
+                .create(true)
+                .auto_sync_threshold(None)
+                .max_total_bytes(None)
+                .with_zero_index_lag()
+                .open(tmp.path())?;
+            for offset in src.iter().with_offsets() {
+                append_filtered(&mut new_log, &src, offset?.0, &mut filter)?;
+                if new_log.mem_buf.len() >= REWRITE_SYNC_THRESHOLD {
+                    new_log.sync()?;
+                }
+            }
+            new_log.sync()?;
+
+            // Bump epoch since this is a non-append-only change.
+            let epoch = src.meta.epoch.wrapping_add(1);
+            let mut new_meta = new_log.meta.clone();
+            new_meta.epoch = epoch;
+            new_meta.user = src.meta.user.clone();
+            new_meta.format_version = new_meta.format_version.max(format_version);
+            let index_names: Vec<String> =
+                options.index_defs.iter().map(|d| d.filename()).collect();
+
+            // Release mmaps before replacing files. This is required on Windows.
+            drop(src);
+            drop(new_log);
+
+            // Readers are blocked by `reader_lock`. If the process crashes
+            // during the replacement, later readers should see either the old
+            // log, an empty log with the new epoch, or the rewritten log.
+            // Start by replacing metadata with an empty log so readers won't
+            // read the new primary log using the old length.
+            let meta_path = fs_dir.join(META_FILE);
+            let mut empty_meta = LogMetadata::new_with_primary_len(PRIMARY_START_OFFSET);
+            empty_meta.epoch = epoch;
+            empty_meta
+                .write_file(&meta_path, options.fsync)
+                .context("before replacing log")?;
+
+            for name in std::iter::once(PRIMARY_FILE).chain(index_names.iter().map(|s| s.as_str()))
+            {
+                let src_path = tmp.path().join(name);
+                let dst_path = fs_dir.join(name);
+                fs::rename(&src_path, &dst_path)
+                    .context(&dst_path, || format!("cannot replace with {:?}", &src_path))?;
+            }
+
+            new_meta
+                .write_file(&meta_path, options.fsync)
+                .context("after replacing log")?;
+
+            // Best-effort cleanup. The temporary directory has its own lock files.
+            let _ = tmp.close();
+
+            // Opening takes the shared reader lock.
+            drop(reader_lock);
+            options.open_with_lock(&dir, &lock)
+        })();
+
+        result
+            .context("in Log::rewrite")
+            .context(|| format!("  Log.dir = {:?}", dir))
+    }
+}
+
+/// Append the entry at `offset` of `src` to `log`, filtered by `filter`.
+fn append_filtered(
+    log: &mut Log,
+    src: &Log,
+    offset: u64,
+    filter: &mut impl FnMut(&[u8], Option<u64>) -> crate::Result<FlushFilterOutput>,
+) -> crate::Result<()> {
+    let entry = match src.read_entry(offset)? {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+    match filter(entry.data, entry.timestamp)? {
+        FlushFilterOutput::Drop => Ok(()),
+        FlushFilterOutput::Keep => log.append_in_memory(entry.data, entry.timestamp),
+        FlushFilterOutput::Replace(content) => log.append_in_memory(&content, entry.timestamp),
+    }
+}
@@ -7,8 +7,6 @@
 
 use std::cell::RefCell;
 #[cfg(not(windows))]
-use std::io::Read;
-#[cfg(not(windows))]
 use std::ops::Range;
 
 use quickcheck::quickcheck;
@@ -56,6 +54,112 @@ fn test_open_options_create() {
     assert!(opts.open(&log_path).is_err());
 }
 
+#[test]
+fn test_read_only() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+
+    // Write entries without indexing them on disk.
+    let mut log = log_with_index(path, 1 << 30);
+    insert_entries(&mut log, 0, 10);
+    log.sync().unwrap();
+    drop(log);
+    fs::remove_file(path.join("rlock")).unwrap();
+
+    let snapshot = || -> Vec<(std::ffi::OsString, Vec<u8>)> {
+        let mut files: Vec<_> = fs::read_dir(path)
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                // Metadata might be a symlink. See `atomic_write`.
+                let content = match fs::read_link(e.path()) {
+                    Ok(target) => target.to_string_lossy().into_owned().into_bytes(),
+                    Err(_) => fs::read(e.path()).unwrap(),
+                };
+                (e.file_name(), content)
+            })
+            .collect();
+        files.sort();
+        files
+    };
+    let before = snapshot();
+
+    let index_def = IndexDef::new("i", |_| vec![IndexOutput::Reference(0..8)]);
+    let opts = OpenOptions::new()
+        .index_defs(vec![index_def])
+        .fold_def("c", || Box::new(fold::test::CountFold::default()))
+        .create(true)
+        .read_only(true);
+    let mut log = opts.open(path).unwrap();
+
+    // Lookups and folds work using in-memory indexes.
+    assert_eq!(log.iter().count(), 10);
+    let key = 3u64.to_ne_bytes();
+    assert_eq!(log.lookup(0, key).unwrap().count(), 1);
+    let fold = log.fold(0).unwrap();
+    let count = fold.as_any().downcast_ref::<fold::test::CountFold>();
+    assert_eq!(count.unwrap().0, 10);
+    assert_eq!(snapshot(), before);
+
+    // Writes are rejected.
+    assert!(log.append(b"x").is_err());
+    assert!(log.delete(PRIMARY_START_OFFSET).is_err());
+    assert!(log.try_clone().unwrap().rebuild_indexes(true).is_err());
+    assert!(log.try_clone().unwrap().vacuum().is_err());
+
+    // sync() can load new entries.
+    let mut log2 = log_with_index(path, 1 << 30);
+    insert_entries(&mut log2, 10, 5);
+    log2.sync().unwrap();
+    drop(log2);
+    fs::remove_file(path.join("rlock")).unwrap();
+    let before = snapshot();
+    log.sync().unwrap();
+    assert_eq!(log.iter().count(), 15);
+
+    assert_eq!(snapshot(), before);
+    drop(log);
+
+    // Opening a non-existed Log fails even if `create` is set.
+    assert!(opts.open(path.join("nonexistent")).is_err());
+    assert!(!path.join("nonexistent").exists());
+}
+
+#[test]
+fn test_read_only_reader_lock() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let mut log = log_with_index(path, 100);
+    insert_entries(&mut log, 0, 10);
+    log.sync().unwrap();
+    drop(log);
+
+    // The check used by repair to detect active readers.
+    let check_readers = crate::lock::DirLockOptions {
+        exclusive: true,
+        non_blocking: true,
+        ..READER_LOCK_OPTS
+    };
+
+    // Read-only readers share the reader lock with each other.
+    let log1 = OpenOptions::new().read_only(true).open(path).unwrap();
+    let log2 = OpenOptions::new().read_only(true).open(path).unwrap();
+    assert!(ScopedDirLock::new_with_options(path, &check_readers).is_err());
+    drop(log1);
+    assert!(ScopedDirLock::new_with_options(path, &check_readers).is_err());
+    drop(log2);
+    assert!(ScopedDirLock::new_with_options(path, &check_readers).is_ok());
+
+    // Repair is blocked by read-only readers.
+    let log = OpenOptions::new().read_only(true).open(path).unwrap();
+    let err = OpenOptions::new()
+        .repair_on_open(path, crate::Error::blank())
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("active readers"), "{}", err);
+    drop(log);
+}
+
 #[test]
 fn test_incomplete_rewrite() {
     let dir = tempdir().unwrap();
@@ -150,6 +254,179 @@ fn test_checksum_type() {
     );
 }
 
+#[test]
+fn test_checksum_granularity() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let open = |checksum_type, granularity| {
+        OpenOptions::new()
+            .checksum_type(checksum_type)
+            .checksum_granularity(granularity)
+            .create(true)
+            .open(path)
+            .unwrap()
+    };
+
+    let short_bytes = vec![12; 20];
+    let long_bytes: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+    let mut expected = Vec::new();
+    for (checksum_type, granularity) in [
+        (ChecksumType::Xxh3, ChecksumGranularity::Entry),
+        (ChecksumType::Crc32c, ChecksumGranularity::Entry),
+        (ChecksumType::Xxh3, ChecksumGranularity::Chunk),
+        (ChecksumType::Crc32c, ChecksumGranularity::Chunk),
+    ] {
+        let mut log = open(checksum_type, granularity);
+        for data in [&short_bytes, &long_bytes] {
+            log.append(data).unwrap();
+            expected.push(data.clone());
+        }
+        log.sync().unwrap();
+    }
+
+    // Readers do not need to know the checksum types.
+    let log = Log::open(path, Vec::new()).unwrap();
+    let entries = log
+        .iter()
+        .with_offsets()
+        .collect::<crate::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|(_, data)| data.to_vec())
+            .collect::<Vec<_>>(),
+        expected,
+    );
+    let offset = entries[7].0;
+    let data_offset = log.meta.primary_len - long_bytes.len() as u64;
+    assert_eq!(log.verify().unwrap(), []);
+    drop(log);
+
+    // Corrupt the 3rd chunk of the last entry.
+    let chunk_offset = data_offset + 2 * 65536;
+    pwrite(&path.join(PRIMARY_FILE), chunk_offset as i64 + 10, b"x");
+    let log = Log::open(path, Vec::new()).unwrap();
+    let err = log.entry_at(offset).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains(&format!("integrity check failed at {}", chunk_offset)));
+    drop(log);
+
+    // Repair understands chunked checksums.
+    OpenOptions::new().repair(path).unwrap();
+    let log = Log::open(path, Vec::new()).unwrap();
+    assert_eq!(log.iter().count(), 7);
+}
+
+#[test]
+fn test_describe_entries() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new()
+        .create(true)
+        .checksum_type(ChecksumType::Xxhash32)
+        .index("a", |data| match data.first() {
+            Some(b'a') => vec![IndexOutput::Reference(0..1)],
+            _ => Vec::new(),
+        });
+
+    let mut log = opts.clone().open(path).unwrap();
+    log.append(b"abc").unwrap();
+    log.append(b"").unwrap();
+    log.sync().unwrap();
+    let mut log = opts
+        .clone()
+        .checksum_type(ChecksumType::Crc32c)
+        .entry_timestamp(true)
+        .open(path)
+        .unwrap();
+    log.append(b"xyz").unwrap();
+    log.delete(PRIMARY_START_OFFSET).unwrap();
+
+    let descs: Vec<EntryDescription> = log.describe_entries().map(|d| d.unwrap()).collect();
+    assert_eq!(descs.len(), 3);
+    let offsets: Vec<u64> = log.iter().with_offsets().map(|e| e.unwrap().0).collect();
+    assert_eq!(offsets, [descs[1].offset, descs[2].offset]);
+
+    // flags (1 byte) + len (1 byte) + xxhash32 (4 bytes) + data (3 bytes).
+    assert_eq!(
+        descs[0],
+        EntryDescription {
+            offset: PRIMARY_START_OFFSET,
+            len: 9,
+            data_offset: PRIMARY_START_OFFSET + 6,
+            data_len: 3,
+            checksum: Some(ChecksumType::Xxhash32),
+            chunked: false,
+            has_timestamp: false,
+            has_index_keys: true,
+            deleted: true,
+        }
+    );
+    assert_eq!(descs[1].offset, PRIMARY_START_OFFSET + 9);
+    assert_eq!(descs[1].data_len, 0);
+    assert!(!descs[1].has_index_keys);
+
+    // The in-memory entry has a timestamp before its data.
+    let desc = &descs[2];
+    assert_eq!(desc.offset, log.meta.primary_len);
+    assert_eq!(desc.data_offset, desc.offset + 6 + 8);
+    assert_eq!(desc.offset + desc.len, desc.data_offset + desc.data_len);
+    assert_eq!(desc.checksum, Some(ChecksumType::Crc32c));
+    assert!(desc.has_timestamp);
+    assert!(!desc.deleted);
+
+    // Offsets match the primary log file after sync.
+    log.sync().unwrap();
+    let buf = fs::read(path.join(PRIMARY_FILE)).unwrap();
+    let desc = log.describe_entries().last().unwrap().unwrap();
+    let range = desc.data_offset as usize..(desc.data_offset + desc.data_len) as usize;
+    assert_eq!(&buf[range], b"xyz");
+
+    // Corrupted headers are errors.
+    pwrite(
+        &path.join(PRIMARY_FILE),
+        PRIMARY_START_OFFSET as i64 + 9,
+        &[0xff],
+    );
+    let log = OpenOptions::new().open(path).unwrap();
+    let results: Vec<_> = log.describe_entries().collect();
+    assert_eq!(results.len(), 2);
+    assert!(results[1].is_err());
+}
+
+#[test]
+fn test_skip_checksum() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new().create(true).skip_checksum(true);
+
+    let mut log = opts.clone().open(path).unwrap();
+    log.append(b"abc").unwrap();
+    log.sync().unwrap();
+    // flags (1 byte) + len (1 byte) + data (3 bytes), no checksum.
+    assert_eq!(log.meta.primary_len, PRIMARY_START_OFFSET + 5);
+
+    // Mix with checksummed entries.
+    let mut log = opts.clone().skip_checksum(false).open(path).unwrap();
+    log.append(b"def").unwrap();
+    log.sync().unwrap();
+
+    // Corruption of entries without checksums is not detected.
+    pwrite(
+        &path.join(PRIMARY_FILE),
+        PRIMARY_START_OFFSET as i64 + 2,
+        b"x",
+    );
+    let log = Log::open(path, Vec::new()).unwrap();
+    assert_eq!(
+        log.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+        [b"xbc", b"def"]
+    );
+    assert_eq!(log.verify().unwrap(), []);
+}
+
 #[test]
 fn test_iter_and_iter_dirty() {
     let dir = tempdir().unwrap();
@@ -242,6 +519,142 @@ fn get_index_defs(lag_threshold: u64) -> Vec<IndexDef> {
     ]
 }
 
+#[test]
+fn test_entry_offsets() {
+    let dir = tempdir().unwrap();
+    let mut log = Log::open(dir.path(), Vec::new()).unwrap();
+    log.append(b"abc").unwrap();
+    log.append(vec![b'x'; 200]).unwrap();
+    log.sync().unwrap();
+    log.append(b"def").unwrap();
+
+    let entries = log
+        .iter()
+        .with_offsets()
+        .collect::<crate::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].0, PRIMARY_START_OFFSET);
+    for (offset, data) in entries.iter() {
+        assert_eq!(log.entry_at(*offset).unwrap(), Some(*data));
+    }
+
+    // Offsets of on-disk entries are stable across reloads.
+    let offset = entries[1].0;
+    let log = Log::open(dir.path(), Vec::new()).unwrap();
+    assert_eq!(log.entry_at(offset).unwrap().unwrap(), &[b'x'; 200][..]);
+
+    // The end of log.
+    let end = log.meta.primary_len;
+    assert_eq!(log.entry_at(end).unwrap(), None);
+
+    // Out of bound offsets.
+    assert!(log.entry_at(0).is_err());
+    assert!(log.entry_at(end + 1).is_err());
+}
+
+#[test]
+fn test_delete_and_vacuum() {
+    let dir = tempdir().unwrap();
+    let opts = OpenOptions::new()
+        .create(true)
+        .index("first-byte", |_| vec![IndexOutput::Reference(0..1)]);
+    let mut log = opts.open(dir.path()).unwrap();
+    for data in [b"a1", b"b1", b"a2", b"b2"] {
+        log.append(data).unwrap();
+    }
+    log.sync().unwrap();
+    log.append(b"a3").unwrap();
+
+    let offsets: Vec<u64> = log.iter().with_offsets().map(|e| e.unwrap().0).collect();
+
+    // Delete an on-disk entry and an in-memory entry.
+    log.delete(offsets[0]).unwrap();
+    log.delete(offsets[4]).unwrap();
+    log.delete(offsets[4]).unwrap();
+    assert!(log
+        .delete(log.meta.primary_len + log.mem_buf.len() as u64)
+        .is_err());
+
+    let all = |log: &Log| -> Vec<Vec<u8>> { log.iter().map(|e| e.unwrap().to_vec()).collect() };
+    let lookup = |log: &Log, key: &[u8]| -> Vec<Vec<u8>> {
+        let iter = log.lookup(0, key).unwrap();
+        iter.map(|e| e.unwrap().to_vec()).collect()
+    };
+    assert_eq!(all(&log), [b"b1", b"a2", b"b2"]);
+    assert_eq!(lookup(&log, b"a"), [b"a2"]);
+
+    // Another process appends. Deletion of in-memory entries still works.
+    let mut log2 = opts.open(dir.path()).unwrap();
+    log2.append(b"a4").unwrap();
+    log2.sync().unwrap();
+
+    log.delete(offsets[3]).unwrap();
+    log.append(b"a5").unwrap();
+    log.sync().unwrap();
+    assert_eq!(all(&log), [b"b1", b"a2", b"a4", b"a5"]);
+    assert_eq!(lookup(&log, b"a"), [b"a5", b"a4", b"a2"]);
+
+    // Deletions are persisted.
+    drop((log, log2));
+    let log = opts.open(dir.path()).unwrap();
+    assert_eq!(all(&log), [b"b1", b"a2", b"a4", b"a5"]);
+    assert_eq!(lookup(&log, b"b"), [b"b1"]);
+
+    // Vacuum drops deleted entries.
+    let len = log.meta.primary_len;
+    let log = log.vacuum().unwrap();
+    assert!(log.meta.primary_len < len);
+    assert!(log.meta.deleted.is_empty());
+    assert_eq!(all(&log), [b"b1", b"a2", b"a4", b"a5"]);
+    assert_eq!(lookup(&log, b"a"), [b"a5", b"a4", b"a2"]);
+}
+
+#[test]
+fn test_codec() {
+    use crate::codec::tests::XorCodec;
+
+    let dir = tempdir().unwrap();
+    let opts = OpenOptions::new()
+        .create(true)
+        .index("key", |data| {
+            vec![IndexOutput::Reference(0..data.len() as u64)]
+        })
+        .codec(Some(Arc::new(XorCodec(7))));
+
+    let mut log = opts.open(dir.path()).unwrap();
+    log.append(b"plaintext1").unwrap();
+    log.sync().unwrap();
+    log.append(b"plaintext2").unwrap();
+    log.sync().unwrap();
+
+    // On-disk files do not contain plaintext.
+    for name in [PRIMARY_FILE, "index2-key"] {
+        let content = fs::read(dir.path().join(name)).unwrap();
+        assert!(!content.windows(9).any(|w| w == b"plaintext"));
+    }
+
+    // Reading with the same codec works.
+    let log = opts.open(dir.path()).unwrap();
+    assert_eq!(
+        log.iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        [b"plaintext1", b"plaintext2"]
+    );
+    assert_eq!(log.lookup(0, b"plaintext2").unwrap().count(), 1);
+    assert!(log.try_clone().unwrap().rebuild_indexes(true).is_ok());
+    let log = opts.open(dir.path()).unwrap();
+    assert_eq!(log.lookup(0, b"plaintext1").unwrap().count(), 1);
+
+    // Reading with a different codec fails integrity checks.
+    let log = opts
+        .clone()
+        .index_defs(Vec::new())
+        .codec(Some(Arc::new(XorCodec(8))))
+        .open(dir.path())
+        .unwrap();
+    assert!(log.iter().next().unwrap().unwrap_err().is_corruption());
+}
+
 #[test]
 fn test_slice_to_bytes() {
     let dir = tempdir().unwrap();
@@ -288,6 +701,34 @@ fn test_slice_to_bytes() {
     assert_eq!(bytes1.as_ptr(), bytes2.as_ptr());
 }
 
+#[test]
+fn test_bytes_iter() {
+    let dir = tempdir().unwrap();
+    let mut log = Log::open(dir.path(), get_index_defs(0)).unwrap();
+    log.append(b"0123456").unwrap();
+    log.sync().unwrap();
+    log.append(b"1231516").unwrap();
+
+    let slices = log.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+    let bytes = log
+        .iter()
+        .into_bytes_iter()
+        .collect::<crate::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(bytes, slices);
+
+    // On-disk entries are zero-copy. In-memory entries are copied.
+    assert_eq!(bytes[0].as_ptr(), slices[0].as_ptr());
+    assert_ne!(bytes[1].as_ptr(), slices[1].as_ptr());
+
+    let bytes = log.lookup(0, b"23").unwrap().into_bytes_vec().unwrap();
+    assert_eq!(bytes, [&b"1231516"[..], b"0123456"]);
+
+    // Bytes outlive the Log.
+    drop(log);
+    assert_eq!(bytes[1], b"0123456");
+}
+
 #[test]
 fn test_fmt_debug() -> crate::Result<()> {
     let dir = tempdir().unwrap();
@@ -404,9 +845,11 @@ fn test_index_reorder() {
 }
 
 // This test rewrites mmaped files which is unsupoorted by Windows.
-#[cfg(not(windows))]
+#[cfg(all(not(windows), feature = "std-fs"))]
 #[test]
 fn test_index_mark_corrupt() {
+    use std::io::Read;
+
     let dir = tempdir().unwrap();
     let indexes = get_index_defs(0);
 
@@ -438,6 +881,79 @@ fn test_index_mark_corrupt() {
     assert!(log.lookup(1, b"23").is_err());
 }
 
+#[test]
+fn test_lazy_indexes() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let open = |lag| {
+        OpenOptions::new()
+            .index_defs(get_index_defs(lag))
+            .lazy_indexes(true)
+            .create(true)
+            .open(path)
+            .unwrap()
+    };
+    let mut log = open(0);
+    log.append(b"123").unwrap();
+    log.sync().unwrap();
+    // Not written to the on-disk indexes.
+    let mut log = open(1 << 20);
+    log.append(b"234").unwrap();
+    log.sync().unwrap();
+
+    // Lookups load indexes and update them for lagging entries.
+    let log = open(1 << 20);
+    assert!(log.is_index_lazy(0));
+    assert!(log.is_index_lazy(1));
+    let found = log.lookup(0, b"23").unwrap().into_vec().unwrap();
+    assert_eq!(found, [b"234", b"123"]);
+    let loaded = |log: &Log, i: usize| log.lazy_indexes[i].as_ref().unwrap().get().is_some();
+    assert!(loaded(&log, 0));
+    assert!(!loaded(&log, 1));
+
+    // Appending loads all indexes.
+    let mut log2 = log.try_clone().unwrap();
+    assert!(!loaded(&log2, 0));
+    log2.append(b"345").unwrap();
+    assert!(!log2.has_lazy_indexes());
+    assert_eq!(log2.lookup(1, b"234").unwrap().count(), 1);
+    assert_eq!(log2.lookup(1, b"345").unwrap().count(), 1);
+    log2.sync().unwrap();
+
+    // Changes are visible after sync.
+    let mut log = log;
+    log.sync().unwrap();
+    assert!(log.has_lazy_indexes());
+    assert_eq!(log.lookup(0, b"45").unwrap().count(), 1);
+    assert_eq!(log.lookup(1, b"123").unwrap().count(), 1);
+}
+
+// This test rewrites index files which is unsupoorted by Windows.
+#[cfg(all(not(windows), feature = "std-fs"))]
+#[test]
+fn test_lazy_indexes_corrupted() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new()
+        .index_defs(get_index_defs(0))
+        .lazy_indexes(true)
+        .create(true);
+    let mut log = opts.clone().open(path).unwrap();
+    log.append(b"123").unwrap();
+    log.sync().unwrap();
+    drop(log);
+
+    let size = fs::metadata(path.join("index2-y")).unwrap().len();
+    fs::write(path.join("index2-y"), vec![0; size as usize]).unwrap();
+
+    // Errors are reported by lookups using the corrupted index.
+    let mut log = opts.open(path).unwrap();
+    assert_eq!(log.lookup(0, b"23").unwrap().count(), 1);
+    assert!(log.lookup(1, b"123").is_err());
+    assert!(log.append(b"234").is_err());
+    assert!(log.lookup(0, b"23").is_err());
+}
+
 #[test]
 fn test_lookup_prefix_and_range() {
     let dir = tempdir().unwrap();
@@ -810,6 +1326,183 @@ fn test_auto_sync_threshold() {
     assert_eq!(log.iter_dirty().count(), 0);
 }
 
+#[test]
+fn test_durability() {
+    let dir = tempdir().unwrap();
+    let open_opts = OpenOptions::new()
+        .create(true)
+        .index("i", |_| vec![IndexOutput::Reference(0..1)])
+        .durability(Durability::FsyncBatched(Duration::from_millis(10)));
+    let mut log = open_opts.open(dir.path()).unwrap();
+    log.append(b"a").unwrap();
+    log.sync().unwrap();
+
+    // flush_async writes data before returning.
+    log.append(b"b").unwrap();
+    let handle = log.flush_async().unwrap();
+    let log2 = open_opts.open(dir.path()).unwrap();
+    assert_eq!(log2.iter().count(), 2);
+    assert_eq!(log2.lookup(0, b"b").unwrap().count(), 1);
+    handle.wait().unwrap();
+
+    // flush_async restores the fsync setting.
+    let mut log = open_opts.clone().fsync(true).open(dir.path()).unwrap();
+    log.append(b"c").unwrap();
+    log.flush_async().unwrap().wait().unwrap();
+    assert!(log.open_options.fsync);
+    assert!(log.indexes.iter().all(|i| i.fsync));
+
+    // flush_async works for in-memory logs.
+    let mut log = open_opts.open(()).unwrap();
+    log.append(b"d").unwrap();
+    log.flush_async().unwrap().wait().unwrap();
+}
+
+#[test]
+fn test_append_writer() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let open_opts = OpenOptions::new()
+        .create(true)
+        .index("i", |data| match data.len() {
+            0 => Vec::new(),
+            _ => vec![IndexOutput::Reference(0..1)],
+        });
+    let mut log = open_opts.open(path).unwrap();
+    log.append(b"abc").unwrap();
+
+    let large: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let mut writer = log.append_writer().unwrap();
+    for chunk in large.chunks(7000) {
+        writer.write_all(chunk).unwrap();
+    }
+    let offset = writer.finish().unwrap();
+
+    // Pending in-memory entries are written before the entry.
+    assert_eq!(log.iter_dirty().count(), 0);
+    assert_eq!(log.entry_at(offset).unwrap(), Some(&large[..]));
+    assert_eq!(
+        log.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+        [&b"abc"[..], &large[..]]
+    );
+    assert_eq!(
+        log.lookup(0, [0]).unwrap().into_vec().unwrap(),
+        [&large[..]]
+    );
+
+    // Empty entry.
+    let offset = log.append_writer().unwrap().finish().unwrap();
+    assert_eq!(log.entry_at(offset).unwrap(), Some(&b""[..]));
+
+    // Dropped writers do not append.
+    let mut writer = log.append_writer().unwrap();
+    writer.write_all(b"x").unwrap();
+    drop(writer);
+
+    let log = open_opts.open(path).unwrap();
+    assert_eq!(log.iter().count(), 3);
+    assert_eq!(log.verify().unwrap(), []);
+    let mut names: Vec<_> = fs::read_dir(path)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.retain(|n| n.starts_with("append"));
+    assert!(names.is_empty());
+}
+
+#[test]
+fn test_update_meta() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let mut log1 = Log::open(path, Vec::new()).unwrap();
+    let mut log2 = Log::open(path, Vec::new()).unwrap();
+
+    // Changes are visible in-memory before sync.
+    log1.update_meta([("version", Some(b"1")), ("tip", Some(b"a"))])
+        .unwrap();
+    assert_eq!(log1.meta("version"), Some(&b"1"[..]));
+    assert_eq!(log1.meta("foo"), None);
+    log1.sync().unwrap();
+    assert!(log2.is_changed_on_disk());
+
+    // Changes to different keys are merged.
+    log2.append(b"x").unwrap();
+    log2.update_meta([("tip", Some(b"b")), ("version", None)])
+        .unwrap();
+    log2.update_meta([("other", Some(b"c"))]).unwrap();
+    log2.sync().unwrap();
+    assert_eq!(log2.meta("version"), None);
+    assert_eq!(log2.meta("tip"), Some(&b"b"[..]));
+    assert_eq!(log2.meta("other"), Some(&b"c"[..]));
+
+    // Reload from disk.
+    log1.sync().unwrap();
+    assert_eq!(log1.meta("tip"), Some(&b"b"[..]));
+    let log3 = Log::open(path, Vec::new()).unwrap();
+    assert_eq!(log3.meta("other"), Some(&b"c"[..]));
+
+    // Discarded by clear_dirty.
+    log1.update_meta([("tip", Some(b"z"))]).unwrap();
+    log1.clear_dirty().unwrap();
+    assert_eq!(log1.meta("tip"), Some(&b"b"[..]));
+
+    // Preserved by rewrite.
+    drop(log1);
+    drop(log2);
+    let log3 = log3.vacuum().unwrap();
+    assert_eq!(log3.meta("tip"), Some(&b"b"[..]));
+}
+
+#[test]
+fn test_is_changed_on_disk() {
+    let dir = tempdir().unwrap();
+    let mut log1 = log_with_index(dir.path(), 100);
+    let mut log2 = log_with_index(dir.path(), 100);
+    assert!(!log1.is_changed_on_disk());
+
+    insert_entries(&mut log2, 0, 3);
+    assert!(!log1.is_changed_on_disk());
+    log2.sync().unwrap();
+    assert!(log1.is_changed_on_disk());
+    log1.sync().unwrap();
+    assert!(!log1.is_changed_on_disk());
+
+    log2.delete(PRIMARY_START_OFFSET).unwrap();
+    log2.sync().unwrap();
+    assert!(log1.is_changed_on_disk());
+    log1.sync().unwrap();
+    assert!(!log1.is_changed_on_disk());
+
+    // In-memory Log is never changed on disk.
+    assert!(!OpenOptions::new().open(()).unwrap().is_changed_on_disk());
+}
+
+#[test]
+fn test_watch() {
+    let dir = tempdir().unwrap();
+    let log1 = log_with_index(dir.path(), 100);
+    let mut log2 = log_with_index(dir.path(), 100);
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let watcher = log1
+        .watch(Duration::from_millis(1), move |log| {
+            let _ = sender.send(log.iter().count());
+        })
+        .unwrap();
+
+    insert_entries(&mut log2, 0, 3);
+    log2.sync().unwrap();
+    assert_eq!(receiver.recv().unwrap(), 3);
+
+    insert_entries(&mut log2, 3, 2);
+    log2.sync().unwrap();
+    assert_eq!(receiver.recv().unwrap(), 5);
+
+    let log1 = watcher.stop();
+    assert_eq!(log1.iter().count(), 5);
+    assert!(receiver.recv().is_err());
+}
+
 #[test]
 fn test_sync_missing_meta() {
     let dir = tempdir().unwrap();
@@ -896,20 +1589,1401 @@ fn test_rebuild_indexes() {
     assert_eq!(log.lookup(0, b"xyz").unwrap().count(), 0);
 }
 
-pub(crate) fn pwrite(path: &Path, offset: i64, data: &[u8]) {
-    let mut file = fs::OpenOptions::new()
-        .write(true)
-        .read(true)
-        .open(path)
-        .unwrap();
-    if offset < 0 {
-        file.seek(SeekFrom::End(offset)).unwrap();
-    } else {
-        file.seek(SeekFrom::Start(offset as u64)).unwrap();
-    }
-    file.write_all(data).unwrap();
-}
-
+#[test]
+fn test_copy_to() {
+    let dir = tempdir().unwrap();
+    let src_path = dir.path().join("src");
+    let dest_path = dir.path().join("dest");
+    let opts = OpenOptions::new()
+        .create(true)
+        .index("c", |_| vec![IndexOutput::Reference(0..1)]);
+
+    let mut log1 = opts.open(&src_path).unwrap();
+    log1.append(b"a").unwrap();
+    log1.sync().unwrap();
+    log1.append(b"b").unwrap();
+
+    // Writers can continue after log1 takes its snapshot.
+    let mut log2 = opts.open(&src_path).unwrap();
+    log2.append(b"c").unwrap();
+    log2.sync().unwrap();
+
+    // In-memory entries, or entries appended by others are not copied.
+    log1.copy_to(&dest_path).unwrap();
+    let log3 = opts.clone().create(false).open(&dest_path).unwrap();
+    assert_eq!(
+        log3.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+        [b"a"]
+    );
+    assert_eq!(log3.lookup(0, b"a").unwrap().into_vec().unwrap(), [b"a"]);
+    assert_eq!(log3.verify().unwrap(), []);
+
+    // Refuse to overwrite.
+    assert!(log2.copy_to(&dest_path).is_err());
+}
+
+#[test]
+fn test_export_import() {
+    let dir = tempdir().unwrap();
+    let src_path = dir.path().join("src");
+    let dest_path = dir.path().join("dest");
+    let opts = OpenOptions::new()
+        .create(true)
+        .entry_timestamp(true)
+        .index("c", |_| vec![IndexOutput::Reference(0..1)]);
+
+    let mut log = opts.open(&src_path).unwrap();
+    log.append_in_memory(b"a", Some(1000)).unwrap();
+    log.append(b"b").unwrap();
+    log.sync().unwrap();
+    log.delete(PRIMARY_START_OFFSET).unwrap();
+    log.append(b"c").unwrap();
+    log.update_meta([("version", Some(b"1"))]).unwrap();
+
+    // Deleted entries are skipped. In-memory changes are included.
+    let mut archive = Vec::new();
+    log.export_to(&mut archive).unwrap();
+    let log2 = Log::import_from(&archive[..], &dest_path, &opts).unwrap();
+    assert_eq!(
+        log2.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+        [b"b", b"c"]
+    );
+    assert_eq!(log2.lookup(0, b"c").unwrap().into_vec().unwrap(), [b"c"]);
+    assert_eq!(log2.meta("version"), Some(&b"1"[..]));
+    assert_eq!(log2.verify().unwrap(), []);
+
+    // Timestamps are preserved.
+    let timestamps = |log: &Log| -> Vec<Option<SystemTime>> {
+        log.iter()
+            .with_offsets()
+            .map(|e| log.entry_timestamp(e.unwrap().0).unwrap())
+            .collect()
+    };
+    log.sync().unwrap();
+    assert_eq!(timestamps(&log2), timestamps(&log));
+
+    // Refuse to overwrite.
+    assert!(Log::import_from(&archive[..], &dest_path, &opts).is_err());
+
+    // Import in memory.
+    let log3 = Log::import_in_memory(&archive[..], &opts).unwrap();
+    assert_eq!(log3.lookup(0, b"c").unwrap().into_vec().unwrap(), [b"c"]);
+    assert_eq!(log3.meta("version"), Some(&b"1"[..]));
+
+    // Corrupted or truncated archives are rejected without leaving a Log.
+    let path = dir.path().join("corrupted");
+    let mut corrupted = archive.clone();
+    let last = corrupted.len() - 10;
+    corrupted[last] ^= 1;
+    assert!(Log::import_from(&corrupted[..], &path, &opts).is_err());
+    let truncated = &archive[..archive.len() - 1];
+    assert!(Log::import_from(truncated, &path, &opts).is_err());
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_auto_sync_interval() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new().create(true);
+    assert!(opts.open(path).unwrap().into_auto_sync().is_err());
+
+    let opts = opts.auto_sync_interval(Duration::from_millis(10));
+    let log = opts.open(path).unwrap().into_auto_sync().unwrap();
+    log.lock().append(b"a").unwrap();
+
+    // The background thread syncs the entry.
+    let count = |log: &Log| log.iter().count();
+    let mut log2 = opts.open(path).unwrap();
+    for _ in 0..500 {
+        if count(&log2) > 0 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        log2.sync().unwrap();
+    }
+    assert_eq!(count(&log2), 1);
+    assert!(log.take_error().is_none());
+
+    // into_inner stops the background thread and returns the Log.
+    let log = log.into_inner();
+    assert_eq!(count(&log), 1);
+
+    // Dropping syncs pending changes.
+    let log = opts.open(path).unwrap().into_auto_sync().unwrap();
+    log.lock().append(b"c").unwrap();
+    drop(log);
+    log2.sync().unwrap();
+    assert_eq!(count(&log2), 2);
+}
+
+#[test]
+fn test_preallocate_chunk_size() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new()
+        .create(true)
+        .preallocate_chunk_size(4096)
+        .index("c", |_| vec![IndexOutput::Reference(0..1)]);
+    let file_len = || path.join(PRIMARY_FILE).metadata().unwrap().len();
+
+    let mut log = opts.open(path).unwrap();
+    log.append(b"a").unwrap();
+    log.sync().unwrap();
+    assert_eq!(file_len(), 4096);
+
+    // Space after the logical length is overwritten.
+    let mut log2 = opts.open(path).unwrap();
+    log2.append(b"b").unwrap();
+    log2.sync().unwrap();
+    log.append(vec![b'c'; 5000]).unwrap();
+    log.sync().unwrap();
+    assert_eq!(file_len(), 8192);
+    assert!(log.meta.primary_len < file_len());
+
+    let mut writer = log.append_writer().unwrap();
+    writer.write_all(&[b'd'; 5000]).unwrap();
+    writer.finish().unwrap();
+    assert_eq!(file_len(), 12288);
+    assert!(log.meta.primary_len > 8192);
+
+    let log = opts.open(path).unwrap();
+    let lens: Vec<usize> = log.iter().map(|e| e.unwrap().len()).collect();
+    assert_eq!(lens, [1, 1, 5000, 5000]);
+    assert_eq!(log.lookup(0, b"b").unwrap().count(), 1);
+    assert_eq!(log.verify().unwrap(), []);
+
+    // Repair does not treat preallocated space as entries.
+    drop(log);
+    opts.repair(path).unwrap();
+    assert_eq!(opts.open(path).unwrap().iter().count(), 4);
+}
+
+#[test]
+fn test_memory_dir() {
+    let dir = MemoryDir::new();
+    let opts = OpenOptions::new().index("c", |_| vec![IndexOutput::Reference(0..1)]);
+    assert!(opts.open(&dir).is_err());
+    assert!(!dir.exists());
+
+    let opts = opts.create(true);
+    let mut log1 = opts.open(&dir).unwrap();
+    let mut log2 = opts.open(&dir).unwrap();
+    assert!(dir.exists());
+    let entries = |log: &Log| -> Vec<Vec<u8>> { log.iter().map(|e| e.unwrap().to_vec()).collect() };
+    let lookup = |log: &Log, key: &[u8]| -> Vec<Vec<u8>> {
+        let iter = log.lookup(0, key).unwrap();
+        iter.map(|e| e.unwrap().to_vec()).collect()
+    };
+
+    // Changes are visible to others after sync.
+    log1.append(b"a").unwrap();
+    log1.update_meta([("k", Some(b"v"))]).unwrap();
+    log2.append(b"b").unwrap();
+    assert!(!log2.is_changed_on_disk());
+    log1.sync().unwrap();
+    assert!(log2.is_changed_on_disk());
+    assert_eq!(entries(&log2), [b"b"]);
+    log2.sync().unwrap();
+    assert_eq!(entries(&log2), [b"a", b"b"]);
+    assert_eq!(lookup(&log2, b"a"), [b"a"]);
+    assert_eq!(log2.meta("k"), Some(&b"v"[..]));
+
+    // Indexes are built on open.
+    log1.delete(PRIMARY_START_OFFSET).unwrap();
+    log1.append(b"c").unwrap();
+    log1.sync().unwrap();
+    let log3 = opts.open(&dir).unwrap();
+    assert_eq!(entries(&log3), [b"b", b"c"]);
+    assert_eq!(lookup(&log3, b"b"), [b"b"]);
+    assert_eq!(lookup(&log3, b"c"), [b"c"]);
+    assert_eq!(log3.verify().unwrap(), []);
+
+    // Rewrite bumps epoch. Existing Logs keep their snapshots until sync.
+    let log3 = log3
+        .rewrite(
+            |data| {
+                Ok(match data {
+                    b"b" => FlushFilterOutput::Drop,
+                    _ => FlushFilterOutput::Keep,
+                })
+            },
+            &opts,
+        )
+        .unwrap();
+    assert_eq!(entries(&log3), [b"c"]);
+    assert_eq!(entries(&log2), [b"a", b"b"]);
+    log2.append(b"d").unwrap();
+    log2.sync().unwrap();
+    assert_eq!(entries(&log2), [b"c", b"d"]);
+    assert_eq!(lookup(&log2, b"d"), [b"d"]);
+    assert_eq!(log2.meta("k"), Some(&b"v"[..]));
+
+    // Snapshots are not affected by other writers.
+    let snapshot = log2.try_clone_without_dirty().unwrap();
+    log1.sync().unwrap();
+    log1.append(b"e").unwrap();
+    log1.sync().unwrap();
+    assert_eq!(entries(&snapshot), [b"c", b"d"]);
+    assert_eq!(entries(&opts.open(&dir).unwrap()), [b"c", b"d", b"e"]);
+
+    // A Log that is the only reader extends the primary log in place.
+    let dir = MemoryDir::new();
+    let mut log = opts.open(&dir).unwrap();
+    let mut buffers = std::collections::HashSet::new();
+    for i in 0..1000u32 {
+        log.append(i.to_be_bytes()).unwrap();
+        log.sync().unwrap();
+        buffers.insert(log.disk_buf.as_ptr());
+    }
+    assert!(buffers.len() < 50, "{} buffers", buffers.len());
+    assert_eq!(log.iter().count(), 1000);
+    assert_eq!(lookup(&log, &[0]).len(), 1000);
+}
+
+#[test]
+fn test_epoch_and_change_detector() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new()
+        .create(true)
+        .index("c", |_| vec![IndexOutput::Reference(0..1)]);
+
+    let mut log1 = opts.open(path).unwrap();
+    let mut log2 = opts.open(path).unwrap();
+    let mut detector = log2.change_detector();
+    let epoch = log1.epoch();
+    assert_eq!(log2.epoch(), epoch);
+    assert!(!detector.is_changed());
+
+    // Appending does not change epoch.
+    log1.append(b"a").unwrap();
+    log1.sync().unwrap();
+    assert!(detector.is_changed());
+    assert!(!detector.is_changed());
+    assert_eq!(detector.epoch(), epoch);
+
+    // Index-only changes are ignored.
+    log2.sync().unwrap();
+    assert!(!detector.is_changed());
+
+    // Bump epoch. Pending changes are written.
+    log1.append(b"b").unwrap();
+    let new_epoch = log1.bump_epoch().unwrap();
+    assert_ne!(new_epoch, epoch);
+    assert_eq!(log1.epoch(), new_epoch);
+    assert!(detector.is_changed());
+    assert_eq!(detector.epoch(), new_epoch);
+
+    // Other Logs reload, and keep their pending changes.
+    log2.append(b"c").unwrap();
+    log2.sync().unwrap();
+    assert_eq!(log2.epoch(), new_epoch);
+    assert_eq!(
+        log2.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+        [b"a", b"b", b"c"]
+    );
+    assert_eq!(log2.lookup(0, b"b").unwrap().count(), 1);
+
+    // In-memory Logs.
+    let mut log = opts.open(()).unwrap();
+    let epoch = log.epoch();
+    assert_ne!(log.bump_epoch().unwrap(), epoch);
+    assert!(!log.change_detector().is_changed());
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_tracing_spans() {
+    let dir = tempdir().unwrap();
+    let lines = dev_logger::traced("info", || {
+        let mut log = OpenOptions::new()
+            .create(true)
+            .index_defs(get_index_defs(0))
+            .open(dir.path())
+            .unwrap();
+        log.append(b"abcd").unwrap();
+        log.sync().unwrap();
+    });
+    let has_exit = |name: &str, field: &str| {
+        lines
+            .iter()
+            .any(|l| l.contains(name) && l.contains(field) && l.ends_with("exit"))
+    };
+    assert!(has_exit(
+        "indexedlog::log::sync{",
+        "bytes_written=10 duration_us="
+    ));
+    assert!(has_exit("indexedlog::index::flush{", "bytes_written="));
+    assert!(has_exit("indexedlog::log::update_index{", "index=\"x\""));
+    assert!(has_exit("indexedlog::lock{", "exclusive=true"));
+}
+
+#[test]
+fn test_metrics() {
+    let dir = tempdir().unwrap();
+    let opts = OpenOptions::new()
+        .create(true)
+        .index_defs(vec![IndexDef::new("x", |_| {
+            vec![IndexOutput::Reference(0..2)]
+        })]);
+    let mut log = opts.clone().open(dir.path()).unwrap();
+    let metrics = log.metrics();
+
+    log.append(b"abc").unwrap();
+    log.append(b"bcd").unwrap();
+    log.append(b"cde").unwrap();
+    log.sync().unwrap();
+    assert_eq!(metrics.appends(), 3);
+    assert_eq!(
+        metrics.bytes_written(),
+        log.meta.primary_len - PRIMARY_START_OFFSET
+    );
+
+    assert_eq!(log.lookup(0, b"ab").unwrap().count(), 1);
+    assert_eq!(log.lookup(0, b"xy").unwrap().count(), 0);
+    assert_eq!(log.lookup_prefix(0, b"c").unwrap().count(), 1);
+    assert_eq!(metrics.lookups(), 3);
+    assert_eq!(metrics.index_misses(), 1);
+
+    // Counters are shared with Logs opened by the same OpenOptions.
+    let mut log2 = opts.clone().open(dir.path()).unwrap();
+    log2.append(b"def").unwrap();
+    assert_eq!(metrics.appends(), 4);
+
+    // Contended lock.
+    let lock = ScopedDirLock::new(dir.path()).unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let thread = std::thread::spawn(move || {
+        tx.send(()).unwrap();
+        log2.sync().unwrap()
+    });
+    rx.recv().unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    drop(lock);
+    thread.join().unwrap();
+    assert_eq!(metrics.lock_waits(), 1);
+
+    opts.repair(dir.path()).unwrap();
+    assert_eq!(metrics.repairs(), 1);
+}
+
+#[test]
+fn test_mmap_advice() {
+    let dir = tempdir().unwrap();
+    let opts = OpenOptions::new();
+    assert_eq!(opts.lookup_advice, MmapAdvice::Random);
+    assert_eq!(opts.iter_advice, MmapAdvice::Sequential);
+    let mut log = opts
+        .create(true)
+        .index_defs(vec![IndexDef::new("x", |_| {
+            vec![IndexOutput::Reference(0..4)]
+        })])
+        .release_after_iter(true)
+        .open(dir.path())
+        .unwrap();
+    let entries: Vec<Vec<u8>> = (0..5000u32)
+        .map(|i| {
+            let mut data = i.to_be_bytes().to_vec();
+            data.resize(100, b'x');
+            data
+        })
+        .collect();
+    for entry in &entries {
+        log.append(entry).unwrap();
+    }
+    log.sync().unwrap();
+
+    // Pages released after iteration are read again from the file.
+    assert_eq!(log.iter().take(10).count(), 10);
+    for _ in 0..2 {
+        let all: Vec<&[u8]> = log.iter().map(|e| e.unwrap()).collect();
+        assert_eq!(all, entries);
+    }
+    let key = 4999u32.to_be_bytes();
+    assert_eq!(log.lookup(0, key).unwrap().count(), 1);
+}
+
+#[test]
+fn test_max_entry_size() {
+    let dir = tempdir().unwrap();
+    let mut log = OpenOptions::new()
+        .create(true)
+        .entry_timestamp(true)
+        .max_entry_size(4)
+        .open(dir.path())
+        .unwrap();
+    log.append(b"1234").unwrap();
+    let err = log.append(b"12345").unwrap_err();
+    assert!(err.is_entry_too_large());
+    assert_eq!(err.kind(), crate::ErrorKind::EntryTooLarge);
+
+    let mut writer = log.append_writer().unwrap();
+    writer.write_all(b"123").unwrap();
+    let err = writer.write_all(b"45").unwrap_err();
+    let err = err
+        .get_ref()
+        .unwrap()
+        .downcast_ref::<crate::Error>()
+        .unwrap();
+    assert!(err.is_entry_too_large());
+    writer.write_all(b"4").unwrap();
+    writer.finish().unwrap();
+
+    let entries: Vec<&[u8]> = log.iter().map(|e| e.unwrap()).collect();
+    assert_eq!(entries, [b"1234"; 2]);
+    drop(log);
+
+    // Existing entries are not checked. For example, by rewrite.
+    let opts = OpenOptions::new().create(true);
+    let mut log = opts.clone().open(dir.path()).unwrap();
+    log.append(b"123456").unwrap();
+    log.sync().unwrap();
+    drop(log);
+    let opts = opts.max_entry_size(4);
+    let log = opts.clone().open(dir.path()).unwrap();
+    let log = log.rewrite(|_| Ok(FlushFilterOutput::Keep), &opts).unwrap();
+    assert_eq!(log.iter().count(), 3);
+}
+
+#[test]
+fn test_absurd_entry_length() {
+    let dir = tempdir().unwrap();
+    let mut log = Log::open(dir.path(), Vec::new()).unwrap();
+    log.append(&[b'x'; 20][..]).unwrap();
+    log.sync().unwrap();
+
+    // Overwrite LEN of the entry with u64::MAX.
+    let mut len = Vec::new();
+    len.write_vlq(u64::MAX).unwrap();
+    pwrite(&dir.path().join(PRIMARY_FILE), 13, &len);
+
+    let log = Log::open(dir.path(), Vec::new()).unwrap();
+    let err = log.iter().next().unwrap().unwrap_err();
+    assert!(err.is_corruption());
+}
+
+#[test]
+fn test_checksum_verification() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let mut log = Log::open(path, Vec::new()).unwrap();
+    log.append(b"abc").unwrap();
+    log.append(b"def").unwrap();
+    log.sync().unwrap();
+    let offsets: Vec<u64> = log.iter().with_offsets().map(|e| e.unwrap().0).collect();
+
+    let primary_path = path.join(PRIMARY_FILE);
+    let len = primary_path.metadata().unwrap().len() as i64;
+    let open = |verification| {
+        OpenOptions::new()
+            .checksum_verification(verification)
+            .open(path)
+            .unwrap()
+    };
+
+    // Verified entries are not checked again.
+    let log = open(ChecksumVerification::FirstAccess);
+    assert_eq!(log.iter().count(), 2);
+    pwrite(&primary_path, len - 1, b"x");
+    assert!(log.iter().all(|e| e.is_ok()));
+
+    let log = open(ChecksumVerification::Always);
+    assert!(log.iter().nth(1).unwrap().unwrap_err().is_corruption());
+
+    // Reads do not check. verify_range does.
+    let log = open(ChecksumVerification::OnDemand);
+    assert_eq!(
+        log.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+        [b"abc", b"dex"]
+    );
+    log.verify_range(..offsets[1]).unwrap();
+    assert!(log.verify_range(offsets[1]..).unwrap_err().is_corruption());
+    assert!(log.verify_range(..).is_err());
+}
+
+#[test]
+fn test_verified_ranges() {
+    let mut ranges = VerifiedRanges::default();
+    ranges.insert(10..20);
+    ranges.insert(30..40);
+    assert!(!ranges.contains(9));
+    assert!(ranges.contains(10));
+    assert!(ranges.contains(19));
+    assert!(!ranges.contains(20));
+    assert!(ranges.contains(35));
+
+    // Adjacent and overlapping ranges are merged.
+    ranges.insert(20..30);
+    ranges.insert(5..12);
+    assert!(!ranges.contains(4));
+    assert!((5..40).all(|i| ranges.contains(i)));
+    assert!(!ranges.contains(40));
+}
+
+#[test]
+fn test_poison() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let mut log1 = Log::open(path, Vec::new()).unwrap();
+    let mut log2 = Log::open(path, Vec::new()).unwrap();
+
+    log1.append(b"a").unwrap();
+    log1.poison("migrating").unwrap();
+
+    let err = Log::open(path, Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), crate::ErrorKind::Poisoned);
+    assert_eq!(err.poison_reason(), Some("migrating"));
+    assert!(log1.poison("again").is_err());
+
+    log2.append(b"b").unwrap();
+    let err = log2.sync().unwrap_err();
+    assert_eq!(err.poison_reason(), Some("migrating"));
+
+    // Read-only Logs can still be used.
+    let log = OpenOptions::new().read_only(true).open(path).unwrap();
+    assert_eq!(log.iter().count(), 1);
+
+    log1.unpoison().unwrap();
+    log2.sync().unwrap();
+    let log = Log::open(path, Vec::new()).unwrap();
+    assert_eq!(log.iter().count(), 2);
+}
+
+#[test]
+fn test_repair_poisoned() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let mut log = Log::open(path, Vec::new()).unwrap();
+    log.append(b"a").unwrap();
+    log.poison("migrating").unwrap();
+
+    // Poisoned Logs are not repaired, even automatically.
+    let err = OpenOptions::new().repair(path).unwrap_err();
+    assert_eq!(err.poison_reason(), Some("migrating"));
+    let opts = OpenOptions::new().auto_repair(true);
+    let err = opts.open(path).unwrap_err();
+    assert_eq!(err.poison_reason(), Some("migrating"));
+
+    log.unpoison().unwrap();
+    let log = Log::open(path, Vec::new()).unwrap();
+    assert_eq!(log.iter().count(), 1);
+}
+
+#[test]
+fn test_format_version() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let meta_path = path.join(META_FILE);
+    let set_format_version = |version| {
+        let mut meta = LogMetadata::read_file(&meta_path).unwrap();
+        meta.format_version = version;
+        meta.write_file(&meta_path, false).unwrap();
+    };
+    let opts = OpenOptions::new()
+        .create(true)
+        .index("c", |_| vec![IndexOutput::Reference(0..1)]);
+
+    let mut log = opts.open(path).unwrap();
+    assert_eq!(log.format_version(), meta::INITIAL_FORMAT_VERSION);
+    log.append(b"a").unwrap();
+    log.sync().unwrap();
+
+    // Entries using newer flags bump the version, and change the header so
+    // older versions refuse the metadata.
+    let mut log2 = opts
+        .clone()
+        .checksum_type(ChecksumType::Xxh3)
+        .open(path)
+        .unwrap();
+    log2.append(b"x").unwrap();
+    log2.clear_dirty().unwrap();
+    log2.sync().unwrap();
+    assert_eq!(log2.format_version(), meta::INITIAL_FORMAT_VERSION);
+    assert!(utils::atomic_read(&meta_path)
+        .unwrap()
+        .starts_with(b"meta\x01"));
+    drop(log2);
+
+    // Newer versions are refused. They are not corruption.
+    set_format_version(LATEST_FORMAT_VERSION + 1);
+    let err = opts.clone().auto_repair(true).open(path).unwrap_err();
+    assert!(!err.is_corruption());
+    assert_eq!(err.kind(), crate::ErrorKind::Unsupported);
+    assert!(err.to_string().contains("format version"));
+    log.append(b"b").unwrap();
+    assert!(log.sync().is_err());
+
+    // Older versions can be used, and migrated.
+    set_format_version(0);
+    log.sync().unwrap();
+    drop(log);
+    let log = opts.open(path).unwrap();
+    assert_eq!(log.format_version(), 0);
+    let log = log.migrate_to_latest().unwrap();
+    assert_eq!(log.format_version(), LATEST_FORMAT_VERSION);
+    assert!(utils::atomic_read(&meta_path)
+        .unwrap()
+        .starts_with(b"meta\x02"));
+    assert_eq!(log.lookup(0, b"b").unwrap().count(), 1);
+    assert_eq!(
+        log.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+        [b"a", b"b"]
+    );
+    drop(log);
+
+    // Newer entry flags bump the version.
+    let mut log = opts
+        .clone()
+        .checksum_type(ChecksumType::Xxh3)
+        .open(path)
+        .unwrap();
+    set_format_version(meta::INITIAL_FORMAT_VERSION);
+    log.append(b"c").unwrap();
+    log.sync().unwrap();
+    assert_eq!(log.format_version(), ENTRY_FLAGS_FORMAT_VERSION);
+    assert!(utils::atomic_read(&meta_path)
+        .unwrap()
+        .starts_with(b"meta\x02"));
+}
+
+#[test]
+fn test_entry_count() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new().create(true);
+    let mut log = opts.open(path).unwrap();
+    assert_eq!(log.meta.entry_count, Some(0));
+
+    // Deleted entries are recorded, but not returned.
+    log.append(b"a").unwrap();
+    log.append(b"b").unwrap();
+    log.delete(PRIMARY_START_OFFSET).unwrap();
+    assert_eq!(log.recorded_entry_count(), Some(1));
+    log.sync().unwrap();
+    assert_eq!(log.meta.entry_count, Some(2));
+    assert_eq!(log.recorded_entry_count(), Some(1));
+
+    let mut writer = log.append_writer().unwrap();
+    writer.write_all(b"c").unwrap();
+    writer.finish().unwrap();
+    log.sync().unwrap();
+    assert_eq!(log.meta.entry_count, Some(3));
+
+    // Rewrite drops deleted entries.
+    let log = log.vacuum().unwrap();
+    assert_eq!(log.meta.entry_count, Some(2));
+    drop(log);
+
+    // Repair records missing counts.
+    let meta_path = path.join(META_FILE);
+    let mut meta = LogMetadata::read_file(&meta_path).unwrap();
+    meta.entry_count = None;
+    meta.write_file(&meta_path, false).unwrap();
+    let log = opts.open(path).unwrap();
+    assert_eq!(log.recorded_entry_count(), None);
+    drop(log);
+    opts.repair(path).unwrap();
+    let log = opts.open(path).unwrap();
+    assert_eq!(log.meta.entry_count, Some(2));
+    assert_eq!(log.recorded_entry_count(), Some(log.iter().count()));
+}
+
+#[test]
+fn test_rebuild_indexes_via_temp_file() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new()
+        .create(true)
+        .fsync(true)
+        .index("c", |_| vec![IndexOutput::Reference(0..1)])
+        .index("c.rebuild", |_| vec![IndexOutput::Reference(0..1)]);
+    let mut log = opts.open(path).unwrap();
+    log.append(b"a").unwrap();
+    log.sync().unwrap();
+    drop(log);
+
+    // A temporary file left by a crashed rebuild is tracked by metadata.
+    let tmp_name = "index2-c.rebuild-crashed";
+    fs::write(path.join(tmp_name), b"garbage").unwrap();
+    let meta_path = path.join(META_FILE);
+    let mut meta = LogMetadata::read_file(&meta_path).unwrap();
+    meta.temp_files.insert(tmp_name.to_string());
+    meta.write_file(&meta_path, false).unwrap();
+    let log = opts.open(path).unwrap();
+    assert_eq!(log.lookup(0, b"a").unwrap().count(), 1);
+
+    // The next rebuild removes it. Indexes with similar names are kept.
+    let message = log.rebuild_indexes(true).unwrap();
+    assert!(message.contains("Rebuilt index \"c\""));
+    assert!(!path.join(tmp_name).exists());
+    let log = opts.open(path).unwrap();
+    assert!(log.meta.temp_files.is_empty());
+    assert_eq!(log.lookup(0, b"a").unwrap().count(), 1);
+    assert_eq!(log.lookup(1, b"a").unwrap().count(), 1);
+    let mut names: Vec<_> = fs::read_dir(path)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.retain(|n| n.starts_with("index2-"));
+    names.sort();
+    assert_eq!(names, ["index2-c", "index2-c.rebuild"]);
+}
+
+#[cfg(feature = "std-fs")]
+#[test]
+fn test_rebuild_indexes_partial_recovery() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new()
+        .create(true)
+        .index_defs(vec![IndexDef::new("k", |_| {
+            vec![IndexOutput::Reference(0..1)]
+        })
+        .lag_threshold(0)
+        .checksum_chunk_size_logarithm(6)]);
+    let mut log = opts.open(path).unwrap();
+    for i in 0..300u32 {
+        log.append([(i * 37 % 256) as u8, i as u8]).unwrap();
+    }
+    log.sync().unwrap();
+    let lookup_all = |log: &Log| -> Vec<Vec<Vec<u8>>> {
+        (0..=255u8)
+            .map(|k| {
+                let iter = log.lookup(0, [k]).unwrap();
+                iter.map(|v| v.unwrap().to_vec()).collect()
+            })
+            .collect()
+    };
+    let expected = lookup_all(&log);
+    drop(log);
+
+    // Corrupt a byte in the middle of the index.
+    let index_path = path.join("index2-k");
+    let index_len = fs::metadata(&index_path).unwrap().len();
+    let offset = index_len / 2;
+    let byte = fs::read(&index_path).unwrap()[offset as usize];
+    pwrite(&index_path, offset as i64, &[byte ^ 1]);
+
+    let report = opts.repair_with_report(path).unwrap();
+    assert_eq!(report.rebuilt_indexes, ["k"]);
+    assert_eq!(report.recovered_indexes.len(), 1, "{}", report.message);
+    let recovery = &report.recovered_indexes[0];
+    assert_eq!(recovery.name, "k");
+    assert_eq!(recovery.damaged_ranges.len(), 1);
+    assert_eq!(
+        recovery.damaged_ranges[0],
+        (offset & !63)..(offset & !63) + 64
+    );
+    assert!(recovery.kept_keys > 0);
+    assert!(recovery.reindexed_values > 0);
+    assert!(report.message.contains("Kept"));
+
+    let log = opts.open(path).unwrap();
+    assert_eq!(lookup_all(&log), expected);
+}
+
+#[test]
+fn test_max_total_bytes() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new().create(true).max_total_bytes(100);
+    let mut log = opts.open(path).unwrap();
+    log.append(vec![b'a'; 40]).unwrap();
+    log.sync().unwrap();
+
+    let err = log.append(vec![b'b'; 60]).unwrap_err();
+    assert_eq!(err.kind(), crate::ErrorKind::QuotaExceeded);
+
+    // Entry headers and checksums count.
+    assert_eq!(log.meta.total_bytes(), 58);
+    assert!(log.append(vec![b'b'; 40]).unwrap_err().is_quota_exceeded());
+    log.append(b"c").unwrap();
+
+    // Entries written by others count.
+    let mut log2 = OpenOptions::new().open(path).unwrap();
+    log2.append(vec![b'd'; 40]).unwrap();
+    log2.sync().unwrap();
+    assert!(log.sync().unwrap_err().is_quota_exceeded());
+    assert!(log.append_writer().unwrap().finish().is_err());
+    drop(log2);
+
+    // Rewrite to free up space. Pending entries are kept.
+    let mut log = log
+        .rewrite(
+            |data| match data[0] {
+                b'd' => Ok(FlushFilterOutput::Drop),
+                _ => Ok(FlushFilterOutput::Keep),
+            },
+            &opts,
+        )
+        .unwrap();
+    assert_eq!(log.iter().count(), 2);
+    log.append(b"c").unwrap();
+    log.sync().unwrap();
+}
+
+#[test]
+fn test_lookup_range_order() {
+    let log_path = tempdir().unwrap();
+    let mut log = Log::open(
+        log_path.path(),
+        vec![IndexDef::new("k", |data| {
+            vec![IndexOutput::Reference(0..data.len() as u64)]
+        })],
+    )
+    .unwrap();
+    for key in [b"fox", b"fop", b"foo"] {
+        log.append(key).unwrap();
+    }
+    log.append(b"foo1").unwrap();
+
+    use std::ops::Bound;
+    use std::ops::Bound::*;
+    let keys = |range: (Bound<&[u8]>, Bound<&[u8]>)| -> Vec<Vec<u8>> {
+        log.lookup_range(0, range)
+            .unwrap()
+            .map(|item| item.unwrap().0.to_vec())
+            .collect()
+    };
+    assert_eq!(
+        keys((Included(b"foo"), Excluded(b"fop"))),
+        [&b"foo"[..], b"foo1"]
+    );
+    assert_eq!(
+        keys((Excluded(b"foo"), Unbounded)),
+        [&b"foo1"[..], b"fop", b"fox"]
+    );
+    let rev: Vec<Vec<u8>> = log
+        .lookup_range(0, ..)
+        .unwrap()
+        .rev()
+        .map(|item| item.unwrap().0.to_vec())
+        .collect();
+    assert_eq!(rev, [&b"fox"[..], b"fop", b"foo1", b"foo"]);
+
+    match log.lookup_range(1, ..) {
+        Err(err) => assert_eq!(err.kind(), crate::ErrorKind::Programming),
+        Ok(_) => panic!("lookup_range with an invalid index_id should fail"),
+    }
+}
+
+#[test]
+fn test_lookup_prefix_reverse() {
+    let log_path = tempdir().unwrap();
+    let mut log = Log::open(
+        log_path.path(),
+        vec![IndexDef::new("id", |_| vec![IndexOutput::Reference(0..9)])],
+    )
+    .unwrap();
+    // Entries are a group byte followed by a big-endian id.
+    for (group, id) in [(1u8, 5u64), (1, 300), (2, 7), (1, 42)] {
+        let mut data = vec![group];
+        data.extend_from_slice(&id.to_be_bytes());
+        log.append(&data).unwrap();
+    }
+
+    let ids = |iter: &mut dyn Iterator<Item = crate::Result<(Cow<[u8]>, LogLookupIter)>>| {
+        iter.map(|item| {
+            let key = item.unwrap().0;
+            u64::from_be_bytes(<[u8; 8]>::try_from(&key[1..]).unwrap())
+        })
+        .collect::<Vec<u64>>()
+    };
+    // Highest id first, without collecting all keys.
+    let mut iter = log.lookup_prefix(0, [1u8]).unwrap().rev().take(1);
+    assert_eq!(ids(&mut iter), [300]);
+    let mut iter = log.lookup_prefix(0, [1u8]).unwrap().rev();
+    assert_eq!(ids(&mut iter), [300, 42, 5]);
+    let mut iter = log.lookup_range(0, ..).unwrap().rev();
+    assert_eq!(ids(&mut iter), [7, 300, 42, 5]);
+
+    for result in [log.lookup_prefix(1, [1u8]), log.lookup_prefix_hex(1, b"01")] {
+        match result {
+            Err(err) => assert_eq!(err.kind(), crate::ErrorKind::Programming),
+            Ok(_) => panic!("lookup_prefix with an invalid index_id should fail"),
+        }
+    }
+}
+
+#[test]
+fn test_index_remove_in_scans() {
+    let dir = tempdir().unwrap();
+    let mut log = Log::open(dir.path(), get_index_defs(0)).unwrap();
+    let keys = |log: &Log| -> Vec<Vec<u8>> {
+        log.lookup_prefix(0, b"a")
+            .unwrap()
+            .map(|item| item.unwrap().0.to_vec())
+            .collect()
+    };
+    for data in [b"ab", b"ac", b"ad", b"ba"] {
+        log.append(data).unwrap();
+    }
+    log.append(b"-ac").unwrap();
+    log.append(b"=ad").unwrap();
+    assert_eq!(keys(&log), [b"ab"]);
+    log.sync().unwrap();
+    assert_eq!(keys(&log), [b"ab"]);
+    assert_eq!(log.lookup(0, b"ac").unwrap().count(), 0);
+    let all: Vec<Vec<u8>> = log
+        .lookup_range(0, ..)
+        .unwrap()
+        .rev()
+        .map(|item| item.unwrap().0.to_vec())
+        .collect();
+    assert_eq!(all, [b"ba", b"ab"]);
+
+    // Removed keys can be inserted again by later entries.
+    log.append(b"ac").unwrap();
+    assert_eq!(keys(&log), [b"ab", b"ac"]);
+    assert_eq!(log.lookup(0, b"ac").unwrap().count(), 1);
+}
+
+#[test]
+fn test_lookup_prefix_keys() {
+    let dir = tempdir().unwrap();
+    let mut log = Log::open(dir.path(), get_index_defs(0)).unwrap();
+    let keys = |log: &Log, prefix: &[u8]| -> Vec<Vec<u8>> {
+        log.lookup_prefix_keys(0, prefix)
+            .unwrap()
+            .map(|key| key.unwrap().to_vec())
+            .collect()
+    };
+    // Keys of index 0 are every 2 bytes. "ab" is used by 2 entries.
+    for data in [&b"abc"[..], b"ab", b"ad", b"ba"] {
+        log.append(data).unwrap();
+    }
+    assert_eq!(keys(&log, b"a"), [b"ab", b"ad"]);
+    log.sync().unwrap();
+    log.append(b"ae").unwrap();
+    assert_eq!(keys(&log, b"a"), [b"ab", b"ad", b"ae"]);
+    assert_eq!(keys(&log, b"b"), [b"ba", b"bc"]);
+    assert!(keys(&log, b"c").is_empty());
+    assert_eq!(keys(&log, b""), [b"ab", b"ad", b"ae", b"ba", b"bc"]);
+
+    let last = log.lookup_prefix_keys(0, b"a").unwrap().next_back();
+    assert_eq!(last.unwrap().unwrap().as_ref(), &b"ae"[..]);
+    assert!(log.lookup_prefix_keys(2, b"a").is_err());
+}
+
+#[test]
+fn test_count_lookup() {
+    let dir = tempdir().unwrap();
+    let opts = OpenOptions::new()
+        .create(true)
+        .index("first-byte", |_| vec![IndexOutput::Reference(0..1)]);
+    let mut log = opts.open(dir.path()).unwrap();
+    for data in [b"a1", b"b1", b"a2"] {
+        log.append(data).unwrap();
+    }
+    log.sync().unwrap();
+    log.append(b"a3").unwrap();
+    assert_eq!(log.count_lookup(0, b"a").unwrap(), 3);
+    assert_eq!(log.count_lookup(0, b"b").unwrap(), 1);
+    assert_eq!(log.count_lookup(0, b"c").unwrap(), 0);
+
+    let offset = log.iter().with_offsets().next().unwrap().unwrap().0;
+    log.delete(offset).unwrap();
+    assert_eq!(log.count_lookup(0, b"a").unwrap(), 2);
+    assert_eq!(
+        log.count_lookup(0, b"a").unwrap(),
+        log.lookup(0, b"a").unwrap().count()
+    );
+    assert!(log.count_lookup(1, b"a").is_err());
+}
+
+#[test]
+fn test_index_bloom_filter() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let bloom_path = path.join("index2-k.bloom");
+    let opts = OpenOptions::new()
+        .create(true)
+        .index_defs(vec![IndexDef::new("k", |_| {
+            vec![IndexOutput::Reference(0..2)]
+        })
+        .lag_threshold(0)
+        .bloom_filter(0.01)]);
+    let mut log = opts.open(path).unwrap();
+    for data in [b"a1", b"b1", b"a1"] {
+        log.append(data).unwrap();
+    }
+    assert_eq!(log.lookup(0, b"a1").unwrap().count(), 2);
+    assert_eq!(log.lookup(0, b"c1").unwrap().count(), 0);
+    log.sync().unwrap();
+    assert!(bloom_path.exists());
+
+    let mut log = opts.open(path).unwrap();
+    assert_eq!(log.lookup(0, b"b1").unwrap().count(), 1);
+    assert_eq!(log.lookup(0, b"c1").unwrap().count(), 0);
+    log.append(b"c1").unwrap();
+    assert_eq!(log.lookup(0, b"c1").unwrap().count(), 1);
+    log.sync().unwrap();
+
+    // The filter is rebuilt with the index.
+    let log = opts.open(path).unwrap();
+    log.rebuild_indexes(true).unwrap();
+    assert!(bloom_path.exists());
+    assert!(!path.join("index2-k.rebuild.bloom").exists());
+    let log = opts.open(path).unwrap();
+    for (key, count) in [(b"a1", 2), (b"b1", 1), (b"c1", 1), (b"d1", 0)] {
+        assert_eq!(log.lookup(0, key).unwrap().count(), count);
+    }
+}
+
+#[test]
+fn test_index_checksum_chunk_size() {
+    let dir = tempdir().unwrap();
+    let open = |name: &str, chunk_size_logarithm: u32| {
+        let def = IndexDef::new("k", |_| vec![IndexOutput::Reference(0..4)])
+            .lag_threshold(0)
+            .checksum_chunk_size_logarithm(chunk_size_logarithm);
+        OpenOptions::new()
+            .create(true)
+            .index_defs(vec![def])
+            .open(dir.path().join(name))
+            .unwrap()
+    };
+    let index_size = |name: &str| {
+        fs::metadata(dir.path().join(name).join("index2-k"))
+            .unwrap()
+            .len()
+    };
+
+    for (name, chunk_size_logarithm) in [("small", 6), ("default", 20)] {
+        let mut log = open(name, chunk_size_logarithm);
+        for i in 0..1000u32 {
+            log.append(i.to_be_bytes()).unwrap();
+        }
+        log.sync().unwrap();
+        let log = open(name, chunk_size_logarithm);
+        assert_eq!(log.lookup(0, 42u32.to_be_bytes()).unwrap().count(), 1);
+        assert_eq!(log.lookup(0, 1000u32.to_be_bytes()).unwrap().count(), 0);
+    }
+    // Smaller chunks need more checksums.
+    assert!(index_size("small") > index_size("default"));
+
+    // Existing index files keep their chunk size.
+    let mut log = open("default", 6);
+    log.append(1000u32.to_be_bytes()).unwrap();
+    log.sync().unwrap();
+    let log = open("default", 20);
+    assert_eq!(log.lookup(0, 1000u32.to_be_bytes()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_index_dirty_memory_threshold() {
+    let dir = tempdir().unwrap();
+    let open = |threshold: Option<u64>| {
+        let def = IndexDef::new("k", |_| vec![IndexOutput::Reference(0..4)])
+            .lag_threshold(u64::MAX)
+            .dirty_memory_threshold(threshold);
+        OpenOptions::new()
+            .create(true)
+            .index_defs(vec![def])
+            .open(dir.path())
+            .unwrap()
+    };
+
+    // No limit. The index is kept in memory because of lag_threshold.
+    let mut log = open(None);
+    for i in 0..100u32 {
+        log.append(i.to_be_bytes()).unwrap();
+    }
+    assert!(log.indexes[0].memory_used() > 1000);
+    log.sync().unwrap();
+    let log = open(None);
+    assert!(log.indexes[0].memory_used() > 1000);
+
+    // With a limit, open writes the index built in memory.
+    let mut log = open(Some(1000));
+    assert!(log.indexes[0].memory_used() <= 1000);
+
+    // Append syncs to write the index.
+    for i in 100..200u32 {
+        log.append(i.to_be_bytes()).unwrap();
+        assert!(log.indexes[0].memory_used() <= 1000);
+    }
+    assert!(log.mem_buf.len() < 400);
+    log.sync().unwrap();
+    let log = open(None);
+    assert_eq!(log.lookup(0, 42u32.to_be_bytes()).unwrap().count(), 1);
+    assert_eq!(log.lookup(0, 199u32.to_be_bytes()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_index_build_in_background() {
+    let dir = tempdir().unwrap();
+    let open = |background: Option<bool>| {
+        let defs = match background {
+            Some(background) => vec![IndexDef::new("k", |_| vec![IndexOutput::Reference(0..4)])
+                .lag_threshold(0)
+                .build_in_background(background)],
+            None => Vec::new(),
+        };
+        OpenOptions::new()
+            .create(true)
+            .index_defs(defs)
+            .open(dir.path())
+            .unwrap()
+    };
+
+    let mut log = open(None);
+    for i in 0..1000u32 {
+        log.append(i.to_be_bytes()).unwrap();
+    }
+    log.sync().unwrap();
+
+    // Open and sync do not build the new index.
+    let mut log = open(Some(true));
+    assert!(!log.is_index_ready(0));
+    assert!(log.lookup(0, 1u32.to_be_bytes()).is_err());
+    assert!(log.lookup_prefix(0, b"").is_err());
+    log.append(1000u32.to_be_bytes()).unwrap();
+    log.sync().unwrap();
+    assert!(!log.is_index_ready(0));
+
+    let build = log.build_indexes_in_background().unwrap();
+    // Entries appended during the build are indexed.
+    let mut log2 = open(Some(true));
+    log2.append(1001u32.to_be_bytes()).unwrap();
+    log2.sync().unwrap();
+    while !build.is_finished() {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    assert_eq!(build.progress(), 1.0);
+    build.wait().unwrap();
+
+    // Sync picks up the built index.
+    assert!(!log.is_index_ready(0));
+    log.sync().unwrap();
+    assert!(log.is_index_ready(0));
+    for i in [0u32, 999, 1000, 1001] {
+        assert_eq!(log.lookup(0, i.to_be_bytes()).unwrap().count(), 1);
+    }
+    assert_eq!(log.lookup(0, 1002u32.to_be_bytes()).unwrap().count(), 0);
+
+    // The index is updated as usual from now on.
+    let mut log = open(Some(true));
+    assert!(log.is_index_ready(0));
+    log.append(1002u32.to_be_bytes()).unwrap();
+    assert_eq!(log.lookup(0, 1002u32.to_be_bytes()).unwrap().count(), 1);
+    log.sync().unwrap();
+    let log = open(Some(false));
+    assert_eq!(log.lookup(0, 1001u32.to_be_bytes()).unwrap().count(), 1);
+    assert_eq!(log.lookup(0, 1002u32.to_be_bytes()).unwrap().count(), 1);
+
+    // Nothing to build.
+    let build = log.build_indexes_in_background().unwrap();
+    assert!(build.is_finished());
+    assert_eq!(build.progress(), 1.0);
+    build.wait().unwrap();
+}
+
+#[test]
+fn test_index_version() {
+    let dir = tempdir().unwrap();
+    // Version 0 indexes the first byte. Version 1 indexes the second byte.
+    let open = |version: u64, background: bool| {
+        let def = IndexDef::new("k", move |_| {
+            vec![IndexOutput::Reference(version..version + 1)]
+        })
+        .lag_threshold(0)
+        .version(version)
+        .build_in_background(background);
+        OpenOptions::new()
+            .create(true)
+            .index_defs(vec![def])
+            .open(dir.path())
+            .unwrap()
+    };
+    let count = |log: &Log, key: &[u8]| log.lookup(0, key).unwrap().count();
+
+    let mut log = open(0, false);
+    log.append(b"ab").unwrap();
+    log.append(b"bc").unwrap();
+    log.sync().unwrap();
+    assert_eq!(count(&log, b"a"), 1);
+    assert_eq!(count(&log, b"c"), 0);
+
+    // A different version rebuilds the index on open.
+    let log = open(1, false);
+    assert_eq!(count(&log, b"a"), 0);
+    assert_eq!(count(&log, b"b"), 1);
+    assert_eq!(count(&log, b"c"), 1);
+    assert!(log
+        .meta
+        .index_len(&log.open_options.index_defs[0])
+        .is_some());
+
+    // The rebuilt index is used by later opens.
+    let log = open(1, false);
+    assert_eq!(count(&log, b"c"), 1);
+
+    // The index can be rebuilt in background.
+    let mut log = open(0, true);
+    assert!(!log.is_index_ready(0));
+    log.build_indexes_in_background().unwrap().wait().unwrap();
+    log.sync().unwrap();
+    assert!(log.is_index_ready(0));
+    assert_eq!(count(&log, b"a"), 1);
+    assert_eq!(count(&log, b"c"), 0);
+}
+
+#[test]
+fn test_purge_older_than() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let opts = OpenOptions::new()
+        .create(true)
+        .index("c", |_| vec![IndexOutput::Reference(0..1)]);
+
+    // Entries without timestamps do not expire.
+    let mut log = opts.clone().open(path).unwrap();
+    log.append(b"a").unwrap();
+    log.sync().unwrap();
+    assert_eq!(log.entry_timestamp(PRIMARY_START_OFFSET).unwrap(), None);
+    drop(log);
+
+    let opts = opts.entry_timestamp(true);
+    let mut log = opts.clone().open(path).unwrap();
+    let hour_ago = utils::now_millis() - 3600 * 1000;
+    log.append_in_memory(b"b", Some(hour_ago)).unwrap();
+    log.append(b"c").unwrap();
+    log.sync().unwrap();
+
+    // Older versions cannot read timestamps. They refuse the log.
+    assert_eq!(log.format_version(), ENTRY_FLAGS_FORMAT_VERSION);
+    let meta = utils::atomic_read(&path.join(META_FILE)).unwrap();
+    assert!(meta.starts_with(b"meta\x02"));
+
+    let timestamps: Vec<Option<SystemTime>> = log
+        .iter()
+        .with_offsets()
+        .map(|e| log.entry_timestamp(e.unwrap().0).unwrap())
+        .collect();
+    let hour_ago = UNIX_EPOCH + Duration::from_millis(hour_ago);
+    assert_eq!(timestamps[..2], [None, Some(hour_ago)]);
+    assert!(timestamps[2].unwrap() > hour_ago);
+
+    // Timestamps are preserved by rewrite.
+    let log = log.purge_older_than(Duration::from_secs(7200)).unwrap();
+    assert_eq!(log.iter().count(), 3);
+    let log = log.purge_older_than(Duration::from_secs(60)).unwrap();
+    assert_eq!(
+        log.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+        [b"a", b"c"]
+    );
+    assert!(log.lookup(0, b"b").unwrap().into_vec().unwrap().is_empty());
+    assert_eq!(log.lookup(0, b"c").unwrap().into_vec().unwrap(), [b"c"]);
+    assert_eq!(log.verify().unwrap(), []);
+    assert_eq!(log.format_version(), ENTRY_FLAGS_FORMAT_VERSION);
+}
+
+#[test]
+fn test_rewrite() {
+    let dir = tempdir().unwrap();
+    let open_opts = OpenOptions::new()
+        .create(true)
+        .index_defs(vec![IndexDef::new("first-byte", |_| {
+            vec![IndexOutput::Reference(0..1)]
+        })]);
+    let mut log = open_opts.open(dir.path()).unwrap();
+    for data in [b"abc", b"bcd", b"cde", b"ade"] {
+        log.append(data).unwrap();
+    }
+    log.sync().unwrap();
+    let epoch = log.meta.epoch;
+
+    // Another reader prevents rewrite.
+    let reader = open_opts.open(dir.path()).unwrap();
+    let cloned = log.try_clone().unwrap();
+    drop(log);
+    assert!(cloned
+        .rewrite(|_| Ok(FlushFilterOutput::Keep), &open_opts)
+        .is_err());
+
+    // Dirty entries are also rewritten. Entries can be dropped or replaced.
+    drop(reader);
+    let mut log = open_opts.open(dir.path()).unwrap();
+    log.append(b"bxx").unwrap();
+    let log = log
+        .rewrite(
+            |data| {
+                Ok(match data[0] {
+                    b'a' => FlushFilterOutput::Drop,
+                    b'c' => FlushFilterOutput::Replace(b"aaa".to_vec()),
+                    _ => FlushFilterOutput::Keep,
+                })
+            },
+            &open_opts,
+        )
+        .unwrap();
+    assert_ne!(log.meta.epoch, epoch);
+    assert_eq!(
+        log.iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        [b"bcd", b"aaa", b"bxx"]
+    );
+    assert_eq!(log.lookup(0, b"a").unwrap().into_vec().unwrap(), [b"aaa"]);
+    assert_eq!(
+        log.lookup(0, b"b").unwrap().into_vec().unwrap(),
+        [b"bxx", b"bcd"]
+    );
+    drop(log);
+
+    // Data and indexes are persisted.
+    let log = open_opts.open(dir.path()).unwrap();
+    assert_eq!(log.iter().count(), 3);
+    assert_eq!(log.lookup(0, b"c").unwrap().count(), 0);
+
+    // Readers opening during rewrite wait for it to complete.
+    let mut reader = None;
+    let log = log
+        .rewrite(
+            |data| {
+                if reader.is_none() {
+                    let path = dir.path().to_path_buf();
+                    let opts = open_opts.clone();
+                    reader = Some(std::thread::spawn(move || {
+                        let log = opts.open(path).unwrap();
+                        log.iter().count()
+                    }));
+                }
+                Ok(match data[0] {
+                    b'a' => FlushFilterOutput::Drop,
+                    _ => FlushFilterOutput::Keep,
+                })
+            },
+            &open_opts,
+        )
+        .unwrap();
+    drop(log);
+    assert_eq!(reader.unwrap().join().unwrap(), 2);
+
+    // Rewrite works for in-memory logs too.
+    let mut log = open_opts.open(()).unwrap();
+    log.append(b"abc").unwrap();
+    log.append(b"bcd").unwrap();
+    let log = log
+        .rewrite(|_| Ok(FlushFilterOutput::Drop), &open_opts)
+        .unwrap();
+    assert_eq!(log.iter().count(), 0);
+}
+
+pub(crate) fn pwrite(path: &Path, offset: i64, data: &[u8]) {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .read(true)
+        .open(path)
+        .unwrap();
+    if offset < 0 {
+        file.seek(SeekFrom::End(offset)).unwrap();
+    } else {
+        file.seek(SeekFrom::Start(offset as u64)).unwrap();
+    }
+    file.write_all(data).unwrap();
+}
+
 #[test]
 fn test_repair() {
     let dir = tempdir().unwrap();
@@ -982,7 +3056,7 @@ fn test_repair_on_open() {
     opts.open(path).unwrap_err();
 
     // Auto repair fails because it detects active reader.
-    opts.open_with_repair(path).unwrap_err();
+    assert!(opts.open_with_repair(path).unwrap_err().is_busy());
     opts.open(path).unwrap_err();
 
     // Drop the active reader.
@@ -998,6 +3072,91 @@ fn test_repair_on_open() {
     );
 }
 
+#[test]
+fn test_auto_repair() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let index_def = IndexDef::new("c", |_| vec![IndexOutput::Reference(0..1)]);
+    let opts = OpenOptions::new()
+        .create(true)
+        .index_defs(vec![index_def.lag_threshold(1 << 20)]);
+
+    let mut log = opts.open(path).unwrap();
+    log.append(b"abc").unwrap();
+    let def_offset = log.meta.primary_len + log.mem_buf.len() as u64;
+    log.append(b"def").unwrap();
+    log.append(b"ghi").unwrap();
+    log.sync().unwrap();
+    let primary_len = log.meta.primary_len;
+    drop(log);
+
+    // Corrupt the "def" entry. Building the lagging index on open fails.
+    pwrite(&path.join(PRIMARY_FILE), primary_len as i64 - 12, b"x");
+    assert!(opts.open(path).unwrap_err().is_corruption());
+
+    // Auto repair is skipped with active readers.
+    let reader = opts.clone().index_defs(Vec::new()).open(path).unwrap();
+    let opts = opts.auto_repair(true);
+    assert!(opts.open(path).is_err());
+    drop(reader);
+
+    let (log, report) = opts.open_with_report(path).unwrap();
+    let report = report.unwrap();
+    assert_eq!(report.dropped_bytes, primary_len - def_offset);
+    assert_eq!(report.dropped_entries, 2);
+    assert_eq!(report.rebuilt_indexes, ["c"]);
+    assert_eq!(
+        log.iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        vec![b"abc"]
+    );
+    drop(log);
+
+    // No report if nothing was repaired.
+    let (_log, report) = opts.open_with_report(path).unwrap();
+    assert!(report.is_none());
+}
+
+#[test]
+fn test_verify() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let index_def = IndexDef::new("c", |_| vec![IndexOutput::Reference(0..1)]);
+    let opts = OpenOptions::new()
+        .create(true)
+        .index_defs(vec![index_def.lag_threshold(0)]);
+
+    let mut log = opts.open(path).unwrap();
+    log.append(b"abc").unwrap();
+    let def_offset = log.meta.primary_len + log.mem_buf.len() as u64;
+    log.append(b"def").unwrap();
+    log.append(b"ghi").unwrap();
+    assert_eq!(log.verify().unwrap(), []);
+    log.sync().unwrap();
+    let primary_len = log.meta.primary_len;
+    assert_eq!(log.verify().unwrap(), []);
+    drop(log);
+
+    // Corrupt the "def" entry. Entries after it are still checked.
+    pwrite(&path.join(PRIMARY_FILE), primary_len as i64 - 14, b"x");
+    let mut log = opts.open(path).unwrap();
+    let problems = log.verify().unwrap();
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].kind, VerifyProblemKind::Entry);
+    assert_eq!(problems[0].offset, Some(def_offset));
+    assert_eq!(problems[0].index_name, None);
+
+    // Index pointing to a non-entry.
+    log.indexes[0].insert(&b"x", def_offset + 1).unwrap();
+    let problems = log.verify().unwrap();
+    assert_eq!(problems.len(), 2);
+    assert_eq!(problems[1].kind, VerifyProblemKind::IndexDanglingOffset);
+    assert_eq!(problems[1].offset, Some(def_offset + 1));
+    assert_eq!(problems[1].index_name.as_deref(), Some("c"));
+    assert!(problems[1]
+        .to_string()
+        .starts_with("IndexDanglingOffset (index \"c\")"));
+}
+
 #[test]
 fn test_repair_noop() {
     // Repair does nothing if the Log can be read out without issues.
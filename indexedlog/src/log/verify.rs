@@ -0,0 +1,290 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::ops::Bound;
+use std::ops::Range;
+use std::ops::RangeBounds;
+
+use super::repair::next_entry_offset_lossy;
+use crate::errors::ResultExt;
+#[cfg(doc)]
+use crate::log::ChecksumVerification;
+use crate::log::Log;
+use crate::log::PRIMARY_FILE;
+use crate::log::PRIMARY_START_OFFSET;
+
+/// Kind of a problem found by [`Log::verify`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VerifyProblemKind {
+    /// The primary log file is shorter than what the metadata says.
+    LogLength,
+
+    /// An entry in the primary log cannot be read, or does not match its
+    /// checksum.
+    Entry,
+
+    /// An index file is shorter than what the metadata says.
+    IndexLength,
+
+    /// An index does not pass its integrity check, or cannot be read.
+    Index,
+
+    /// An index points to an offset that is not the start of an entry.
+    /// Offsets of corrupted entries are not reported as dangling.
+    IndexDanglingOffset,
+}
+
+/// A problem found by [`Log::verify`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifyProblem {
+    /// Kind of the problem.
+    pub kind: VerifyProblemKind,
+
+    /// Offset in the primary log related to the problem, if any.
+    pub offset: Option<u64>,
+
+    /// Name of the index related to the problem, if any.
+    pub index_name: Option<String>,
+
+    /// Details for human consumption.
+    pub message: String,
+}
+
+impl fmt::Display for VerifyProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.kind)?;
+        if let Some(name) = &self.index_name {
+            write!(f, " (index {:?})", name)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at {}", offset)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Non-overlapping ranges of the primary log with verified checksums.
+/// Adjacent ranges are merged, so sequential reads use little memory.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct VerifiedRanges {
+    // start -> end
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl VerifiedRanges {
+    /// Test if `offset` is covered.
+    pub(crate) fn contains(&self, offset: u64) -> bool {
+        match self.ranges.range(..=offset).next_back() {
+            Some((_, &end)) => offset < end,
+            None => false,
+        }
+    }
+
+    /// Mark `range` as verified.
+    pub(crate) fn insert(&mut self, range: Range<u64>) {
+        let (mut start, mut end) = (range.start, range.end);
+        if start >= end {
+            return;
+        }
+        // Merge with the previous range, if it overlaps or touches.
+        if let Some((&prev_start, &prev_end)) = self.ranges.range(..=start).next_back() {
+            if prev_end >= start {
+                start = prev_start;
+                end = end.max(prev_end);
+            }
+        }
+        // Merge with following ranges.
+        let following: Vec<(u64, u64)> = self
+            .ranges
+            .range(start..=end)
+            .map(|(&s, &e)| (s, e))
+            .collect();
+        for (next_start, next_end) in following {
+            self.ranges.remove(&next_start);
+            end = end.max(next_end);
+        }
+        self.ranges.insert(start, end);
+    }
+}
+
+impl Log {
+    /// Verify checksums of entries starting within `range` of offsets.
+    ///
+    /// `range.start` should be the offset of an entry, or unbounded to start
+    /// from the first entry. Entries are verified regardless of
+    /// [`OpenOptions::checksum_verification`](crate::log::OpenOptions::checksum_verification).
+    /// With [`ChecksumVerification::FirstAccess`], verified entries are
+    /// remembered so reading them later skips the check.
+    ///
+    /// Return an error on the first corrupted entry.
+    pub fn verify_range(&self, range: impl RangeBounds<u64>) -> crate::Result<()> {
+        let result: crate::Result<_> = (|| {
+            let start = match range.start_bound() {
+                Bound::Included(&start) => start,
+                Bound::Excluded(&start) => start.saturating_add(1),
+                Bound::Unbounded => PRIMARY_START_OFFSET,
+            };
+            let end = match range.end_bound() {
+                Bound::Included(&end) => end.saturating_add(1),
+                Bound::Excluded(&end) => end,
+                Bound::Unbounded => u64::MAX,
+            };
+            let mut offset = start.max(PRIMARY_START_OFFSET);
+            while offset < end {
+                match self.read_entry_with_verify(offset, true)? {
+                    Some(entry) => offset = entry.next_offset,
+                    None => break,
+                }
+            }
+            Ok(())
+        })();
+
+        result
+            .context("in Log::verify_range")
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+
+    /// Check integrity of all data. This is much more expensive than what
+    /// [`OpenOptions::open`](crate::log::OpenOptions::open) does.
+    ///
+    /// Check:
+    /// - File lengths match the metadata.
+    /// - All entries (including in-memory and deleted ones) match their
+    ///   checksums.
+    /// - All indexes pass their integrity checks, and only point to
+    ///   offsets of entries.
+    ///
+    /// Return problems found. An empty list means no problems. Use
+    /// [`OpenOptions::repair`](crate::log::OpenOptions::repair) to fix them.
+    pub fn verify(&self) -> crate::Result<Vec<VerifyProblem>> {
+        let mut problems = Vec::new();
+        let mut problem = |kind, offset, index_name: Option<&str>, message: String| {
+            problems.push(VerifyProblem {
+                kind,
+                offset,
+                index_name: index_name.map(|s| s.to_string()),
+                message,
+            })
+        };
+
+        // File lengths.
+        if let Some(dir) = self.dir.as_opt_path() {
+            let path = dir.join(PRIMARY_FILE);
+            let len = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if len < self.meta.primary_len {
+                let msg = format!(
+                    "{:?} has {} bytes, shorter than {} bytes in metadata",
+                    path, len, self.meta.primary_len
+                );
+                problem(VerifyProblemKind::LogLength, None, None, msg);
+            }
+            for def in self.open_options.index_defs.iter() {
+                let meta_len = match self.meta.index_len(def) {
+                    Some(len) => len,
+                    None => continue,
+                };
+                let path = dir.join(def.filename());
+                let len = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                if len < meta_len {
+                    let msg = format!(
+                        "{:?} has {} bytes, shorter than {} bytes in metadata",
+                        path, len, meta_len
+                    );
+                    problem(VerifyProblemKind::IndexLength, None, Some(&def.name), msg);
+                }
+            }
+        }
+
+        // Entries. Try to skip corrupted entries to find more problems.
+        let mut entry_offsets = BTreeSet::new();
+        let mut offset = PRIMARY_START_OFFSET;
+        loop {
+            match self.read_entry_with_verify(offset, true) {
+                Ok(None) => break,
+                Ok(Some(entry)) => {
+                    entry_offsets.insert(offset);
+                    offset = entry.next_offset;
+                }
+                Err(err) => {
+                    entry_offsets.insert(offset);
+                    problem(
+                        VerifyProblemKind::Entry,
+                        Some(offset),
+                        None,
+                        err.to_string(),
+                    );
+                    let next_offset = if offset < self.meta.primary_len {
+                        next_entry_offset_lossy(&self.disk_buf, offset)
+                    } else {
+                        next_entry_offset_lossy(&self.mem_buf, offset - self.meta.primary_len)
+                            .map(|o| o + self.meta.primary_len)
+                    };
+                    match next_offset {
+                        Some(next_offset) => offset = next_offset,
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        // Indexes.
+        for (i, def) in self.open_options.index_defs.iter().enumerate() {
+            let name = Some(def.name.as_str());
+            let index = match self.get_index(i) {
+                Ok(index) => index,
+                Err(err) => {
+                    problem(VerifyProblemKind::Index, None, name, err.to_string());
+                    continue;
+                }
+            };
+            if let Err(err) = index.verify() {
+                problem(VerifyProblemKind::Index, None, name, err.to_string());
+                continue;
+            }
+            let iter = match index.range(..) {
+                Ok(iter) => iter,
+                Err(err) => {
+                    problem(VerifyProblemKind::Index, None, name, err.to_string());
+                    continue;
+                }
+            };
+            for item in iter {
+                let (key, link_offset) = match item {
+                    Ok(item) => item,
+                    Err(err) => {
+                        problem(VerifyProblemKind::Index, None, name, err.to_string());
+                        break;
+                    }
+                };
+                for value in link_offset.values(index) {
+                    match value {
+                        Ok(offset) if !entry_offsets.contains(&offset) => {
+                            let msg = format!("key {:?} points to a non-entry", key.as_ref());
+                            problem(
+                                VerifyProblemKind::IndexDanglingOffset,
+                                Some(offset),
+                                name,
+                                msg,
+                            );
+                        }
+                        Ok(_) => {}
+                        Err(err) => {
+                            problem(VerifyProblemKind::Index, None, name, err.to_string());
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(problems)
+    }
+}
@@ -0,0 +1,81 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::log::Log;
+
+/// A background thread that polls a [`Log`] for changes made by other
+/// processes. Created by [`Log::watch`].
+///
+/// Dropping the [`LogWatcher`] stops the thread.
+pub struct LogWatcher {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<Log>>,
+}
+
+impl Log {
+    /// Watch for changes made by other processes by polling.
+    ///
+    /// Every `interval`, check [`Log::is_changed_on_disk`]. If changed,
+    /// call [`Log::sync`] to load the changes, then call `on_change` with
+    /// the updated [`Log`].
+    ///
+    /// The [`Log`] is moved to a background thread. Use [`LogWatcher::stop`]
+    /// to get it back.
+    pub fn watch(
+        mut self,
+        interval: Duration,
+        mut on_change: impl FnMut(&Log) + Send + 'static,
+    ) -> crate::Result<LogWatcher> {
+        let (stop, stop_receiver) = mpsc::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("indexedlog-watch".to_string())
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) =
+                    stop_receiver.recv_timeout(interval)
+                {
+                    if !self.is_changed_on_disk() {
+                        continue;
+                    }
+                    match self.sync() {
+                        Ok(_) => on_change(&self),
+                        Err(err) => tracing::warn!("cannot sync watched Log: {}", err),
+                    }
+                }
+                self
+            })
+            .map_err(|err| crate::Error::from(("cannot spawn watch thread", err)))?;
+        Ok(LogWatcher {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl LogWatcher {
+    /// Stop watching. Return the watched [`Log`].
+    ///
+    /// Panic if `on_change` panicked.
+    pub fn stop(mut self) -> Log {
+        self.stop_internal().expect("watch thread panicked")
+    }
+
+    fn stop_internal(&mut self) -> Option<Log> {
+        // Dropping the sender wakes up the thread.
+        drop(self.stop.take());
+        self.thread.take()?.join().ok()
+    }
+}
+
+impl Drop for LogWatcher {
+    fn drop(&mut self) {
+        self.stop_internal();
+    }
+}
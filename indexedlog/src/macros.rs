@@ -71,3 +71,35 @@ macro_rules! impl_offset {
         }
     };
 }
+
+// Enter an `info` span for an expensive operation. Return an
+// `instrument::OpSpan`, which records the `duration_us` field on drop.
+//
+// Without the `tracing` feature, the span is disabled and fields are not
+// evaluated.
+macro_rules! op_span {
+    ($name:expr $(, $($field:tt)*)?) => {
+        crate::instrument::OpSpan::new(if cfg!(feature = "tracing") {
+            tracing::info_span!($name, $($($field)*,)? duration_us = tracing::field::Empty)
+        } else {
+            tracing::Span::none()
+        })
+    };
+}
+
+// Return an error, or panic if the failpoint `$name` is enabled. `$before` is
+// called first to simulate partial writes. See `failpoint`.
+//
+// Without the `failpoints` feature, this does nothing.
+macro_rules! fail_point {
+    ($name:expr) => {
+        fail_point!($name, || {})
+    };
+    ($name:expr, $before:expr) => {
+        if cfg!(feature = "failpoints") {
+            if let Some(err) = crate::failpoint::eval($name, $before) {
+                return Err(err);
+            }
+        }
+    };
+}
@@ -54,8 +54,9 @@ pub struct OpenOptions {
 /// exchange data with the filesystem.
 ///
 /// [`Log`]s will be accessible via indexing. For example, `multilog[0]`
-/// accesses the first [`Log`]. [`Log`]s can also be moved out of this
-/// struct by [`MultiLog::detach_logs`].
+/// accesses the first [`Log`], and `multilog["a"]` accesses the [`Log`]
+/// named "a". [`Log`]s can also be moved out of this struct by
+/// [`MultiLog::detach_logs`].
 ///
 /// [`MultiLog`] makes sure the data consistency on disk but not always
 /// in memory. In case [`MultiLog::write_meta`] is not called or is not
@@ -72,6 +73,9 @@ pub struct MultiLog {
     /// Logs loaded by MultiLog.
     logs: Vec<log::Log>,
 
+    /// Names of `logs`, in the same order.
+    names: Vec<&'static str>,
+
     /// Log used for `MultiMeta`. For data recovery.
     multimeta_log: log::Log,
 
@@ -90,6 +94,10 @@ const INDEX_REVERSE_KEY: &[u8] = b"r";
 /// The reverse index is the first index. See [`multi_meta_log_open_options`].
 const INDEX_REVERSE: usize = 0;
 
+/// Metadata of all [`Log`](log::Log)s in a [`MultiLog`].
+///
+/// It is written as a whole by [`MultiLog::write_meta`]. So changes to
+/// multiple [`Log`](log::Log)s become visible to other processes together.
 #[derive(Debug)]
 pub struct MultiMeta {
     metas: BTreeMap<String, Arc<Mutex<LogMetadata>>>,
@@ -104,11 +112,13 @@ impl OpenOptions {
     /// Create [`OpenOptions`] from names and OpenOptions of [`Log`].
     pub fn from_name_opts(name_opts: Vec<(&'static str, log::OpenOptions)>) -> Self {
         // Sanity check.
-        for (name, _) in &name_opts {
+        for (i, (name, _)) in name_opts.iter().enumerate() {
             if name == &"multimeta" {
                 panic!("MultiLog: cannot use 'multimeta' as Log name");
             } else if name.contains('/') || name.contains('\\') {
                 panic!("MultiLog: cannot use '/' or '\\' in Log name");
+            } else if name_opts[..i].iter().any(|(n, _)| n == name) {
+                panic!("MultiLog: duplicated Log name {:?}", name);
             }
         }
         Self {
@@ -187,6 +197,7 @@ impl OpenOptions {
             Ok(MultiLog {
                 path: path.to_path_buf(),
                 logs,
+                names: self.name_open_options.iter().map(|(n, _)| *n).collect(),
                 multimeta,
                 multimeta_log,
                 leacy_multimeta_source: self.leacy_multimeta_source,
@@ -199,6 +210,39 @@ impl OpenOptions {
 }
 
 impl MultiLog {
+    /// Open [`MultiLog`] at the given directory, with names and OpenOptions
+    /// of its [`Log`]s.
+    ///
+    /// This is a shortcut for [`OpenOptions::from_name_opts`] followed by
+    /// [`OpenOptions::open`].
+    pub fn open(
+        path: impl AsRef<Path>,
+        name_opts: Vec<(&'static str, log::OpenOptions)>,
+    ) -> crate::Result<Self> {
+        OpenOptions::from_name_opts(name_opts).open(path.as_ref())
+    }
+
+    /// Get the [`Log`] with the given name.
+    ///
+    /// Return `None` if the name is unknown, or logs were detached.
+    pub fn get(&self, name: &str) -> Option<&log::Log> {
+        let index = self.names.iter().position(|n| *n == name)?;
+        self.logs.get(index)
+    }
+
+    /// Get the mutable [`Log`] with the given name.
+    ///
+    /// Return `None` if the name is unknown, or logs were detached.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut log::Log> {
+        let index = self.names.iter().position(|n| *n == name)?;
+        self.logs.get_mut(index)
+    }
+
+    /// Names of [`Log`]s, in the same order as indexing.
+    pub fn names(&self) -> &[&'static str] {
+        &self.names
+    }
+
     /// Lock the MultiLog directory for writing.
     ///
     /// After taking the lock, metadata will be reloaded so [`Log`]s can see the
@@ -296,9 +340,17 @@ impl MultiLog {
     /// [`MultiLog::write_meta`]. For more advanced use-cases, call those
     /// functions manually.
     ///
+    /// Changes of all [`Log`]s become visible to other processes together.
+    /// If this function fails or the process crashes in the middle, none of
+    /// the changes will be visible.
+    ///
     /// This function should not be called if logs were detached.
-    /// This does not seem very useful practically. So it is private.
-    fn sync(&mut self) -> crate::Result<()> {
+    pub fn sync(&mut self) -> crate::Result<()> {
+        if self.logs.is_empty() && !self.multimeta.metas.is_empty() {
+            return Err(crate::Error::programming(
+                "MultiLog::sync cannot be used after detach_logs",
+            ));
+        }
         let lock = self.lock()?;
         for log in self.logs.iter_mut() {
             log.sync()?;
@@ -328,6 +380,7 @@ fn apply_legacy_meta_if_it_is_newer(meta_path: &Path, multimeta: &mut MultiMeta)
                     let newer = newer.lock().unwrap();
                     current.primary_len = newer.primary_len;
                     current.indexes = newer.indexes.clone();
+                    current.entry_count = newer.entry_count;
                 }
             }
         }
@@ -361,6 +414,25 @@ impl ops::IndexMut<usize> for MultiLog {
     }
 }
 
+impl ops::Index<&str> for MultiLog {
+    type Output = log::Log;
+    fn index(&self, name: &str) -> &Self::Output {
+        match self.get(name) {
+            Some(log) => log,
+            None => panic!("MultiLog: no Log named {:?}", name),
+        }
+    }
+}
+
+impl ops::IndexMut<&str> for MultiLog {
+    fn index_mut(&mut self, name: &str) -> &mut Self::Output {
+        match self.get_mut(name) {
+            Some(log) => log,
+            None => panic!("MultiLog: no Log named {:?}", name),
+        }
+    }
+}
+
 impl OpenOptionsRepair for OpenOptions {
     fn open_options_repair(&self, path: impl AsRef<Path>) -> crate::Result<String> {
         let path = path.as_ref();
@@ -522,7 +594,7 @@ impl MultiMeta {
         let format_version: usize = reader.read_vlq()?;
         if format_version != 0 {
             return Err(io::Error::new(
-                io::ErrorKind::Other,
+                io::ErrorKind::Unsupported,
                 format!("MultiMeta format {} is unsupported", format_version),
             ));
         }
@@ -657,6 +729,46 @@ mod tests {
         )])
     }
 
+    #[test]
+    fn test_access_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        let name_opts = vec![
+            ("a", log::OpenOptions::new()),
+            ("b", log::OpenOptions::new()),
+        ];
+        let mut mlog = MultiLog::open(path, name_opts).unwrap();
+        assert_eq!(mlog.names(), ["a", "b"]);
+
+        mlog["b"].append(b"2").unwrap();
+        mlog.get_mut("a").unwrap().append(b"1").unwrap();
+        mlog.sync().unwrap();
+        assert!(mlog.get("c").is_none());
+
+        let mlog = simple_multilog(path);
+        assert_eq!(
+            mlog[0].iter().collect::<Result<Vec<_>, _>>().unwrap(),
+            [b"1"]
+        );
+        assert_eq!(
+            mlog["b"].iter().collect::<Result<Vec<_>, _>>().unwrap(),
+            [b"2"]
+        );
+
+        let mut mlog = simple_multilog(path);
+        mlog.detach_logs();
+        assert!(mlog.get("a").is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_duplicated_names() {
+        OpenOptions::from_name_opts(vec![
+            ("a", log::OpenOptions::new()),
+            ("a", log::OpenOptions::new()),
+        ]);
+    }
+
     #[test]
     fn test_individual_log_can_be_opened_directly() {
         let dir = tempfile::tempdir().unwrap();
@@ -711,6 +823,36 @@ mod tests {
         assert_eq!(mlog2[1].iter().count(), 0);
     }
 
+    #[test]
+    fn test_sync_is_all_or_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        let mut mlog = simple_multilog(path);
+        mlog[0].append(b"1").unwrap();
+        mlog[1].append(b"2").unwrap();
+
+        // Simulate a crash after writing logs but before writing multimeta.
+        {
+            let _lock = mlog.lock().unwrap();
+            mlog[0].sync().unwrap();
+            mlog[1].sync().unwrap();
+        }
+        let mlog2 = simple_multilog(path);
+        assert_eq!(mlog2[0].iter().count(), 0);
+        assert_eq!(mlog2[1].iter().count(), 0);
+
+        mlog[0].append(b"3").unwrap();
+        mlog[1].append(b"4").unwrap();
+        mlog.sync().unwrap();
+        let mlog2 = simple_multilog(path);
+        assert_eq!(mlog2[0].iter().count(), 1);
+        assert_eq!(mlog2[1].iter().count(), 1);
+
+        // sync() refuses to work after logs are detached.
+        let _logs = mlog.detach_logs();
+        assert!(mlog.sync().is_err());
+    }
+
     #[test]
     fn test_version() {
         let dir = tempfile::tempdir().unwrap();
@@ -967,7 +1109,7 @@ Write valid MultiMeta"#
         assert_eq!(
             repair(),
             r#"Repairing MultiMeta Log:
-  Reset log size to 111
+  Reset log size to 125
   Rebuilt index "reverse"
 Repairing Log a
   Rebuilt index "x"
@@ -7,6 +7,8 @@
 
 //! Rotation support for a set of [`Log`]s.
 
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::fmt;
 use std::fs;
 use std::io;
@@ -14,15 +16,24 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering::SeqCst;
-
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 use minibytes::Bytes;
 use once_cell::sync::OnceCell;
+use rayon::prelude::*;
 use tracing::debug;
 use tracing::debug_span;
 use tracing::trace;
 
 use crate::errors::IoResultExt;
 use crate::errors::ResultExt;
+use crate::lock::read_only_reader_lock;
 use crate::lock::ScopedDirLock;
 use crate::lock::READER_LOCK_OPTS;
 use crate::log;
@@ -31,6 +42,7 @@ use crate::log::FlushFilterFunc;
 use crate::log::FlushFilterOutput;
 use crate::log::IndexDef;
 use crate::log::Log;
+use crate::log::MmapAdvice;
 use crate::repair::OpenOptionsOutput;
 use crate::repair::OpenOptionsRepair;
 use crate::repair::RepairMessage;
@@ -50,16 +62,42 @@ pub struct RotateLog {
     latest: u8,
     // Indicate an active reader. Destrictive writes (repair) are unsafe.
     reader_lock: Option<ScopedDirLock>,
+    // Background threads deleting old logs. See `remove_in_background`.
+    removals: Mutex<Vec<(PathBuf, thread::JoinHandle<()>)>>,
+    // Archives loaded by `lookup_archived`, keyed by archive number.
+    archives: Mutex<BTreeMap<u64, Arc<Log>>>,
     // Run after log.sync(). For testing purpose only.
     #[cfg(test)]
-    hook_after_log_sync: Option<Box<dyn Fn()>>,
+    hook_after_log_sync: Option<Box<dyn Fn() + Send + Sync>>,
 }
 
 // On disk, a RotateLog is a directory containing:
 // - 0/, 1/, 2/, 3/, ...: one Log per directory.
 // - latest: a file, the name of the directory that is considered "active".
+// - archive/: optional, 1.gz, 2.gz, ...: compressed archives of deleted Logs,
+//   written by Log::export_to. Larger numbers are newer. Numbers are not
+//   reused.
+// - archiving-<n>/: optional, deleted Logs waiting to be compressed to
+//   archive/<n>.gz. See `archive_expired`.
+// - pinned: optional, a file with comma-separated ids of Logs that should not
+//   be deleted. Not part of 'latest' so older readers can still parse it.
+// - pinned-<id>-<n>/: optional, pinned Logs moved aside by rotation so their
+//   ids can be reused.
+// - removing-<id>-<n>/: optional, deleted Logs waiting to be removed in the
+//   background. See `remove_in_background`.
 
 const LATEST_FILE: &str = "latest";
+const ARCHIVE_DIR: &str = "archive";
+const PINNED_FILE: &str = "pinned";
+const REMOVING_PREFIX: &str = "removing-";
+const ARCHIVING_PREFIX: &str = "archiving-";
+
+// Number of the newest Logs looked up one at a time by RotateLog::lookup
+// before looking up the rest in parallel. See `lookup_threads`.
+const SERIAL_LOOKUP_LOG_COUNT: usize = 2;
+
+// Attempts to delete an old Log in the background.
+const REMOVE_RETRY_COUNT: usize = 5;
 
 /// Options used to configure how a [`RotateLog`] is opened.
 #[derive(Clone)]
@@ -68,6 +106,59 @@ pub struct OpenOptions {
     pub(crate) max_log_count: u8,
     pub(crate) log_open_options: log::OpenOptions,
     pub(crate) auto_sync_threshold: Option<u64>,
+    pub(crate) max_total_bytes: Option<u64>,
+    pub(crate) lookup_threads: usize,
+    // Shared by RotateLogs opened with (clones of) these options. Created
+    // on first use. `None` if threads cannot be spawned.
+    pub(crate) lookup_pool: Arc<OnceCell<Option<rayon::ThreadPool>>>,
+    pub(crate) archive_expired: bool,
+    pub(crate) max_archive_count: Option<usize>,
+    pub(crate) remove_in_background: bool,
+    pub(crate) before_rotate: Option<RotateHookFunc>,
+    pub(crate) before_remove: Option<RotateHookFunc>,
+}
+
+/// Function called with a [`Log`] in a [`RotateLog`]. See
+/// [`OpenOptions::before_rotate`] and [`OpenOptions::before_remove`].
+pub type RotateHookFunc = Arc<dyn Fn(&LogStats) + Send + Sync + 'static>;
+
+/// Information about a [`Log`] in a [`RotateLog`], passed to
+/// [`RotateHookFunc`]s.
+#[derive(Clone, Debug)]
+pub struct LogStats {
+    /// Directory of the [`Log`].
+    pub path: PathBuf,
+
+    /// Id of the [`Log`]. Also the name of its directory.
+    pub id: u8,
+
+    /// Size of the primary log in bytes.
+    pub primary_len: u64,
+
+    /// Size of the primary log and indexes in bytes.
+    pub total_bytes: u64,
+}
+
+/// Information about a [`Log`] retained by a [`RotateLog`]. Returned by
+/// [`RotateLog::logs_info`].
+#[derive(Clone, Debug)]
+pub struct LogInfo {
+    /// Position in the rotation. 0 is the writable [`Log`]. Larger numbers
+    /// are older.
+    pub index: usize,
+
+    /// Id of the [`Log`]. Also the name of its directory.
+    pub id: u8,
+
+    /// Creation time of the [`Log`] directory. `None` if the [`RotateLog`]
+    /// is in-memory, or the filesystem does not record creation time.
+    pub created: Option<SystemTime>,
+
+    /// Number of entries, including entries not yet written to disk.
+    pub entry_count: usize,
+
+    /// Size of the primary log and indexes on disk in bytes.
+    pub total_bytes: u64,
 }
 
 impl OpenOptions {
@@ -80,6 +171,8 @@ impl OpenOptions {
     /// - No indexes.
     /// - Do not create on demand.
     /// - Do not sync automatically on append().
+    /// - No limit on the total size.
+    /// - Look up older logs one by one.
     pub fn new() -> Self {
         // Some "seemingly reasonable" default values. Not scientifically chosen.
         let max_log_count = 2;
@@ -89,12 +182,22 @@ impl OpenOptions {
             max_log_count,
             log_open_options: log::OpenOptions::new(),
             auto_sync_threshold: None,
+            max_total_bytes: None,
+            lookup_threads: 1,
+            lookup_pool: Default::default(),
+            archive_expired: false,
+            max_archive_count: None,
+            remove_in_background: false,
+            before_rotate: None,
+            before_remove: None,
         }
     }
 
     /// Set the maximum [`Log`] count.
     ///
     /// A larger value would hurt lookup performance.
+    ///
+    /// On rotation, the oldest [`Log`]s over the count are deleted.
     pub fn max_log_count(mut self, count: u8) -> Self {
         assert!(count >= 1);
         self.max_log_count = count;
@@ -102,12 +205,118 @@ impl OpenOptions {
     }
 
     /// Set the maximum bytes per [`Log`].
+    ///
+    /// The limit is checked by [`RotateLog::sync`]. If the writable [`Log`]
+    /// reaches the limit after writing in-memory entries, a new empty
+    /// [`Log`] becomes writable. So a [`Log`] can exceed the limit by the
+    /// size of one sync.
     pub fn max_bytes_per_log(mut self, bytes: u64) -> Self {
         assert!(bytes > 0);
         self.max_bytes_per_log = bytes;
         self
     }
 
+    /// Set the maximum total size of all [`Log`]s, including indexes.
+    /// `None` means no limit.
+    ///
+    /// The limit is checked by [`RotateLog::sync`] and
+    /// [`RotateLog::remove_old_logs`]. Oldest [`Log`]s are deleted until the
+    /// total size fits. The writable [`Log`] is never deleted, so it can
+    /// still exceed the limit until it gets rotated.
+    pub fn max_total_bytes(mut self, bytes: impl Into<Option<u64>>) -> Self {
+        self.max_total_bytes = bytes.into();
+        self
+    }
+
+    /// Set the number of threads used by [`RotateLog::lookup`] to look up
+    /// older [`Log`]s in parallel.
+    ///
+    /// The newest [`Log`]s are looked up one at a time. If iteration goes
+    /// past them, for example, because the key is missing there, the
+    /// remaining [`Log`]s are loaded and their matching entries are read in
+    /// parallel. This reduces the latency of looking up keys that are
+    /// missing in newer [`Log`]s, if reading indexes and entries waits for
+    /// disk I/O. The order of entries is unchanged.
+    ///
+    /// The threads are created on first use, and are shared by
+    /// [`RotateLog`]s opened by the returned [`OpenOptions`] and its clones.
+    ///
+    /// If keys are usually missing, [`IndexDef::bloom_filter`] is cheaper.
+    pub fn lookup_threads(mut self, threads: usize) -> Self {
+        assert!(threads >= 1);
+        self.lookup_threads = threads;
+        self.lookup_pool = Default::default();
+        self
+    }
+
+    /// Set whether to keep [`Log`]s deleted by `max_log_count` or
+    /// `max_total_bytes` as compressed archives.
+    ///
+    /// Archives are written by [`Log::export_to`] to the `archive`
+    /// directory, and do not contain indexes. They are read-only, and are
+    /// only used by [`RotateLog::lookup_archived`].
+    ///
+    /// Under the directory lock, expired [`Log`]s are only moved aside.
+    /// They are compressed after the lock is released, before
+    /// [`RotateLog::sync`] or [`RotateLog::remove_old_logs`] returns. If a
+    /// [`Log`] cannot be archived, it is kept and retried next time.
+    ///
+    /// Archives do not count towards `max_total_bytes`. Use
+    /// [`OpenOptions::max_archive_count`] to limit them.
+    pub fn archive_expired(mut self, archive: bool) -> Self {
+        self.archive_expired = archive;
+        self
+    }
+
+    /// Set the maximum number of archives written by
+    /// [`OpenOptions::archive_expired`]. `None` means no limit.
+    ///
+    /// After archiving, the oldest archives over the count are deleted.
+    pub fn max_archive_count(mut self, count: impl Into<Option<usize>>) -> Self {
+        let count = count.into();
+        assert!(count != Some(0));
+        self.max_archive_count = count;
+        self
+    }
+
+    /// Set whether to delete files of old [`Log`]s in background threads.
+    ///
+    /// Deleting a large [`Log`] can take a while. If set, [`RotateLog::sync`]
+    /// and [`RotateLog::remove_old_logs`] only delete the metadata of old
+    /// [`Log`]s, which marks them as deleted, and move their directories to
+    /// `removing-<id>-<n>`, so the ids can be reused right away. The moved
+    /// directories are deleted in the background. The `before_remove` hook
+    /// and archiving still happen before returning.
+    ///
+    /// Failed deletions (ex. files are mmap-ed by other processes on
+    /// Windows) are retried a few times with delays, then by the next
+    /// rotation. Use [`RotateLog::wait_for_removals`] to wait for scheduled
+    /// deletions.
+    pub fn remove_in_background(mut self, background: bool) -> Self {
+        self.remove_in_background = background;
+        self
+    }
+
+    /// Set a function to call before the writable [`Log`] gets rotated,
+    /// that is, becomes read-only and a new [`Log`] is created.
+    ///
+    /// The function is called with the directory lock taken.
+    pub fn before_rotate(mut self, hook: impl Fn(&LogStats) + Send + Sync + 'static) -> Self {
+        self.before_rotate = Some(Arc::new(hook));
+        self
+    }
+
+    /// Set a function to call before an old [`Log`] gets deleted, because of
+    /// `max_log_count` or `max_total_bytes`.
+    ///
+    /// The function is called with the directory lock taken. Files of the
+    /// [`Log`] can be copied elsewhere, for example, to archive entries
+    /// before they are lost.
+    pub fn before_remove(mut self, hook: impl Fn(&LogStats) + Send + Sync + 'static) -> Self {
+        self.before_remove = Some(Arc::new(hook));
+        self
+    }
+
     /// Sets the checksum type.
     ///
     /// See [log::ChecksumType] for details.
@@ -116,6 +325,62 @@ impl OpenOptions {
         self
     }
 
+    /// Sets the checksum granularity.
+    ///
+    /// See [log::ChecksumGranularity] for details.
+    pub fn checksum_granularity(mut self, granularity: log::ChecksumGranularity) -> Self {
+        self.log_open_options = self.log_open_options.checksum_granularity(granularity);
+        self
+    }
+
+    /// Sets when to verify checksums of entries being read.
+    ///
+    /// See [log::ChecksumVerification] for details.
+    pub fn checksum_verification(mut self, verification: log::ChecksumVerification) -> Self {
+        self.log_open_options = self.log_open_options.checksum_verification(verification);
+        self
+    }
+
+    /// Sets whether to grow primary log files in chunks.
+    ///
+    /// See [log::OpenOptions::preallocate_chunk_size] for details.
+    pub fn preallocate_chunk_size(mut self, size: impl Into<Option<u64>>) -> Self {
+        self.log_open_options = self.log_open_options.preallocate_chunk_size(size);
+        self
+    }
+
+    /// Sets the maximum size of a new entry in bytes.
+    ///
+    /// See [log::OpenOptions::max_entry_size] for details.
+    pub fn max_entry_size(mut self, size: impl Into<Option<u64>>) -> Self {
+        self.log_open_options = self.log_open_options.max_entry_size(size);
+        self
+    }
+
+    /// Sets the access pattern hint for lookups.
+    ///
+    /// See [log::OpenOptions::lookup_advice] for details.
+    pub fn lookup_advice(mut self, advice: MmapAdvice) -> Self {
+        self.log_open_options = self.log_open_options.lookup_advice(advice);
+        self
+    }
+
+    /// Sets the access pattern hint for iteration.
+    ///
+    /// See [log::OpenOptions::iter_advice] for details.
+    pub fn iter_advice(mut self, advice: MmapAdvice) -> Self {
+        self.log_open_options = self.log_open_options.iter_advice(advice);
+        self
+    }
+
+    /// Sets whether to release pages read by iteration.
+    ///
+    /// See [log::OpenOptions::release_after_iter] for details.
+    pub fn release_after_iter(mut self, release: bool) -> Self {
+        self.log_open_options = self.log_open_options.release_after_iter(release);
+        self
+    }
+
     /// Set whether create the [`RotateLog`] structure if it does not exist.
     pub fn create(mut self, create: bool) -> Self {
         self.log_open_options = self.log_open_options.create(create);
@@ -148,6 +413,22 @@ impl OpenOptions {
         self
     }
 
+    /// Sets whether to open the [`RotateLog`] in read-only mode.
+    ///
+    /// Similar to [`log::OpenOptions::read_only`], a read-only [`RotateLog`]
+    /// never creates, locks, or writes files. It does not rotate, does not
+    /// delete old [`Log`]s, and does not write the 'latest' file. `create`
+    /// is ignored.
+    ///
+    /// Lookups and iteration work across existing [`Log`]s.
+    /// [`RotateLog::sync`] can still be used to load new entries written by
+    /// other processes. [`RotateLog::append`] and other functions that write
+    /// data return errors.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.log_open_options = self.log_open_options.read_only(read_only);
+        self
+    }
+
     /// Call `sync` automatically if the in-memory buffer size has exceeded
     /// the given size threshold.
     ///
@@ -161,7 +442,12 @@ impl OpenOptions {
     pub fn open(&self, dir: impl AsRef<Path>) -> crate::Result<RotateLog> {
         let dir = dir.as_ref();
         let result: crate::Result<_> = (|| {
-            let reader_lock = ScopedDirLock::new_with_options(dir, &READER_LOCK_OPTS)?;
+            let read_only = self.log_open_options.read_only;
+            let reader_lock = if read_only {
+                read_only_reader_lock(dir)
+            } else {
+                Some(ScopedDirLock::new_with_options(dir, &READER_LOCK_OPTS)?)
+            };
             let span = debug_span!("RotateLog::open", dir = &dir.to_string_lossy().as_ref());
             let _guard = span.enter();
 
@@ -170,7 +456,9 @@ impl OpenOptions {
             let (latest, logs) = match latest_and_log {
                 Ok((latest, logs)) => (latest, logs),
                 Err(e) => {
-                    if !self.log_open_options.create {
+                    if read_only {
+                        return Err(e).context("not creating new logs in read-only mode");
+                    } else if !self.log_open_options.create {
                         return Err(e)
                             .context("not creating new logs since OpenOption::create is not set");
                     } else {
@@ -238,7 +526,9 @@ impl OpenOptions {
                 logs,
                 logs_len,
                 latest,
-                reader_lock: Some(reader_lock),
+                reader_lock,
+                removals: Default::default(),
+                archives: Default::default(),
                 #[cfg(test)]
                 hook_after_log_sync: None,
             })
@@ -261,6 +551,8 @@ impl OpenOptions {
                 logs_len,
                 latest: 0,
                 reader_lock: None,
+                removals: Default::default(),
+                archives: Default::default(),
                 #[cfg(test)]
                 hook_after_log_sync: None,
             })
@@ -344,6 +636,17 @@ impl fmt::Debug for OpenOptions {
         write!(f, "max_bytes_per_log: {}, ", self.max_bytes_per_log)?;
         write!(f, "max_log_count: {}, ", self.max_log_count)?;
         write!(f, "auto_sync_threshold: {:?}, ", self.auto_sync_threshold)?;
+        write!(f, "max_total_bytes: {:?}, ", self.max_total_bytes)?;
+        write!(f, "lookup_threads: {}, ", self.lookup_threads)?;
+        write!(f, "archive_expired: {}, ", self.archive_expired)?;
+        write!(f, "max_archive_count: {:?}, ", self.max_archive_count)?;
+        write!(f, "remove_in_background: {}, ", self.remove_in_background)?;
+        let hook_desc = |hook: &Option<RotateHookFunc>| match hook {
+            Some(_) => "Some(_)",
+            None => "None",
+        };
+        write!(f, "before_rotate: {}, ", hook_desc(&self.before_rotate))?;
+        write!(f, "before_remove: {}, ", hook_desc(&self.before_remove))?;
         write!(f, "log_open_options: {:?} }}", &self.log_open_options)?;
         Ok(())
     }
@@ -376,13 +679,15 @@ impl RotateLog {
     ) -> crate::Result<RotateLogLookupIter> {
         let key = key.into();
         let result: crate::Result<_> = (|| {
+            let inner_iter = self.logs[0].get().unwrap().lookup(index_id, &key)?;
             Ok(RotateLogLookupIter {
-                inner_iter: self.logs[0].get().unwrap().lookup(index_id, &key)?,
+                inner_iter,
                 end: false,
                 log_rotate: self,
                 log_index: 0,
                 index_id,
                 key: key.clone(),
+                prefetched: None,
             })
         })();
         result
@@ -390,16 +695,63 @@ impl RotateLog {
             .context(|| format!("  RotateLog.dir = {:?}", self.dir))
     }
 
+    /// Look up entries in [`Log`]s starting from `start` in parallel, using
+    /// the [`OpenOptions::lookup_threads`] pool. Return `None` if the pool is
+    /// not available. Entries are in the same order as
+    /// [`RotateLogLookupIter`] would produce without prefetching.
+    fn lookup_old_logs_parallel(
+        &self,
+        index_id: usize,
+        key: &[u8],
+        start: usize,
+    ) -> Option<Vec<crate::Result<&[u8]>>> {
+        let pool = self.open_options.lookup_pool.get_or_init(|| {
+            let threads = self.open_options.lookup_threads;
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .thread_name(|i| format!("indexedlog-rotate-lookup-{}", i))
+                .build()
+                .map_err(|err| debug!("Error creating rotate lookup threads: {:?}", err))
+                .ok()
+        });
+        let pool = pool.as_ref()?;
+        let per_log: Vec<Option<crate::Result<Vec<&[u8]>>>> = pool.install(|| {
+            (start..self.logs.len())
+                .into_par_iter()
+                .map(|i| match self.load_log(i) {
+                    Ok(Some(log)) => {
+                        Some(log.lookup(index_id, key).and_then(|iter| iter.collect()))
+                    }
+                    // Not fatal. See RotateLogLookupIter::next.
+                    Ok(None) | Err(_) => None,
+                })
+                .collect()
+        });
+
+        // Stop at the first Log that cannot be loaded or looked up, like
+        // RotateLogLookupIter::next.
+        let mut result = Vec::new();
+        for entries in per_log {
+            match entries {
+                None => break,
+                Some(Ok(entries)) => result.extend(entries.into_iter().map(Ok)),
+                Some(Err(err)) => {
+                    result.push(Err(err));
+                    break;
+                }
+            }
+        }
+        Some(result)
+    }
+
     /// Convert a slice to [`Bytes`].
     ///
     /// Do not copy the slice if it's from the main on-disk buffer of
     /// one of the loaded logs.
     pub fn slice_to_bytes(&self, slice: &[u8]) -> Bytes {
         for log in &self.logs {
-            if let Some(log) = log.get() {
-                if log.disk_buf.range_of_slice(slice).is_some() {
-                    return log.slice_to_bytes(slice);
-                }
+            if let Some(bytes) = log.get().and_then(|log| log.disk_buf.slice_of(slice)) {
+                return bytes;
             }
         }
         Bytes::copy_from_slice(slice)
@@ -525,6 +877,9 @@ impl RotateLog {
                     self.writable_log().finalize_indexes(&lock)?;
                     self.rotate_internal(&lock)?;
                 }
+                self.remove_logs_over_total_bytes(&lock);
+                drop(lock);
+                self.archive_pending_logs();
             }
 
             Ok(self.latest)
@@ -541,16 +896,92 @@ impl RotateLog {
     /// which indicates rotation was triggered elsewhere, or the [`RotateLog`]
     /// is in-memory.
     pub fn remove_old_logs(&mut self) -> crate::Result<()> {
+        self.check_writable()?;
         if let Some(dir) = &self.dir {
             let lock = ScopedDirLock::new(dir)?;
             let latest = read_latest(dir)?;
             if latest == self.latest {
                 self.try_remove_old_logs(&lock);
+                self.remove_logs_over_total_bytes(&lock);
             }
+            drop(lock);
+            self.archive_pending_logs();
+        }
+        Ok(())
+    }
+
+    /// Prevent the [`Log`] with the given id from being deleted by rotation,
+    /// `max_total_bytes`, or [`RotateLog::remove_old_logs`]. The id of the
+    /// writable [`Log`] is returned by [`RotateLog::sync`].
+    ///
+    /// Pins are stored on disk and affect all [`RotateLog`]s using the same
+    /// directory. A pinned [`Log`] outside the rotation window stays on
+    /// disk, but is no longer read by [`RotateLog`]. It can be opened at
+    /// `<dir>/<id>` by [`log::OpenOptions`]. Pinned [`Log`]s do not count
+    /// towards `max_total_bytes`.
+    ///
+    /// Ids are reused after 256 rotations. Rotating to a pinned id moves the
+    /// pinned [`Log`] to `<dir>/pinned-<id>-<n>`, where `<n>` makes the name
+    /// unique, and unpins the id. The moved [`Log`] is kept until it is
+    /// deleted manually.
+    ///
+    /// Does nothing if the [`RotateLog`] is in-memory.
+    pub fn pin(&mut self, id: u8) -> crate::Result<()> {
+        let result = self.modify_pinned(|pinned| {
+            pinned.insert(id);
+        });
+
+        result
+            .context(|| format!("in RotateLog::pin({})", id))
+            .context(|| format!("  RotateLog.dir = {:?}", self.dir))
+    }
+
+    /// Undo [`RotateLog::pin`]. The [`Log`] can be deleted by the next
+    /// rotation or [`RotateLog::remove_old_logs`].
+    pub fn unpin(&mut self, id: u8) -> crate::Result<()> {
+        let result = self.modify_pinned(|pinned| {
+            pinned.remove(&id);
+        });
+
+        result
+            .context(|| format!("in RotateLog::unpin({})", id))
+            .context(|| format!("  RotateLog.dir = {:?}", self.dir))
+    }
+
+    /// Ids of [`Log`]s pinned by [`RotateLog::pin`], sorted.
+    pub fn pinned(&self) -> crate::Result<Vec<u8>> {
+        let result: crate::Result<_> = (|| match &self.dir {
+            Some(dir) => Ok(read_pinned(dir)?.into_iter().collect()),
+            None => Ok(Vec::new()),
+        })();
+
+        result
+            .context("in RotateLog::pinned")
+            .context(|| format!("  RotateLog.dir = {:?}", self.dir))
+    }
+
+    fn modify_pinned(&self, func: impl FnOnce(&mut BTreeSet<u8>)) -> crate::Result<()> {
+        self.check_writable()?;
+        if let Some(dir) = &self.dir {
+            let _lock = ScopedDirLock::new(dir)?;
+            let mut pinned = read_pinned(dir)?;
+            func(&mut pinned);
+            write_pinned(dir, &pinned, self.open_options.log_open_options.fsync)?;
         }
         Ok(())
     }
 
+    /// Return an error if the [`RotateLog`] was opened in read-only mode.
+    fn check_writable(&self) -> crate::Result<()> {
+        if self.open_options.log_open_options.read_only {
+            Err(crate::Error::programming(
+                "RotateLog was opened in read-only mode",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
     /// Force create a new [`Log`]. Bump latest.
     ///
     /// This function requires it's protected by a directory lock, and the
@@ -563,8 +994,19 @@ impl RotateLog {
         }
         let _guard = span.enter();
 
+        if let Some(hook) = &self.open_options.before_rotate {
+            hook(&log_stats(self.dir.as_ref().unwrap(), self.latest));
+        }
+
         // Create a new Log. Bump latest.
+        let dir = self.dir.as_ref().unwrap();
         let next = self.latest.wrapping_add(1);
+        let mut pinned = read_pinned(dir)?;
+        if pinned.remove(&next) {
+            // Ids wrapped around. Keep the pinned log under a different name.
+            move_pinned_log(dir, next)?;
+            write_pinned(dir, &pinned, self.open_options.log_open_options.fsync)?;
+        }
         let log = create_empty_log(
             Some(self.dir.as_ref().unwrap()),
             &self.open_options,
@@ -605,44 +1047,15 @@ impl RotateLog {
                             if (latest >= earliest && (id > latest || id < earliest))
                                 || (latest < earliest && (id > latest && id < earliest))
                             {
-                                // Explicitly delete the `meta` file first. This marks
-                                // the log as "deleted" in an atomic way.
-                                //
-                                // Errors are not fatal. On Windows, this can fail if
-                                // other processes have files in entry.path() mmap-ed.
-                                // Newly opened or flushed RotateLog will unmap files.
-                                // New rotation would trigger remove_dir_all to try
-                                // remove old logs again.
-                                match fs::remove_file(entry.path().join(log::META_FILE)) {
-                                    Ok(()) => {}
-                                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                                        // Meta file is already deleted.
-                                    }
-                                    Err(e) => {
-                                        // Don't delete the log if we were unable to delete the
-                                        // meta file.
-                                        debug!(
-                                            "Error removing rotate log meta: {:?} {:?}",
-                                            name, e
-                                        );
-                                        continue;
-                                    }
-                                }
-
-                                // Delete the rest of the directory.
-                                let res = fs::remove_dir_all(entry.path());
-                                match res {
-                                    Ok(_) => debug!("Removed rotate log: {:?}", name),
-                                    Err(err) => {
-                                        debug!("Error removing rotate log directory: {:?}", err)
-                                    }
-                                };
+                                self.remove_log(id);
                             } else {
                                 debug!(
                                     "Not removing rotate log: {:?} (latest: {:?}, earliest: {:?})",
                                     name, latest, earliest
                                 );
                             }
+                        } else if name.starts_with(REMOVING_PREFIX) {
+                            self.remove_moved_log_dir(entry.path());
                         }
                     }
                 }
@@ -650,6 +1063,258 @@ impl RotateLog {
         }
     }
 
+    /// Delete oldest [`Log`]s until the total size fits in `max_total_bytes`.
+    /// The writable [`Log`] is kept.
+    fn remove_logs_over_total_bytes(&mut self, _lock: &ScopedDirLock) {
+        let (dir, max) = match (&self.dir, self.open_options.max_total_bytes) {
+            (Some(dir), Some(max)) => (dir.clone(), max),
+            _ => return,
+        };
+        let pinned = read_pinned(&dir).unwrap_or_default();
+        let len = self.logs_len.load(SeqCst);
+        let mut total = self.writable_log().meta.total_bytes();
+        for index in 1..len {
+            let id = self.latest.wrapping_sub(index as u8);
+            if pinned.contains(&id) {
+                continue;
+            }
+            let meta_path = dir.join(id.to_string()).join(log::META_FILE);
+            // Missing or broken logs are counted as empty.
+            total += match log::LogMetadata::read_file(&meta_path) {
+                Ok(meta) => meta.total_bytes(),
+                Err(_) => 0,
+            };
+            if total > max {
+                for old_index in index..len {
+                    self.remove_log(self.latest.wrapping_sub(old_index as u8));
+                }
+                self.logs.truncate(index);
+                self.logs_len.store(index, SeqCst);
+                break;
+            }
+        }
+    }
+
+    /// Delete a non-writable [`Log`] by id. Call the `before_remove` hook,
+    /// and archive the [`Log`] first. Pinned [`Log`]s are kept.
+    fn remove_log(&self, id: u8) {
+        let dir = self.dir.as_ref().unwrap();
+        let path = dir.join(id.to_string());
+        match read_pinned(dir) {
+            Ok(pinned) if !pinned.contains(&id) => {}
+            Ok(_) => {
+                debug!("Not removing pinned rotate log: {:?}", path);
+                return;
+            }
+            Err(err) => {
+                // Keep the log if it is unknown whether it is pinned.
+                debug!("Error reading pinned rotate logs: {:?}", err);
+                return;
+            }
+        }
+        // Logs without metadata are considered deleted.
+        if fs::symlink_metadata(path.join(log::META_FILE)).is_ok() {
+            if let Some(hook) = &self.open_options.before_remove {
+                hook(&log_stats(dir, id));
+            }
+            if self.open_options.archive_expired {
+                // Compress it later without the lock. See `archive_pending_logs`.
+                if let Err(err) = move_archiving_log(dir, id) {
+                    // Keep the log so it can be archived next time.
+                    debug!("Error moving rotate log {:?}: {:?}", path, err);
+                }
+                return;
+            }
+        }
+        if !self.open_options.remove_in_background {
+            remove_log_dir(&path);
+        } else if remove_log_meta(&path) {
+            // Move the directory away under the lock so the background
+            // thread does not race with a new Log reusing the id.
+            match move_removed_log(dir, id) {
+                Ok(path) => self.remove_dir_in_background(path),
+                // The next rotation will try again.
+                Err(err) => debug!("Error moving rotate log {:?}: {:?}", path, err),
+            }
+        }
+    }
+
+    /// Delete a moved-aside [`Log`] that is no longer needed, such as one
+    /// left by an interrupted background deletion.
+    fn remove_moved_log_dir(&self, path: PathBuf) {
+        if !self.open_options.remove_in_background {
+            match fs::remove_dir_all(&path) {
+                Ok(_) => debug!("Removed rotate log: {:?}", path),
+                Err(err) => debug!("Error removing rotate log directory: {:?}", err),
+            }
+        } else {
+            let removals = self.removals.lock().unwrap();
+            if removals.iter().any(|(p, t)| p == &path && !t.is_finished()) {
+                // Being deleted by this RotateLog.
+                return;
+            }
+            drop(removals);
+            self.remove_dir_in_background(path);
+        }
+    }
+
+    /// Delete `path` in a background thread. `path` must not be used by
+    /// anything else, such as a moved-aside deleted [`Log`].
+    fn remove_dir_in_background(&self, path: PathBuf) {
+        let thread_path = path.clone();
+        let thread = thread::Builder::new()
+            .name("indexedlog-rotate-remove".to_string())
+            .spawn(move || remove_dir_with_retry(&thread_path));
+        match thread {
+            Ok(thread) => {
+                let mut removals = self.removals.lock().unwrap();
+                removals.retain(|(_, t)| !t.is_finished());
+                removals.push((path, thread));
+            }
+            // The next rotation will try again.
+            Err(err) => debug!("Error spawning rotate log removal thread: {:?}", err),
+        }
+    }
+
+    /// Wait for deletions scheduled by [`OpenOptions::remove_in_background`].
+    pub fn wait_for_removals(&self) {
+        let removals = std::mem::take(&mut *self.removals.lock().unwrap());
+        for (_, thread) in removals {
+            let _ = thread.join();
+        }
+    }
+
+    /// Compress [`Log`]s moved to `archiving-<n>` by `remove_log` to
+    /// archives, then delete them. This runs without the directory lock
+    /// since compressing can be slow. Errors are not fatal. [`Log`]s that
+    /// fail to archive are retried next time.
+    fn archive_pending_logs(&self) {
+        let dir = self.dir.as_ref().unwrap();
+        let archive_dir = dir.join(ARCHIVE_DIR);
+        let pending = match list_numbered(dir, ARCHIVING_PREFIX, "") {
+            Ok(pending) => pending,
+            Err(err) => {
+                debug!("Error listing rotate logs to archive: {:?}", err);
+                return;
+            }
+        };
+        for n in pending {
+            let path = dir.join(format!("{}{}", ARCHIVING_PREFIX, n));
+            // The archive might be written by others already.
+            if !archive_dir.join(format!("{}.gz", n)).exists() {
+                if let Err(err) = self.archive_log(&path, n) {
+                    debug!("Error archiving rotate log {:?}: {:?}", path, err);
+                    continue;
+                }
+            }
+            self.remove_moved_log_dir(path);
+        }
+
+        if let Some(max) = self.open_options.max_archive_count {
+            let numbers = match list_archives(&archive_dir) {
+                Ok(numbers) => numbers,
+                Err(err) => {
+                    debug!("Error listing rotate log archives: {:?}", err);
+                    return;
+                }
+            };
+            let count = numbers.len().saturating_sub(max);
+            for n in &numbers[..count] {
+                let path = archive_dir.join(format!("{}.gz", n));
+                match fs::remove_file(&path) {
+                    Ok(()) => debug!("Removed rotate log archive: {:?}", path),
+                    Err(err) => debug!("Error removing rotate log archive: {:?}", err),
+                }
+            }
+        }
+    }
+
+    /// Export a [`Log`] moved aside by `remove_log` to `archive/<n>.gz`.
+    fn archive_log(&self, path: &Path, n: u64) -> crate::Result<()> {
+        let log = self
+            .open_options
+            .log_open_options
+            .clone()
+            .create(false)
+            .index_defs(Vec::new())
+            .open(path)?;
+        let archive_dir = self.dir.as_ref().unwrap().join(ARCHIVE_DIR);
+        utils::mkdir_p(&archive_dir)?;
+        let mut tmp = tempfile::NamedTempFile::new_in(&archive_dir)
+            .context(&archive_dir, "cannot create temporary archive")?;
+        let mut encoder = GzEncoder::new(tmp.as_file_mut(), flate2::Compression::default());
+        log.export_to(&mut encoder)?;
+        encoder
+            .finish()
+            .context(&archive_dir, "cannot write archive")?;
+        let archive_path = archive_dir.join(format!("{}.gz", n));
+        tmp.persist(&archive_path)
+            .map_err(|e| e.error)
+            .context(&archive_path, "cannot persist archive")?;
+        debug!("Archived rotate log {:?} to {:?}", path, archive_path);
+        Ok(())
+    }
+
+    /// Look up entries in archives of deleted [`Log`]s. See
+    /// [`OpenOptions::archive_expired`]. Newer archives are checked first.
+    ///
+    /// Each archive is decompressed and indexed in memory the first time it
+    /// is looked up, then kept in memory by this [`RotateLog`]. So the first
+    /// lookup is much slower than [`RotateLog::lookup`], and memory usage
+    /// grows with the size of archives. See [`OpenOptions::max_archive_count`].
+    pub fn lookup_archived(
+        &self,
+        index_id: usize,
+        key: impl AsRef<[u8]>,
+    ) -> crate::Result<Vec<Bytes>> {
+        let key = key.as_ref();
+        let result: crate::Result<_> = (|| {
+            let mut result = Vec::new();
+            let archive_dir = match &self.dir {
+                Some(dir) => dir.join(ARCHIVE_DIR),
+                None => return Ok(result),
+            };
+            let numbers = list_archives(&archive_dir)?;
+            let logs = {
+                let mut archives = self.archives.lock().unwrap();
+                archives.retain(|n, _| numbers.binary_search(n).is_ok());
+                let mut logs = Vec::with_capacity(numbers.len());
+                for &n in numbers.iter().rev() {
+                    if let Some(log) = archives.get(&n) {
+                        logs.push(log.clone());
+                        continue;
+                    }
+                    let path = archive_dir.join(format!("{}.gz", n));
+                    let file = match fs::File::open(&path) {
+                        Ok(file) => file,
+                        // Deleted by max_archive_count.
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                        Err(e) => return Err(e).context(&path, "cannot open archive"),
+                    };
+                    let log = Log::import_in_memory(
+                        GzDecoder::new(io::BufReader::new(file)),
+                        &self.open_options.log_open_options,
+                    )
+                    .context(|| format!("  archive = {:?}", path))?;
+                    let log = Arc::new(log);
+                    archives.insert(n, log.clone());
+                    logs.push(log);
+                }
+                logs
+            };
+            for log in logs {
+                for entry in log.lookup(index_id, key)? {
+                    result.push(Bytes::copy_from_slice(entry?));
+                }
+            }
+            Ok(result)
+        })();
+
+        result
+            .context(|| format!("in RotateLog::lookup_archived({}, {:?})", index_id, key))
+            .context(|| format!("  RotateLog.dir = {:?}", self.dir))
+    }
+
     /// Get the writable [`Log`].
     fn writable_log(&mut self) -> &mut Log {
         self.logs[0].get_mut().unwrap()
@@ -695,16 +1360,73 @@ impl RotateLog {
 
     /// Iterate over all the entries.
     ///
-    /// The entries are returned in FIFO order.
+    /// The entries are returned in FIFO order, from the oldest retained
+    /// [`Log`] to the writable [`Log`], including in-memory entries.
     pub fn iter(&self) -> impl Iterator<Item = crate::Result<&[u8]>> {
         let logs = self.logs();
         logs.into_iter().rev().flat_map(|log| log.iter())
     }
 
+    /// Iterate over all the entries in reversed order. Newest first.
+    ///
+    /// Entries can only be parsed forwards, so entries of a [`Log`] are
+    /// collected when the iteration reaches that [`Log`].
+    pub fn iter_rev(&self) -> impl Iterator<Item = crate::Result<&[u8]>> {
+        let logs = self.logs();
+        logs.into_iter().flat_map(
+            |log| match log.iter().collect::<crate::Result<Vec<&[u8]>>>() {
+                Ok(entries) => entries.into_iter().rev().map(Ok).collect(),
+                Err(err) => vec![Err(err)],
+            },
+        )
+    }
+
     /// Iterate over all dirty entries.
     pub fn iter_dirty(&self) -> impl Iterator<Item = crate::Result<&[u8]>> {
         self.logs[0].get().unwrap().iter_dirty()
     }
+
+    /// Describe the [`Log`]s retained by the [`RotateLog`]. Newest first.
+    ///
+    /// This answers questions like how much history is retained. Entry
+    /// counts are read from the metadata of [`Log`]s. [`Log`]s written by
+    /// older versions do not record them, and are counted by iterating.
+    pub fn logs_info(&self) -> crate::Result<Vec<LogInfo>> {
+        let result: crate::Result<_> = (|| {
+            let mut infos = Vec::new();
+            for (index, log) in self.logs().into_iter().enumerate() {
+                let id = self.latest.wrapping_sub(index as u8);
+                let created = self.dir.as_ref().and_then(|dir| {
+                    let metadata = fs::metadata(dir.join(id.to_string())).ok()?;
+                    metadata.created().ok()
+                });
+                let entry_count = match log.recorded_entry_count() {
+                    Some(count) => count,
+                    // Logs written by older versions do not record counts.
+                    None => {
+                        let mut count = 0;
+                        for entry in log.iter() {
+                            entry?;
+                            count += 1;
+                        }
+                        count
+                    }
+                };
+                infos.push(LogInfo {
+                    index,
+                    id,
+                    created,
+                    entry_count,
+                    total_bytes: log.meta.total_bytes(),
+                });
+            }
+            Ok(infos)
+        })();
+
+        result
+            .context("in RotateLog::logs_info")
+            .context(|| format!("  RotateLog.dir = {:?}", self.dir))
+    }
 }
 
 /// Wrap `Log` in a `OnceCell`.
@@ -751,6 +1473,7 @@ impl RotateLowLevelExt for RotateLog {
     }
 
     fn force_rotate(&mut self) -> crate::Result<()> {
+        self.check_writable()?;
         if self.dir.is_none() {
             // rotate does not make sense for an in-memory RotateLog.
             return Ok(());
@@ -777,6 +1500,8 @@ pub struct RotateLogLookupIter<'a> {
     log_index: usize,
     index_id: usize,
     key: Bytes,
+    // Entries of the remaining logs, if they were looked up in parallel.
+    prefetched: Option<std::vec::IntoIter<crate::Result<&'a [u8]>>>,
 }
 
 impl<'a> Iterator for RotateLogLookupIter<'a> {
@@ -788,7 +1513,29 @@ impl<'a> Iterator for RotateLogLookupIter<'a> {
         }
         match self.inner_iter.next() {
             None => {
-                if self.log_index + 1 >= self.log_rotate.logs.len() {
+                if let Some(prefetched) = &mut self.prefetched {
+                    let next = prefetched.next();
+                    if next.is_none() {
+                        self.end = true;
+                    }
+                    return next;
+                }
+                let next_index = self.log_index + 1;
+                if self.log_rotate.open_options.lookup_threads > 1
+                    && next_index >= SERIAL_LOOKUP_LOG_COUNT
+                    && next_index + 1 < self.log_rotate.logs.len()
+                {
+                    // The newest Logs are exhausted. Look up the rest in parallel.
+                    let log_rotate = self.log_rotate;
+                    if let Some(entries) =
+                        log_rotate.lookup_old_logs_parallel(self.index_id, &self.key, next_index)
+                    {
+                        self.prefetched = Some(entries.into_iter());
+                        return self.next();
+                    }
+                    // Threads are unavailable. Look up one Log at a time.
+                }
+                if next_index >= self.log_rotate.logs.len() {
                     self.end = true;
                     None
                 } else {
@@ -827,6 +1574,17 @@ impl<'a> Iterator for RotateLogLookupIter<'a> {
     }
 }
 
+impl<'a> RotateLogLookupIter<'a> {
+    /// A convenient way to get data as [`Bytes`].
+    ///
+    /// On-disk entries are not copied. See [`RotateLog::slice_to_bytes`].
+    pub fn into_bytes_vec(self) -> crate::Result<Vec<Bytes>> {
+        let log_rotate = self.log_rotate;
+        self.map(|result| result.map(|data| log_rotate.slice_to_bytes(data)))
+            .collect()
+    }
+}
+
 fn create_empty_log(
     dir: Option<&Path>,
     open_options: &OpenOptions,
@@ -848,6 +1606,189 @@ fn create_empty_log(
     })
 }
 
+/// Read [`LogStats`] of a [`Log`]. Sizes are 0 if the metadata cannot be
+/// read.
+fn log_stats(dir: &Path, id: u8) -> LogStats {
+    let path = dir.join(id.to_string());
+    let meta = log::LogMetadata::read_file(path.join(log::META_FILE)).ok();
+    LogStats {
+        primary_len: meta.as_ref().map_or(0, |m| m.primary_len),
+        total_bytes: meta.as_ref().map_or(0, |m| m.total_bytes()),
+        path,
+        id,
+    }
+}
+
+/// List numbers of archives in `archive_dir`, sorted.
+fn list_archives(archive_dir: &Path) -> crate::Result<Vec<u64>> {
+    list_numbered(archive_dir, "", ".gz")
+}
+
+/// List numbers `n` of `<prefix><n><suffix>` entries in `dir`, sorted.
+fn list_numbered(dir: &Path, prefix: &str, suffix: &str) -> crate::Result<Vec<u64>> {
+    let read_dir = match dir.read_dir() {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(dir, "cannot readdir"),
+    };
+    let mut numbers = Vec::new();
+    for entry in read_dir {
+        let entry = entry.context(dir, "cannot readdir")?;
+        let name = entry.file_name();
+        if let Some(n) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(prefix))
+            .and_then(|n| n.strip_suffix(suffix))
+        {
+            if let Ok(n) = n.parse::<u64>() {
+                numbers.push(n);
+            }
+        }
+    }
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+/// Delete a non-writable [`Log`]. Errors are not fatal.
+fn remove_log_dir(path: &Path) {
+    if remove_log_meta(path) {
+        // Delete the rest of the directory.
+        match fs::remove_dir_all(path) {
+            Ok(_) => debug!("Removed rotate log: {:?}", path),
+            Err(err) => debug!("Error removing rotate log directory: {:?}", err),
+        };
+    }
+}
+
+/// Mark a [`Log`] as deleted by deleting its metadata. Return `false` if
+/// the metadata cannot be deleted.
+fn remove_log_meta(path: &Path) -> bool {
+    // Explicitly delete the `meta` file first. This marks the log as
+    // "deleted" in an atomic way.
+    //
+    // Errors are not fatal. On Windows, this can fail if other processes
+    // have files in `path` mmap-ed. Newly opened or flushed RotateLog will
+    // unmap files. New rotation would trigger remove_dir_all to try remove
+    // old logs again.
+    match fs::remove_file(path.join(log::META_FILE)) {
+        Ok(()) => true,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            // Meta file is already deleted.
+            true
+        }
+        Err(e) => {
+            // Don't delete the log if we were unable to delete the meta file.
+            debug!("Error removing rotate log meta: {:?} {:?}", path, e);
+            false
+        }
+    }
+}
+
+/// Delete the directory of a [`Log`] marked as deleted. Retry with delays
+/// since files might be temporarily mmap-ed by others on Windows.
+fn remove_dir_with_retry(path: &Path) {
+    let mut delay = Duration::from_millis(100);
+    for _ in 0..REMOVE_RETRY_COUNT {
+        match fs::remove_dir_all(path) {
+            Ok(_) => {
+                debug!("Removed rotate log: {:?}", path);
+                return;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+            Err(err) => debug!("Error removing rotate log directory: {:?}", err),
+        }
+        thread::sleep(delay);
+        delay *= 2;
+    }
+}
+
+/// Move the expired [`Log`] `<dir>/<id>` to `<dir>/archiving-<n>`, where
+/// `<n>` is the next archive number. The caller must hold the directory lock.
+fn move_archiving_log(dir: &Path, id: u8) -> crate::Result<()> {
+    let src_path = dir.join(id.to_string());
+    let last_archived = list_archives(&dir.join(ARCHIVE_DIR))?.last().copied();
+    let last_pending = list_numbered(dir, ARCHIVING_PREFIX, "")?.last().copied();
+    let next = last_archived.max(last_pending).map_or(1, |n| n + 1);
+    let dst_path = dir.join(format!("{}{}", ARCHIVING_PREFIX, next));
+    fs::rename(&src_path, &dst_path).context(&src_path, || {
+        format!("cannot move expired log to {:?}", &dst_path)
+    })?;
+    debug!("Moved rotate log {:?} to {:?}", src_path, dst_path);
+    Ok(())
+}
+
+/// Move the deleted [`Log`] `<dir>/<id>` to an unused
+/// `<dir>/removing-<id>-<n>`. Return the new path.
+fn move_removed_log(dir: &Path, id: u8) -> crate::Result<PathBuf> {
+    let src_path = dir.join(id.to_string());
+    let dst_path = (0..)
+        .map(|n| dir.join(format!("{}{}-{}", REMOVING_PREFIX, id, n)))
+        .find(|path| !path.exists())
+        .unwrap();
+    fs::rename(&src_path, &dst_path).context(&src_path, || {
+        format!("cannot move deleted log to {:?}", &dst_path)
+    })?;
+    Ok(dst_path)
+}
+
+/// Move the pinned [`Log`] `<dir>/<id>` to an unused `<dir>/pinned-<id>-<n>`.
+fn move_pinned_log(dir: &Path, id: u8) -> crate::Result<()> {
+    let src_path = dir.join(id.to_string());
+    if !src_path.exists() {
+        return Ok(());
+    }
+    let dst_path = (0..)
+        .map(|n| dir.join(format!("pinned-{}-{}", id, n)))
+        .find(|path| !path.exists())
+        .unwrap();
+    fs::rename(&src_path, &dst_path).context(&src_path, || {
+        format!("cannot move pinned log to {:?}", &dst_path)
+    })?;
+    debug!("Moved pinned rotate log {:?} to {:?}", src_path, dst_path);
+    Ok(())
+}
+
+/// Write ids of pinned [`Log`]s. See [`RotateLog::pin`].
+fn write_pinned(dir: &Path, pinned: &BTreeSet<u8>, fsync: bool) -> crate::Result<()> {
+    let path = dir.join(PINNED_FILE);
+    if pinned.is_empty() {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(e).context(&path, "cannot remove pinned file");
+            }
+            _ => {}
+        }
+    } else {
+        let content = pinned
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        utils::atomic_write(&path, content, fsync)?;
+    }
+    Ok(())
+}
+
+/// Read ids of pinned [`Log`]s. See [`RotateLog::pin`].
+fn read_pinned(dir: &Path) -> crate::Result<BTreeSet<u8>> {
+    let path = dir.join(PINNED_FILE);
+    let data = match utils::atomic_read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeSet::new()),
+        Err(e) => return Err(e).context(&path, "cannot read pinned"),
+    };
+    let content = String::from_utf8_lossy(&data);
+    content
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<u8>().map_err(|_| {
+                crate::Error::corruption(&path, format!("failed to parse {:?} as u8 integer", s))
+            })
+        })
+        .collect()
+}
+
 fn read_latest(dir: &Path) -> crate::Result<u8> {
     read_latest_raw(dir).context(dir, "cannot read latest")
 }
@@ -948,6 +1889,8 @@ fn guess_latest(mut ids: Vec<u8>) -> u8 {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
+
     use log::IndexOutput;
     use tempfile::tempdir;
 
@@ -992,6 +1935,8 @@ mod tests {
                 "slice_to_bytes should return zero-copy"
             );
         }
+        let bytes = rotate.lookup(0, key.to_vec()).unwrap().into_bytes_vec();
+        assert_eq!(bytes.unwrap(), values);
         values
     }
 
@@ -1211,6 +2156,225 @@ mod tests {
         assert_eq!(iter(&rotate2), vec![b"a2"]);
     }
 
+    #[test]
+    fn test_lookup_threads() {
+        let dir = tempdir().unwrap();
+        let open_opts = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(1)
+            .max_log_count(6)
+            .index("first-byte", |_| vec![IndexOutput::Reference(0..1)]);
+        let mut rotate = open_opts.open(&dir).unwrap();
+        for i in 0..5 {
+            rotate.append(format!("a{}", i)).unwrap();
+            rotate.append(format!("b{}", i)).unwrap();
+            rotate.sync().unwrap();
+        }
+        rotate.append(b"a5").unwrap();
+        let expected = lookup(&rotate, b"a");
+        assert_eq!(expected, [b"a5", b"a4", b"a3", b"a2", b"a1", b"a0"]);
+
+        for threads in [2, 3, 10] {
+            let mut rotate = open_opts
+                .clone()
+                .lookup_threads(threads)
+                .open(&dir)
+                .unwrap();
+            rotate.append(b"a5").unwrap();
+            assert_eq!(lookup(&rotate, b"a"), expected);
+            assert_eq!(lookup(&rotate, b"b").len(), 5);
+            assert!(lookup(&rotate, b"c").is_empty());
+        }
+
+        // Threads are not used if the newest Logs answer the lookup.
+        let open_opts = open_opts.lookup_threads(2);
+        let rotate = open_opts.open(&dir).unwrap();
+        let mut iter = rotate.lookup(0, b"a" as &[u8]).unwrap();
+        assert_eq!(iter.next().unwrap().unwrap(), b"a4");
+        assert!(open_opts.lookup_pool.get().is_none());
+        assert_eq!(iter.next().unwrap().unwrap(), b"a3");
+        assert!(open_opts.lookup_pool.get().is_some());
+
+        // The threads are shared by RotateLogs opened by the same options.
+        let rotate = open_opts.open(&dir).unwrap();
+        assert_eq!(lookup(&rotate, b"a").len(), 5);
+        assert!(Arc::ptr_eq(
+            &open_opts.lookup_pool,
+            &rotate.open_options.lookup_pool
+        ));
+    }
+
+    #[test]
+    fn test_archive_expired() {
+        let dir = tempdir().unwrap();
+        let opts = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(1)
+            .max_log_count(2)
+            .archive_expired(true)
+            .index("first-byte", |_| vec![IndexOutput::Reference(0..1)]);
+        let mut rotate = opts.open(&dir).unwrap();
+        for i in 0..4 {
+            rotate.append(format!("a{}", i)).unwrap();
+            rotate.sync().unwrap();
+        }
+
+        // Logs with "a0", "a1", "a2" were deleted and archived.
+        assert_eq!(lookup(&rotate, b"a"), [b"a3"]);
+        let archived = |rotate: &RotateLog, key: &[u8]| -> Vec<Vec<u8>> {
+            let entries = rotate.lookup_archived(0, key).unwrap();
+            entries.iter().map(|e| e.to_vec()).collect()
+        };
+        assert_eq!(archived(&rotate, b"a"), [b"a2", b"a1", b"a0"]);
+        assert!(archived(&rotate, b"b").is_empty());
+        assert_eq!(
+            list_archives(&dir.path().join(ARCHIVE_DIR)).unwrap(),
+            [1, 2, 3]
+        );
+
+        // Expired logs are compressed and deleted before sync returns.
+        let names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|n| n.starts_with(ARCHIVING_PREFIX))
+            .collect();
+        assert!(names.is_empty(), "{:?}", names);
+
+        // Loaded archives are cached. Deleted archives are skipped.
+        assert_eq!(rotate.archives.lock().unwrap().len(), 3);
+        fs::remove_file(dir.path().join(ARCHIVE_DIR).join("1.gz")).unwrap();
+        assert_eq!(archived(&rotate, b"a"), [b"a2", b"a1"]);
+        assert_eq!(rotate.archives.lock().unwrap().len(), 2);
+
+        // Oldest archives over max_archive_count are deleted.
+        let mut rotate = opts.clone().max_archive_count(2).open(&dir).unwrap();
+        rotate.append(b"a4").unwrap();
+        rotate.sync().unwrap();
+        assert_eq!(archived(&rotate, b"a"), [b"a3", b"a2"]);
+        assert_eq!(
+            list_archives(&dir.path().join(ARCHIVE_DIR)).unwrap(),
+            [3, 4]
+        );
+
+        // Without archive_expired, logs are deleted without archiving.
+        let mut rotate = opts.archive_expired(false).open(&dir).unwrap();
+        rotate.append(b"a5").unwrap();
+        rotate.sync().unwrap();
+        assert_eq!(archived(&rotate, b"a").len(), 2);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let dir = tempdir().unwrap();
+        let opts = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(100)
+            .max_log_count(3)
+            .index("first-byte", |_| vec![IndexOutput::Reference(0..1)]);
+        let mut rotate = opts.clone().open(&dir).unwrap();
+        for i in 1..=3u8 {
+            rotate.append(vec![i; 150]).unwrap();
+            rotate.sync().unwrap();
+        }
+        drop(rotate);
+        fs::remove_file(dir.path().join("rlock")).unwrap();
+
+        fn snapshot(path: &Path, files: &mut Vec<(PathBuf, Vec<u8>)>) {
+            for entry in fs::read_dir(path).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    snapshot(&path, files);
+                } else {
+                    // Metadata might be a symlink. See `atomic_write`.
+                    let content = match fs::read_link(&path) {
+                        Ok(target) => target.to_string_lossy().into_owned().into_bytes(),
+                        Err(_) => fs::read(&path).unwrap(),
+                    };
+                    files.push((path, content));
+                }
+            }
+            files.sort();
+        }
+        let mut before = Vec::new();
+        snapshot(dir.path(), &mut before);
+
+        let mut rotate = opts.clone().read_only(true).open(&dir).unwrap();
+        assert_eq!(lookup(&rotate, b"\x02"), [&[2; 150][..]]);
+        assert_eq!(iter(&rotate).len(), 2);
+
+        // Writes are rejected.
+        assert!(rotate.append(b"x").is_err());
+        assert!(rotate.remove_old_logs().is_err());
+        assert!(rotate.pin(0).is_err());
+        assert!(rotate.force_rotate().is_err());
+        rotate.sync().unwrap();
+        let mut after = Vec::new();
+        snapshot(dir.path(), &mut after);
+        assert_eq!(after, before);
+
+        // New entries written by others can be loaded.
+        let mut rotate2 = opts.open(&dir).unwrap();
+        rotate2.append(b"\x04").unwrap();
+        rotate2.sync().unwrap();
+        rotate.sync().unwrap();
+        assert_eq!(lookup(&rotate, b"\x04"), [b"\x04"]);
+
+        // Missing RotateLogs are not created.
+        let path = dir.path().join("missing");
+        assert!(OpenOptions::new()
+            .create(true)
+            .read_only(true)
+            .open(&path)
+            .is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_logs_info() {
+        let dir = tempdir().unwrap();
+        let mut rotate = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(100)
+            .max_log_count(3)
+            .open(&dir)
+            .unwrap();
+        for i in 0..4u8 {
+            rotate.append(vec![i; 150]).unwrap();
+            rotate.sync().unwrap();
+        }
+        rotate.append(b"a").unwrap();
+        rotate.append(b"b").unwrap();
+
+        let infos = rotate.logs_info().unwrap();
+        let summary = |infos: &[LogInfo]| {
+            infos
+                .iter()
+                .map(|i| (i.index, i.id, i.entry_count))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary(&infos), [(0, 4, 2), (1, 3, 1), (2, 2, 1)]);
+        assert!(infos[0].total_bytes < 100);
+        assert!(infos[1].total_bytes > 150);
+
+        // Logs without recorded counts are counted by iterating.
+        let meta_path = dir.path().join("3").join(log::META_FILE);
+        let mut meta = log::LogMetadata::read_file(&meta_path).unwrap();
+        assert_eq!(meta.entry_count, Some(1));
+        meta.entry_count = None;
+        meta.write_file(&meta_path, false).unwrap();
+        let mut rotate = OpenOptions::new().max_log_count(3).open(&dir).unwrap();
+        rotate.append(b"a").unwrap();
+        assert_eq!(
+            summary(&rotate.logs_info().unwrap()),
+            [(0, 4, 1), (1, 3, 1), (2, 2, 1)]
+        );
+
+        let rotate = OpenOptions::new().create_in_memory().unwrap();
+        let infos = rotate.logs_info().unwrap();
+        assert_eq!(summary(&infos), [(0, 0, 0)]);
+        assert!(infos[0].created.is_none());
+    }
+
     #[test]
     fn test_lookup_truncated_meta() {
         // Look up or iteration should work with rotated logs.
@@ -1414,6 +2578,14 @@ mod tests {
             vec![&a[..], &b, &a, &a],
         );
 
+        assert_eq!(
+            rotate
+                .iter_rev()
+                .map(|e| e.unwrap())
+                .collect::<Vec<&[u8]>>(),
+            vec![&a[..], &a, &b, &a],
+        );
+
         rotate.sync().unwrap(); // trigger rotate
         assert_eq!(
             rotate.iter().map(|e| e.unwrap()).collect::<Vec<&[u8]>>(),
@@ -1474,6 +2646,187 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_max_total_bytes() {
+        let dir = tempdir().unwrap();
+        let opts = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(100)
+            .max_log_count(10)
+            .max_total_bytes(400);
+        let mut rotate = opts.clone().open(&dir).unwrap();
+        for i in 0..10u8 {
+            rotate.append(vec![i; 150]).unwrap();
+            rotate.sync().unwrap();
+        }
+
+        // Each rotated log takes more than 150 bytes. Only 2 of them fit.
+        let count_dirs = || {
+            dir.path()
+                .read_dir()
+                .unwrap()
+                .filter(|e| {
+                    let name = e.as_ref().unwrap().file_name();
+                    name.to_str().unwrap().parse::<u8>().is_ok()
+                })
+                .count()
+        };
+        assert_eq!(count_dirs(), 3);
+        let firsts = |rotate: &RotateLog| rotate.iter().map(|e| e.unwrap()[0]).collect::<Vec<u8>>();
+        assert_eq!(firsts(&rotate), [8, 9]);
+        let rotate2 = opts.clone().open(&dir).unwrap();
+        assert_eq!(firsts(&rotate2), [8, 9]);
+
+        // The writable log is kept even if it exceeds the limit.
+        let mut rotate = opts.max_total_bytes(10).open(&dir).unwrap();
+        rotate.remove_old_logs().unwrap();
+        assert_eq!(count_dirs(), 1);
+        assert!(firsts(&rotate).is_empty());
+    }
+
+    #[test]
+    fn test_pin() {
+        let dir = tempdir().unwrap();
+        let opts = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(100)
+            .max_log_count(2);
+        let mut rotate = opts.clone().open(&dir).unwrap();
+        let id = rotate.sync().unwrap();
+        rotate.pin(id).unwrap();
+        rotate.pin(id.wrapping_add(5)).unwrap();
+        assert_eq!(rotate.pinned().unwrap(), [id, id.wrapping_add(5)]);
+
+        // Pinned logs survive rotation.
+        for i in 1..5u8 {
+            rotate.append(vec![i; 150]).unwrap();
+            rotate.sync().unwrap();
+        }
+        let log_dir = dir.path().join(id.to_string());
+        let log = log::OpenOptions::new().open(&log_dir).unwrap();
+        assert_eq!(log.iter().next().unwrap().unwrap()[0], 1);
+        drop(log);
+
+        // Rotating to a pinned id unpins it.
+        rotate.append(vec![5; 150]).unwrap();
+        rotate.sync().unwrap();
+        assert_eq!(rotate.pinned().unwrap(), [id]);
+
+        // Unpinned logs are removed by the next rotation.
+        rotate.unpin(id).unwrap();
+        assert!(rotate.pinned().unwrap().is_empty());
+        let mut rotate = opts.open(&dir).unwrap();
+        rotate.append(vec![6; 150]).unwrap();
+        rotate.sync().unwrap();
+        assert!(!log_dir.exists());
+    }
+
+    #[test]
+    fn test_pin_wraparound() {
+        let dir = tempdir().unwrap();
+        let opts = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(10)
+            .max_log_count(2);
+        let mut rotate = opts.open(&dir).unwrap();
+        let id = rotate.sync().unwrap();
+        rotate.append(vec![1; 20]).unwrap();
+        rotate.pin(id).unwrap();
+
+        // Rotation continues after ids wrap around. The pinned log is kept.
+        for _ in 0..300 {
+            rotate.append(vec![2; 20]).unwrap();
+            rotate.sync().unwrap();
+        }
+        assert!(rotate.pinned().unwrap().is_empty());
+        let moved_dir = dir.path().join(format!("pinned-{}-0", id));
+        let log = log::OpenOptions::new().open(&moved_dir).unwrap();
+        assert_eq!(log.iter().next().unwrap().unwrap(), [1; 20]);
+    }
+
+    #[test]
+    fn test_remove_in_background() {
+        let dir = tempdir().unwrap();
+        let mut rotate = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(100)
+            .max_log_count(2)
+            .remove_in_background(true)
+            .open(&dir)
+            .unwrap();
+        for i in 0..5u8 {
+            rotate.append(vec![i; 150]).unwrap();
+            rotate.sync().unwrap();
+        }
+        assert_eq!(iter(&rotate), [&[4; 150][..]]);
+
+        rotate.wait_for_removals();
+        let mut names: Vec<String> = dir
+            .path()
+            .read_dir()
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|n| n.parse::<u8>().is_ok())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["4", "5"]);
+        assert!(!dir.path().join("removing-3-0").exists());
+
+        // Directories left by interrupted deletions are removed by the next
+        // rotation.
+        let leftover = dir.path().join("removing-1-0");
+        fs::create_dir(&leftover).unwrap();
+        fs::write(leftover.join("log"), b"x").unwrap();
+        rotate.append(vec![5; 150]).unwrap();
+        rotate.sync().unwrap();
+        rotate.wait_for_removals();
+        assert!(!leftover.exists());
+        assert_eq!(iter(&rotate), [&[5; 150][..]]);
+    }
+
+    #[test]
+    fn test_rotate_hooks() {
+        let dir = tempdir().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let opts = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(100)
+            .max_log_count(2)
+            .before_rotate({
+                let events = events.clone();
+                move |stats| {
+                    assert!(stats.path.is_dir());
+                    assert!(stats.primary_len > 150);
+                    assert!(stats.total_bytes >= stats.primary_len);
+                    events.lock().unwrap().push(("rotate", stats.id));
+                }
+            })
+            .before_remove({
+                let events = events.clone();
+                move |stats| {
+                    assert!(stats.primary_len > 150);
+                    assert!(stats.total_bytes >= stats.primary_len);
+                    events.lock().unwrap().push(("remove", stats.id));
+                }
+            });
+        let mut rotate = opts.open(&dir).unwrap();
+        for _ in 0..3 {
+            rotate.append(vec![b'x'; 150]).unwrap();
+            rotate.sync().unwrap();
+        }
+
+        assert_eq!(
+            events.lock().unwrap().clone(),
+            [
+                ("rotate", 0),
+                ("rotate", 1),
+                ("remove", 0),
+                ("rotate", 2),
+                ("remove", 1)
+            ]
+        );
+    }
+
     #[test]
     fn test_index_lag() {
         let dir = tempdir().unwrap();
@@ -0,0 +1,425 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Multi-process stress tests for [`Log`]. Requires the `stress` feature.
+//!
+//! [`StressOptions::run`] spawns writer, reader and repairer processes
+//! against a directory and checks:
+//! - Entries are not corrupted.
+//! - Entries from a writer are in the order they were written, without gaps.
+//! - A reader never sees fewer entries after reloading.
+//! - The index finds every entry.
+//! - [`Log::verify`] reports no problems.
+//! - Repairing the log does not drop data.
+//! - After all processes exit, the log has all entries that were written.
+//!
+//! Child processes run the current executable again, with the arguments
+//! set by [`StressOptions::child_args`] and environment variables that
+//! describe their role. The program, or the test, must call [`run_child`]
+//! before doing anything else:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use indexedlog::stress;
+//!
+//! #[test]
+//! fn test_stress() {
+//!     stress::run_child();
+//!     let dir = tempfile::tempdir().unwrap();
+//!     let report = stress::StressOptions::new()
+//!         .writers(3)
+//!         .duration(Duration::from_secs(2))
+//!         .child_args(["--exact", "test_stress"])
+//!         .run(dir.path())
+//!         .unwrap();
+//!     assert!(report.entries > 0);
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::env;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::process::Command;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::errors::IoResultExt;
+use crate::log::IndexDef;
+use crate::log::IndexOutput;
+use crate::log::Log;
+use crate::log::OpenOptions;
+use crate::Error;
+
+const ENV_ROLE: &str = "INDEXEDLOG_STRESS_ROLE";
+const ENV_DIR: &str = "INDEXEDLOG_STRESS_DIR";
+const ENV_DURATION_MS: &str = "INDEXEDLOG_STRESS_DURATION_MS";
+const ENV_BATCH_SIZE: &str = "INDEXEDLOG_STRESS_BATCH_SIZE";
+
+/// Children print this, followed by a number, when they succeed.
+const RESULT_PREFIX: &str = "INDEXEDLOG_STRESS_RESULT ";
+
+/// Size of the `(writer, seq)` header of an entry. It is also the index key.
+const HEADER_LEN: usize = 12;
+
+/// Options for a stress test.
+#[derive(Clone, Debug)]
+pub struct StressOptions {
+    writers: usize,
+    readers: usize,
+    repairers: usize,
+    duration: Duration,
+    batch_size: usize,
+    program: Option<PathBuf>,
+    child_args: Vec<String>,
+}
+
+/// Summary of a successful stress test.
+#[derive(Clone, Debug, Default)]
+pub struct StressReport {
+    /// Number of entries written by all writers.
+    pub entries: u64,
+
+    /// Number of times readers reloaded and checked the log.
+    pub reads: u64,
+
+    /// Number of times repairers repaired the log.
+    pub repairs: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Role {
+    Writer(u32),
+    Reader,
+    Repairer,
+}
+
+impl Default for StressOptions {
+    fn default() -> Self {
+        Self {
+            writers: 2,
+            readers: 2,
+            repairers: 1,
+            duration: Duration::from_secs(1),
+            batch_size: 10,
+            program: None,
+            child_args: Vec::new(),
+        }
+    }
+}
+
+impl StressOptions {
+    /// Creates the default options: 2 writers, 2 readers and 1 repairer
+    /// running for 1 second.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of writer processes.
+    pub fn writers(mut self, writers: usize) -> Self {
+        self.writers = writers;
+        self
+    }
+
+    /// Set the number of reader processes.
+    pub fn readers(mut self, readers: usize) -> Self {
+        self.readers = readers;
+        self
+    }
+
+    /// Set the number of repairer processes.
+    pub fn repairers(mut self, repairers: usize) -> Self {
+        self.repairers = repairers;
+        self
+    }
+
+    /// Set how long child processes run.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Set how many entries a writer appends before each `sync`.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Set the executable of child processes. Defaults to the current
+    /// executable.
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = Some(program.into());
+        self
+    }
+
+    /// Set the arguments of child processes.
+    ///
+    /// For a test, this is usually `["--exact", name_of_the_test]` so
+    /// the child only runs the test that calls [`run_child`].
+    pub fn child_args(mut self, args: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.child_args = args.into_iter().map(|a| a.to_string()).collect();
+        self
+    }
+
+    /// Run child processes against `dir` and wait for them.
+    ///
+    /// Return an error if a child process fails, or an invariant is broken.
+    pub fn run(&self, dir: &Path) -> crate::Result<StressReport> {
+        let program = match &self.program {
+            Some(program) => program.clone(),
+            None => env::current_exe().context(dir, "cannot locate the current executable")?,
+        };
+
+        // Create the log so readers can open it.
+        open_options().open(dir)?;
+
+        let mut roles = Vec::new();
+        roles.extend((0..self.writers as u32).map(Role::Writer));
+        roles.extend((0..self.readers).map(|_| Role::Reader));
+        roles.extend((0..self.repairers).map(|_| Role::Repairer));
+
+        let mut children = Vec::with_capacity(roles.len());
+        for role in roles {
+            let child = Command::new(&program)
+                .args(&self.child_args)
+                .env(ENV_ROLE, role.to_env())
+                .env(ENV_DIR, dir)
+                .env(ENV_DURATION_MS, self.duration.as_millis().to_string())
+                .env(ENV_BATCH_SIZE, self.batch_size.to_string())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .context(dir, || format!("cannot spawn {:?} as {:?}", &program, role))?;
+            children.push((role, child));
+        }
+
+        let mut report = StressReport::default();
+        let mut written: HashMap<u32, u64> = HashMap::new();
+        let mut errors = Vec::new();
+        for (role, child) in children {
+            let output = child
+                .wait_with_output()
+                .context(dir, || format!("cannot wait for {:?}", role))?;
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            // The test harness might print something before the result on
+            // the same line.
+            let count = stdout
+                .split(RESULT_PREFIX)
+                .nth(1)
+                .and_then(|s| s.lines().next())
+                .and_then(|s| s.trim().parse::<u64>().ok());
+            match (output.status.success(), count) {
+                (true, Some(count)) => match role {
+                    Role::Writer(id) => {
+                        written.insert(id, count);
+                        report.entries += count;
+                    }
+                    Role::Reader => report.reads += count,
+                    Role::Repairer => report.repairs += count,
+                },
+                _ => errors.push(format!(
+                    "{:?} failed ({}):\n{}",
+                    role,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim_end()
+                )),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(Error::at_path(dir, errors.join("\n")));
+        }
+
+        // All writers have exited. Every entry they wrote should be there.
+        let log = open_options().open(dir)?;
+        let seen = check_log(&log, 0).map_err(|e| Error::at_path(dir, e))?;
+        for (&id, &count) in &written {
+            let found = seen.get(&id).copied().unwrap_or(0);
+            if found != count {
+                let msg = format!("writer {} wrote {} entries, found {}", id, count, found);
+                return Err(Error::at_path(dir, msg));
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Run the role of a child process spawned by [`StressOptions::run`], then
+/// exit the process.
+///
+/// Do nothing if the current process is not such a child.
+pub fn run_child() {
+    let role = match env::var(ENV_ROLE).ok().and_then(|r| Role::from_env(&r)) {
+        Some(role) => role,
+        None => return,
+    };
+    let dir = PathBuf::from(env::var_os(ENV_DIR).unwrap_or_default());
+    let duration = env_u64(ENV_DURATION_MS).map(Duration::from_millis);
+    let batch_size = env_u64(ENV_BATCH_SIZE).unwrap_or(1).max(1) as usize;
+    let deadline = Instant::now() + duration.unwrap_or_default();
+
+    let result = match role {
+        Role::Writer(id) => run_writer(&dir, id, deadline, batch_size),
+        Role::Reader => run_reader(&dir, deadline),
+        Role::Repairer => run_repairer(&dir, deadline),
+    };
+
+    // Write to the stdio handles directly. The test harness captures
+    // `println!` and would drop the output on `exit`.
+    match result {
+        Ok(count) => {
+            let _ = writeln!(std::io::stdout(), "{}{}", RESULT_PREFIX, count);
+            process::exit(0);
+        }
+        Err(message) => {
+            let _ = writeln!(std::io::stderr(), "{}", message);
+            process::exit(1);
+        }
+    }
+}
+
+impl Role {
+    fn to_env(self) -> String {
+        match self {
+            Role::Writer(id) => format!("writer:{}", id),
+            Role::Reader => "reader".to_string(),
+            Role::Repairer => "repairer".to_string(),
+        }
+    }
+
+    fn from_env(s: &str) -> Option<Self> {
+        match s {
+            "reader" => Some(Role::Reader),
+            "repairer" => Some(Role::Repairer),
+            _ => Some(Role::Writer(s.strip_prefix("writer:")?.parse().ok()?)),
+        }
+    }
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    env::var(name).ok()?.parse().ok()
+}
+
+fn open_options() -> OpenOptions {
+    OpenOptions::new()
+        .create(true)
+        .index_defs(vec![IndexDef::new("key", |_| {
+            vec![IndexOutput::Reference(0..HEADER_LEN as u64)]
+        })])
+}
+
+/// An entry is `writer` (u32 BE), `seq` (u64 BE), then a payload derived
+/// from them.
+fn make_entry(writer: u32, seq: u64) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(HEADER_LEN + 64);
+    entry.extend_from_slice(&writer.to_be_bytes());
+    entry.extend_from_slice(&seq.to_be_bytes());
+    let len = (seq % 64) as usize;
+    entry.extend((0..len).map(|i| (writer as u64 ^ seq).wrapping_add(i as u64) as u8));
+    entry
+}
+
+/// Parse `(writer, seq)` from an entry and check its payload.
+fn parse_entry(entry: &[u8]) -> Result<(u32, u64), String> {
+    if entry.len() < HEADER_LEN {
+        return Err(format!("entry {:?} is too short", entry));
+    }
+    let writer = u32::from_be_bytes(entry[0..4].try_into().unwrap());
+    let seq = u64::from_be_bytes(entry[4..12].try_into().unwrap());
+    if entry != make_entry(writer, seq) {
+        return Err(format!("entry {:?} is corrupted", entry));
+    }
+    Ok((writer, seq))
+}
+
+/// Check entries starting from the `index_checked`-th are found by the index,
+/// and entries of each writer are in order.
+///
+/// Return the number of entries of each writer.
+fn check_log(log: &Log, index_checked: usize) -> Result<HashMap<u32, u64>, String> {
+    let mut next_seq: HashMap<u32, u64> = HashMap::new();
+    for (i, entry) in log.iter().enumerate() {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let (writer, seq) = parse_entry(entry)?;
+        let expected = next_seq.entry(writer).or_default();
+        if seq != *expected {
+            return Err(format!(
+                "writer {} entry {} is out of order (expected {})",
+                writer, seq, expected
+            ));
+        }
+        *expected += 1;
+        if i >= index_checked {
+            let mut found = log
+                .lookup(0, &entry[..HEADER_LEN])
+                .map_err(|e| e.to_string())?;
+            if found.next().is_none() {
+                return Err(format!("index cannot find writer {} entry {}", writer, seq));
+            }
+        }
+    }
+    Ok(next_seq)
+}
+
+fn run_writer(dir: &Path, id: u32, deadline: Instant, batch_size: usize) -> Result<u64, String> {
+    let mut log = open_options().open(dir).map_err(|e| e.to_string())?;
+    let mut seq = 0;
+    while Instant::now() < deadline {
+        for _ in 0..batch_size {
+            log.append(make_entry(id, seq)).map_err(|e| e.to_string())?;
+            seq += 1;
+        }
+        log.sync().map_err(|e| e.to_string())?;
+    }
+    Ok(seq)
+}
+
+fn run_reader(dir: &Path, deadline: Instant) -> Result<u64, String> {
+    let mut log = open_options().open(dir).map_err(|e| e.to_string())?;
+    let mut reads = 0;
+    let mut last_len = 0;
+    while Instant::now() < deadline {
+        // Alternate between reloading and opening from scratch.
+        if reads % 2 == 0 {
+            log.sync().map_err(|e| e.to_string())?;
+        } else {
+            log = open_options().open(dir).map_err(|e| e.to_string())?;
+        }
+        let seen = check_log(&log, last_len)?;
+        let len = seen.values().sum::<u64>() as usize;
+        if len < last_len {
+            return Err(format!("entry count went from {} to {}", last_len, len));
+        }
+        let problems = log.verify().map_err(|e| e.to_string())?;
+        if !problems.is_empty() {
+            return Err(format!("verify reported {:?}", problems));
+        }
+        last_len = len;
+        reads += 1;
+    }
+    Ok(reads)
+}
+
+fn run_repairer(dir: &Path, deadline: Instant) -> Result<u64, String> {
+    let mut repairs = 0;
+    while Instant::now() < deadline {
+        let report = open_options()
+            .repair_with_report(dir)
+            .map_err(|e| e.to_string())?;
+        if report.dropped_bytes > 0 {
+            return Err(format!("repair dropped data:\n{}", report.message));
+        }
+        repairs += 1;
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(repairs)
+}
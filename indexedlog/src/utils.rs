@@ -14,12 +14,16 @@ use std::io::Read;
 use std::io::Write;
 use std::path::Path;
 use std::sync::atomic;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
+#[cfg(feature = "std-fs")]
 use memmap::MmapOptions;
 use minibytes::Bytes;
 use twox_hash::XxHash;
 use twox_hash::XxHash32;
 
+use crate::codec::Codec;
 use crate::config;
 use crate::errors::IoResultExt;
 use crate::errors::ResultExt;
@@ -27,6 +31,9 @@ use crate::errors::ResultExt;
 /// Return a read-only view of the entire file.
 ///
 /// If `len` is `None`, detect the file length automatically.
+///
+/// Without the `std-fs` feature, the file is read into memory instead of
+/// being memory-mapped.
 pub fn mmap_bytes(file: &File, len: Option<u64>) -> io::Result<Bytes> {
     let actual_len = file.metadata()?.len();
     let len = match len {
@@ -48,9 +55,22 @@ pub fn mmap_bytes(file: &File, len: Option<u64>) -> io::Result<Bytes> {
     if len == 0 {
         Ok(Bytes::new())
     } else {
-        Ok(Bytes::from(unsafe {
-            MmapOptions::new().len(len as usize).map(&file)
-        }?))
+        #[cfg(feature = "std-fs")]
+        {
+            Ok(Bytes::from(unsafe {
+                MmapOptions::new().len(len as usize).map(file)
+            }?))
+        }
+        #[cfg(not(feature = "std-fs"))]
+        {
+            use std::io::Read;
+            use std::io::Seek;
+            let mut file = file;
+            let mut buf = vec![0; len as usize];
+            file.seek(io::SeekFrom::Start(0))?;
+            file.read_exact(&mut buf)?;
+            Ok(Bytes::from(buf))
+        }
     }
 }
 
@@ -91,6 +111,23 @@ pub fn mmap_path(path: &Path, len: u64) -> crate::Result<Bytes> {
     }
 }
 
+/// Decode `bytes` read from a file using `codec`, skipping the first `skip`
+/// bytes. The decoded content is kept in memory.
+///
+/// Return `bytes` as-is if `codec` is `None`.
+pub(crate) fn decode_bytes(bytes: Bytes, codec: Option<&dyn Codec>, skip: usize) -> Bytes {
+    match codec {
+        None => bytes,
+        Some(codec) => {
+            let mut buf = bytes.to_vec();
+            if buf.len() > skip {
+                codec.decode(skip as u64, &mut buf[skip..]);
+            }
+            Bytes::from(buf)
+        }
+    }
+}
+
 /// Open a path. Usually for locking purpose.
 ///
 /// The path is assumed to be a directory. But this function does not do extra
@@ -127,6 +164,79 @@ pub fn xxhash32<T: AsRef<[u8]>>(buf: T) -> u32 {
     xx.finish() as u32
 }
 
+#[inline]
+pub fn xxh3<T: AsRef<[u8]>>(buf: T) -> u64 {
+    twox_hash::xxh3::hash64(buf.as_ref())
+}
+
+/// Milliseconds since UNIX epoch.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// CRC-32C (Castagnoli). Use the SSE 4.2 instruction if available.
+pub fn crc32c<T: AsRef<[u8]>>(buf: T) -> u32 {
+    let buf = buf.as_ref();
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("sse4.2") {
+            // Safety: the CPU supports SSE 4.2.
+            return unsafe { crc32c_sse42(buf) };
+        }
+    }
+    crc32c_table(buf)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_sse42(buf: &[u8]) -> u32 {
+    use std::arch::x86_64::_mm_crc32_u64;
+    use std::arch::x86_64::_mm_crc32_u8;
+
+    let mut crc = !0u64;
+    let mut chunks = buf.chunks_exact(8);
+    for chunk in &mut chunks {
+        let value = u64::from_le_bytes(chunk.try_into().unwrap());
+        crc = _mm_crc32_u64(crc, value);
+    }
+    let mut crc = crc as u32;
+    for &byte in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, byte);
+    }
+    !crc
+}
+
+fn crc32c_table(buf: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut j = 0;
+            while j < 8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0x82f63b78
+                } else {
+                    crc >> 1
+                };
+                j += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    let mut crc = !0u32;
+    for &byte in buf {
+        crc = TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
 /// Atomically create or replace a file with the given content.
 /// Attempt to use symlinks on unix if `SYMLINK_ATOMIC_WRITE` is set.
 pub fn atomic_write(
@@ -299,6 +409,22 @@ pub(crate) fn mkdir_p(dir: impl AsRef<Path>) -> crate::Result<()> {
     })()
 }
 
+/// Make sure `file` has at least `len` bytes allocated, by growing it to a
+/// multiple of `chunk_size`. New space is filled with zeros.
+///
+/// This reduces fragmentation when appending small pieces of data.
+pub(crate) fn preallocate(file: &File, len: u64, chunk_size: u64) -> io::Result<()> {
+    use crate::fs_ext::FileExt;
+    if chunk_size == 0 {
+        return Ok(());
+    }
+    let len = len.div_ceil(chunk_size) * chunk_size;
+    if file.metadata()?.len() >= len {
+        return Ok(());
+    }
+    file.allocate(len)
+}
+
 /// Attempt to chmod a path.
 pub(crate) fn fix_perm_path(path: &Path, is_dir: bool) -> io::Result<()> {
     #[cfg(unix)]
@@ -388,6 +514,19 @@ pub(crate) fn rand_u64() -> u64 {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_crc32c() {
+        for (data, expected) in [
+            (&b""[..], 0),
+            (b"a", 0xc1d04330),
+            (b"123456789", 0xe3069283),
+            (&[0u8; 32], 0x8a9136aa),
+        ] {
+            assert_eq!(crc32c(data), expected);
+            assert_eq!(crc32c_table(data), expected);
+        }
+    }
+
     fn check_atomic_read_write(data: &[u8]) {
         config::SYMLINK_ATOMIC_WRITE.store(true, atomic::Ordering::SeqCst);
         let dir = tempfile::tempdir().unwrap();
@@ -0,0 +1,73 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+// Test that synced data survives crashes in write paths.
+
+#![cfg(feature = "failpoints")]
+
+use std::panic;
+use std::panic::AssertUnwindSafe;
+
+use indexedlog::failpoint;
+use indexedlog::failpoint::FailAction;
+use indexedlog::log::IndexDef;
+use indexedlog::log::IndexOutput;
+use indexedlog::log::OpenOptions;
+use tempfile::tempdir;
+
+fn open_options() -> OpenOptions {
+    OpenOptions::new()
+        .create(true)
+        .auto_repair(true)
+        .index_defs(vec![IndexDef::new("key", |_| {
+            vec![IndexOutput::Reference(0..2)]
+        })
+        .lag_threshold(0)])
+}
+
+#[test]
+fn test_crash_in_sync() {
+    for &name in failpoint::FAILPOINTS {
+        for action in [FailAction::Error, FailAction::Panic] {
+            let dir = tempdir().unwrap();
+            let path = dir.path();
+
+            let mut log = open_options().open(path).unwrap();
+            log.append(b"a1").unwrap();
+            log.sync().unwrap();
+
+            // "Crash" while syncing the second entry.
+            failpoint::enable(name, action);
+            let result = panic::catch_unwind(AssertUnwindSafe(move || {
+                log.append(b"b2").unwrap();
+                log.sync().map(|_| ())
+            }));
+            failpoint::disable_all();
+            match action {
+                FailAction::Error => assert!(result.unwrap().is_err(), "{}", name),
+                FailAction::Panic => assert!(result.is_err(), "{}", name),
+            }
+
+            // The first entry survives. The second entry is either complete
+            // or missing.
+            let log = open_options().open(path).unwrap();
+            let entries: Vec<&[u8]> = log.iter().map(|e| e.unwrap()).collect();
+            match name {
+                "log::sync::after_meta_write" => assert_eq!(entries, [b"a1", b"b2"]),
+                _ => assert_eq!(entries, [b"a1"], "{}", name),
+            }
+            assert_eq!(log.lookup(0, b"a1").unwrap().count(), 1);
+            assert!(log.verify().is_ok(), "{}", name);
+
+            // The log is still writable.
+            let mut log = log;
+            log.append(b"c3").unwrap();
+            log.sync().unwrap();
+            assert_eq!(log.lookup(0, b"c3").unwrap().count(), 1);
+        }
+    }
+}
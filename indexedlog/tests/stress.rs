@@ -0,0 +1,35 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+// Run readers, writers and repairers in separate processes.
+
+#![cfg(feature = "stress")]
+
+use std::time::Duration;
+
+use indexedlog::stress;
+use indexedlog::stress::StressOptions;
+use tempfile::tempdir;
+
+#[test]
+fn test_stress() {
+    stress::run_child();
+
+    let dir = tempdir().unwrap();
+    let report = StressOptions::new()
+        .writers(3)
+        .readers(2)
+        .repairers(1)
+        .batch_size(5)
+        .duration(Duration::from_millis(500))
+        .child_args(["--exact", "test_stress"])
+        .run(dir.path())
+        .unwrap();
+    assert!(report.entries > 0);
+    assert!(report.reads > 0);
+    assert!(report.repairs > 0);
+}
@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Hints about the access pattern of memory-mapped [`Bytes`].
+
+use crate::Bytes;
+
+/// Expected access pattern of memory-mapped [`Bytes`]. Used as a hint to
+/// the operating system (`madvise`) to tune readahead.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MmapAdvice {
+    /// No special treatment. Use the default readahead.
+    Normal,
+
+    /// Expect reads in order. Read ahead aggressively, and free pages soon
+    /// after they are read.
+    Sequential,
+
+    /// Expect reads in random order. Disable readahead.
+    Random,
+}
+
+impl Bytes {
+    /// Passes `advice` about the slice to `madvise`, if it is backed by a
+    /// memory-mapped file. `None` means the pages are no longer needed
+    /// (`DONTNEED`). They are read from the file again on access.
+    ///
+    /// This is best-effort. Errors are ignored. Does nothing on non-Unix
+    /// platforms, or if the slice is not memory-mapped.
+    pub fn advise(&self, advice: Option<MmapAdvice>) {
+        // `DONTNEED` discards the content of anonymous memory. Only apply
+        // advice to read-only file mappings.
+        #[cfg(all(unix, feature = "frommmap"))]
+        if self.downcast_ref::<memmap::Mmap>().is_some() {
+            match advice {
+                Some(advice) => madvise(self, advice.to_libc()),
+                None => madvise(self, libc::MADV_DONTNEED),
+            }
+        }
+        #[cfg(not(all(unix, feature = "frommmap")))]
+        let _ = advice;
+    }
+}
+
+#[cfg(all(unix, feature = "frommmap"))]
+impl MmapAdvice {
+    pub(crate) fn to_libc(self) -> libc::c_int {
+        match self {
+            MmapAdvice::Normal => libc::MADV_NORMAL,
+            MmapAdvice::Sequential => libc::MADV_SEQUENTIAL,
+            MmapAdvice::Random => libc::MADV_RANDOM,
+        }
+    }
+}
+
+/// Calls `madvise` on pages covering `buf`. Errors are ignored.
+#[cfg(all(unix, feature = "frommmap"))]
+pub(crate) fn madvise(buf: &[u8], advice: libc::c_int) {
+    if buf.is_empty() {
+        return;
+    }
+    // The address must be page-aligned. Pages partially covered by `buf`
+    // are affected too.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let start = buf.as_ptr() as usize;
+    let aligned_start = start & !(page_size - 1);
+    unsafe {
+        libc::madvise(
+            aligned_start as *mut libc::c_void,
+            start + buf.len() - aligned_start,
+            advice,
+        )
+    };
+}
@@ -9,8 +9,10 @@ use std::any::Any;
 use std::ops::Range;
 use std::ops::RangeBounds;
 use std::sync::Arc;
+use std::sync::Weak;
 
 pub type Bytes = AbstractBytes<[u8]>;
+pub type WeakBytes = AbstractWeakBytes<[u8]>;
 pub trait BytesOwner: AsRef<[u8]> + Send + Sync + 'static {}
 
 /// Immutable bytes with zero-copy slicing and cloning.
@@ -22,20 +24,40 @@ pub struct AbstractBytes<T: ?Sized> {
     pub(crate) owner: Option<Arc<dyn AbstractOwner<T>>>,
 }
 
+/// Weak reference to the owner of [`AbstractBytes`]. Does not keep the
+/// owner alive. Created by [`AbstractBytes::downgrade`].
+pub struct AbstractWeakBytes<T: ?Sized> {
+    ptr: *const u8,
+    len: usize,
+
+    // None for static buffers, which are always alive.
+    owner: Option<Weak<dyn AbstractOwner<T>>>,
+}
+
 /// The actual storage owning the bytes.
 pub trait AbstractOwner<T: ?Sized>: AsRef<T> + Send + Sync + 'static {
+    fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any_arc(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
 }
 
 impl<T: BytesOwner> AbstractOwner<[u8]> for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+    fn into_any_arc(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
 }
 
 // AbstractOwner<T> is Send + Sync and AbstractBytes<T> is immutable.
 unsafe impl<T: ?Sized> Send for AbstractBytes<T> {}
 unsafe impl<T: ?Sized> Sync for AbstractBytes<T> {}
+unsafe impl<T: ?Sized> Send for AbstractWeakBytes<T> {}
+unsafe impl<T: ?Sized> Sync for AbstractWeakBytes<T> {}
 
 // #[derive(Clone)] does not work well with type parameters.
 // Therefore implement Clone manually.
@@ -49,6 +71,16 @@ impl<T: ?Sized> Clone for AbstractBytes<T> {
     }
 }
 
+impl<T: ?Sized> Clone for AbstractWeakBytes<T> {
+    fn clone(&self) -> Self {
+        Self {
+            ptr: self.ptr,
+            len: self.len,
+            owner: self.owner.clone(),
+        }
+    }
+}
+
 // Core implementation of Bytes.
 impl<T> AbstractBytes<T>
 where
@@ -89,12 +121,23 @@ where
     /// This is similar to `bytes::Bytes::slice_ref` from `bytes 0.5.4`,
     /// but does not panic.
     pub fn slice_to_bytes(&self, slice: &T) -> Self {
-        match self.range_of_slice(slice) {
-            Some(range) => self.slice(range),
+        match self.slice_of(slice) {
+            Some(bytes) => bytes,
             None => Self::copy_from_slice(slice),
         }
     }
 
+    /// Convert `slice` to a zero-copy slice of this `Bytes`.
+    ///
+    /// Returns `None` if `slice` is outside the memory range of this
+    /// `Bytes`. Unlike [`AbstractBytes::slice_to_bytes`], this never
+    /// copies.
+    ///
+    /// This operation is `O(1)`.
+    pub fn slice_of(&self, slice: &T) -> Option<Self> {
+        self.range_of_slice(slice).map(|range| self.slice(range))
+    }
+
     /// Return a range `x` so that `self[x]` matches `slice` exactly
     /// (not only content, but also internal pointer addresses).
     ///
@@ -137,6 +180,14 @@ where
         }
     }
 
+    /// Creates `Bytes` from a part of a [`BytesOwner`], without copying.
+    /// Same as `from_owner(value).slice(range)`.
+    ///
+    /// This is useful for custom owners like buffers from a memory pool.
+    pub fn from_owner_range(value: impl AbstractOwner<T>, range: impl RangeBounds<usize>) -> Self {
+        Self::from_owner(value).slice(range)
+    }
+
     /// Creates `Bytes` instance from slice, by copying it.
     pub fn copy_from_slice(data: &T) -> Self {
         Self::from_owner(data.to_owned())
@@ -163,6 +214,75 @@ where
         let any = owner.as_any_mut();
         any.downcast_mut()
     }
+
+    /// Attempt to downcast the owner to a shared reference.
+    ///
+    /// Returns None if the type mismatches, or `self` does not have an
+    /// owner (ex. created by `from_static`).
+    pub fn downcast_ref<A: Any>(&self) -> Option<&A> {
+        self.owner.as_ref()?.as_any().downcast_ref()
+    }
+
+    /// Creates a weak reference that does not keep the owner alive.
+    ///
+    /// This is useful for caches to detect whether the owner (ex. an
+    /// mmap-ed file) is still used by other `Bytes`.
+    pub fn downgrade(&self) -> AbstractWeakBytes<T> {
+        AbstractWeakBytes {
+            ptr: self.ptr,
+            len: self.len,
+            owner: self.owner.as_ref().map(Arc::downgrade),
+        }
+    }
+
+    /// Number of `Bytes` (including clones and slices) sharing the owner.
+    ///
+    /// Returns None if `self` does not have an owner (ex. created by
+    /// `from_static`).
+    pub fn strong_count(&self) -> Option<usize> {
+        self.owner.as_ref().map(Arc::strong_count)
+    }
+
+    /// Attempt to take the owner out, without copying.
+    ///
+    /// The owner is returned as-is, even if `self` is only a slice of it.
+    /// Returns `self` back if the type mismatches, `self` does not have an
+    /// owner, or the owner is shared with other `Bytes` or `WeakBytes`.
+    pub fn try_into_owner<A: Any + Send + Sync>(mut self) -> Result<A, Self> {
+        // `get_mut` checks there are no other strong or weak references in
+        // one step. So the owner cannot be shared by `upgrade` afterwards.
+        match self.owner.as_mut().and_then(Arc::get_mut) {
+            Some(owner) if owner.as_any().is::<A>() => {}
+            _ => return Err(self),
+        }
+        let owner = self.owner.unwrap().into_any_arc().downcast::<A>();
+        match owner.ok().and_then(|owner| Arc::try_unwrap(owner).ok()) {
+            Some(owner) => Ok(owner),
+            None => unreachable!("owner type and reference count were checked"),
+        }
+    }
+}
+
+impl<T: ?Sized> AbstractWeakBytes<T> {
+    /// Attempt to get the `Bytes` back. Returns None if the owner was
+    /// dropped.
+    pub fn upgrade(&self) -> Option<AbstractBytes<T>> {
+        let owner = match &self.owner {
+            Some(owner) => Some(owner.upgrade()?),
+            None => None,
+        };
+        Some(AbstractBytes {
+            ptr: self.ptr,
+            len: self.len,
+            owner,
+        })
+    }
+
+    /// Number of `Bytes` sharing the owner. Returns 0 if the owner was
+    /// dropped. Returns None for static buffers.
+    pub fn strong_count(&self) -> Option<usize> {
+        self.owner.as_ref().map(Weak::strong_count)
+    }
 }
 
 impl Bytes {
@@ -180,6 +300,20 @@ impl Bytes {
         }
     }
 
+    /// Compare with `other` in time that only depends on the lengths, not
+    /// the content. Useful for comparing secrets like tokens.
+    pub fn ct_eq(&self, other: &[u8]) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        let diff = self
+            .as_slice()
+            .iter()
+            .zip(other)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b));
+        std::hint::black_box(diff) == 0
+    }
+
     /// Convert to `Vec<u8>`, in a zero-copy way if possible.
     pub fn into_vec(mut self) -> Vec<u8> {
         let len = self.len();
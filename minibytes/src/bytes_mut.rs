@@ -0,0 +1,170 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::ascii::escape_default;
+use std::fmt;
+use std::io;
+use std::ops;
+
+use crate::Bytes;
+
+/// A growable buffer that can be converted to [`Bytes`] without copying.
+///
+/// Build the content using [`BytesMut::extend_from_slice`], `io::Write`,
+/// or as a mutable slice, then call [`BytesMut::freeze`]. The buffer
+/// becomes the owner of the [`Bytes`] as-is. It is not shrunk or copied.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct BytesMut {
+    buf: Vec<u8>,
+}
+
+impl BytesMut {
+    /// Creates an empty `BytesMut`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty `BytesMut` with at least the given capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Number of bytes in the buffer.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Test if the buffer is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Number of bytes the buffer can hold without reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Reserves capacity for at least `additional` more bytes.
+    pub fn reserve(&mut self, additional: usize) {
+        self.buf.reserve(additional)
+    }
+
+    /// Appends bytes to the buffer.
+    pub fn extend_from_slice(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data)
+    }
+
+    /// Shortens the buffer to `len` bytes. Does nothing if the buffer is
+    /// not longer than `len`.
+    pub fn truncate(&mut self, len: usize) {
+        self.buf.truncate(len)
+    }
+
+    /// Removes all bytes. Keeps the capacity.
+    pub fn clear(&mut self) {
+        self.buf.clear()
+    }
+
+    /// Converts to immutable [`Bytes`]. This operation is `O(1)`.
+    pub fn freeze(self) -> Bytes {
+        Bytes::from(self.buf)
+    }
+
+    /// Converts to `Vec<u8>`. This operation is `O(1)`.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+impl From<Vec<u8>> for BytesMut {
+    fn from(buf: Vec<u8>) -> Self {
+        Self { buf }
+    }
+}
+
+impl From<&[u8]> for BytesMut {
+    fn from(data: &[u8]) -> Self {
+        Self { buf: data.to_vec() }
+    }
+}
+
+impl From<BytesMut> for Bytes {
+    fn from(value: BytesMut) -> Self {
+        value.freeze()
+    }
+}
+
+impl ops::Deref for BytesMut {
+    type Target = [u8];
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl ops::DerefMut for BytesMut {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl AsRef<[u8]> for BytesMut {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl AsMut<[u8]> for BytesMut {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl Extend<u8> for BytesMut {
+    fn extend<I: IntoIterator<Item = u8>>(&mut self, iter: I) {
+        self.buf.extend(iter)
+    }
+}
+
+impl<'a> Extend<&'a u8> for BytesMut {
+    fn extend<I: IntoIterator<Item = &'a u8>>(&mut self, iter: I) {
+        self.buf.extend(iter)
+    }
+}
+
+impl io::Write for BytesMut {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.buf.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BytesMut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("b\"")?;
+        for &byte in &self.buf {
+            fmt::Display::fmt(&escape_default(byte), f)?;
+        }
+        f.write_str("\"")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::ops;
+
+use crate::Bytes;
+
+/// [`Bytes`] with a precomputed hash.
+///
+/// Useful as keys of hash maps when the same large [`Bytes`] is hashed
+/// repeatedly. [`Hash`] only writes the precomputed `u64`, so its output
+/// differs from hashing the content. Because of this, `HashedBytes` does
+/// not implement `Borrow<[u8]>`.
+///
+/// The precomputed hash uses fixed keys. Do not use it for untrusted keys
+/// where hash flooding is a concern.
+#[derive(Clone)]
+pub struct HashedBytes {
+    bytes: Bytes,
+    hash: u64,
+}
+
+impl HashedBytes {
+    /// Hashes `bytes` and remembers the result.
+    pub fn new(bytes: Bytes) -> Self {
+        let mut hasher = DefaultHasher::new();
+        bytes.as_ref().hash(&mut hasher);
+        let hash = hasher.finish();
+        Self { bytes, hash }
+    }
+
+    /// The precomputed hash.
+    #[inline]
+    pub fn hash_value(&self) -> u64 {
+        self.hash
+    }
+
+    /// The underlying [`Bytes`].
+    #[inline]
+    pub fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+
+    /// Converts to the underlying [`Bytes`].
+    pub fn into_bytes(self) -> Bytes {
+        self.bytes
+    }
+}
+
+impl From<Bytes> for HashedBytes {
+    fn from(bytes: Bytes) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl ops::Deref for HashedBytes {
+    type Target = [u8];
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.bytes
+    }
+}
+
+impl AsRef<[u8]> for HashedBytes {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Hash for HashedBytes {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl PartialEq for HashedBytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash && self.bytes == other.bytes
+    }
+}
+
+impl Eq for HashedBytes {}
+
+impl fmt::Debug for HashedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.bytes, f)
+    }
+}
@@ -121,6 +121,21 @@ impl AsRef<str> for Text {
     }
 }
 
+impl AsRef<[u8]> for Text {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl TryFrom<Bytes> for Text {
+    type Error = std::str::Utf8Error;
+
+    fn try_from(value: Bytes) -> Result<Self, Self::Error> {
+        Self::from_utf8(value)
+    }
+}
+
 impl ops::Deref for Text {
     type Target = str;
     #[inline]
@@ -178,3 +193,39 @@ impl fmt::Display for Text {
         fmt::Display::fmt(self.as_slice(), f)
     }
 }
+
+#[cfg(feature = "frombytes")]
+impl From<Bytes> for bytes::Bytes {
+    /// Convert to `bytes::Bytes` without copying.
+    fn from(value: Bytes) -> Self {
+        if let Some(owner) = value.downcast_ref::<bytes::Bytes>() {
+            // Slice the original `bytes::Bytes` to avoid nested owners.
+            let start = value.as_ptr() as usize - owner.as_ptr() as usize;
+            return owner.slice(start..start + value.len());
+        }
+        Self::from_owner(value)
+    }
+}
+
+#[cfg(feature = "frombytes")]
+impl bytes::Buf for Bytes {
+    #[inline]
+    fn remaining(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn chunk(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        *self = self.slice(cnt..);
+    }
+
+    fn copy_to_bytes(&mut self, len: usize) -> bytes::Bytes {
+        let result = self.slice(..len);
+        self.advance(len);
+        result.into()
+    }
+}
@@ -14,10 +14,19 @@
 //!
 //! Aside from supporting `Vec<u8>` as the underlying storage, [`Bytes`] also
 //! supports [`memmap::Mmap`]. Libraries can implement [`BytesOwner`] for other
-//! types to further extend storage support.
+//! types to further extend storage support. [`MmapOptions`] maps files with
+//! hints about page fault behavior.
+//!
+//! [`BytesMut`] is a growable buffer that can be converted to [`Bytes`]
+//! without copying.
 
+mod advice;
 mod bytes;
+mod bytes_mut;
+mod hashed;
 mod impls;
+#[cfg(feature = "frommmap")]
+mod mmap;
 mod owners;
 mod serde;
 mod text;
@@ -25,8 +34,16 @@ mod text;
 #[cfg(test)]
 mod tests;
 
+pub use advice::MmapAdvice;
+#[cfg(feature = "frommmap")]
+pub use mmap::MmapOptions;
+pub use serde::with_backing_bytes;
 pub use text::Text;
 pub use text::TextOwner;
+pub use text::WeakText;
 
 pub use crate::bytes::Bytes;
 pub use crate::bytes::BytesOwner;
+pub use crate::bytes::WeakBytes;
+pub use crate::bytes_mut::BytesMut;
+pub use crate::hashed::HashedBytes;
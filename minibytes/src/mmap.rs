@@ -0,0 +1,125 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Create [`Bytes`] by memory-mapping files with tunable page fault
+//! behavior.
+
+use std::fs::File;
+use std::io;
+
+#[cfg(unix)]
+use crate::advice::madvise;
+use crate::Bytes;
+use crate::MmapAdvice;
+
+/// Options to memory-map a file as [`Bytes`].
+///
+/// Hints are best-effort. They are ignored on platforms that do not
+/// support them.
+#[derive(Clone, Debug, Default)]
+pub struct MmapOptions {
+    offset: u64,
+    len: Option<usize>,
+    populate: bool,
+    huge_pages: bool,
+    advice: Option<MmapAdvice>,
+}
+
+impl MmapOptions {
+    /// Creates options to map the whole file without hints.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the offset in the file to start mapping from. Must be a
+    /// multiple of the page size.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Sets the number of bytes to map. Defaults to the rest of the file.
+    pub fn len(mut self, len: usize) -> Self {
+        self.len = Some(len);
+        self
+    }
+
+    /// Sets whether to load all pages when mapping (prefault).
+    ///
+    /// This avoids page faults on later reads, at the cost of reading the
+    /// whole range upfront.
+    pub fn populate(mut self, populate: bool) -> Self {
+        self.populate = populate;
+        self
+    }
+
+    /// Sets whether to prefer transparent huge pages. Only affects Linux.
+    pub fn huge_pages(mut self, huge_pages: bool) -> Self {
+        self.huge_pages = huge_pages;
+        self
+    }
+
+    /// Sets the expected access pattern.
+    pub fn advice(mut self, advice: MmapAdvice) -> Self {
+        self.advice = Some(advice);
+        self
+    }
+
+    /// Maps `file` as read-only [`Bytes`].
+    ///
+    /// The file should not be modified while the [`Bytes`] or its slices
+    /// are alive.
+    pub fn map(&self, file: &File) -> io::Result<Bytes> {
+        let len = match self.len {
+            Some(len) => len,
+            None => file.metadata()?.len().saturating_sub(self.offset) as usize,
+        };
+        if len == 0 {
+            // mmap does not support empty ranges.
+            return Ok(Bytes::new());
+        }
+        let mmap = unsafe {
+            memmap::MmapOptions::new()
+                .offset(self.offset)
+                .len(len)
+                .map(file)
+        }?;
+        self.advise(&mmap);
+        if self.populate {
+            prefault(&mmap);
+        }
+        Ok(Bytes::from(mmap))
+    }
+
+    fn advise(&self, buf: &[u8]) {
+        #[cfg(unix)]
+        {
+            if let Some(advice) = self.advice {
+                madvise(buf, advice.to_libc());
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            if self.huge_pages {
+                madvise(buf, libc::MADV_HUGEPAGE);
+            }
+            if self.populate {
+                madvise(buf, libc::MADV_WILLNEED);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = buf;
+    }
+}
+
+/// Touch every page so they are loaded.
+fn prefault(buf: &[u8]) {
+    const PAGE_SIZE: usize = 4096;
+    let mut sum = 0u8;
+    for i in (0..buf.len()).step_by(PAGE_SIZE) {
+        sum = sum.wrapping_add(unsafe { std::ptr::read_volatile(buf.as_ptr().add(i)) });
+    }
+    std::hint::black_box(sum);
+}
@@ -7,15 +7,41 @@
 
 //! Implement [`BytesOwner`] and [`TextOwner`] for common types.
 
+use std::sync::Arc;
+
+use crate::Bytes;
 use crate::BytesOwner;
+use crate::Text;
 use crate::TextOwner;
 
 impl BytesOwner for Vec<u8> {}
 impl BytesOwner for Box<[u8]> {}
+impl BytesOwner for Arc<[u8]> {}
 impl BytesOwner for String {}
 #[cfg(feature = "frommmap")]
 impl BytesOwner for memmap::Mmap {}
 #[cfg(feature = "frombytes")]
 impl BytesOwner for bytes::Bytes {}
+impl BytesOwner for Text {}
 
 impl TextOwner for String {}
+impl TextOwner for Box<str> {}
+impl TextOwner for Arc<str> {}
+
+/// `Arc<Vec<u8>>` only implements `AsRef<Vec<u8>>`. Wrap it to implement
+/// [`BytesOwner`].
+struct ArcVec(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for ArcVec {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl BytesOwner for ArcVec {}
+
+impl From<Arc<Vec<u8>>> for Bytes {
+    fn from(value: Arc<Vec<u8>>) -> Self {
+        Self::from_owner(ArcVec(value))
+    }
+}
@@ -5,6 +5,7 @@
  * LICENSE file in the root directory of this source tree.
  */
 
+use std::cell::RefCell;
 use std::fmt;
 
 use serde::de;
@@ -22,6 +23,38 @@ impl Serialize for Bytes {
     }
 }
 
+thread_local! {
+    static BACKING: RefCell<Option<Bytes>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with `backing` as the buffer being deserialized.
+///
+/// During `f`, [`Bytes`] deserialized from borrowed data inside `backing`
+/// (ex. `mincode::deserialize(&backing)`) share `backing` as the owner,
+/// instead of copying. This only affects the current thread. Calls can be
+/// nested.
+pub fn with_backing_bytes<R>(backing: &Bytes, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Bytes>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            BACKING.with(|b| *b.borrow_mut() = previous);
+        }
+    }
+    let previous = BACKING.with(|b| b.replace(Some(backing.clone())));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Convert a borrowed slice to `Bytes`. Zero-copy if the slice is inside
+/// the backing buffer set by [`with_backing_bytes`].
+fn borrowed_to_bytes(slice: &[u8]) -> Bytes {
+    BACKING.with(|b| match b.borrow().as_ref() {
+        Some(backing) => backing.slice_to_bytes(slice),
+        None => Bytes::copy_from_slice(slice),
+    })
+}
+
 struct BytesVisitor;
 
 impl<'de> de::Visitor<'de> for BytesVisitor {
@@ -32,11 +65,11 @@ impl<'de> de::Visitor<'de> for BytesVisitor {
     }
 
     fn visit_borrowed_bytes<E: de::Error>(self, v: &'de [u8]) -> Result<Self::Value, E> {
-        Ok(Bytes::copy_from_slice(v))
+        Ok(borrowed_to_bytes(v))
     }
 
     fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
-        Ok(Bytes::copy_from_slice(v.as_bytes()))
+        Ok(borrowed_to_bytes(v.as_bytes()))
     }
 
     fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E> {
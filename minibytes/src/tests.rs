@@ -8,6 +8,8 @@
 use quickcheck::quickcheck;
 
 use crate::Bytes;
+use crate::BytesMut;
+use crate::HashedBytes;
 use crate::Text;
 
 quickcheck! {
@@ -42,6 +44,16 @@ quickcheck! {
     }
 }
 
+#[test]
+fn test_slice_of() {
+    let a = Bytes::from(b"abcdef".to_vec());
+    let b = a.slice_of(&a[2..4]).unwrap();
+    assert_eq!(b, b"cd");
+    assert_eq!(b.as_ptr(), a[2..].as_ptr());
+    assert!(a.slice_of(b"cd").is_none());
+    assert!(b.slice_of(&a[1..3]).is_none());
+}
+
 static SAMPLE_TEXT: &str = "这是测试用的文字";
 
 #[test]
@@ -79,6 +91,82 @@ fn test_downcast_mut() {
     assert!(c.downcast_mut::<Vec<u8>>().is_none());
 }
 
+#[test]
+fn test_downcast_ref() {
+    let b = Bytes::from(b"abcd".to_vec());
+    let c = b.slice(1..);
+    assert_eq!(c.downcast_ref::<Vec<u8>>().unwrap(), b"abcd");
+    assert!(c.downcast_ref::<String>().is_none());
+    assert!(Bytes::from_static(b"x").downcast_ref::<Vec<u8>>().is_none());
+}
+
+#[test]
+fn test_try_into_owner() {
+    let v = b"abcd".to_vec();
+    let ptr = v.as_ptr();
+    let b = Bytes::from(v);
+    let c = b.clone();
+    let b = b.try_into_owner::<Vec<u8>>().unwrap_err();
+    drop(c);
+    let b = b.try_into_owner::<String>().unwrap_err();
+    let c = b.slice(1..);
+    drop(b);
+    let v = c.try_into_owner::<Vec<u8>>().unwrap();
+    assert_eq!(v.as_ptr(), ptr);
+    assert!(Bytes::new().try_into_owner::<Vec<u8>>().is_err());
+
+    let t = Text::from("abc".to_string());
+    assert_eq!(t.try_into_owner::<String>().unwrap(), "abc");
+}
+
+#[test]
+fn test_try_into_owner_with_weak() {
+    let b = Bytes::from(b"abcd".to_vec());
+    let weak = b.downgrade();
+    let b = b.try_into_owner::<Vec<u8>>().unwrap_err();
+    assert_eq!(weak.upgrade().unwrap(), b"abcd");
+    drop(weak);
+    assert_eq!(b.try_into_owner::<Vec<u8>>().unwrap(), b"abcd");
+}
+
+#[test]
+fn test_downgrade() {
+    let b = Bytes::from(b"abcd".to_vec());
+    let c = b.slice(1..3);
+    assert_eq!(b.strong_count(), Some(2));
+    let weak = c.downgrade();
+    assert_eq!(weak.strong_count(), Some(2));
+    assert_eq!(weak.upgrade().unwrap(), b"bc");
+    drop(b);
+    drop(c);
+    assert_eq!(weak.strong_count(), Some(0));
+    assert!(weak.upgrade().is_none());
+
+    let s = Bytes::from_static(b"abc");
+    assert_eq!(s.strong_count(), None);
+    assert_eq!(s.downgrade().upgrade().unwrap(), b"abc");
+}
+
+#[test]
+fn test_shared_owners() {
+    let v = std::sync::Arc::new(b"abcd".to_vec());
+    let b = Bytes::from(v.clone());
+    assert_eq!(b.as_ptr(), v.as_ptr());
+
+    let a: std::sync::Arc<[u8]> = v.as_slice().into();
+    let b = Bytes::from(a.clone());
+    assert_eq!(b.as_ptr(), a.as_ptr());
+
+    let boxed: Box<[u8]> = b"abcd".to_vec().into_boxed_slice();
+    let ptr = boxed.as_ptr();
+    let b = Bytes::from_owner_range(boxed, 1..3);
+    assert_eq!(b, b"bc");
+    assert_eq!(b.as_ptr(), ptr.wrapping_add(1));
+
+    let t = Text::from(std::sync::Arc::<str>::from("abc"));
+    assert_eq!(t, "abc");
+}
+
 #[test]
 fn test_into_vec() {
     let v = b"abcd".to_vec();
@@ -108,3 +196,149 @@ fn test_bytes_debug_format() {
     let expected = r#"b"printable\t\r\n\'\"\\\x00\x01\x02printable""#;
     assert_eq!(escaped, expected);
 }
+
+#[test]
+fn test_bytes_mut_freeze() {
+    let mut buf = BytesMut::with_capacity(16);
+    buf.extend_from_slice(b"abc");
+    std::io::Write::write_all(&mut buf, b"de").unwrap();
+    buf[0] = b'A';
+    assert_eq!(format!("{:?}", buf), r#"b"Abcde""#);
+    let ptr = buf.as_ptr();
+    let b = buf.freeze();
+    assert_eq!(b, b"Abcde");
+    assert_eq!(b.as_ptr(), ptr);
+    let v = b.into_vec();
+    assert_eq!(v.as_ptr(), ptr);
+}
+
+#[test]
+fn test_text_bytes_conversion() {
+    let a: Text = SAMPLE_TEXT.to_string().into();
+    let b: Bytes = a.slice(3..12).into();
+    assert_eq!(b, &SAMPLE_TEXT.as_bytes()[3..12]);
+    assert_eq!(b.as_ptr(), a[3..].as_ptr());
+
+    let c = Text::from_utf8(b.clone()).unwrap();
+    assert_eq!(c, &SAMPLE_TEXT[3..12]);
+    assert_eq!(c.as_ptr(), b.as_ptr());
+    assert_eq!(c.to_bytes().as_ptr(), b.as_ptr());
+
+    assert!(Text::from_utf8(b.slice(1..)).is_err());
+    assert!(Text::try_from(Bytes::from_static(b"abc")).is_ok());
+}
+
+#[test]
+fn test_deserialize_with_backing_bytes() {
+    use serde::de::value::BorrowedBytesDeserializer;
+    use serde::de::value::Error;
+    use serde::Deserialize;
+
+    let backing = Bytes::from(b"abcdef".to_vec());
+    let deserialize =
+        |slice: &[u8]| Bytes::deserialize(BorrowedBytesDeserializer::<Error>::new(slice)).unwrap();
+
+    // Without backing, borrowed data is copied.
+    let b = deserialize(&backing[1..3]);
+    assert_eq!(b, b"bc");
+    assert_ne!(b.as_ptr(), backing[1..].as_ptr());
+
+    crate::with_backing_bytes(&backing, || {
+        let b = deserialize(&backing[1..3]);
+        assert_eq!(b, b"bc");
+        assert_eq!(b.as_ptr(), backing[1..].as_ptr());
+
+        // Data outside the backing buffer is copied.
+        let other = b"xyz".to_vec();
+        assert_eq!(deserialize(&other), b"xyz");
+    });
+    // The backing buffer is released after the scope.
+    assert_eq!(backing.strong_count(), Some(1));
+}
+
+#[cfg(feature = "frommmap")]
+#[test]
+fn test_mmap_options() {
+    use std::io::Write;
+
+    use crate::MmapAdvice;
+    use crate::MmapOptions;
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&[7; 10000]).unwrap();
+
+    let b = MmapOptions::new()
+        .populate(true)
+        .huge_pages(true)
+        .advice(MmapAdvice::Sequential)
+        .map(&file)
+        .unwrap();
+    assert_eq!(b.len(), 10000);
+    assert!(b.iter().all(|&x| x == 7));
+    assert!(b.downcast_ref::<memmap::Mmap>().is_some());
+
+    let b = MmapOptions::new().offset(4096).len(10).map(&file).unwrap();
+    assert_eq!(b, [7; 10]);
+    let b = MmapOptions::new().offset(20000).map(&file).unwrap();
+    assert!(b.is_empty());
+
+    // Pages dropped by `advise(None)` are read from the file again.
+    let b = MmapOptions::new().map(&file).unwrap();
+    b.slice(100..5000).advise(Some(MmapAdvice::Random));
+    b.slice(100..5000).advise(None);
+    assert!(b.iter().all(|&x| x == 7));
+
+    // Advice is not applied to memory that is not a file mapping.
+    let b = Bytes::from(vec![7; 10000]);
+    b.advise(None);
+    assert!(b.iter().all(|&x| x == 7));
+}
+
+#[cfg(feature = "frombytes")]
+#[test]
+fn test_bytes_crate_interop() {
+    use bytes::Buf;
+
+    let a = Bytes::from(b"abcdef".to_vec());
+    let b: bytes::Bytes = a.slice(1..).into();
+    assert_eq!(b.as_ptr(), a[1..].as_ptr());
+
+    // Round trip shares the original `bytes::Bytes`.
+    let c = Bytes::from(b.clone()).slice(1..3);
+    let d: bytes::Bytes = c.into();
+    assert_eq!(d, &b"cd"[..]);
+    assert_eq!(d.as_ptr(), b[1..].as_ptr());
+
+    let mut buf = a.clone();
+    assert_eq!(buf.get_u8(), b'a');
+    let e = buf.copy_to_bytes(2);
+    assert_eq!(e.as_ptr(), a[1..].as_ptr());
+    assert_eq!(buf.remaining(), 3);
+    assert_eq!(buf.chunk(), b"def");
+}
+
+#[test]
+fn test_hashed_bytes() {
+    use std::collections::HashSet;
+
+    let a = HashedBytes::new(Bytes::from(b"abc".to_vec()));
+    let b = HashedBytes::from(Bytes::from_static(b"abc"));
+    let c = HashedBytes::from(Bytes::from_static(b"abd"));
+    assert_eq!(a, b);
+    assert_eq!(a.hash_value(), b.hash_value());
+    assert_ne!(a, c);
+
+    let set: HashSet<HashedBytes> = vec![a.clone(), b, c].into_iter().collect();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(&a));
+    assert_eq!(a.into_bytes(), b"abc");
+}
+
+#[test]
+fn test_ct_eq() {
+    let a = Bytes::from_static(b"secret");
+    assert!(a.ct_eq(b"secret"));
+    assert!(!a.ct_eq(b"secreT"));
+    assert!(!a.ct_eq(b"secre"));
+    assert!(Bytes::new().ct_eq(b""));
+}
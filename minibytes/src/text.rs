@@ -6,18 +6,29 @@
  */
 
 use std::any::Any;
+use std::str::Utf8Error;
+use std::sync::Arc;
 
 use super::bytes::AbstractBytes;
 use super::bytes::AbstractOwner;
+use super::bytes::AbstractWeakBytes;
 use super::bytes::SliceLike;
+use crate::Bytes;
 
 pub type Text = AbstractBytes<str>;
+pub type WeakText = AbstractWeakBytes<str>;
 pub trait TextOwner: AsRef<str> + Send + Sync + 'static {}
 
 impl<T: TextOwner> AbstractOwner<str> for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+    fn into_any_arc(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
 }
 
 impl Text {
@@ -30,6 +41,19 @@ impl Text {
         }
     }
 
+    /// Creates `Text` from UTF-8 `Bytes`, without copying.
+    ///
+    /// Returns an error if `bytes` is not valid UTF-8.
+    pub fn from_utf8(bytes: Bytes) -> Result<Self, Utf8Error> {
+        std::str::from_utf8(bytes.as_slice())?;
+        Ok(Self::from_owner(Utf8Bytes(bytes)))
+    }
+
+    /// Converts to `Bytes`, without copying.
+    pub fn to_bytes(&self) -> Bytes {
+        Bytes::from_owner(self.clone())
+    }
+
     #[inline]
     pub(crate) fn as_slice(&self) -> &str {
         let bytes = self.as_bytes();
@@ -58,3 +82,16 @@ impl SliceLike for str {
         self.to_string()
     }
 }
+
+/// Owner of `Text` converted from validated `Bytes`.
+struct Utf8Bytes(Bytes);
+
+impl AsRef<str> for Utf8Bytes {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        // Validated by Text::from_utf8.
+        unsafe { std::str::from_utf8_unchecked(self.0.as_slice()) }
+    }
+}
+
+impl TextOwner for Utf8Bytes {}
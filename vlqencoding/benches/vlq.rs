@@ -0,0 +1,73 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+use std::hint::black_box;
+use std::io::Cursor;
+
+use minibench::bench;
+use minibench::elapsed;
+use vlqencoding::VLQDecode;
+use vlqencoding::VLQDecodeAt;
+use vlqencoding::VLQEncode;
+
+const N: usize = 1_000_000;
+
+/// Encode `N` pseudo-random values of up to `max_len` bytes each.
+/// If `mixed` is true, lengths vary from 1 to `max_len` bytes.
+fn encode(max_len: u32, mixed: bool) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for i in 0..N as u64 {
+        // Spread bits so values cover the range.
+        let x = i.wrapping_mul(0x9e3779b97f4a7c15);
+        let len = if mixed {
+            (x >> 59) as u32 % max_len + 1
+        } else {
+            max_len
+        };
+        let value = x.checked_shr(64 - (7 * len).min(64)).unwrap_or(0);
+        buf.write_vlq(value).unwrap();
+    }
+    buf
+}
+
+fn main() {
+    for (name, max_len, mixed) in [
+        ("1 byte", 1, false),
+        ("2 bytes", 2, false),
+        ("3 bytes", 3, false),
+        ("5 bytes", 5, false),
+        ("1-3 bytes", 3, true),
+        ("1-10 bytes", 10, true),
+    ] {
+        let buf = encode(max_len, mixed);
+
+        bench(format!("read_vlq_at u64 ({})", name), || {
+            elapsed(|| {
+                let mut offset = 0;
+                let mut sum = 0u64;
+                while offset < buf.len() {
+                    let (value, len): (u64, _) = buf.read_vlq_at(offset).unwrap();
+                    sum = sum.wrapping_add(value);
+                    offset += len;
+                }
+                black_box(sum);
+            })
+        });
+
+        bench(format!("read_vlq u64 ({})", name), || {
+            elapsed(|| {
+                let mut cur = Cursor::new(&buf);
+                let mut sum = 0u64;
+                for _ in 0..N {
+                    let value: u64 = cur.read_vlq().unwrap();
+                    sum = sum.wrapping_add(value);
+                }
+                black_box(sum);
+            })
+        });
+    }
+}
@@ -0,0 +1,160 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Async VLQ encoding and decoding for `tokio::io` streams.
+
+use std::future::Future;
+use std::io;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+use crate::read_vlq_at;
+use crate::VLQDecodeAt;
+use crate::VLQEncodeInto;
+use crate::MAX_VLQ_LEN;
+
+pub trait AsyncVLQWrite: AsyncWrite + Unpin + Send {
+    /// Encode an integer to a VLQ byte array and write it to an
+    /// `AsyncWrite` object.
+    ///
+    /// This is the async version of [`crate::VLQEncode::write_vlq`], named
+    /// differently to avoid ambiguity with it. The encoded bytes are
+    /// written using a single `write_all`.
+    fn write_vlq_async<T>(&mut self, value: T) -> impl Future<Output = io::Result<()>> + Send
+    where
+        T: VLQEncodeInto,
+    {
+        let mut buf = [0u8; MAX_VLQ_LEN];
+        let len = value.write_vlq_into(&mut buf);
+        async move { self.write_all(&buf[..len]).await }
+    }
+}
+
+pub trait AsyncVLQRead: AsyncRead + Unpin + Send {
+    /// Read a VLQ byte array from an `AsyncRead` object and decode it to an
+    /// integer.
+    ///
+    /// This is the async version of [`crate::VLQDecode::read_vlq`], named
+    /// differently to avoid ambiguity with it. Bytes after the VLQ byte
+    /// array are not consumed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vlqencoding::AsyncVLQRead;
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let mut reader: &[u8] = &[120u8, 211, 171, 202, 220, 84];
+    /// let x: u8 = reader.read_vlq_async().await.unwrap();
+    /// assert_eq!(x, 120);
+    /// let x: u64 = reader.read_vlq_async().await.unwrap();
+    /// assert_eq!(x, 22742734291);
+    /// # });
+    /// ```
+    fn read_vlq_async<T>(&mut self) -> impl Future<Output = io::Result<T>> + Send
+    where
+        for<'a> &'a [u8]: VLQDecodeAt<T>,
+    {
+        async move {
+            let mut buf = [0u8; MAX_VLQ_LEN];
+            let mut len = 0;
+            loop {
+                let byte = self.read_u8().await?;
+                buf[len] = byte;
+                len += 1;
+                // A full buffer is rejected by `read_vlq_at` below as too
+                // long for any integer type.
+                if byte & 128 == 0 || len == buf.len() {
+                    break;
+                }
+            }
+            let (value, _) = read_vlq_at(&buf[..len], 0)?;
+            Ok(value)
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send + ?Sized> AsyncVLQWrite for W {}
+
+impl<R: AsyncRead + Unpin + Send + ?Sized> AsyncVLQRead for R {}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use quickcheck::quickcheck;
+
+    use super::*;
+    use crate::VLQDecode;
+    use crate::VLQEncode;
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    #[test]
+    fn test_async_round_trip() {
+        block_on(async {
+            let mut buf = Vec::new();
+            buf.write_vlq_async(300u64).await.unwrap();
+            buf.write_vlq_async(-5i32).await.unwrap();
+            buf.write_vlq_async(u128::MAX).await.unwrap();
+            buf.push(42);
+
+            let mut reader = &buf[..];
+            let x: u64 = reader.read_vlq_async().await.unwrap();
+            let y: i32 = reader.read_vlq_async().await.unwrap();
+            let z: u128 = reader.read_vlq_async().await.unwrap();
+            assert_eq!((x, y, z), (300, -5, u128::MAX));
+            assert_eq!(reader, &[42]);
+        });
+    }
+
+    #[test]
+    fn test_async_read_errors() {
+        block_on(async {
+            let mut reader: &[u8] = &[0x80, 0x80];
+            let r: io::Result<u64> = reader.read_vlq_async().await;
+            assert_eq!(r.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+            let mut reader: &[u8] = &[0xff, 0x02];
+            let r: io::Result<u8> = reader.read_vlq_async().await;
+            assert_eq!(r.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+            let mut reader: &[u8] = &[0x80; 100];
+            let r: io::Result<u64> = reader.read_vlq_async().await;
+            let e = r.unwrap_err();
+            let e = e.get_ref().unwrap().downcast_ref::<crate::VLQDecodeError>();
+            assert_eq!(e, Some(&crate::VLQDecodeError::TooLong { max_len: 10 }));
+        });
+    }
+
+    quickcheck! {
+        fn test_async_matches_sync(x: u64, y: i64) -> bool {
+            block_on(async {
+                let mut buf = Vec::new();
+                buf.write_vlq_async(x).await.unwrap();
+                buf.write_vlq_async(y).await.unwrap();
+
+                let mut expected = Vec::new();
+                expected.write_vlq(x).unwrap();
+                expected.write_vlq(y).unwrap();
+
+                let mut reader = &buf[..];
+                let x2: u64 = reader.read_vlq().unwrap();
+                let y2: i64 = reader.read_vlq().unwrap();
+                buf == expected && x2 == x && y2 == y
+            })
+        }
+    }
+}
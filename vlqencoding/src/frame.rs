@@ -0,0 +1,197 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under the MIT license found in the
+ * LICENSE file in the root directory of this source tree.
+ */
+
+//! Length-prefixed frames. A frame is the VLQ-encoded length of the data,
+//! followed by the data.
+
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use crate::VLQDecode;
+use crate::VLQDecodeAt;
+use crate::VLQDecodeError;
+use crate::VLQEncode;
+
+pub trait FrameWrite {
+    /// Write `data` as a frame: its length as VLQ, then the data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vlqencoding::FrameWrite;
+    /// let mut v = vec![];
+    /// v.write_frame(b"abc").unwrap();
+    /// assert_eq!(v, b"\x03abc");
+    /// ```
+    fn write_frame(&mut self, data: &[u8]) -> io::Result<()>;
+}
+
+pub trait FrameRead {
+    /// Read a frame written by [`FrameWrite::write_frame`].
+    ///
+    /// Frames longer than `limit` are rejected with
+    /// [`VLQDecodeError::FrameTooLarge`] before allocating a buffer for
+    /// them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vlqencoding::FrameRead;
+    /// let mut r: &[u8] = b"\x03abc\x02de";
+    /// assert_eq!(r.read_frame(10).unwrap(), b"abc");
+    /// assert!(r.read_frame(1).is_err());
+    /// ```
+    fn read_frame(&mut self, limit: usize) -> io::Result<Vec<u8>>;
+}
+
+impl<W: Write + ?Sized> FrameWrite for W {
+    fn write_frame(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_vlq(data.len())?;
+        self.write_all(data)
+    }
+}
+
+impl<R: Read + ?Sized> FrameRead for R {
+    fn read_frame(&mut self, limit: usize) -> io::Result<Vec<u8>> {
+        let len: usize = self.read_vlq()?;
+        check_frame_len(len, limit)?;
+        let mut data = vec![0; len];
+        self.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Read a frame from `buf` at `offset` without copying.
+///
+/// Returns `Ok((data, bytes_read))` on success. `bytes_read` includes the
+/// length prefix. Frames longer than `limit` are rejected.
+pub fn read_frame_at(buf: &[u8], offset: usize, limit: usize) -> io::Result<(&[u8], usize)> {
+    let (len, vlq_len): (usize, _) = buf.read_vlq_at(offset)?;
+    check_frame_len(len, limit)?;
+    let start = offset + vlq_len;
+    match buf.get(start..).and_then(|rest| rest.get(..len)) {
+        Some(data) => Ok((data, vlq_len + len)),
+        None => Err(io::ErrorKind::InvalidData.into()),
+    }
+}
+
+/// Iterate through consecutive frames in `buf`. Frames longer than `limit`
+/// are rejected.
+///
+/// The iteration stops after the first error.
+///
+/// # Examples
+///
+/// ```
+/// let buf = b"\x01a\x00\x02bc";
+/// let frames: Vec<_> = vlqencoding::frames(buf, 10).map(|f| f.unwrap()).collect();
+/// assert_eq!(frames, [&b"a"[..], b"", b"bc"]);
+/// ```
+pub fn frames(buf: &[u8], limit: usize) -> Frames<'_> {
+    Frames {
+        buf,
+        offset: 0,
+        limit,
+    }
+}
+
+/// Iterator returned by [`frames`].
+pub struct Frames<'a> {
+    buf: &'a [u8],
+    offset: usize,
+    limit: usize,
+}
+
+impl<'a> Frames<'a> {
+    /// Offset of the next frame. After an error, it is the offset of the
+    /// frame that failed to decode.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = io::Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.buf.len() {
+            return None;
+        }
+        match read_frame_at(self.buf, self.offset, self.limit) {
+            Ok((data, len)) => {
+                self.offset += len;
+                Some(Ok(data))
+            }
+            Err(e) => {
+                // Stop iteration. Keep `offset` pointing to the bad frame.
+                self.buf = &self.buf[..self.offset];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+fn check_frame_len(len: usize, limit: usize) -> io::Result<()> {
+    if len > limit {
+        Err(VLQDecodeError::FrameTooLarge { len, limit }.into())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck::quickcheck;
+
+    use super::*;
+
+    #[test]
+    fn test_frame_errors() {
+        // Truncated.
+        let buf = b"\x05abc";
+        assert!(read_frame_at(buf, 0, 10).is_err());
+        assert!((&buf[..]).read_frame(10).is_err());
+
+        // Too large. The guard applies before reading the data.
+        let mut buf = vec![];
+        buf.write_vlq(usize::MAX).unwrap();
+        let e = read_frame_at(&buf, 0, 10).unwrap_err();
+        let e = e.get_ref().unwrap().downcast_ref::<VLQDecodeError>();
+        let expected = VLQDecodeError::FrameTooLarge {
+            len: usize::MAX,
+            limit: 10,
+        };
+        assert_eq!(e, Some(&expected));
+        assert!((&buf[..]).read_frame(10).is_err());
+
+        // Iteration stops after an error.
+        let mut iter = frames(b"\x01a\x05b\x01c", 10);
+        assert_eq!(iter.next().unwrap().unwrap(), b"a");
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+        assert_eq!(iter.offset(), 2);
+    }
+
+    quickcheck! {
+        fn test_frame_round_trip(items: Vec<Vec<u8>>) -> bool {
+            let mut buf = vec![];
+            for item in &items {
+                buf.write_frame(item).unwrap();
+            }
+
+            let mut reader = &buf[..];
+            let read: Vec<Vec<u8>> = items
+                .iter()
+                .map(|_| reader.read_frame(usize::MAX).unwrap())
+                .collect();
+
+            let iterated: Vec<&[u8]> = frames(&buf, usize::MAX).map(|f| f.unwrap()).collect();
+            read == items && reader.is_empty() && iterated == items
+        }
+    }
+}
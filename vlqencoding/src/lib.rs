@@ -6,12 +6,41 @@
  */
 
 //! VLQ (Variable-length quantity) encoding.
+//!
+//! Unsigned integers are encoded 7 bits per byte, least significant group
+//! first. The highest bit of a byte indicates whether more bytes follow.
+//!
+//! Signed integers are mapped to unsigned integers using zig-zag encoding
+//! (0, -1, 1, -2, ... to 0, 1, 2, 3, ...) first, so values close to 0 are
+//! short regardless of their sign. Deltas can be written directly using
+//! [`VLQEncode::write_vlq`] with signed types.
+//!
+//! With the `tokio` feature, `AsyncVLQRead` and `AsyncVLQWrite` provide
+//! the same encoding for `tokio::io` streams.
+//!
+//! [`FrameWrite`] and [`FrameRead`] write and read length-prefixed byte
+//! arrays, with a limit to reject corrupted lengths.
 
+use std::fmt;
 use std::io;
 use std::io::Read;
 use std::io::Write;
 use std::mem::size_of;
 
+#[cfg(feature = "tokio")]
+mod async_io;
+mod frame;
+
+#[cfg(feature = "tokio")]
+pub use async_io::AsyncVLQRead;
+#[cfg(feature = "tokio")]
+pub use async_io::AsyncVLQWrite;
+pub use frame::frames;
+pub use frame::read_frame_at;
+pub use frame::FrameRead;
+pub use frame::FrameWrite;
+pub use frame::Frames;
+
 pub trait VLQEncode<T> {
     /// Encode an integer to a VLQ byte array and write it directly to a stream.
     ///
@@ -48,6 +77,96 @@ pub trait VLQEncode<T> {
     fn write_vlq(&mut self, value: T) -> io::Result<()>;
 }
 
+/// Error of decoding a malformed VLQ byte array.
+///
+/// Decoders return it wrapped in an `io::Error` of kind
+/// `io::ErrorKind::InvalidData`. Use `io::Error::get_ref` and `downcast_ref`
+/// to get it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VLQDecodeError {
+    /// The byte array is longer than `max_len`, the maximum length for the
+    /// integer type. For example, 10 for `u64`.
+    TooLong { max_len: usize },
+
+    /// The decoded value does not fit in the integer type.
+    Overflow,
+
+    /// The length of a frame exceeds the limit. See [`FrameRead`].
+    FrameTooLarge { len: usize, limit: usize },
+}
+
+impl fmt::Display for VLQDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VLQDecodeError::TooLong { max_len } => {
+                write!(f, "VLQ byte array is longer than {} bytes", max_len)
+            }
+            VLQDecodeError::Overflow => write!(f, "VLQ value overflows the integer type"),
+            VLQDecodeError::FrameTooLarge { len, limit } => {
+                write!(f, "frame length {} exceeds limit {}", len, limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VLQDecodeError {}
+
+impl From<VLQDecodeError> for io::Error {
+    fn from(err: VLQDecodeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Maximum length of a VLQ byte array of supported integers (`u128`).
+pub(crate) const MAX_VLQ_LEN: usize = 19;
+
+/// Integers that can be VLQ-encoded into a byte slice.
+pub trait VLQEncodeInto: Copy {
+    /// Number of bytes of the VLQ encoding of this integer.
+    fn vlq_len(self) -> usize;
+
+    /// Encode this integer to the start of `buf`. Returns the number of
+    /// bytes written, which equals [`VLQEncodeInto::vlq_len`].
+    ///
+    /// Panics if `buf` is shorter than [`VLQEncodeInto::vlq_len`].
+    fn write_vlq_into(self, buf: &mut [u8]) -> usize;
+}
+
+/// Number of bytes of the VLQ encoding of `value`.
+///
+/// Useful to pre-size buffers without encoding into a temporary `Vec`.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(vlqencoding::vlq_len(0u64), 1);
+/// assert_eq!(vlqencoding::vlq_len(127u8), 1);
+/// assert_eq!(vlqencoding::vlq_len(128u8), 2);
+/// assert_eq!(vlqencoding::vlq_len(-65i32), 2);
+/// assert_eq!(vlqencoding::vlq_len(u64::MAX), 10);
+/// ```
+#[inline]
+pub fn vlq_len<T: VLQEncodeInto>(value: T) -> usize {
+    value.vlq_len()
+}
+
+/// Encode `value` to the start of `buf`. Returns the number of bytes
+/// written.
+///
+/// Panics if `buf` is shorter than [`vlq_len`] of `value`.
+///
+/// # Examples
+///
+/// ```
+/// let mut buf = [0u8; 8];
+/// let len = vlqencoding::write_vlq_into(&mut buf, 22742734291u64);
+/// assert_eq!(&buf[..len], [211, 171, 202, 220, 84]);
+/// ```
+#[inline]
+pub fn write_vlq_into<T: VLQEncodeInto>(buf: &mut [u8], value: T) -> usize {
+    value.write_vlq_into(buf)
+}
+
 pub trait VLQDecode<T> {
     /// Read a VLQ byte array from stream and decode it to an integer.
     ///
@@ -122,26 +241,85 @@ pub trait VLQDecodeAt<T> {
     fn read_vlq_at(&self, offset: usize) -> io::Result<(T, usize)>;
 }
 
+/// Read a VLQ-encoded integer from `buf` at `offset`.
+///
+/// Returns `Ok((decoded_integer, bytes_read))` on success. This is the free
+/// function form of [`VLQDecodeAt::read_vlq_at`]. It does not need `io::Read`
+/// or a `Cursor`, which makes it suitable for hot decode paths that track
+/// offsets themselves.
+///
+/// # Examples
+///
+/// ```
+/// let buf = [0x80u8, 0x01, 0x05];
+/// let (x, len): (u32, _) = vlqencoding::read_vlq_at(&buf, 0).unwrap();
+/// assert_eq!((x, len), (128, 2));
+/// let (y, _): (u8, _) = vlqencoding::read_vlq_at(&buf, len).unwrap();
+/// assert_eq!(y, 5);
+/// ```
+#[inline]
+pub fn read_vlq_at<T>(buf: &[u8], offset: usize) -> io::Result<(T, usize)>
+where
+    for<'a> &'a [u8]: VLQDecodeAt<T>,
+{
+    VLQDecodeAt::read_vlq_at(&buf, offset)
+}
+
+/// Decode a VLQ byte array of 1 to 3 bytes at the start of `buf`, which
+/// covers the most common values, with unrolled checks.
+///
+/// Returns `None` if the byte array is longer, or `buf` is too short.
+#[inline]
+fn decode_short(buf: &[u8]) -> Option<(u64, usize)> {
+    let b0 = u64::from(*buf.first()?);
+    if b0 & 128 == 0 {
+        return Some((b0, 1));
+    }
+    let b1 = u64::from(*buf.get(1)?);
+    if b1 & 128 == 0 {
+        return Some(((b0 & 127) | (b1 << 7), 2));
+    }
+    let b2 = u64::from(*buf.get(2)?);
+    if b2 & 128 == 0 {
+        return Some(((b0 & 127) | ((b1 & 127) << 7) | (b2 << 14), 3));
+    }
+    None
+}
+
 macro_rules! impl_unsigned_primitive {
     ($T: ident) => {
-        impl<W: Write + ?Sized> VLQEncode<$T> for W {
-            fn write_vlq(&mut self, value: $T) -> io::Result<()> {
-                let mut buf = [0u8];
-                let mut value = value;
+        impl VLQEncodeInto for $T {
+            #[inline]
+            fn vlq_len(self) -> usize {
+                let bits = ($T::BITS - self.leading_zeros()) as usize;
+                bits.max(1).div_ceil(7)
+            }
+
+            fn write_vlq_into(self, buf: &mut [u8]) -> usize {
+                let mut value = self;
+                let mut len = 0;
                 loop {
                     let mut byte = (value & 127) as u8;
                     let next = value >> 7;
                     if next != 0 {
                         byte |= 128;
                     }
-                    buf[0] = byte;
-                    self.write_all(&buf)?;
+                    buf[len] = byte;
+                    len += 1;
                     value = next;
                     if value == 0 {
                         break;
                     }
                 }
-                Ok(())
+                len
+            }
+        }
+
+        impl<W: Write + ?Sized> VLQEncode<$T> for W {
+            fn write_vlq(&mut self, value: $T) -> io::Result<()> {
+                let mut buf = [0u8; MAX_VLQ_LEN];
+                let len = value.write_vlq_into(&mut buf);
+                self.write_all(&buf[..len])
             }
         }
 
@@ -151,27 +329,47 @@ macro_rules! impl_unsigned_primitive {
                 let mut value = 0 as $T;
                 let mut base = 1 as $T;
                 let base_multiplier = (1 << 7) as $T;
+                let max_len = ($T::BITS as usize).div_ceil(7);
+                let mut size = 0;
                 loop {
                     self.read_exact(&mut buf)?;
                     let byte = buf[0];
+                    size += 1;
                     value = ($T::from(byte & 127))
                         .checked_mul(base)
                         .and_then(|v| v.checked_add(value))
-                        .ok_or(io::ErrorKind::InvalidData)?;
+                        .ok_or(VLQDecodeError::Overflow)?;
                     if byte & 128 == 0 {
                         break;
                     }
+                    if size >= max_len {
+                        return Err(VLQDecodeError::TooLong { max_len }.into());
+                    }
                     base = base
                         .checked_mul(base_multiplier)
-                        .ok_or(io::ErrorKind::InvalidData)?;
+                        .ok_or(VLQDecodeError::Overflow)?;
                 }
                 Ok(value)
             }
         }
 
         impl<R: AsRef<[u8]>> VLQDecodeAt<$T> for R {
+            #[inline]
             fn read_vlq_at(&self, offset: usize) -> io::Result<($T, usize)> {
                 let buf = self.as_ref();
+                let max_len = ($T::BITS as usize).div_ceil(7);
+
+                // Fast path. Falls back to the loop below for long or
+                // malformed byte arrays.
+                if let Some((value, size)) = buf.get(offset..).and_then(decode_short) {
+                    if size <= max_len {
+                        return match <$T>::try_from(value) {
+                            Ok(value) => Ok((value, size)),
+                            Err(_) => Err(VLQDecodeError::Overflow.into()),
+                        };
+                    }
+                }
+
                 let mut size = 0;
                 let mut value = 0 as $T;
                 let mut base = 1 as $T;
@@ -182,13 +380,16 @@ macro_rules! impl_unsigned_primitive {
                         value = ($T::from(byte & 127))
                             .checked_mul(base)
                             .and_then(|v| v.checked_add(value))
-                            .ok_or(io::ErrorKind::InvalidData)?;
+                            .ok_or(VLQDecodeError::Overflow)?;
                         if byte & 128 == 0 {
                             break;
                         }
+                        if size >= max_len {
+                            return Err(VLQDecodeError::TooLong { max_len }.into());
+                        }
                         base = base
                             .checked_mul(base_multiplier)
-                            .ok_or(io::ErrorKind::InvalidData)?;
+                            .ok_or(VLQDecodeError::Overflow)?;
                     } else {
                         return Err(io::ErrorKind::InvalidData.into());
                     }
@@ -200,6 +401,7 @@ macro_rules! impl_unsigned_primitive {
 }
 
 impl_unsigned_primitive!(usize);
+impl_unsigned_primitive!(u128);
 impl_unsigned_primitive!(u64);
 impl_unsigned_primitive!(u32);
 impl_unsigned_primitive!(u16);
@@ -207,6 +409,17 @@ impl_unsigned_primitive!(u8);
 
 macro_rules! impl_signed_primitive {
     ($T: ty, $U: ty) => {
+        impl VLQEncodeInto for $T {
+            #[inline]
+            fn vlq_len(self) -> usize {
+                (((self << 1) ^ (self >> (size_of::<$U>() * 8 - 1))) as $U).vlq_len()
+            }
+
+            fn write_vlq_into(self, buf: &mut [u8]) -> usize {
+                (((self << 1) ^ (self >> (size_of::<$U>() * 8 - 1))) as $U).write_vlq_into(buf)
+            }
+        }
+
         impl<W: Write + ?Sized> VLQEncode<$T> for W {
             fn write_vlq(&mut self, v: $T) -> io::Result<()> {
                 self.write_vlq(((v << 1) ^ (v >> (size_of::<$U>() * 8 - 1))) as $U)
@@ -229,6 +442,7 @@ macro_rules! impl_signed_primitive {
 }
 
 impl_signed_primitive!(isize, usize);
+impl_signed_primitive!(i128, u128);
 impl_signed_primitive!(i64, u64);
 impl_signed_primitive!(i32, u32);
 impl_signed_primitive!(i16, u16);
@@ -257,14 +471,36 @@ mod tests {
             let mut z = x;
             let t = v.read_vlq_at(0).unwrap();
             z = t.0;
+            let u = read_vlq_at(&v, 0).unwrap();
+            let w = u.0;
 
             let mut c = Cursor::new(v);
             let y = x;
             x = c.read_vlq().unwrap();
-            x == y && y == z && t.1 == c.position() as usize
+            x == y && y == z && z == w && t.1 == c.position() as usize && t.1 == u.1
         }};
     }
 
+    #[test]
+    fn test_vlq_len_boundaries() {
+        assert_eq!(vlq_len(0u8), 1);
+        assert_eq!(vlq_len(u8::MAX), 2);
+        assert_eq!(vlq_len(u16::MAX), 3);
+        assert_eq!(vlq_len(u32::MAX), 5);
+        assert_eq!(vlq_len(u64::MAX), 10);
+        assert_eq!(vlq_len(u128::MAX), MAX_VLQ_LEN);
+        assert_eq!(vlq_len(i64::MIN), 10);
+        assert_eq!(vlq_len(-64i8), 1);
+        assert_eq!(vlq_len(64i8), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_write_vlq_into_short_buffer() {
+        let mut buf = [0u8; 1];
+        write_vlq_into(&mut buf, 128u32);
+    }
+
     #[test]
     fn test_round_trip_manual() {
         for i in (0..64)
@@ -282,6 +518,10 @@ mod tests {
             assert!(check_round_trip!(i as u32));
             assert!(check_round_trip!(i as u64));
             assert!(check_round_trip!(i as usize));
+            assert!(check_round_trip!(i as u128));
+            assert!(check_round_trip!(i as i128));
+            assert!(check_round_trip!((i as u128) << 64 | !i as u128));
+            assert!(check_round_trip!(((i as u128) << 64 | !i as u128) as i128));
         }
     }
 
@@ -306,6 +546,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_too_long() {
+        fn decode_error(e: io::Error) -> Option<VLQDecodeError> {
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            e.get_ref()?.downcast_ref().copied()
+        }
+
+        // u64::MAX takes the maximum 10 bytes.
+        let mut v = vec![];
+        v.write_vlq(u64::MAX).expect("write");
+        assert_eq!(v.len(), 10);
+        let x: u64 = v.read_vlq_at(0).unwrap().0;
+        assert_eq!(x, u64::MAX);
+
+        // Endless continuation bits are rejected after 10 bytes.
+        let v = vec![0x80u8; 100];
+        let r: io::Result<(u64, _)> = v.read_vlq_at(0);
+        let e = Some(VLQDecodeError::TooLong { max_len: 10 });
+        assert_eq!(decode_error(r.unwrap_err()), e);
+        let mut c = Cursor::new(&v);
+        let r: io::Result<u64> = c.read_vlq();
+        assert_eq!(decode_error(r.unwrap_err()), e);
+        assert_eq!(c.position(), 10);
+
+        let r: io::Result<(i8, _)> = v.read_vlq_at(0);
+        let e = Some(VLQDecodeError::TooLong { max_len: 2 });
+        assert_eq!(decode_error(r.unwrap_err()), e);
+
+        // Short enough, but does not fit.
+        let r: io::Result<(u8, _)> = [0x80u8, 0x02].read_vlq_at(0);
+        assert_eq!(decode_error(r.unwrap_err()), Some(VLQDecodeError::Overflow));
+    }
+
+    #[test]
+    fn test_decode_short() {
+        assert_eq!(decode_short(&[]), None);
+        assert_eq!(decode_short(&[0x05, 0xff]), Some((5, 1)));
+        assert_eq!(decode_short(&[0x81, 0x01]), Some((129, 2)));
+        assert_eq!(decode_short(&[0xff, 0xff, 0x7f]), Some(((1 << 21) - 1, 3)));
+        assert_eq!(decode_short(&[0x81, 0x81]), None);
+        assert_eq!(decode_short(&[0x81, 0x81, 0x81, 0x01]), None);
+    }
+
+    #[test]
+    fn test_u128_max() {
+        let mut v = vec![];
+        v.write_vlq(u128::MAX).expect("write");
+        assert_eq!(v.len(), 19);
+        let x: u128 = v.read_vlq_at(0).unwrap().0;
+        assert_eq!(x, u128::MAX);
+        let x: io::Result<(u64, _)> = v.read_vlq_at(0);
+        assert_eq!(x.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
     #[test]
     fn test_zig_zag() {
         let mut c = Cursor::new(vec![]);
@@ -342,5 +636,86 @@ mod tests {
         fn test_round_trip_i8_quickcheck(x: i8) -> bool {
             check_round_trip!(x)
         }
+
+        fn test_round_trip_u16_quickcheck(x: u16) -> bool {
+            check_round_trip!(x)
+        }
+
+        fn test_round_trip_i16_quickcheck(x: i16) -> bool {
+            check_round_trip!(x)
+        }
+
+        fn test_round_trip_u32_quickcheck(x: u32) -> bool {
+            check_round_trip!(x)
+        }
+
+        fn test_round_trip_i32_quickcheck(x: i32) -> bool {
+            check_round_trip!(x)
+        }
+
+        fn test_round_trip_isize_quickcheck(x: isize) -> bool {
+            check_round_trip!(x)
+        }
+
+        fn test_round_trip_u128_quickcheck(x: u64, y: u64) -> bool {
+            check_round_trip!((x as u128) << 64 | y as u128)
+        }
+
+        fn test_round_trip_i128_quickcheck(x: i64, y: u64) -> bool {
+            check_round_trip!((x as i128) << 64 | y as i128)
+        }
+
+        fn test_zig_zag_i64_quickcheck(x: i64) -> bool {
+            // Zig-zag keeps small magnitudes short regardless of the sign.
+            let mut v = vec![];
+            v.write_vlq(x).expect("write");
+            let mut w = vec![];
+            w.write_vlq(x.unsigned_abs() << 1).expect("write");
+            x == i64::MIN || v.len() <= w.len()
+        }
+
+        fn test_read_vlq_at_matches_read_vlq(buf: Vec<u8>, offset: u8) -> bool {
+            // `read_vlq_at` has fast paths. `read_vlq` does not.
+            fn check<T: PartialEq>(buf: &[u8], offset: usize) -> bool
+            where
+                for<'a> &'a [u8]: VLQDecodeAt<T> + VLQDecode<T>,
+            {
+                let decode_error = |e: io::Error| -> Option<VLQDecodeError> {
+                    e.get_ref()?.downcast_ref().copied()
+                };
+                let at = read_vlq_at::<T>(buf, offset);
+                let mut reader = buf.get(offset..).unwrap_or_default();
+                let read = VLQDecode::<T>::read_vlq(&mut reader);
+                match (at, read) {
+                    (Ok((x, len)), Ok(y)) => x == y && len + reader.len() + offset == buf.len(),
+                    (Err(e1), Err(e2)) => decode_error(e1) == decode_error(e2),
+                    _ => false,
+                }
+            }
+            // Make continuation bits and long byte arrays likely.
+            let buf: Vec<u8> = buf.iter().map(|b| if b % 4 == 0 { *b } else { b | 128 }).collect();
+            (0..=(offset as usize).min(buf.len())).all(|offset| {
+                check::<u8>(&buf, offset)
+                    && check::<u16>(&buf, offset)
+                    && check::<u32>(&buf, offset)
+                    && check::<u64>(&buf, offset)
+                    && check::<u128>(&buf, offset)
+                    && check::<i64>(&buf, offset)
+            })
+        }
+
+        fn test_write_vlq_into_quickcheck(x: u64, y: i32, z: u128) -> bool {
+            fn check<T: VLQEncodeInto>(x: T) -> bool
+            where
+                Vec<u8>: VLQEncode<T>,
+            {
+                let mut v = vec![];
+                v.write_vlq(x).expect("write");
+                let mut buf = [0xffu8; MAX_VLQ_LEN];
+                let len = write_vlq_into(&mut buf, x);
+                vlq_len(x) == v.len() && len == v.len() && buf[..len] == v[..]
+            }
+            check(x) && check(y) && check(z) && check(x as u8) && check(y as i8)
+        }
     }
 }